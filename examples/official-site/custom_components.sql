@@ -132,12 +132,39 @@ and SQLPage adds a few more:
 - `static_path`: returns the path to one of the static files bundled with SQLPage. Accepts arguments like `sqlpage.js`, `sqlpage.css`, `apexcharts.js`, etc.
 - `app_config`: returns the value of a configuration parameter from sqlpage''s configuration file, such as `max_uploaded_file_size`, `site_prefix`, etc.
 - `icon_img`: generate an svg icon from a *tabler* icon name
+- `image_srcset`: returns a `srcset` attribute listing resized versions of an image of the site, served by `/_sqlpage/img/`, like `<img src="{{image}}" {{#with (image_srcset image)}}srcset="{{this}}"{{/with}}>`. Returns null for external images and for files that are not raster images.
 - `markdown`: renders markdown text. Accepts an optional 2nd argument `''allow_unsafe''` that will render embedded html blocks: use only on trusted content. See the [Commonmark spec](https://spec.commonmark.org/0.31.2/#html-blocks) for more info.
 - `each_row`: iterates over the rows of a query result
 - `typeof`: returns the type of a value (`string`, `number`, `boolean`, `object`, `array`, `null`)
 - `rfc2822_date`: formats a date as a string in the [RFC 2822](https://tools.ietf.org/html/rfc2822#section-3.3) format, that is, `Thu, 21 Dec 2000 16:01:07 +0200`
+- `format_date`: formats a date in the language of the user. Accepts an optional [strftime pattern](https://docs.rs/chrono/latest/chrono/format/strftime/index.html), like `{{format_date created_at ''%d %B %Y''}}`, and `relative=true` to display *3 days ago*.
+- `format_number`: formats a number with the separators of the language of the user. Accepts a number of decimals, like `{{format_number price 2}}`, and `compact=true` to display *1.2k*.
+- `format_currency`: formats an amount of money, like `{{format_currency price ''EUR''}}`. Amounts are rounded as decimal numbers, without floating point errors.
+- `format_bytes`: formats a file size, like *1.5 MB*.
+- `format`: formats a value according to a format of the `format` option of the [table](component.sql?component=table) component, like `{{format value ''currency:USD''}}`.
+- `column_format`: returns the format of a column in the `format` option of the current component, like `{{format this (column_format @key)}}`. The option is parsed only once per component.
 - `url_encode`: percent-encodes a string for use in a URL. For instance, `{{url_encode "hello world"}}` returns `hello%20world`.
 
+### Custom helpers
+
+When the built-in helpers are not enough, for instance to format amounts of money or map statuses to colors,
+you can write your own helpers in [Rhai](https://rhai.rs/book/), a small scripting language.
+A helper named `money` is defined in a `sqlpage/helpers/money.rhai` file in the configuration directory,
+or in the `sqlpage_files` table.
+The script receives the positional arguments of the helper in the `params` array,
+and its named arguments in the `hash` map. The value of its last expression is the value of the helper.
+
+```rhai
+let cents = params[0];
+let currency = hash.currency ?? "€";
+`${cents / 100}.${cents % 100} ${currency}`
+```
+
+This helper can then be used in any template as `{{money price currency="$"}}`.
+Like templates, helpers are reloaded when their file changes.
+Scripts cannot access files, the network or the database, and a script that runs for too long is interrupted.
+Built-in helpers cannot be replaced: give custom helpers names that are not already used.
+
 ### Attributes
 
 In addition to the parameters you pass to your components in your SQL queries,
@@ -169,6 +196,31 @@ you can create a file called `sqlpage/templates/shell.handlebars` and write your
 If you don''t want to start from scratch, you can copy the default `shell` component
 [from the SQLPage source code](https://github.com/sqlpage/SQLPage/blob/main/sqlpage/templates/shell.handlebars).
 
+### Extending the default components
+
+To change only a part of a default component, and keep getting the fixes made to the rest of it
+when you upgrade SQLPage, extend it instead of copying it.
+Some parts of the default components are named blocks, written `{{#> block_name}}default content{{/block_name}}`.
+A custom component can include a default component with `{{> component_name}}`,
+and replace some of its blocks with inline partials of the same name.
+Inside an inline partial, `{{> @partial-block}}` renders the default content of the block.
+
+For instance, this `sqlpage/templates/table.handlebars` file makes all table cells bold,
+and changes the message displayed for empty tables:
+
+```handlebars
+{{#*inline "cell"}}<b>{{> @partial-block}}</b>{{/inline}}
+{{#*inline "no_data"}}Nothing to see here{{/inline}}
+{{> table}}
+```
+
+Blocks are evaluated in the context where they appear in the default component:
+in the `cell` block of the table, `this` is the value of the cell and `@key` is the name of its column.
+The available blocks are:
+
+- `table`: `search` (the search bar), `header_cell` (the contents of a column header), `cell` (the contents of a cell), and `no_data` (the message displayed when there are no rows).
+- `shell`: `head` (empty by default, to add tags to the `<head>` of the page), `header` (the navigation bar), and `footer` (the contents of the footer).
+
 ## Examples
 
 All the default components are written in handlebars, and you can read their source code to learn how to write your own.
@@ -0,0 +1,64 @@
+INSERT INTO component(name, icon, introduced_in_version, description) VALUES
+('xlsx', 'file-spreadsheet', '0.46.0', 'Lets the user download data as an Excel spreadsheet (`.xlsx` file).
+
+This is a **header component**: it must be used at the top of the page, before any other component that displays something.
+Each column from the following queries becomes a column of the spreadsheet, with the column names in a bold first row.
+
+The spreadsheet is streamed to the browser while the rows are read from the database,
+so even very large result sets can be downloaded without being loaded in memory.
+
+Cell types follow the values returned by the database: numbers and booleans become numeric and boolean cells,
+and columns with a date or timestamp type in the database become Excel dates. Everything else is written as text,
+including text that looks like a date: cast such columns to a date type in SQL to get date cells.
+A sheet holds at most 1,048,576 rows and 16,384 columns: the download stops with an error when there are more.
+Texts longer than 32,767 characters, the maximum length of an Excel cell, are truncated.
+If an error happens while rows are being sent, the error message is written in the last row of the sheet.
+'),
+('parquet', 'file-database', '0.46.0', 'Lets the user download data as an [Apache Parquet](https://parquet.apache.org/) file,
+a compressed columnar format understood by most data tools (pandas, DuckDB, Spark, ...).
+
+This is a **header component**: it must be used at the top of the page, before any other component that displays something.
+Each column from the following queries becomes a column of the file.
+
+Rows are read from the database and sent in row groups, so only one row group is held in memory at a time.
+The type of each column (boolean, 64-bit integer, double or text) is inferred from its values in the first row group:
+cast your columns in SQL if a column can contain values of different types.
+A parquet file cannot contain an error message: if an error happens while rows are being sent, or if a value does not match the type of its column,
+the download is interrupted, so the browser reports it as failed, and the error is logged on the server.
+');
+
+INSERT INTO parameter(component, name, description, type, top_level, optional) SELECT 'xlsx', * FROM (VALUES
+    ('filename', 'The name of the file that should be downloaded. The `.xlsx` extension is added if the name has none.', 'TEXT', TRUE, TRUE),
+    ('sheet_name', 'The name of the sheet inside the workbook. "Sheet1" by default. At most 31 characters, without any of `[]:*?/\`.', 'TEXT', TRUE, TRUE)
+) x;
+
+INSERT INTO parameter(component, name, description, type, top_level, optional) SELECT 'parquet', * FROM (VALUES
+    ('filename', 'The name of the file that should be downloaded. The `.parquet` extension is added if the name has none.', 'TEXT', TRUE, TRUE),
+    ('row_group_size', 'The number of rows in each row group. Defaults to 10000. Larger row groups compress better but use more memory on the server.', 'INTEGER', TRUE, TRUE)
+) x;
+
+INSERT INTO example(component, description) VALUES
+('xlsx', '
+### Excel export of a large table
+
+Create a file named `export.sql` and link to it from your pages. Visiting it downloads `sales.xlsx`.
+
+```sql
+select ''xlsx'' as component, ''sales'' as filename, ''Sales 2024'' as sheet_name;
+select order_date, customer, amount from orders where order_date >= ''2024-01-01'';
+```
+'),
+('parquet', '
+### Parquet export for a data team
+
+```sql
+select ''parquet'' as component, ''events'' as filename;
+select id, user_id, cast(amount as double precision) as amount, created_at from events;
+```
+
+The file can be queried directly, for instance with DuckDB:
+
+```sql
+select user_id, sum(amount) from ''events.parquet'' group by user_id;
+```
+');
@@ -0,0 +1,91 @@
+INSERT INTO component(name, icon, introduced_in_version, description) VALUES
+('pdf', 'file-type-pdf', '0.46.0', 'Renders the page as a printable PDF document, instead of a web page.
+
+This is a **header component**: it must be used at the top of the page, before any other component that displays something.
+The components that follow it are laid out on pages by SQLPage itself, without a web browser:
+
+ - [`text`](?component=text) and [`title`](?component=title) become paragraphs and headings,
+ - [`table`](?component=table) becomes a table with a bold header that is repeated at the top of each page,
+ - [`list`](?component=list), [`card`](?component=card) and [`big_number`](?component=big_number) show their titles, descriptions and values.
+
+Other components are skipped. Rows without a component are displayed in a table, like in web pages.
+Texts use the Helvetica font included in all PDF readers, which supports Western European characters: other characters are replaced with `?`.
+
+Pages are sent as soon as they are full, so long tables do not have to be kept in memory.
+
+A page can also be downloaded as PDF without this component, when the client sends an `Accept: application/pdf` header.
+To generate a PDF document from SQL, for instance to attach it to an email,
+use [`sqlpage.render_pdf`](/functions.sql?function=render_pdf).
+');
+
+INSERT INTO parameter(component, name, description, type, top_level, optional) SELECT 'pdf', * FROM (VALUES
+    ('filename', 'The name of the file that should be downloaded. The `.pdf` extension is added if the name has none. If neither filename nor title is set, the document is displayed in the browser.', 'TEXT', TRUE, TRUE),
+    ('title', 'The title of the document, shown by PDF readers. Also used as the file name when filename is not set.', 'TEXT', TRUE, TRUE),
+    ('page_size', 'The size of the pages: A3, A4, A5, letter or legal. A4 by default.', 'TEXT', TRUE, TRUE),
+    ('orientation', '"portrait" (the default) or "landscape".', 'TEXT', TRUE, TRUE)
+) x;
+
+INSERT INTO example(component, description) VALUES
+('pdf', '
+### Printable invoice
+
+```sql
+select ''pdf'' as component, ''invoice-'' || $id as filename, ''Invoice '' || $id as title;
+select ''title'' as component, ''Invoice '' || $id as contents;
+select ''table'' as component;
+select product, quantity, price from invoice_lines where invoice_id = $id;
+```
+');
+
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'render_pdf',
+        '0.46.0',
+        'file-type-pdf',
+        'Executes another SQL file and returns the page it displays as a PDF document,
+in the form of a [data URL](https://developer.mozilla.org/en-US/docs/Web/URI/Schemes/data).
+
+The components returned by the file are laid out like in the [`pdf`](/component.sql?component=pdf) component.
+The file runs like with [`sqlpage.run_sql`](/functions.sql?function=run_sql): it has access to the same variables as the calling file,
+and you can pass it additional parameters.
+
+### Example: email a report
+
+```sql
+set result = sqlpage.send_mail(json_object(
+    ''to'', ''boss@example.com'',
+    ''subject'', ''Monthly report'',
+    ''body'', ''The report is attached.'',
+    ''attachments'', json_array(json_object(
+        ''filename'', ''report.pdf'',
+        ''data_url'', sqlpage.render_pdf(''reports/monthly.sql'', json_object(''month'', $month))
+    ))
+));
+```
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'render_pdf',
+        1,
+        'file',
+        'Path to the SQL file to render, absolute or relative to the web root. In-database files, from the sqlpage_files table, are supported.',
+        'TEXT'
+    ),(
+        'render_pdf',
+        2,
+        'parameters',
+        'Optional JSON object with variables for the rendered file, as in `sqlpage.run_sql`.',
+        'JSON'
+    );
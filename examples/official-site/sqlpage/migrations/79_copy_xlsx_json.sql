@@ -0,0 +1,31 @@
+INSERT INTO example(component, description) VALUES
+('form', '
+## Excel and JSON data import
+
+The `COPY` statement shown above for CSV files also accepts Excel workbooks and JSON files,
+with the `format` option:
+
+```sql
+-- import the "Products" sheet of an uploaded .xlsx file
+copy product(name, description, price) from ''product_data_input'' with (format xlsx, sheet ''Products'');
+
+-- import a JSON array of objects: [{"name": "SQLPage", "description": "...", "price": 0}, ...]
+copy product(name, description, price) from ''product_data_input'' with (format json);
+
+-- import a file with one JSON object per line
+copy product(name, description, price) from ''product_data_input'' with (format jsonlines);
+```
+
+Columns are matched by name with the first row of the sheet, or with the keys of the JSON objects.
+Missing keys and empty cells are imported as `NULL`.
+Without a `sheet` option, the first sheet of the workbook is imported.
+With `header false`, the columns of the sheet are imported in order, starting from the first row, into the columns listed in the `COPY` statement, which is then required.
+Excel dates are imported as `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS` text.
+
+When the `COPY` statement does not list the columns, all the columns of the file are imported.
+Their names are then used as quoted identifiers, so they must match the names of the columns of the table exactly, including their case.
+
+The file is read one row at a time, and imported with a native `COPY` on PostgreSQL and `INSERT` statements on other databases.
+When a cell contains an error, a line is not valid JSON, or a row cannot be inserted,
+the error message contains its row number in the spreadsheet, or its line number in the JSON file.
+');
@@ -0,0 +1,36 @@
+INSERT INTO example (component, description)
+VALUES (
+        'status_code',
+        '
+### Custom error pages
+
+When a page fails before it has started sending its response, SQLPage looks for a custom error page
+in the directory of the failing file, and then in each parent directory, just like [`404.sql` files](/your-first-sql-website/custom_urls.sql).
+In each directory, a file named after the status code (for instance `500.sql` or `403.sql`) takes precedence over a generic `error.sql`.
+
+The error page runs with the original request (same URL parameters, form fields and cookies),
+and receives the following variables:
+
+ - `$error_status`: the HTTP status code of the error, for instance `500`,
+ - `$error_message`: a message that is safe to show to the user (a generic message in production),
+ - `$request_id`: an identifier of the request, that is also included in the server logs.
+
+The response keeps the status code of the error, unless the error page sets another one.
+In development, errors in SQL queries are displayed directly in the page instead.
+
+```sql
+-- error.sql
+INSERT INTO error_log (status, request_id, path) VALUES ($error_status, $request_id, sqlpage.path());
+
+SELECT ''shell'' AS component, ''Oops'' AS title;
+SELECT ''alert'' AS component, ''Something went wrong'' AS title,
+    ''Please try again later. Reference: '' || $request_id AS description;
+```
+
+An `api/error.sql` file can return JSON errors for the files in the `api/` directory:
+
+```sql
+SELECT ''json'' AS component,
+    json_object(''status'', $error_status, ''error'', $error_message, ''request_id'', $request_id) AS contents;
+```
+');
@@ -0,0 +1,78 @@
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        't',
+        '0.46.0',
+        'language',
+        'Translates a message into the language of the current user.
+
+### Translation catalogs
+
+Translations are JSON files named after a language tag, like `fr.json` or `pt-BR.json`,
+in the `sqlpage/locales` folder (the `locales` folder of the [configuration directory](https://github.com/sqlpage/SQLPage/blob/main/configuration.md)).
+They are loaded when SQLPage starts. Nested objects define dotted keys:
+
+```json
+{
+  "greeting": "Hello {name}!",
+  "menu": { "home": "Home" }
+}
+```
+
+Here, `sqlpage.t(''menu.home'')` returns `Home`.
+
+### Choosing the language
+
+The language of a request is the first one that has a catalog among:
+
+ 1. the value of the `lang` cookie (the cookie name can be changed with the `locale_cookie` configuration option),
+ 2. the `locale` claim of users logged in with [OIDC](/sso),
+ 3. the languages accepted by the browser, from the `Accept-Language` header,
+ 4. the `default_locale` configuration option, `en` by default.
+
+`fr-CA` users get the `fr` catalog when there is no `fr-CA` catalog.
+A message missing from a catalog falls back to the language without its region,
+then to the default locale. When no catalog defines a key, the key itself is returned.
+
+### Placeholders
+
+Messages can contain `{name}` placeholders, replaced by the values of the JSON object passed as second argument:
+
+```sql
+select ''text'' as component, sqlpage.t(''greeting'', json_object(''name'', $name)) as contents;
+```
+
+### Templates and built-in components
+
+In [custom components](/custom_components.sql), use the `t` helper: `{{t "greeting" name=user_name}}`.
+
+The texts of the built-in components (search bar, table messages, form buttons, pagination, error pages...)
+are translated in English, French and German. They use keys starting with `sqlpage.`, like `sqlpage.no_data` or `sqlpage.submit`,
+that your catalogs can override. When the application has catalogs, the `lang` attribute of the page
+and the number formatting of tables and charts follow the language of the user.
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        't',
+        1,
+        'key',
+        'The key of the message in the translation catalogs, like `menu.home`.',
+        'TEXT'
+    ),(
+        't',
+        2,
+        'arguments',
+        'Optional JSON object with the values of the `{name}` placeholders of the message.',
+        'JSON'
+    );
@@ -0,0 +1,25 @@
+INSERT INTO parameter(component, name, description_md, type, top_level, optional) SELECT * FROM (VALUES
+    ('table', 'format', 'A JSON object that maps column names to a format, like `{"price": "currency:EUR", "created_at": "relative_date"}`. Formats follow the language of the user: `date` or `date:<pattern>` with a [strftime pattern](https://docs.rs/chrono/latest/chrono/format/strftime/index.html) like `%d %B %Y`, `relative_date` (*3 days ago*), `number` or `number:<decimals>`, `compact` (*1.2k*), `currency` or `currency:<ISO 4217 code>`, and `bytes` (*1.5 MB*). Formatted columns are still sorted by their raw value.', 'JSON', TRUE, TRUE),
+    ('big_number', 'format', 'The format of the value, like `currency:USD`, `compact`, `bytes`, `number:2` or `date:%d %B %Y`. See the `format` option of the [table](?component=table) component.', 'TEXT', FALSE, TRUE),
+    ('chart', 'format', 'The format of the values shown on the Y axis, in data labels and in tooltips: `number`, `number:<decimals>`, `compact`, `currency:<ISO 4217 code>` or `bytes`. Follows the language of the page.', 'TEXT', TRUE, TRUE)
+) x;
+
+INSERT INTO example(component, description, properties) VALUES
+('table', '
+### Formatted columns
+
+The `format` option formats dates, numbers and amounts of money in the language of the user,
+without changing the way columns are sorted.',
+    json('[{"component":"table", "sort": true, "format": {"Price": "currency:EUR", "Downloads": "compact", "Size": "bytes", "Released": "date:%d %B %Y"}},
+    {"Product": "SQLPage", "Price": 0, "Downloads": 152300, "Size": 27800000, "Released": "2023-07-01"},
+    {"Product": "Cloud hosting", "Price": 19.9, "Downloads": 1250, "Size": 1024, "Released": "2024-11-15"}]')
+),
+('big_number', '
+### Formatted values
+
+Use `format` to display amounts of money, large numbers or file sizes.',
+    json('[{"component":"big_number", "columns": 3},
+    {"title": "Revenue", "value": 1234567.5, "format": "currency:USD"},
+    {"title": "Visitors", "value": 1520000, "format": "compact"},
+    {"title": "Storage", "value": 73400320, "format": "bytes"}]')
+);
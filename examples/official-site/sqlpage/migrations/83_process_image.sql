@@ -0,0 +1,81 @@
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'process_image',
+        '0.46.0',
+        'photo-edit',
+        'Resizes, crops or converts an uploaded image, stores the result in the uploads folder, and returns its path.
+
+Photos taken with a phone are often several megabytes large, and contain metadata such as the GPS coordinates of the place where they were taken.
+`process_image` makes them lighter, and never keeps their metadata: the result contains only the pixels of the image,
+rotated according to the orientation recorded by the camera.
+
+The image type is detected from the contents of the file, not from its name, and files that are not images are rejected.
+Images of more than 50 million pixels are rejected too.
+If the file input field is empty, the function returns NULL.
+
+### Example
+
+#### Profile picture and thumbnail
+
+```sql
+update user
+set
+    picture = sqlpage.process_image(''picture'', ''{"width": 1024, "format": "jpeg"}''),
+    thumbnail = sqlpage.process_image(''picture'', ''{"width": 64, "height": 64, "fit": "cover", "format": "webp"}'')
+where id = $id;
+```
+
+To generate several sizes of the same image, call the function once per size.
+
+#### Image file
+
+Images that are already on the server are processed with the `source` option:
+
+```sql
+select ''card'' as component;
+select ''Banner'' as title, sqlpage.process_image(''images/banner.png'', ''{"source": "file", "width": 640, "format": "webp"}'') as top_image;
+```
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'process_image',
+        1,
+        'image',
+        'Name of the form field containing the uploaded image, or path of an image file when the `source` option is `file`. Relative paths are relative to the web root.',
+        'TEXT'
+    ),
+    (
+        'process_image',
+        2,
+        'options',
+        'Optional JSON object with the following properties:
+ - `source`: `upload` (the default) when `image` is the name of a form field, or `file` when it is the path of an image file.
+ - `width` and `height`: the size of the result, in pixels. Images are resized only when one of them is set.
+ - `fit`: how the image is fitted in `width` and `height`.
+   - `contain` (the default) keeps the proportions of the image, and never enlarges it.
+   - `cover` fills the whole area, and crops what overflows around the center.
+   - `fill` stretches the image.
+ - `crop`: an area of the original image to keep before resizing, like `{"x": 10, "y": 10, "width": 200, "height": 200}`.
+ - `format`: `jpeg`, `png`, `webp`, `avif` or `gif`. By default, the format of the original image.
+ - `quality`: from 1 to 100, for JPEG and AVIF images. 80 by default. WebP images are always lossless, whatever the quality: prefer JPEG or AVIF for photos.
+ - `folder`: the folder where the result is stored, relative to the web root. `uploads` by default. Like the folder of [`persist_uploaded_file`](?function=persist_uploaded_file), it must not come from user input.
+ - `mode`: the Unix permissions of the file, in octal notation, as in [`persist_uploaded_file`](?function=persist_uploaded_file).',
+        'JSON'
+    );
+
+UPDATE sqlpage_function_parameters
+SET description_md = description_md || '
+The contents of images, PDF files, ZIP archives and Office documents are checked: a file named `photo.png` that is not a PNG image is rejected.'
+WHERE "function" = 'persist_uploaded_file' AND "name" = 'allowed_extensions';
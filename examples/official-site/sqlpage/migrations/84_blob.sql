@@ -0,0 +1,95 @@
+INSERT INTO component(name, icon, description, introduced_in_version) VALUES
+    ('blob', 'file-download', '
+Returns the contents of a binary column, such as a file stored as a `BLOB` in the database, as the whole response.
+
+Unlike the [download](?component=download) component, the file is sent as it is read from the database,
+without being converted to a [data URL](https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/Data_URIs) first.
+The whole file is still loaded in memory by the database driver before it is sent,
+so files of more than a few megabytes are better stored on disk, and served from there.
+
+The component must be selected as a constant: `select ''blob'' as component, ...`.
+
+Like the download component, it must be used **at the very top of your SQL page**, before any component that displays content.', '0.46.0');
+
+INSERT INTO parameter(component, name, description, type, top_level, optional) SELECT 'blob', * FROM (VALUES
+    ('contents', 'The contents of the file: a binary column (`BLOB`, `BYTEA`, `VARBINARY`...), or a text that is sent as it is.', 'TEXT', TRUE, FALSE),
+    ('content_type', 'The type of the file, like `image/png` or `application/pdf`. When omitted, it is detected from the contents of the file.', 'TEXT', TRUE, TRUE),
+    ('filename', 'When set, the browser saves the file under this name instead of displaying it.', 'TEXT', TRUE, TRUE)
+) x;
+
+INSERT INTO example(component, description) VALUES
+    ('blob', '
+## Upload a file to the database, and download it
+
+A form with a file input sends the file to `upload.sql`,
+which stores it in the database with [`sqlpage.uploaded_file_blob`](/functions?function=uploaded_file_blob):
+
+```sql
+insert into document(name, mime_type, contents)
+values (
+    sqlpage.uploaded_file_name(''file''),
+    sqlpage.uploaded_file_mime_type(''file''),
+    sqlpage.uploaded_file_blob(''file'')
+)
+returning ''redirect'' as component, ''document.sql?id='' || id as link;
+```
+
+`document.sql` then sends it back:
+
+```sql
+select
+    ''blob'' as component,
+    contents,
+    mime_type as content_type,
+    name as filename
+from document
+where id = $id;
+```
+');
+
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'uploaded_file_blob',
+        '0.46.0',
+        'database-import',
+        'Passes the contents of an uploaded file to the database as binary data, to store it in a `BLOB`, `BYTEA` or `VARBINARY` column.
+
+Unlike [`sqlpage.read_file_as_data_url`](?function=read_file_as_data_url), the file is not encoded as text,
+so it takes less memory, and it is stored in the database exactly as it was uploaded.
+The whole file is passed to the database at once, so files larger than the `max_uploaded_file_size`
+[configuration option](https://github.com/sqlpage/SQLPage/blob/main/configuration.md) are refused:
+store larger files with [`sqlpage.persist_uploaded_file`](?function=persist_uploaded_file) instead.
+
+The result can only be given directly to the database, as a value in an `INSERT` or `UPDATE` statement for instance.
+It cannot be displayed in a component or combined with other SQLPage functions.
+If no file was uploaded in the field, the function returns NULL.
+
+### Example
+
+```sql
+insert into document(name, contents)
+values (sqlpage.uploaded_file_name(''file''), sqlpage.uploaded_file_blob(''file''));
+```
+
+Use the [blob](/component.sql?component=blob) component to send the file back to the browser.
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'uploaded_file_blob',
+        1,
+        'name',
+        'Name of the file input field in the form.',
+        'TEXT'
+    );
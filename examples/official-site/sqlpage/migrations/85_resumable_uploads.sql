@@ -0,0 +1,65 @@
+INSERT INTO parameter(component, name, description, type, top_level, optional) SELECT 'form', * FROM (VALUES
+    ('resumable', 'Used only for inputs of type "file". Sends the file in small chunks before submitting the form, with a progress bar. If the connection is lost, the upload resumes where it stopped. The file is handled by `sqlpage/on_upload.sql`, and the form sends its upload id instead of the file. The file can be up to `max_resumable_upload_size` bytes (1 GiB by default) instead of `max_uploaded_file_size`.', 'BOOLEAN', FALSE, TRUE)
+) x;
+
+INSERT INTO example(component, description) VALUES
+('form', '
+## Resumable uploads of large files
+
+Files sent with a normal form are limited to `max_uploaded_file_size` (5 MiB by default),
+and an upload that fails because of a network problem has to start again from the beginning.
+
+With `resumable` set on a file input, the browser sends the file in chunks of 5 MiB
+to SQLPage, with the [tus protocol](https://tus.io/), and displays a progress bar.
+Chunks are stored in the `uploads` folder of the configuration directory.
+When the connection is interrupted, or when the page is reloaded and the same file is selected again,
+the upload continues from the last chunk that was received.
+
+```sql
+select ''form'' as component, ''videos.sql'' as action;
+select ''video'' as name, ''file'' as type, ''video/*'' as accept, true as resumable;
+```
+
+When the last chunk is received, SQLPage runs `sqlpage/on_upload.sql`, in the configuration directory.
+Resumable uploads are disabled when this file does not exist.
+It sees the file as if it had been posted in a form, so the usual functions work:
+[`sqlpage.uploaded_file_path`](/functions?function=uploaded_file_path),
+[`sqlpage.uploaded_file_name`](/functions?function=uploaded_file_name),
+[`sqlpage.uploaded_file_mime_type`](/functions?function=uploaded_file_mime_type),
+[`sqlpage.persist_uploaded_file`](/functions?function=persist_uploaded_file)...
+The file is deleted when `on_upload.sql` has finished running, so it must be saved there.
+The name of the file input is in the `:field` variable, and the id of the upload in `:upload_id`.
+
+```sql
+-- sqlpage/on_upload.sql
+insert into video(upload_id, name, path)
+values (
+    :upload_id,
+    sqlpage.uploaded_file_name(:field),
+    sqlpage.persist_uploaded_file(:field, ''videos'')
+);
+```
+
+Then, the form is submitted with the upload id in place of the file,
+so `videos.sql` can find the video with `where upload_id = :video`.
+
+### Restricting uploads
+
+Resumable uploads are refused until you create `sqlpage/on_upload_start.sql` in the configuration directory,
+to decide which uploads to accept. To accept uploads from anybody who can reach the server instead,
+set `allow_anonymous_resumable_uploads` to `true` in the configuration.
+It runs before each upload is created, with the cookies and headers of the request,
+and the metadata of the upload as POST variables (`:filename`, `:filetype`, `:field`).
+The upload is refused when this file responds with a status that is not a success,
+for instance with the [`authentication`](/component.sql?component=authentication) or the [`status_code`](/component.sql?component=status_code) component:
+
+```sql
+-- sqlpage/on_upload_start.sql
+select ''status_code'' as component, 403 as status
+where not exists (select 1 from user_session where id = sqlpage.cookie(''session''));
+```
+
+At most `max_pending_resumable_uploads` incomplete uploads (100 by default),
+totalling `max_pending_resumable_uploads_size` bytes (10 GiB by default), can be in progress at the same time.
+Uploads that did not receive any chunk for a day are removed.
+');
@@ -0,0 +1,47 @@
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'uploaded_file_scan_result',
+        '0.46.0',
+        'shield-check',
+        'Returns the result of the antivirus scan of an uploaded file: `clean`, or the name of the virus that was found.
+
+Uploaded files are scanned by a [ClamAV](https://www.clamav.net/) daemon when the `clamd_address` configuration option is set,
+either to `host:port`, or to the path of a unix socket, such as `/run/clamav/clamd.ctl`.
+The file is sent to clamd before your SQL file runs, so an infected file never reaches `sqlpage.persist_uploaded_file` or your database.
+If clamd cannot be reached, the upload fails with an error: files are never accepted without a scan.
+
+By default, files in which a virus is found are rejected with a `422 Unprocessable Entity` error,
+and this function always returns `clean`.
+Set `reject_infected_uploads` to `false` in the configuration to accept them,
+and decide what to do with them in SQL.
+
+The function returns NULL when no scanner is configured, or when no file was uploaded in the field.
+
+### Example
+
+```sql
+insert into quarantine(file_name, virus)
+select sqlpage.uploaded_file_name(''document''), sqlpage.uploaded_file_scan_result(''document'')
+where sqlpage.uploaded_file_scan_result(''document'') <> ''clean'';
+```
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'uploaded_file_scan_result',
+        1,
+        'name',
+        'Name of the file input field in the form.',
+        'TEXT'
+    );
@@ -0,0 +1,95 @@
+UPDATE sqlpage_functions SET description_md = description_md || '
+### Allow-listed commands
+
+Instead of enabling `allow_exec`, which lets SQL files run any program,
+you can declare the commands that `sqlpage.exec` may run in the `exec_commands` option of `sqlpage/sqlpage.json`.
+Each command has an alias, used as the program name in SQL, and can only start its configured program,
+with arguments that match its `allowed_args` patterns, in a clean environment, with a timeout and a maximum output size.
+See [the configuration documentation](https://github.com/sqlpage/SQLPage/blob/main/configuration.md#allow-listed-commands).
+
+```sql
+select ''html'' as component, sqlpage.exec(''markdown_to_html'', ''--toc'') as html;
+```
+
+To send data to the standard input of the command, or to read its exit code, use [`sqlpage.exec_with_meta`](?function=exec_with_meta).
+'
+WHERE name = 'exec';
+
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'exec_with_meta',
+        '0.46.0',
+        'terminal',
+        'Executes a command, like [`sqlpage.exec`](?function=exec), and returns a JSON object with its exit code, its standard output and its standard error.
+
+Unlike `sqlpage.exec`, it does not fail when the command exits with a non-zero exit code,
+and it can write data to the standard input of the command.
+
+The command can be given like in `sqlpage.exec`, as a program name followed by its arguments,
+or as a JSON object with the following properties:
+
+ - `command`: the name of the program, or its alias in `exec_commands`,
+ - `args`: an array of arguments,
+ - `stdin`: a text that is written to the standard input of the command.
+
+The result is a JSON object like `{"exit_code": 0, "stdout": "...", "stderr": "..."}`.
+When the command cannot be started, times out, or writes more than its `max_output_size`,
+the result is `{"error": "..."}` instead. The exit code is null when the command was killed by a signal.
+
+Commands that are not allowed by the configuration, because they are not in `exec_commands` and `allow_exec` is false,
+or because of their arguments, raise an error.
+
+### Example
+
+#### Convert markdown to HTML with pandoc
+
+With this in `sqlpage/sqlpage.json`:
+
+```json
+{
+  "exec_commands": {
+    "pandoc": {
+      "program": "/usr/bin/pandoc",
+      "args": ["--from=markdown", "--to=html"],
+      "timeout_ms": 5000
+    }
+  }
+}
+```
+
+```sql
+set result = sqlpage.exec_with_meta(json_object(''command'', ''pandoc'', ''stdin'', :markdown));
+
+select ''alert'' as component, ''Conversion failed'' as title, coalesce($result->>''error'', $result->>''stderr'') as description
+where $result->>''exit_code'' is null or $result->>''exit_code'' <> 0;
+
+select ''html'' as component, $result->>''stdout'' as html;
+```
+'
+    );
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'exec_with_meta',
+        1,
+        'command',
+        'The name of the program to execute, or a JSON object with `command`, `args` and `stdin` properties.',
+        'TEXT'
+    ),
+    (
+        'exec_with_meta',
+        2,
+        'arguments...',
+        'The arguments to pass to the program, when the command is not a JSON object.',
+        'TEXT'
+    );
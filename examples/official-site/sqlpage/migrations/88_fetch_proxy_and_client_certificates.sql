@@ -0,0 +1,21 @@
+UPDATE sqlpage_functions SET description_md = description_md || '
+# Proxies, private certificate authorities and client certificates
+
+When the [`https_proxy` configuration option](https://github.com/sqlpage/SQLPage/blob/main/configuration.md#outgoing-http-requests)
+or the `HTTPS_PROXY` environment variable is set, HTTPS requests are tunneled through that HTTP proxy,
+except for the hosts listed in `no_proxy`.
+The certificates of servers signed by a private certificate authority are accepted when
+the certificate of the authority is listed in `ca_certificates_files`.
+
+Servers that require a client certificate can be called with the `tls_profile` property of the request,
+which selects one of the client certificates declared in the `tls_profiles` configuration option.
+Introduced in version 0.46.0.
+
+```sql
+set invoices = sqlpage.fetch(json_object(
+    ''url'', ''https://billing.internal.example.com/invoices'',
+    ''tls_profile'', ''billing''
+));
+```
+'
+WHERE name = 'fetch';
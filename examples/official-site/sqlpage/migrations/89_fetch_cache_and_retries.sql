@@ -0,0 +1,26 @@
+UPDATE sqlpage_functions SET description_md = description_md || '
+# Caching and retries
+
+Pages that call slow or unreliable APIs can reuse responses and resend failed requests,
+with two more properties of the request. Introduced in version 0.46.0.
+
+ - `cache_ttl_ms`: How long, in milliseconds, the response of a `GET` or `HEAD` request can be reused by identical requests, from all users.
+   A shorter `max-age` in the `Cache-Control` header of the response takes precedence, and responses with `Cache-Control: no-store` or `private` are never reused.
+   When the response is too old and has an `ETag` or a `Last-Modified` header, SQLPage asks the server whether it changed, and reuses it on `304 Not Modified`.
+   Only successful responses are kept, in memory, up to [`max_fetch_cache_size`](https://github.com/sqlpage/SQLPage/blob/main/configuration.md) bytes.
+ - `retries`: How many times to resend the request when it fails with a network error, or with a `502`, `503` or `504` status.
+   SQLPage waits 100 milliseconds before the first retry, and twice as long before each of the next ones, up to 10 retries.
+   When the server sends a `Retry-After` header, SQLPage waits as long as it asks, and does not retry when it asks to wait more than 10 seconds.
+   Only idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS`) are resent, since resending a `POST` request could apply it twice.
+
+```sql
+set weather = sqlpage.fetch(json_object(
+    ''url'', ''https://api.example.com/weather?city='' || sqlpage.url_encode($city),
+    ''cache_ttl_ms'', 600000,
+    ''retries'', 3
+));
+```
+
+These properties work the same way in [`sqlpage.fetch_with_meta`](?function=fetch_with_meta).
+'
+WHERE name = 'fetch';
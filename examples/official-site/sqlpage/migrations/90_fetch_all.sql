@@ -0,0 +1,57 @@
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'fetch_all',
+        '0.46.0',
+        'arrows-split',
+        'Sends many HTTP requests at the same time, and returns a JSON array with the result of each request, in the same order.
+
+Calling [`sqlpage.fetch`](?function=fetch) once per row sends the requests one after the other,
+which is slow when there are many of them. `sqlpage.fetch_all` takes a JSON array of requests,
+and sends up to [`max_concurrent_fetches`](https://github.com/sqlpage/SQLPage/blob/main/configuration.md) of them (16 by default) at the same time.
+
+Each request is either a URL, or a JSON object in the format accepted by [`sqlpage.fetch`](?function=fetch).
+Each result is a JSON object in the format returned by [`sqlpage.fetch_with_meta`](?function=fetch_with_meta),
+with the `status`, `headers` and `body` of the response, or an `error`.
+A request that fails does not prevent the others from completing: its result contains the error.
+`null` requests give `null` results.
+
+### Example
+
+```sql
+set results = sqlpage.fetch_all(json_array(
+    ''https://api.example.com/weather?city=Paris'',
+    json_object(''url'', ''https://api.example.com/weather?city=Berlin'', ''timeout_ms'', 2000)
+));
+
+select ''table'' as component;
+select
+    key as request_number,
+    value->>''status'' as status,
+    value->''json_body''->>''temperature'' as temperature,
+    value->>''error'' as error
+from json_each($results);
+```
+
+The n-th result is the result of the n-th request, even when the requests complete in a different order.
+'
+    );
+
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'fetch_all',
+        1,
+        'requests',
+        'A JSON array of requests. Each request is a URL, or a JSON object like the ones accepted by `sqlpage.fetch`.',
+        'JSON'
+    );
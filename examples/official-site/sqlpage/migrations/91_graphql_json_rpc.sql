@@ -0,0 +1,118 @@
+INSERT INTO sqlpage_functions (
+        "name",
+        "introduced_in_version",
+        "icon",
+        "description_md"
+    )
+VALUES (
+        'graphql',
+        '0.46.0',
+        'brand-graphql',
+        'Sends a query to a [GraphQL](https://graphql.org/) API, and returns the `data` of the response as a JSON string.
+
+The endpoint is a URL, or a JSON object in the format accepted by [`sqlpage.fetch`](?function=fetch),
+so the request can carry `headers`, a `username` and `password`, a `timeout_ms`, `retries`, or a `tls_profile`.
+The request is sent with the `POST` method, unless the endpoint sets another `method`.
+SQLPage builds the body of the request from the query and its variables, so the endpoint must not have a `body`.
+
+GraphQL servers often answer with a `200 OK` status even when the query failed, and describe the failure in an `errors` array.
+When the response contains errors, the function fails with the messages of the server,
+instead of returning data that may be incomplete.
+
+### Example
+
+```sql
+set repository = sqlpage.graphql(
+    json_object(
+        ''url'', ''https://api.github.com/graphql'',
+        ''headers'', json_object(''Authorization'', ''Bearer '' || sqlpage.environment_variable(''GITHUB_TOKEN''))
+    ),
+    ''query ($owner: String!, $name: String!) { repository(owner: $owner, name: $name) { stargazerCount } }'',
+    json_object(''owner'', ''sqlpage'', ''name'', ''SQLPage'')
+);
+
+select ''big_number'' as component;
+select ''Stars'' as title, $repository->''repository''->>''stargazerCount'' as value;
+```
+
+If the endpoint is NULL, the function returns NULL.
+'
+    ),
+    (
+        'json_rpc',
+        '0.46.0',
+        'api',
+        'Calls a method of a [JSON-RPC 2.0](https://www.jsonrpc.org/specification) API, and returns its `result` as a JSON string.
+
+The endpoint is a URL, or a JSON object in the format accepted by [`sqlpage.fetch`](?function=fetch),
+so the request can carry `headers`, a `username` and `password`, a `timeout_ms`, `retries`, or a `tls_profile`.
+The request is sent with the `POST` method, unless the endpoint sets another `method`.
+
+When the server answers with an `error` object, the function fails with its code, message and data.
+
+### Example
+
+```sql
+set block_number = sqlpage.json_rpc(
+    ''https://ethereum-rpc.publicnode.com'',
+    ''eth_blockNumber'',
+    json_array()
+);
+
+select ''text'' as component, ''Latest block: '' || $block_number as contents;
+```
+
+If the endpoint is NULL, the function returns NULL.
+A `null` result is returned as NULL.
+'
+    );
+
+INSERT INTO sqlpage_function_parameters (
+        "function",
+        "index",
+        "name",
+        "description_md",
+        "type"
+    )
+VALUES (
+        'graphql',
+        1,
+        'endpoint',
+        'The URL of the GraphQL API, or a JSON object like the ones accepted by `sqlpage.fetch`, without a `body`.',
+        'JSON'
+    ),
+    (
+        'graphql',
+        2,
+        'query',
+        'The GraphQL query or mutation.',
+        'TEXT'
+    ),
+    (
+        'graphql',
+        3,
+        'variables',
+        'Optional. A JSON object with the values of the variables of the query.',
+        'JSON'
+    ),
+    (
+        'json_rpc',
+        1,
+        'endpoint',
+        'The URL of the JSON-RPC API, or a JSON object like the ones accepted by `sqlpage.fetch`, without a `body`.',
+        'JSON'
+    ),
+    (
+        'json_rpc',
+        2,
+        'method',
+        'The name of the method to call.',
+        'TEXT'
+    ),
+    (
+        'json_rpc',
+        3,
+        'params',
+        'Optional. The parameters of the method, as a JSON array or object.',
+        'JSON'
+    );
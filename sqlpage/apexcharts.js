@@ -37,6 +37,8 @@ sqlpage_chart = (() => {
     ]),
   );
   const isDarkTheme = document.body?.dataset?.bsTheme === "dark";
+  // Format numbers and dates like the language of the page, or of the browser if it has none
+  const locale = document.documentElement.lang || undefined;
 
   const STACKABLE_CHART_TYPES = ["line", "area", "bar"];
   const APEXCHARTS_TYPE_ALIASES = { column: "bar" };
@@ -49,6 +51,62 @@ sqlpage_chart = (() => {
     heatmap: null,
   };
 
+  const BYTE_UNITS = [
+    "byte",
+    "kilobyte",
+    "megabyte",
+    "gigabyte",
+    "terabyte",
+    "petabyte",
+  ];
+
+  /**
+   * Formats numbers like the `format` option of the table component:
+   * `number`, `number:<decimals>`, `compact`, `currency:<code>` or `bytes`.
+   * @param {unknown} spec
+   * @returns {((value: number) => string) | undefined}
+   */
+  function number_formatter(spec) {
+    if (typeof spec !== "string") return undefined;
+    const separator = spec.indexOf(":");
+    const kind = separator < 0 ? spec : spec.slice(0, separator);
+    const arg = separator < 0 ? undefined : spec.slice(separator + 1);
+    /** @param {Intl.NumberFormatOptions} options */
+    const intl = (options) => {
+      const format = new Intl.NumberFormat(locale, options);
+      return (/** @type {number} */ value) => format.format(value);
+    };
+    switch (kind.trim()) {
+      case "number":
+        return intl(
+          arg
+            ? { minimumFractionDigits: +arg, maximumFractionDigits: +arg }
+            : {},
+        );
+      case "compact":
+        return intl({ notation: "compact", maximumFractionDigits: 1 });
+      case "currency":
+        return arg
+          ? intl({ style: "currency", currency: arg.trim() })
+          : intl({ minimumFractionDigits: 2, maximumFractionDigits: 2 });
+      case "bytes":
+        return (value) => {
+          let unit = 0;
+          while (Math.abs(value) >= 999.95 && unit < BYTE_UNITS.length - 1) {
+            value /= 1000;
+            unit++;
+          }
+          return intl({
+            style: "unit",
+            unit: BYTE_UNITS[unit],
+            maximumFractionDigits: unit ? 1 : 0,
+          })(value);
+        };
+      default:
+        return undefined;
+    }
+  }
+
   /** @typedef {number|string|Date} XValue */
   /** @typedef { {name:string, data:{x:XValue,y:number|null,z?:number}[]} } ChartSeries */
   /** @typedef { { [name:string]: ChartSeries } } Series */
@@ -131,6 +189,9 @@ sqlpage_chart = (() => {
       APEXCHARTS_TYPE_ALIASES[data.type] || data.type || "line";
     const is_stacked =
       !!data.stacked && STACKABLE_CHART_TYPES.includes(chart_type);
+    const y_format = number_formatter(
+      typeof data.format === "object" ? data.format?.y : data.format,
+    );
     /** @type { Series } */
     const series_map = {};
     for (const [name, old_x, old_y, z] of data.points) {
@@ -203,7 +264,10 @@ sqlpage_chart = (() => {
             : chart_type === "pie"
               ? (value, { seriesIndex, w }) =>
                   `${w.config.labels[seriesIndex]}: ${value.toFixed()}%`
-              : (value) => value?.toLocaleString?.() || value,
+              : (value) =>
+                  (typeof value === "number" && y_format?.(value)) ||
+                  value?.toLocaleString?.(locale) ||
+                  value,
       },
       fill: {
         type: chart_type === "area" ? "gradient" : "solid",
@@ -240,6 +304,12 @@ sqlpage_chart = (() => {
         title: {
           text: data.ytitle || undefined,
         },
+        labels: y_format
+          ? {
+              formatter: (value) =>
+                typeof value === "number" ? y_format(value) : value,
+            }
+          : undefined,
       },
       zaxis: {
         title: {
@@ -265,10 +335,11 @@ sqlpage_chart = (() => {
             if (is_timeseries && chart_type === "rangeBar") {
               const d = new Date(value);
               if (d.getHours() === 0 && d.getMinutes() === 0)
-                return d.toLocaleDateString();
-              return d.toLocaleString();
+                return d.toLocaleDateString(locale);
+              return d.toLocaleString(locale);
             }
-            const str_val = value.toLocaleString();
+            if (y_format && typeof value === "number") return y_format(value);
+            const str_val = value.toLocaleString(locale);
             if (str_val.length > 10 && Number.isNaN(value))
               return value.toFixed(2);
             return str_val;
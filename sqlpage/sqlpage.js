@@ -121,7 +121,9 @@ function table_parse_data(table_el, sort_buttons) {
     return {
       el: tr_el,
       sort_keys: sort_buttons.map((_btn_el, idx) => {
-        const str = cells[idx]?.textContent ?? "";
+        // Cells formatted on the server keep their original value for sorting
+        const str =
+          cells[idx]?.dataset.sort_value ?? cells[idx]?.textContent ?? "";
         const num = is_num[idx] ? Number.parseFloat(str) : Number.NaN;
         return { num, str };
       }),
@@ -310,14 +312,30 @@ function sqlpage_form() {
       for (const { size } of input.files ?? []) {
         if (size > max_size) {
           input.classList.add("is-invalid");
+          const message =
+            input.dataset.maxSizeMessage ??
+            "File size must be less than {max_size} kB.";
           return input.setCustomValidity(
-            `File size must be less than ${max_size / 1000} kB.`,
+            message.replace("{max_size}", String(max_size / 1000)),
           );
         }
       }
     });
   }
 
+  /** @type {NodeListOf<HTMLInputElement>} */
+  const resumable_inputs = document.querySelectorAll(
+    "input[type=file][data-resumable]",
+  );
+  const resumable_forms = new Set(
+    [...resumable_inputs].map((input) => input.form).filter(Boolean),
+  );
+  for (const form of resumable_forms) {
+    form.addEventListener("submit", (event) =>
+      submit_resumable_uploads(form, event),
+    );
+  }
+
   /** @type {NodeListOf<HTMLFormElement>} */
   const auto_submit_forms = document.querySelectorAll("form[data-auto-submit]");
   for (const form of auto_submit_forms) {
@@ -325,6 +343,175 @@ function sqlpage_form() {
   }
 }
 
+const UPLOAD_CHUNK_SIZE = 5 * 1024 * 1024;
+const UPLOAD_MAX_RETRIES = 5;
+
+/**
+ * Uploads the files of resumable file inputs before submitting the form.
+ * The files are replaced by hidden inputs containing their upload ids.
+ * @param {HTMLFormElement} form
+ * @param {SubmitEvent} event
+ */
+async function submit_resumable_uploads(form, event) {
+  if (form.dataset.uploading) return event.preventDefault();
+  /** @type {HTMLInputElement[]} */
+  const inputs = [
+    ...form.querySelectorAll("input[type=file][data-resumable]:enabled"),
+  ].filter((input) => input.files?.length);
+  if (!inputs.length) return;
+  event.preventDefault();
+  form.dataset.uploading = "true";
+  try {
+    for (const input of inputs) {
+      await upload_input_files(input);
+    }
+  } catch (err) {
+    console.error(err);
+    return;
+  } finally {
+    delete form.dataset.uploading;
+  }
+  form.requestSubmit(event.submitter);
+}
+
+/** @param {HTMLInputElement} input */
+async function upload_input_files(input) {
+  const progress = input.closest("label")?.querySelector(".progress");
+  const bar = progress?.querySelector(".progress-bar");
+  const files = [...(input.files ?? [])];
+  const total = files.reduce((sum, file) => sum + file.size, 0) || 1;
+  let done = 0;
+  const set_progress = (/** @type {number} */ uploaded) => {
+    const percent = Math.round((100 * (done + uploaded)) / total);
+    if (progress) progress.hidden = false;
+    bar?.setAttribute("aria-valuenow", String(percent));
+    if (bar instanceof HTMLElement) bar.style.width = `${percent}%`;
+  };
+  input.classList.remove("is-invalid");
+  input.setCustomValidity("");
+  const ids = [];
+  for (const file of files) {
+    try {
+      ids.push(
+        await resumable_upload(
+          input.dataset.resumable ?? "",
+          file,
+          input.name,
+          set_progress,
+        ),
+      );
+    } catch (err) {
+      input.classList.add("is-invalid");
+      const message =
+        input.dataset.uploadFailedMessage ?? "The upload of {file} failed.";
+      input.setCustomValidity(message.replace("{file}", file.name));
+      input.reportValidity();
+      throw err;
+    }
+    done += file.size;
+  }
+  for (const id of ids) {
+    const hidden = document.createElement("input");
+    hidden.type = "hidden";
+    hidden.name = input.name;
+    hidden.value = id;
+    input.after(hidden);
+  }
+  input.disabled = true;
+}
+
+/**
+ * Sends a file in chunks, using the tus protocol, and returns its upload id.
+ * Interrupted uploads of the same file resume where they stopped, even after a page reload.
+ * @param {string} endpoint
+ * @param {File} file
+ * @param {string} field
+ * @param {(uploaded: number) => void} on_progress
+ */
+async function resumable_upload(endpoint, file, field, on_progress) {
+  const fingerprint = `sqlpage-upload ${endpoint} ${field} ${file.name} ${file.size} ${file.lastModified}`;
+  let location = localStorage.getItem(fingerprint);
+  let offset = location ? await upload_offset(location) : null;
+  if (!location || offset === null) {
+    const metadata = Object.entries({
+      filename: file.name,
+      filetype: file.type,
+      field,
+    })
+      .filter(([, value]) => value)
+      .map(([key, value]) => `${key} ${base64_encode(value)}`)
+      .join(",");
+    const response = await tus_fetch(endpoint, {
+      method: "POST",
+      headers: {
+        "Upload-Length": String(file.size),
+        "Upload-Metadata": metadata,
+      },
+    });
+    location = new URL(
+      response.headers.get("Location") ?? "",
+      window.location.href,
+    ).href;
+    localStorage.setItem(fingerprint, location);
+    offset = 0;
+  }
+  let retries = 0;
+  do {
+    on_progress(offset);
+    try {
+      const response = await tus_fetch(location, {
+        method: "PATCH",
+        headers: {
+          "Upload-Offset": String(offset),
+          "Content-Type": "application/offset+octet-stream",
+        },
+        body: file.slice(offset, offset + UPLOAD_CHUNK_SIZE),
+      });
+      offset = Number(response.headers.get("Upload-Offset"));
+      retries = 0;
+    } catch (err) {
+      if (++retries > UPLOAD_MAX_RETRIES) throw err;
+      await new Promise((resolve) => setTimeout(resolve, 500 * 2 ** retries));
+      offset = await upload_offset(location);
+      if (offset === null) throw err;
+    }
+  } while (offset < file.size);
+  on_progress(file.size);
+  localStorage.removeItem(fingerprint);
+  return location.split("/").pop();
+}
+
+/** @param {string} location */
+async function upload_offset(location) {
+  try {
+    const response = await tus_fetch(location, { method: "HEAD" });
+    return Number(response.headers.get("Upload-Offset"));
+  } catch {
+    return null;
+  }
+}
+
+/**
+ * @param {string} url
+ * @param {RequestInit & {headers?: Record<string, string>}} init
+ */
+async function tus_fetch(url, init) {
+  const response = await fetch(url, {
+    ...init,
+    headers: { ...init.headers, "Tus-Resumable": "1.0.0" },
+  });
+  if (!response.ok) {
+    const message = await response.text();
+    throw new Error(`${init.method} ${url}: ${response.status} ${message}`);
+  }
+  return response;
+}
+
+/** @param {string} text */
+function base64_encode(text) {
+  return btoa(String.fromCharCode(...new TextEncoder().encode(text)));
+}
+
 function get_tabler_color(name) {
   return getComputedStyle(document.documentElement).getPropertyValue(
     `--tblr-${name}`,
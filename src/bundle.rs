@@ -0,0 +1,371 @@
+//! Deployment bundles: a web root and its configuration directory packaged in a single zip archive.
+//!
+//! `sqlpage bundle app.zip` creates the archive, and `sqlpage --bundle app.zip` serves it.
+//! With `--executable`, the archive is appended to a copy of the `sqlpage` executable,
+//! which then serves it when started without the `--bundle` argument.
+//!
+//! Files of the web root are stored under `web/`, and files of the configuration directory under `config/`.
+//! The server reads pages, static files and templates directly from the archive, which is kept in memory.
+//! The configuration directory is also extracted to a private temporary directory at startup,
+//! because `sqlpage.json`, migrations, `on_connect.sql` and translations are read from the disk.
+//! The directory is removed when the server stops.
+//!
+//! Bundles end with a zip comment that identifies them, so that the executable only has to
+//! read its last bytes at startup to know whether a bundle is appended to it.
+//!
+//! Hidden files, databases, editor backups and the HTTPS certificates cache are never bundled.
+
+use crate::app_config::AppConfig;
+use crate::file_watcher::is_ignored;
+use anyhow::Context;
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// File that identifies a zip archive as a `SQLPage` bundle
+pub const MANIFEST: &str = "sqlpage_bundle.json";
+/// Comment of the bundle archives
+const ARCHIVE_COMMENT: &str = "sqlpage bundle";
+/// Size of the end of central directory record of a zip archive, without its comment
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+pub const WEB_DIR: &str = "web/";
+pub const CONFIG_DIR: &str = "config/";
+
+pub struct Bundle {
+    path: PathBuf,
+    archive: ZipArchive<Cursor<Arc<[u8]>>>,
+}
+
+impl Bundle {
+    /// Opens the bundle at `path`.
+    /// The archive can be preceded by other data, like the `sqlpage` executable.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let archive = ZipArchive::new(&mut file).context("not a zip archive")?;
+        anyhow::ensure!(
+            archive.index_for_name(MANIFEST).is_some(),
+            "{MANIFEST} is missing: the archive was not created with sqlpage bundle"
+        );
+        let offset = archive.offset();
+        drop(archive);
+        file.seek(SeekFrom::Start(offset))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let archive = ZipArchive::new(Cursor::new(Arc::from(contents)))?;
+        Ok(Self {
+            path: path.to_owned(),
+            archive,
+        })
+    }
+
+    /// Opens the bundle at `path` when it is given,
+    /// or else the bundle appended to the current executable, if there is one.
+    pub fn find(path: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        if let Some(path) = path {
+            return Self::open(path)
+                .map(Some)
+                .with_context(|| format!("Unable to open the bundle {}", path.display()));
+        }
+        let executable = std::env::current_exe()?;
+        if !ends_with_bundle(&executable).unwrap_or(false) {
+            return Ok(None);
+        }
+        match Self::open(&executable) {
+            Ok(bundle) => {
+                log::info!("Serving the bundle embedded in {}", executable.display());
+                Ok(Some(bundle))
+            }
+            Err(e) => {
+                log::warn!(
+                    "Unable to open the bundle appended to {}: {e:#}",
+                    executable.display()
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads a file of the archive, like `web/index.sql`.
+    /// Returns an error of kind [`io::ErrorKind::NotFound`] when the file is not in the bundle.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        // Clones share the contents of the archive, and let concurrent requests read it
+        let mut archive = self.archive.clone();
+        let mut file = match archive.by_name(name) {
+            Ok(file) if file.is_file() => file,
+            Ok(_) | Err(ZipError::FileNotFound) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{name} is not in the bundle"),
+                ));
+            }
+            Err(e) => return Err(io::Error::other(e)),
+        };
+        let mut contents = Vec::with_capacity(usize::try_from(file.size()).unwrap_or_default());
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.archive.index_for_name(name).is_some()
+    }
+
+    /// Extracts the configuration directory to a new temporary directory.
+    pub fn extract_configuration(&self) -> anyhow::Result<ExtractedConfiguration> {
+        // The configuration can contain secrets, like database passwords.
+        // The directory is only accessible to the current user.
+        let temp_dir = tempfile::Builder::new()
+            .prefix("sqlpage-bundle-")
+            .tempdir()
+            .context("Unable to create a temporary directory for the configuration")?;
+        let dir = temp_dir.path();
+        let mut archive = self.archive.clone();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let Some(relative) = file
+                .enclosed_name()
+                .and_then(|name| Some(name.strip_prefix(CONFIG_DIR).ok()?.to_owned()))
+            else {
+                continue;
+            };
+            let target = dir.join(relative);
+            if file.is_dir() {
+                std::fs::create_dir_all(&target)?;
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&target)?)
+                .with_context(|| format!("Unable to extract {}", target.display()))?;
+        }
+        log::debug!(
+            "Extracted the configuration of the bundle to {}",
+            dir.display()
+        );
+        Ok(ExtractedConfiguration(Arc::new(temp_dir)))
+    }
+}
+
+/// The configuration directory of a bundle, extracted to a temporary directory
+/// that is removed when the last copy of the configuration is dropped.
+#[derive(Debug, Clone)]
+pub struct ExtractedConfiguration(Arc<tempfile::TempDir>);
+
+impl ExtractedConfiguration {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl PartialEq for ExtractedConfiguration {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Whether the file ends with the end of central directory record of a bundle,
+/// without reading the rest of it.
+fn ends_with_bundle(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut end = [0; END_OF_CENTRAL_DIRECTORY_SIZE + ARCHIVE_COMMENT.len()];
+    let Ok(offset) = i64::try_from(end.len()) else {
+        return Ok(false);
+    };
+    if file.seek(SeekFrom::End(-offset)).is_err() {
+        // The file is too small
+        return Ok(false);
+    }
+    file.read_exact(&mut end)?;
+    let comment_len = u16::try_from(ARCHIVE_COMMENT.len()).unwrap_or_default();
+    Ok(end.starts_with(b"PK\x05\x06")
+        && end[20..22] == comment_len.to_le_bytes()
+        && end.ends_with(ARCHIVE_COMMENT.as_bytes()))
+}
+
+/// Packages the web root and the configuration directory into a bundle at `output`.
+/// When `executable` is given, the bundle is appended to a copy of it.
+/// Returns the number of bundled files.
+pub fn create(
+    config: &AppConfig,
+    output: &Path,
+    executable: Option<&Path>,
+) -> anyhow::Result<usize> {
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let web_root = canonical(&config.web_root);
+    let config_dir = canonical(&config.configuration_directory);
+    // The output does not exist yet, so only its directory can be canonicalized
+    let output_dir = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => canonical(parent),
+        _ => canonical(Path::new(".")),
+    };
+    let excluded = [
+        output_dir.join(output.file_name().unwrap_or_default()),
+        canonical(&config.https_certificate_cache_dir),
+    ];
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.set_comment(ARCHIVE_COMMENT);
+    zip.start_file(MANIFEST, options)?;
+    let manifest = serde_json::json!({ "sqlpage_version": env!("CARGO_PKG_VERSION") });
+    zip.write_all(manifest.to_string().as_bytes())?;
+
+    let mut bundler = Bundler {
+        zip,
+        options,
+        count: 0,
+    };
+    // The configuration directory is usually inside the web root, but must not be served
+    let web_excluded = [excluded.as_slice(), std::slice::from_ref(&config_dir)].concat();
+    bundler.add_dir(&web_root, Path::new(""), WEB_DIR, &web_excluded)?;
+    bundler.add_dir(&config_dir, Path::new(""), CONFIG_DIR, &excluded)?;
+    let count = bundler.count;
+    let archive = bundler.zip.finish()?.into_inner();
+
+    if let Some(executable) = executable {
+        std::fs::copy(executable, output).with_context(|| {
+            format!(
+                "Unable to copy {} to {}",
+                executable.display(),
+                output.display()
+            )
+        })?;
+        let mut file = std::fs::OpenOptions::new().append(true).open(output)?;
+        file.write_all(&archive)?;
+    } else {
+        std::fs::write(output, &archive)?;
+    }
+    Ok(count)
+}
+
+struct Bundler {
+    zip: ZipWriter<Cursor<Vec<u8>>>,
+    options: SimpleFileOptions,
+    count: usize,
+}
+
+impl Bundler {
+    fn add_dir(
+        &mut self,
+        root: &Path,
+        relative: &Path,
+        prefix: &str,
+        excluded: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        let dir = root.join(relative);
+        let mut entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("Unable to read {}", dir.display()))?
+            .collect::<Result<Vec<_>, _>>()?;
+        // Sorted, so that bundling the same files always gives the same archive
+        entries.sort_by_key(std::fs::DirEntry::file_name);
+        for entry in entries {
+            let relative = relative.join(entry.file_name());
+            let path = entry.path();
+            if is_ignored(&relative) || excluded.contains(&path) {
+                log::debug!("Not bundling {}", path.display());
+                continue;
+            }
+            if path.is_dir() {
+                // A link to a parent directory would be followed forever
+                if entry.file_type()?.is_symlink() {
+                    log::warn!("Not bundling the linked directory {}", path.display());
+                    continue;
+                }
+                self.add_dir(root, &relative, prefix, excluded)?;
+            } else {
+                let name = archive_name(prefix, &relative);
+                log::debug!("Bundling {} as {name}", path.display());
+                self.zip.start_file(name, self.options)?;
+                io::copy(&mut File::open(&path)?, &mut self.zip)
+                    .with_context(|| format!("Unable to bundle {}", path.display()))?;
+                self.count += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of a file in the archive, with `/` separators on all platforms
+#[must_use]
+pub fn archive_name(prefix: &str, relative: &Path) -> String {
+    let mut name = prefix.to_owned();
+    for (i, component) in relative.components().enumerate() {
+        if i > 0 {
+            name.push('/');
+        }
+        name.push_str(&component.as_os_str().to_string_lossy());
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path();
+        let web_root = dir.join("site");
+        write(&web_root.join("index.sql"), "select 1;");
+        write(&web_root.join("blog/post.sql"), "select 2;");
+        write(&web_root.join(".env"), "SECRET=1");
+        write(&web_root.join("sqlpage/sqlpage.json"), "{}");
+        write(&web_root.join("sqlpage/sqlpage.db"), "data");
+        write(&web_root.join("sqlpage/templates/x.handlebars"), "x");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&web_root, web_root.join("blog/loop"))?;
+        let mut config = crate::app_config::tests::test_config();
+        config.web_root.clone_from(&web_root);
+        config.configuration_directory = web_root.join("sqlpage");
+
+        let output = dir.join("app.zip");
+        assert_eq!(create(&config, &output, None)?, 4);
+        let bundle = Bundle::open(&output)?;
+        assert_eq!(bundle.read("web/index.sql")?, b"select 1;");
+        assert_eq!(bundle.read("web/blog/post.sql")?, b"select 2;");
+        assert!(bundle.contains("config/templates/x.handlebars"));
+        for name in ["web/.env", "web/sqlpage/sqlpage.json", "config/sqlpage.db"] {
+            assert!(!bundle.contains(name), "{name} should not be bundled");
+        }
+        let err = bundle.read("web/missing.sql").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let extracted = bundle.extract_configuration()?;
+        let config_dir = extracted.path().to_owned();
+        assert!(config_dir.join("sqlpage.json").is_file());
+        assert!(config_dir.join("templates/x.handlebars").is_file());
+        drop(extracted);
+        assert!(!config_dir.exists(), "the configuration must be removed");
+
+        // A bundle appended to an executable
+        let executable = dir.join("sqlpage-executable");
+        std::fs::write(&executable, b"not really an executable")?;
+        let output = dir.join("app");
+        create(&config, &output, Some(&executable))?;
+        assert!(std::fs::read(&output)?.starts_with(b"not really an executable"));
+        let bundle = Bundle::open(&output)?;
+        assert_eq!(bundle.read("web/index.sql")?, b"select 1;");
+
+        assert!(ends_with_bundle(&output)?);
+        assert!(!ends_with_bundle(&executable)?);
+        assert!(Bundle::open(&executable).is_err());
+        Ok(())
+    }
+}
@@ -14,6 +14,9 @@ pub struct Cli {
     /// The path to the configuration file.
     #[clap(short = 'c', long)]
     pub config_file: Option<PathBuf>,
+    /// Serve the site from a bundle created with `sqlpage bundle`.
+    #[clap(long)]
+    pub bundle: Option<PathBuf>,
 
     /// Subcommands for additional functionality.
     #[clap(subcommand)]
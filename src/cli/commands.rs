@@ -1,8 +1,11 @@
 use chrono::Utc;
 use clap::Parser;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::app_config::AppConfig;
+use crate::bundle;
+use crate::releases;
+use crate::webserver::Database;
 
 /// Sub-commands for the sqlpage CLI.
 /// Each subcommand can be executed using the `sqlpage <subcommand name>` from the command line.
@@ -13,6 +16,34 @@ pub enum SubCommand {
         /// Name of the migration.
         migration_name: String,
     },
+    /// Package the site and its configuration into a single file, to deploy it with `sqlpage --bundle`.
+    Bundle {
+        /// Path of the bundle to create.
+        output: PathBuf,
+        /// Append the bundle to a copy of the sqlpage executable, which then serves it when started.
+        #[clap(long)]
+        executable: bool,
+    },
+    /// Publish the files of a directory to the database as a new release, and activate it.
+    Publish {
+        /// Directory to publish. Defaults to the web root.
+        directory: Option<PathBuf>,
+        /// Author of the release.
+        #[clap(long)]
+        author: Option<String>,
+    },
+    /// List the files that changed between two releases.
+    Diff {
+        /// Version of the old release.
+        from: i32,
+        /// Version of the new release. Defaults to the active release.
+        to: Option<i32>,
+    },
+    /// Activate a previous release.
+    Rollback {
+        /// Version of the release to activate. Defaults to the release before the active one.
+        version: Option<i32>,
+    },
 }
 
 impl SubCommand {
@@ -24,6 +55,36 @@ impl SubCommand {
                 create_migration_file(migration_name, &app_config.configuration_directory).await?;
                 Ok(())
             }
+            SubCommand::Bundle { output, executable } => {
+                let executable = if *executable {
+                    Some(std::env::current_exe()?)
+                } else {
+                    None
+                };
+                let count = bundle::create(&app_config, output, executable.as_deref())?;
+                println!("Bundled {count} files in {}", output.display());
+                Ok(())
+            }
+            SubCommand::Publish { directory, author } => {
+                let db = Database::init(&app_config).await?;
+                let directory = directory.as_deref().unwrap_or(&app_config.web_root);
+                let (version, count) = releases::publish(&db, directory, author.as_deref()).await?;
+                println!("Published release {version} with {count} files");
+                Ok(())
+            }
+            SubCommand::Diff { from, to } => {
+                let db = Database::init(&app_config).await?;
+                for (change, path) in releases::diff(&db, *from, *to).await? {
+                    println!("{change}\t{path}");
+                }
+                Ok(())
+            }
+            SubCommand::Rollback { version } => {
+                let db = Database::init(&app_config).await?;
+                let version = releases::rollback(&db, *version).await?;
+                println!("Release {version} is now active");
+                Ok(())
+            }
         }
     }
 }
@@ -0,0 +1,288 @@
+//! Custom handlebars helpers, written in [Rhai](https://rhai.rs) by the users of `SQLPage`.
+//!
+//! A helper named `money` is defined in `sqlpage/helpers/money.rhai`, in the configuration directory
+//! or in the `sqlpage_files` table. The script receives the positional parameters of the helper
+//! in the `params` array, and its named parameters in the `hash` map.
+//! The value of its last expression is the value of the helper.
+//! Built-in helpers cannot be replaced: their names are never looked up in the helpers directory.
+//!
+//! Scripts are sandboxed: they cannot access files, the network or the database,
+//! and their number of operations, call depth and data sizes are limited.
+//! Like templates, helpers are compiled once, cached in a [`crate::file_cache::FileCache`],
+//! and reloaded when their file changes.
+
+use crate::AppState;
+use crate::file_cache::AsyncFromStrWithState;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use handlebars::template::{Parameter, TemplateElement};
+use handlebars::{
+    Context, Handlebars, HelperDef, PathAndJson, RenderError, RenderErrorReason, ScopedJson,
+    Template,
+};
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+static ENGINE: LazyLock<Engine> = LazyLock::new(|| {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .on_print(|message| log::info!(target: "sqlpage::helpers", "{message}"))
+        .on_debug(|message, source, position| {
+            log::debug!(target: "sqlpage::helpers", "{} {position}: {message}", source.unwrap_or_default());
+        });
+    engine
+});
+
+/// A compiled helper script
+pub struct CustomHelper {
+    ast: AST,
+}
+
+#[async_trait(? Send)]
+impl AsyncFromStrWithState for CustomHelper {
+    async fn from_str_with_state(
+        _app_state: &AppState,
+        source: &str,
+        source_path: &Path,
+    ) -> anyhow::Result<Self> {
+        log::debug!("Compiling helper \"{}\"", source_path.display());
+        let mut ast = ENGINE
+            .compile(source)
+            .with_context(|| format!("Unable to compile the helper {}", source_path.display()))?;
+        ast.set_source(source_path.to_string_lossy().into_owned());
+        Ok(Self { ast })
+    }
+}
+
+impl CustomHelper {
+    /// Runs the script with the given positional and named parameters.
+    pub fn call(
+        &self,
+        params: &[JsonValue],
+        hash: &serde_json::Map<String, JsonValue>,
+    ) -> anyhow::Result<JsonValue> {
+        let mut scope = Scope::new();
+        scope.push_constant(
+            "params",
+            params.iter().map(json_to_dynamic).collect::<Array>(),
+        );
+        let hash: Map = hash
+            .iter()
+            .map(|(name, value)| (name.into(), json_to_dynamic(value)))
+            .collect();
+        scope.push_constant("hash", hash);
+        let result = ENGINE
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        Ok(dynamic_to_json(result))
+    }
+}
+
+/// A custom helper registered for the rendering of a component
+#[derive(Clone)]
+pub struct NamedCustomHelper(pub Arc<str>, pub Arc<CustomHelper>);
+
+impl HelperDef for NamedCustomHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        helper: &handlebars::Helper<'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _rc: &mut handlebars::RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let params: Vec<JsonValue> = helper.params().iter().map(|p| p.value().clone()).collect();
+        let hash = helper
+            .hash()
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), PathAndJson::value(value).clone()))
+            .collect();
+        let result = self.1.call(&params, &hash).map_err(|e| {
+            RenderErrorReason::Other(format!("Error in the helper {}: {e:#}", self.0))
+        })?;
+        Ok(ScopedJson::Derived(result))
+    }
+}
+
+/// The names of the helpers called in a template, built-in or custom,
+/// including in inline partials and in the names of dynamic partials.
+#[must_use]
+pub fn helper_names(template: &Template) -> Vec<String> {
+    let mut names = Vec::new();
+    collect_helper_names(template, &mut names);
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+fn collect_helper_names(template: &Template, names: &mut Vec<String>) {
+    for element in &template.elements {
+        collect_element_helpers(element, names);
+    }
+}
+
+fn collect_element_helpers(element: &TemplateElement, names: &mut Vec<String>) {
+    match element {
+        TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) => {
+            if let Parameter::Name(name) = &helper.name {
+                names.push(name.clone());
+            }
+            collect_parameter_helpers(&helper.name, names);
+            for param in helper.params.iter().chain(helper.hash.values()) {
+                collect_parameter_helpers(param, names);
+            }
+            for inner in helper.template.iter().chain(&helper.inverse) {
+                collect_helper_names(inner, names);
+            }
+        }
+        TemplateElement::DecoratorExpression(decorator)
+        | TemplateElement::DecoratorBlock(decorator)
+        | TemplateElement::PartialExpression(decorator)
+        | TemplateElement::PartialBlock(decorator) => {
+            collect_parameter_helpers(&decorator.name, names);
+            for param in decorator.params.iter().chain(decorator.hash.values()) {
+                collect_parameter_helpers(param, names);
+            }
+            if let Some(inner) = &decorator.template {
+                collect_helper_names(inner, names);
+            }
+        }
+        // Raw text and comments
+        _ => {}
+    }
+}
+
+fn collect_parameter_helpers(param: &Parameter, names: &mut Vec<String>) {
+    if let Parameter::Subexpression(subexpression) = param {
+        collect_element_helpers(&subexpression.element, names);
+    }
+}
+
+fn json_to_dynamic(value: &JsonValue) -> Dynamic {
+    match value {
+        JsonValue::Null => Dynamic::UNIT,
+        JsonValue::Bool(b) => Dynamic::from_bool(*b),
+        JsonValue::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Dynamic::from_int(i),
+            (None, Some(f)) => Dynamic::from_float(f),
+            (None, None) => Dynamic::from(n.to_string()),
+        },
+        JsonValue::String(s) => Dynamic::from(s.clone()),
+        JsonValue::Array(values) => {
+            Dynamic::from_array(values.iter().map(json_to_dynamic).collect())
+        }
+        JsonValue::Object(object) => Dynamic::from_map(
+            object
+                .iter()
+                .map(|(name, value)| (name.into(), json_to_dynamic(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn dynamic_to_json(value: Dynamic) -> JsonValue {
+    if value.is_unit() {
+        JsonValue::Null
+    } else if let Ok(b) = value.as_bool() {
+        JsonValue::Bool(b)
+    } else if let Ok(i) = value.as_int() {
+        JsonValue::from(i)
+    } else if let Ok(f) = value.as_float() {
+        serde_json::Number::from_f64(f).map_or(JsonValue::Null, JsonValue::Number)
+    } else if value.is_array() {
+        JsonValue::Array(
+            value
+                .cast::<Array>()
+                .into_iter()
+                .map(dynamic_to_json)
+                .collect(),
+        )
+    } else if value.is_map() {
+        JsonValue::Object(
+            value
+                .cast::<Map>()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), dynamic_to_json(value)))
+                .collect(),
+        )
+    } else {
+        JsonValue::String(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn compile(source: &str) -> CustomHelper {
+        CustomHelper {
+            ast: ENGINE.compile(source).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_custom_helper() {
+        let helper = compile(
+            r#"let amount = params[0];
+            let currency = hash.currency ?? "€";
+            `${amount / 100}.${amount % 100} ${currency}`"#,
+        );
+        let hash = serde_json::Map::from_iter([("currency".to_string(), json!("$"))]);
+        assert_eq!(
+            helper.call(&[json!(1234)], &hash).unwrap(),
+            json!("12.34 $")
+        );
+
+        let helper = compile("#{ name: params[0], tags: [1, 2.5, true, ()] }");
+        assert_eq!(
+            helper.call(&[json!("x")], &serde_json::Map::new()).unwrap(),
+            json!({"name": "x", "tags": [1, 2.5, true, null]})
+        );
+    }
+
+    #[test]
+    fn test_infinite_loop_is_interrupted() {
+        let helper = compile("loop {}");
+        assert!(helper.call(&[], &serde_json::Map::new()).is_err());
+    }
+
+    #[test]
+    fn test_helper_names() {
+        let template = Template::compile(
+            "{{money price}} {{#if (status_color x)}}{{lookup (initials name) 0}}{{/if}} {{title}} {{{money x}}}",
+        )
+        .unwrap();
+        assert_eq!(
+            helper_names(&template),
+            ["if", "initials", "lookup", "money", "status_color"]
+        );
+    }
+
+    #[test]
+    fn test_helper_names_in_partials() {
+        let template = Template::compile(
+            r#"{{#*inline "cell"}}{{money x}}{{/inline}}{{#> card}}{{initials y}}{{/card}}{{> (cell_partial z)}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            helper_names(&template),
+            ["cell_partial", "initials", "money"]
+        );
+    }
+}
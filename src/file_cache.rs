@@ -1,4 +1,6 @@
 use crate::AppState;
+use crate::app_config::AppConfig;
+use crate::file_watcher::FileWatcher;
 use crate::filesystem::FileAccess;
 use crate::webserver::ErrorWithStatus;
 use crate::webserver::routing::FileStore;
@@ -6,6 +8,8 @@ use actix_web::http::StatusCode;
 use anyhow::Context;
 use async_trait::async_trait;
 use chrono::{DateTime, TimeZone, Utc};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -16,20 +20,42 @@ use std::sync::atomic::{
 use std::time::SystemTime;
 use tokio::sync::RwLock;
 
+/// Maximal number of files that each cache remembers not to exist
+const MAX_MISSING_FILES: usize = 1024;
+
 #[derive(Default)]
 struct Cached<T> {
     last_checked_at: AtomicU64,
-    content: Arc<T>,
+    last_used_at: AtomicU64,
+    /// Approximate memory used by the entry, in bytes
+    size: usize,
+    /// `None` when the file does not exist
+    content: Option<Arc<T>>,
+    /// The active release of the database filesystem when the file was loaded
+    release: Option<i32>,
 }
 
 impl<T> Cached<T> {
-    fn new(content: T) -> Self {
-        let s = Self {
-            last_checked_at: AtomicU64::new(0),
-            content: Arc::new(content),
-        };
-        s.update_check_time();
-        s
+    fn new(content: T, size: usize) -> Self {
+        Self::with_content(Some(Arc::new(content)), size)
+    }
+    /// An entry that remembers that a file does not exist
+    fn missing(path: &Path) -> Self {
+        Self::with_content(None, path.as_os_str().len())
+    }
+    fn with_content(content: Option<Arc<T>>, size: usize) -> Self {
+        let now = Self::now_millis();
+        Self {
+            last_checked_at: AtomicU64::new(now),
+            last_used_at: AtomicU64::new(now),
+            size,
+            content,
+            release: None,
+        }
+    }
+    fn in_release(mut self, release: Option<i32>) -> Self {
+        self.release = release;
+        self
     }
     fn last_check_time(&self) -> DateTime<Utc> {
         let millis = self.last_checked_at.load(Acquire);
@@ -41,6 +67,9 @@ impl<T> Cached<T> {
     fn update_check_time(&self) {
         self.last_checked_at.store(Self::now_millis(), Release);
     }
+    fn mark_used(&self) {
+        self.last_used_at.store(Self::now_millis(), Release);
+    }
     fn now_millis() -> u64 {
         SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -55,54 +84,134 @@ impl<T> Cached<T> {
             .saturating_add(stale_cache_duration_ms)
             < Self::now_millis()
     }
-    /// Creates a new cached entry with the same content but a new check time set to now
+    /// Creates a new cached entry with the same content but a new check time set to now.
+    /// The content is shared with the original entry, so it does not count in the cache size.
     fn make_fresh(&self) -> Self {
+        Self::with_content(self.content.clone(), 0)
+    }
+    /// Whether the file watcher saw the file change since it was last checked,
+    /// or another release was activated since it was loaded.
+    /// All the files of a release are reloaded together, so that pages never mix files from two releases.
+    fn changed_since_check(&self, app_state: &AppState, path: &Path) -> bool {
+        self.release != app_state.file_system.active_release()
+            || app_state
+                .file_watcher
+                .as_ref()
+                .is_some_and(|watcher| watcher.changed_since(path, self.last_check_time()))
+    }
+    fn content(&self, path: &Path) -> anyhow::Result<Arc<T>> {
+        self.content.clone().ok_or_else(|| not_found(path))
+    }
+}
+
+fn not_found(path: &Path) -> anyhow::Error {
+    anyhow::Error::new(ErrorWithStatus {
+        status: StatusCode::NOT_FOUND,
+    })
+    .context(format!("\"{}\" does not exist", path.display()))
+}
+
+/// The files of a cache, and their total size
+struct Entries<T> {
+    files: HashMap<PathBuf, Cached<T>>,
+    size: usize,
+}
+
+impl<T> Default for Entries<T> {
+    fn default() -> Self {
         Self {
-            last_checked_at: AtomicU64::from(Self::now_millis()),
-            content: Arc::clone(&self.content),
+            files: HashMap::new(),
+            size: 0,
         }
     }
 }
 
+impl<T> Entries<T> {
+    fn insert(&mut self, path: PathBuf, cached: Cached<T>) {
+        self.size += cached.size;
+        if let Some(previous) = self.files.insert(path, cached) {
+            self.size -= previous.size;
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(previous) = self.files.remove(path) {
+            self.size -= previous.size;
+        }
+    }
+
+    /// Evicts the least recently used files when the cache is over its capacity,
+    /// and returns how many were evicted.
+    fn evict(&mut self, max_files: usize, max_size: usize) -> usize {
+        if self.files.len() <= max_files && self.size <= max_size {
+            return 0;
+        }
+        // Make room for the next files too, so that each insertion does not have to evict again
+        let target_files = max_files - max_files / 10;
+        let target_size = max_size - max_size / 10;
+        let mut by_last_use: Vec<(u64, PathBuf)> = self
+            .files
+            .iter()
+            .map(|(path, cached)| (cached.last_used_at.load(Acquire), path.clone()))
+            .collect();
+        by_last_use.sort_unstable_by_key(|(last_used_at, _)| *last_used_at);
+        let mut evicted = 0;
+        for (_, path) in by_last_use {
+            if self.files.len() <= target_files && self.size <= target_size {
+                break;
+            }
+            log::trace!("Evicting {} from the cache", path.display());
+            self.remove(&path);
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
 pub struct FileCache<T: AsyncFromStrWithState> {
-    cache: Arc<RwLock<HashMap<PathBuf, Cached<T>>>>,
+    /// Identifies the cache in logs and metrics
+    name: &'static str,
+    cache: Arc<RwLock<Entries<T>>>,
+    /// Files recently found not to exist, kept apart so that they never evict parsed files
+    missing: RwLock<Entries<()>>,
     /// Files that are loaded at the beginning of the program,
     /// and used as fallback when there is no match for the request in the file system
     static_files: HashMap<PathBuf, Cached<T>>,
+    max_files: usize,
+    max_size: usize,
 }
 
 impl<T: AsyncFromStrWithState> FileStore for FileCache<T> {
     async fn contains(&self, access: FileAccess<'_>) -> anyhow::Result<bool> {
         let path = access.path();
-        Ok(self.cache.read().await.contains_key(path) || self.static_files.contains_key(path))
-    }
-}
-
-impl<T: AsyncFromStrWithState> Default for FileCache<T> {
-    fn default() -> Self {
-        Self::new()
+        let cached = self.cache.read().await.files.contains_key(path);
+        Ok(cached || self.static_files.contains_key(path))
     }
 }
 
 impl<T: AsyncFromStrWithState> FileCache<T> {
     #[must_use]
-    pub fn new() -> Self {
+    pub fn new(name: &'static str, config: &AppConfig) -> Self {
         Self {
+            name,
             cache: Arc::default(),
+            missing: RwLock::default(),
             static_files: HashMap::new(),
+            max_files: config.max_cached_files,
+            max_size: config.max_cached_files_size,
         }
     }
 
     /// Adds a static file to the cache so that it will never be looked up from the disk
     pub fn add_static(&mut self, path: PathBuf, contents: T) {
         log::trace!("Adding static file {} to the cache.", path.display());
-        self.static_files.insert(path, Cached::new(contents));
+        self.static_files.insert(path, Cached::new(contents, 0));
     }
 
     pub fn get_static(&self, path: &Path) -> anyhow::Result<Arc<T>> {
         self.static_files
             .get(path)
-            .map(|cached| Arc::clone(&cached.content))
+            .and_then(|cached| cached.content.clone())
             .ok_or_else(|| anyhow::anyhow!("File {} not found in static files", path.display()))
     }
 
@@ -113,39 +222,51 @@ impl<T: AsyncFromStrWithState> FileCache<T> {
         access: FileAccess<'_>,
     ) -> anyhow::Result<Arc<T>> {
         let path = access.path();
+        FileWatcher::record_dependency(path);
 
         log::trace!("Attempting to get from cache {}", path.display());
-        if let Some(cached) = self.cache.read().await.get(path) {
-            if !cached.needs_check(app_state.config.cache_stale_duration_ms()) {
+        if self.is_known_missing(app_state, path).await {
+            return Err(not_found(path));
+        }
+        if let Some(cached) = self.cache.read().await.files.get(path) {
+            cached.mark_used();
+            if cached.changed_since_check(app_state, path) {
+                log::trace!("{} was changed, updating cache...", path.display());
+            } else if !cached.needs_check(app_state.config.cache_stale_duration_ms()) {
                 log::trace!(
                     "Cache answer without filesystem lookup for {}",
                     path.display()
                 );
-                return Ok(Arc::clone(&cached.content));
-            }
-            match app_state
-                .file_system
-                .modified_since(app_state, access, cached.last_check_time())
-                .await
-            {
-                Ok(false) => {
-                    log::trace!(
-                        "Cache answer with filesystem metadata read for {}",
-                        path.display()
-                    );
-                    cached.update_check_time();
-                    return Ok(Arc::clone(&cached.content));
+                self.count(&app_state.telemetry_metrics.file_cache.hits, 1);
+                return cached.content(path);
+            } else {
+                match app_state
+                    .file_system
+                    .modified_since(app_state, access, cached.last_check_time())
+                    .await
+                {
+                    Ok(false) => {
+                        log::trace!(
+                            "Cache answer with filesystem metadata read for {}",
+                            path.display()
+                        );
+                        cached.update_check_time();
+                        self.count(&app_state.telemetry_metrics.file_cache.hits, 1);
+                        return cached.content(path);
+                    }
+                    Ok(true) => log::trace!("{} was changed, updating cache...", path.display()),
+                    Err(e) => log::trace!(
+                        "Cannot read metadata of {}, re-loading it: {:#}",
+                        path.display(),
+                        e
+                    ),
                 }
-                Ok(true) => log::trace!("{} was changed, updating cache...", path.display()),
-                Err(e) => log::trace!(
-                    "Cannot read metadata of {}, re-loading it: {:#}",
-                    path.display(),
-                    e
-                ),
             }
         }
         // Read lock is released
+        self.count(&app_state.telemetry_metrics.file_cache.misses, 1);
         log::trace!("Loading and parsing {}", path.display());
+        let release = app_state.file_system.active_release();
         let file_contents = app_state
             .file_system
             .read_to_string(app_state, access)
@@ -154,7 +275,7 @@ impl<T: AsyncFromStrWithState> FileCache<T> {
         let parsed = match file_contents {
             Ok(contents) => {
                 let value = T::from_str_with_state(app_state, &contents, path).await?;
-                Ok(Cached::new(value))
+                Ok(Cached::new(value, contents.len() + path.as_os_str().len()).in_release(release))
             }
             // If a file is not found, we try to load it from the static files
             Err(e)
@@ -168,11 +289,14 @@ impl<T: AsyncFromStrWithState> FileCache<T> {
                         "File {} not found, loading it from static files instead.",
                         path.display()
                     );
-                    let cached: Cached<T> = static_file.make_fresh();
+                    let cached: Cached<T> = static_file.make_fresh().in_release(release);
                     Ok(cached)
                 } else {
-                    Err(e)
-                        .with_context(|| format!("Couldn't load \"{}\" into cache", path.display()))
+                    log::trace!("Remembering that {} does not exist", path.display());
+                    self.record_missing(app_state, path).await;
+                    return Err(e).with_context(|| {
+                        format!("Couldn't load \"{}\" into cache", path.display())
+                    });
                 }
             }
             Err(e) => {
@@ -182,10 +306,9 @@ impl<T: AsyncFromStrWithState> FileCache<T> {
 
         match parsed {
             Ok(value) => {
-                let new_val = Arc::clone(&value.content);
+                let new_val = value.content(path)?;
                 log::trace!("Writing to cache {}", path.display());
-                self.cache.write().await.insert(PathBuf::from(path), value);
-                log::trace!("Done writing to cache {}", path.display());
+                self.insert(app_state, path, value).await;
                 log::trace!("{} loaded in cache", path.display());
                 Ok(new_val)
             }
@@ -202,6 +325,64 @@ impl<T: AsyncFromStrWithState> FileCache<T> {
             }
         }
     }
+
+    /// Whether the file was recently found not to exist, so that it does not have to be looked up again.
+    pub(crate) async fn is_known_missing(&self, app_state: &AppState, path: &Path) -> bool {
+        let known_missing = self
+            .missing
+            .read()
+            .await
+            .files
+            .get(path)
+            .is_some_and(|cached| {
+                cached.mark_used();
+                !cached.needs_check(app_state.config.cache_stale_duration_ms())
+                    && !cached.changed_since_check(app_state, path)
+            });
+        if known_missing {
+            log::trace!("{} is known not to exist", path.display());
+            self.count(&app_state.telemetry_metrics.file_cache.hits, 1);
+        }
+        known_missing
+    }
+
+    /// Remembers that a file does not exist, for `cache_stale_duration_ms`.
+    pub(crate) async fn record_missing(&self, app_state: &AppState, path: &Path) {
+        let missing = Cached::missing(path).in_release(app_state.file_system.active_release());
+        let evicted = {
+            let mut entries = self.missing.write().await;
+            entries.insert(PathBuf::from(path), missing);
+            entries.evict(MAX_MISSING_FILES, usize::MAX)
+        };
+        self.count_evictions(app_state, evicted);
+    }
+
+    async fn insert(&self, app_state: &AppState, path: &Path, cached: Cached<T>) {
+        self.missing.write().await.remove(path);
+        let evicted = {
+            let mut entries = self.cache.write().await;
+            entries.insert(PathBuf::from(path), cached);
+            entries.evict(self.max_files, self.max_size)
+        };
+        self.count_evictions(app_state, evicted);
+    }
+
+    fn count_evictions(&self, app_state: &AppState, evicted: usize) {
+        if evicted > 0 {
+            log::debug!(
+                "Evicted {evicted} files from the {} cache to respect its capacity",
+                self.name
+            );
+            self.count(
+                &app_state.telemetry_metrics.file_cache.evictions,
+                u64::try_from(evicted).unwrap_or(u64::MAX),
+            );
+        }
+    }
+
+    fn count(&self, counter: &Counter<u64>, n: u64) {
+        counter.add(n, &[KeyValue::new("sqlpage.file_cache.name", self.name)]);
+    }
 }
 
 #[async_trait(? Send)]
@@ -220,7 +401,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_cache_duration() {
-        let cached = Cached::new(());
+        let cached = Cached::new((), 0);
         assert!(
             !cached.needs_check(1000),
             "Should not need check immediately after creation"
@@ -235,4 +416,81 @@ mod tests {
             "Should need check after duration expires"
         );
     }
+
+    fn entries_used_at(files: &[(&str, u64, usize)]) -> Entries<()> {
+        let mut entries = Entries::default();
+        for &(path, last_used_at, size) in files {
+            let cached = Cached::new((), size);
+            cached.last_used_at.store(last_used_at, Release);
+            entries.insert(PathBuf::from(path), cached);
+        }
+        entries
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_files() {
+        let mut entries = entries_used_at(&[("a", 3, 1), ("b", 1, 1), ("c", 2, 1)]);
+        assert_eq!(entries.evict(3, 100), 0);
+        assert_eq!(entries.evict(2, 100), 1);
+        assert!(!entries.files.contains_key(Path::new("b")));
+        assert_eq!(entries.size, 2);
+    }
+
+    #[test]
+    fn test_evicts_files_over_size() {
+        let mut entries = entries_used_at(&[("a", 1, 60), ("b", 2, 30), ("c", 3, 30)]);
+        assert_eq!(entries.evict(100, 100), 1);
+        assert!(!entries.files.contains_key(Path::new("a")));
+        assert_eq!(entries.size, 60);
+        entries.remove(Path::new("b"));
+        assert_eq!(entries.size, 30);
+    }
+
+    #[actix_web::test]
+    async fn test_missing_files_are_remembered() -> anyhow::Result<()> {
+        let mut config = crate::app_config::tests::test_config();
+        config.cache_stale_duration_ms = Some(60_000);
+        let state = AppState::init(&config).await?;
+        let path = Path::new("tests/file_cache_does_not_exist.sql");
+        let cache = &state.sql_file_cache;
+        assert!(!cache.is_known_missing(&state, path).await);
+        let err = cache
+            .get(&state, FileAccess::unprivileged(path)?)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ErrorWithStatus {
+                status: StatusCode::NOT_FOUND
+            })
+        );
+        assert!(cache.is_known_missing(&state, path).await);
+        assert!(!cache.contains(FileAccess::unprivileged(path)?).await?);
+        let err = cache
+            .get(&state, FileAccess::unprivileged(path)?)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ErrorWithStatus {
+                status: StatusCode::NOT_FOUND
+            })
+        );
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_missing_files_are_bounded_and_kept_apart() -> anyhow::Result<()> {
+        let state = AppState::init(&crate::app_config::tests::test_config()).await?;
+        let cache = &state.sql_file_cache;
+        for i in 0..=MAX_MISSING_FILES {
+            let path = PathBuf::from(format!("missing_{i}.sql"));
+            cache.record_missing(&state, &path).await;
+        }
+        assert!(cache.missing.read().await.files.len() <= MAX_MISSING_FILES);
+        assert!(cache.cache.read().await.files.is_empty());
+        Ok(())
+    }
 }
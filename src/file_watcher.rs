@@ -0,0 +1,375 @@
+//! Watches the files of the site in development, to reload them as soon as they change.
+//!
+//! Local files are watched with the notification system of the operating system
+//! (inotify, `FSEvents`, `ReadDirectoryChangesW`). Files of the `sqlpage_files` table
+//! are polled every second. Each change is recorded, so that [`crate::file_cache::FileCache`]
+//! reloads the file on its next use, and broadcast to the browsers that display
+//! a page of the site. The files that each page loads from the file cache are remembered,
+//! so that a browser only refreshes its page when one of them changes.
+
+use crate::app_config::AppConfig;
+use crate::filesystem::{DbFileChanges, FileSystem};
+use crate::webserver::Database;
+use crate::{HELPERS_DIR, TEMPLATES_DIR};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const DB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximal number of files whose changes and dependencies are remembered.
+/// Above it, they are forgotten, and every cached file is reloaded once.
+const MAX_TRACKED_FILES: usize = 10_000;
+
+/// Extensions of the files that pages load from the file cache.
+/// Other files, like stylesheets and images, can be used by any page.
+const PAGE_DEPENDENCY_EXTENSIONS: &[&str] = &["sql", "handlebars", "rhai"];
+
+/// Files that are not part of the site, like databases and editor backups
+const IGNORED_EXTENSIONS: &[&str] = &["db", "sqlite", "sqlite3", "swp", "tmp"];
+const IGNORED_SUFFIXES: &[&str] = &["~", "-journal", "-wal", "-shm"];
+
+pub struct FileWatcher {
+    changes: Arc<Changes>,
+    _watcher: RecommendedWatcher,
+}
+
+tokio::task_local! {
+    /// The files loaded from the file cache by the page that runs in the current task
+    static LOADED_FILES: RefCell<HashSet<PathBuf>>;
+}
+
+struct Changes {
+    changed_at: Mutex<ChangeTimes>,
+    /// The files loaded by each page, indexed by the path of its SQL file
+    dependencies: Mutex<HashMap<PathBuf, HashSet<PathBuf>>>,
+    sender: broadcast::Sender<Arc<Path>>,
+}
+
+struct ChangeTimes {
+    /// When each file last changed, indexed by its path in the file cache
+    files: HashMap<PathBuf, DateTime<Utc>>,
+    /// When the changes were last forgotten. Any file may have changed before.
+    forgotten_at: DateTime<Utc>,
+}
+
+impl Changes {
+    fn new(sender: broadcast::Sender<Arc<Path>>) -> Self {
+        Self {
+            changed_at: Mutex::new(ChangeTimes {
+                files: HashMap::new(),
+                forgotten_at: DateTime::<Utc>::MIN_UTC,
+            }),
+            dependencies: Mutex::default(),
+            sender,
+        }
+    }
+
+    fn record(&self, path: PathBuf) {
+        log::debug!("{} changed", path.display());
+        let now = Utc::now();
+        let mut changed_at = self.changed_at.lock().expect("file changes lock poisoned");
+        if changed_at.files.len() >= MAX_TRACKED_FILES && !changed_at.files.contains_key(&path) {
+            changed_at.files.clear();
+            changed_at.forgotten_at = now;
+        }
+        changed_at.files.insert(path.clone(), now);
+        drop(changed_at);
+        // There is no receiver when no browser is open
+        let _ = self.sender.send(path.into());
+    }
+
+    fn changed_since(&self, path: &Path, since: DateTime<Utc>) -> bool {
+        let changed_at = self.changed_at.lock().expect("file changes lock poisoned");
+        changed_at
+            .files
+            .get(path)
+            .copied()
+            .unwrap_or(changed_at.forgotten_at)
+            >= since
+    }
+
+    fn add_dependencies(&self, page: &Path, files: HashSet<PathBuf>) {
+        let mut dependencies = self
+            .dependencies
+            .lock()
+            .expect("page dependencies lock poisoned");
+        if dependencies.len() >= MAX_TRACKED_FILES && !dependencies.contains_key(page) {
+            dependencies.clear();
+        }
+        dependencies
+            .entry(page.to_owned())
+            .or_default()
+            .extend(files);
+    }
+
+    fn affects(&self, page: &Path, path: &Path) -> bool {
+        let loaded_by_pages = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| PAGE_DEPENDENCY_EXTENSIONS.contains(&ext));
+        !loaded_by_pages
+            || page == path
+            || self
+                .dependencies
+                .lock()
+                .expect("page dependencies lock poisoned")
+                .get(page)
+                .is_some_and(|files| files.contains(path))
+    }
+}
+
+impl FileWatcher {
+    /// Starts watching the web root and the templates and helpers directories,
+    /// and the `sqlpage_files` table when it is used.
+    pub(crate) async fn start(
+        config: &AppConfig,
+        file_system: &FileSystem,
+        db: &Database,
+    ) -> anyhow::Result<Self> {
+        let (sender, _) = broadcast::channel(64);
+        let changes = Arc::new(Changes::new(sender));
+        let roots = watched_roots(config);
+        let mut watcher = notify::recommended_watcher({
+            let changes = Arc::clone(&changes);
+            let roots = roots.clone();
+            move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if is_modification(event.kind) => {
+                    for path in event.paths {
+                        if let Some(key) = cache_key(&roots, &path) {
+                            changes.record(key);
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Error while watching the site files: {e}"),
+            }
+        })?;
+        for (dir, _) in &roots {
+            let in_other_root = roots
+                .iter()
+                .any(|(root, _)| root != dir && dir.starts_with(root));
+            if in_other_root || !dir.is_dir() {
+                continue;
+            }
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .with_context(|| format!("Unable to watch {} for changes", dir.display()))?;
+            log::info!("Watching {} for changes", dir.display());
+        }
+        if file_system.uses_database() {
+            let db_changes = DbFileChanges::init(db).await?;
+            tokio::spawn(poll_database(db_changes, Arc::downgrade(&changes)));
+        }
+        Ok(Self {
+            changes,
+            _watcher: watcher,
+        })
+    }
+
+    /// Whether the file at `path` in the file cache changed after `since`.
+    #[must_use]
+    pub fn changed_since(&self, path: &Path, since: DateTime<Utc>) -> bool {
+        self.changes.changed_since(path, since)
+    }
+
+    /// Runs the page of the SQL file at `page`, and remembers the files it loads.
+    pub(crate) async fn track_dependencies<F: Future>(&self, page: &Path, future: F) -> F::Output {
+        let (output, files) = LOADED_FILES
+            .scope(RefCell::default(), async {
+                let output = future.await;
+                (output, LOADED_FILES.with(RefCell::take))
+            })
+            .await;
+        self.changes.add_dependencies(page, files);
+        output
+    }
+
+    /// Records that the page running in the current task loaded the file at `path`.
+    pub(crate) fn record_dependency(path: &Path) {
+        let _ = LOADED_FILES.try_with(|files| {
+            let mut files = files.borrow_mut();
+            if !files.contains(path) {
+                files.insert(path.to_owned());
+            }
+        });
+    }
+
+    /// Whether a change of the file at `path` can affect the page of the SQL file at `page`.
+    #[must_use]
+    pub fn affects(&self, page: &Path, path: &Path) -> bool {
+        self.changes.affects(page, path)
+    }
+
+    /// Receives the paths of the files that change from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Path>> {
+        self.changes.sender.subscribe()
+    }
+}
+
+/// The watched directories, and the prefix of their files in the file cache.
+/// Templates and helpers come first, because their directories can be inside the web root.
+fn watched_roots(config: &AppConfig) -> Vec<(PathBuf, PathBuf)> {
+    let templates = config.configuration_directory.join("templates");
+    let helpers = config.configuration_directory.join("helpers");
+    [
+        (templates, PathBuf::from(TEMPLATES_DIR)),
+        (helpers, PathBuf::from(HELPERS_DIR)),
+        (config.web_root.clone(), PathBuf::new()),
+    ]
+    .into_iter()
+    // Events are reported with absolute paths
+    .map(|(dir, prefix)| (std::fs::canonicalize(&dir).unwrap_or(dir), prefix))
+    .collect()
+}
+
+fn is_modification(kind: EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+fn cache_key(roots: &[(PathBuf, PathBuf)], path: &Path) -> Option<PathBuf> {
+    let (relative, prefix) = roots
+        .iter()
+        .find_map(|(root, prefix)| Some((path.strip_prefix(root).ok()?, prefix)))?;
+    if is_ignored(relative) {
+        return None;
+    }
+    Some(prefix.join(relative))
+}
+
+/// Whether a file, given by its path relative to the web root or the configuration directory,
+/// is not part of the site: hidden files, databases and editor backups.
+pub(crate) fn is_ignored(path: &Path) -> bool {
+    let hidden = path.components().any(|c| match c {
+        Component::Normal(name) => name.as_encoded_bytes().starts_with(b"."),
+        _ => false,
+    });
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return true;
+    };
+    let ignored_extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IGNORED_EXTENSIONS.contains(&ext));
+    hidden || ignored_extension || IGNORED_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+async fn poll_database(db_changes: DbFileChanges, changes: Weak<Changes>) {
+    let mut interval = tokio::time::interval(DB_POLL_INTERVAL);
+    let mut since = Utc::now();
+    loop {
+        interval.tick().await;
+        let Some(changes) = changes.upgrade() else {
+            // The watcher was dropped
+            return;
+        };
+        let now = Utc::now();
+        match db_changes.modified_since(since).await {
+            Ok(paths) => {
+                for path in paths {
+                    changes.record(path);
+                }
+                since = now;
+            }
+            Err(e) => log::warn!("{e:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let roots = [
+            (
+                PathBuf::from("/site/sqlpage/templates"),
+                PathBuf::from(TEMPLATES_DIR),
+            ),
+            (PathBuf::from("/site"), PathBuf::new()),
+        ];
+        let key = |path: &str| cache_key(&roots, Path::new(path));
+        assert_eq!(key("/site/index.sql"), Some(PathBuf::from("index.sql")));
+        assert_eq!(key("/site/a/b.sql"), Some(PathBuf::from("a/b.sql")));
+        assert_eq!(
+            key("/site/sqlpage/templates/card.handlebars"),
+            Some(PathBuf::from("sqlpage/templates/card.handlebars"))
+        );
+        assert_eq!(key("/elsewhere/index.sql"), None);
+    }
+
+    #[test]
+    fn test_ignored_files() {
+        for path in [
+            ".git/index",
+            "sqlpage/sqlpage.db",
+            "sqlpage/sqlpage.db-journal",
+            "data.sqlite-wal",
+            ".index.sql.swp",
+            "index.sql~",
+        ] {
+            assert!(is_ignored(Path::new(path)), "{path} should be ignored");
+        }
+        for path in [
+            "index.sql",
+            "sqlpage/templates/card.handlebars",
+            "style.css",
+        ] {
+            assert!(!is_ignored(Path::new(path)), "{path} should not be ignored");
+        }
+    }
+
+    #[test]
+    fn test_changed_since() {
+        let changes = Changes::new(broadcast::channel(1).0);
+        let before = Utc::now();
+        changes.record(PathBuf::from("a.sql"));
+        assert!(changes.changed_since(Path::new("a.sql"), before));
+        assert!(!changes.changed_since(Path::new("b.sql"), before));
+        assert!(!changes.changed_since(Path::new("a.sql"), Utc::now()));
+        for i in 0..MAX_TRACKED_FILES {
+            changes.record(PathBuf::from(format!("{i}.sql")));
+        }
+        let forgotten = changes.changed_at.lock().unwrap();
+        assert!(forgotten.files.len() <= MAX_TRACKED_FILES);
+        drop(forgotten);
+        // A file whose change was forgotten may have changed
+        assert!(changes.changed_since(Path::new("a.sql"), before));
+    }
+
+    #[tokio::test]
+    async fn test_page_dependencies() {
+        let changes = Changes::new(broadcast::channel(1).0);
+        let files = LOADED_FILES
+            .scope(RefCell::default(), async {
+                FileWatcher::record_dependency(Path::new("included.sql"));
+                FileWatcher::record_dependency(Path::new("sqlpage/templates/card.handlebars"));
+                LOADED_FILES.with(RefCell::take)
+            })
+            .await;
+        changes.add_dependencies(Path::new("page.sql"), files);
+        let page = Path::new("page.sql");
+        for path in [
+            "page.sql",
+            "included.sql",
+            "sqlpage/templates/card.handlebars",
+            "style.css",
+        ] {
+            assert!(changes.affects(page, Path::new(path)), "{path}");
+        }
+        for path in ["other.sql", "sqlpage/templates/list.handlebars"] {
+            assert!(!changes.affects(page, Path::new(path)), "{path}");
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use crate::app_config::AppConfig;
+use crate::bundle::{Bundle, CONFIG_DIR, WEB_DIR, archive_name};
+use crate::releases::{self, ReleaseTracker};
+use crate::s3::S3Bucket;
 use crate::webserver::ErrorWithStatus;
 use crate::webserver::database::SupportedDatabase;
 use crate::webserver::{Database, StatusCodeResultExt, make_placeholder};
-use crate::{AppState, TEMPLATES_DIR};
+use crate::{AppState, HELPERS_DIR, TEMPLATES_DIR};
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use sqlx::any::{AnyStatement, AnyTypeInfo};
@@ -45,6 +49,10 @@ impl<'a> FileAccess<'a> {
 
 pub(crate) struct FileSystem {
     local_root: PathBuf,
+    /// Replaces the local files when serving a bundle
+    bundle: Option<Bundle>,
+    /// Looked up when a file is not in the web root
+    s3: Option<S3Bucket>,
     db_fs_queries: Option<DbFsQueries>,
 }
 
@@ -52,6 +60,8 @@ impl FileSystem {
     pub(crate) async fn init(local_root: impl Into<PathBuf>, db: &Database) -> Self {
         Self {
             local_root: local_root.into(),
+            bundle: None,
+            s3: None,
             db_fs_queries: match DbFsQueries::init(db).await {
                 Ok(q) => Some(q),
                 Err(e) => {
@@ -68,6 +78,22 @@ impl FileSystem {
         }
     }
 
+    /// Serves the files of a bundle instead of those of the local web root.
+    pub(crate) fn with_bundle(mut self, bundle: Option<Bundle>) -> Self {
+        self.bundle = bundle;
+        self
+    }
+
+    pub(crate) fn with_s3(mut self, s3: Option<S3Bucket>) -> Self {
+        self.s3 = s3;
+        self
+    }
+
+    /// The bucket that uploaded files are stored in, when `s3_uploads` is enabled.
+    pub(crate) fn uploads_bucket(&self, config: &AppConfig) -> Option<&S3Bucket> {
+        self.s3.as_ref().filter(|_| config.s3_uploads)
+    }
+
     pub(crate) async fn modified_since(
         &self,
         app_state: &AppState,
@@ -76,7 +102,20 @@ impl FileSystem {
     ) -> anyhow::Result<bool> {
         let path = access.path();
         let local_path = self.safe_local_path(app_state, access);
-        let local_result = file_modified_since_local(&local_path, since).await;
+        let local_result = if let Some(bundle) = &self.bundle {
+            // Bundled files never change
+            if bundle.contains(&bundle_file_name(access)) {
+                Ok(false)
+            } else {
+                Err(std::io::Error::from(ErrorKind::NotFound))
+            }
+        } else {
+            file_modified_since_local(&local_path, since).await
+        };
+        let local_result = match (local_result, &self.s3) {
+            (Err(e), Some(s3)) if is_path_missing_error(&e) => s3.modified_since(path, since).await,
+            (result, _) => result,
+        };
         log::trace!(
             "Local file {} modified since {since:?} ? {local_result:?}",
             local_path.display()
@@ -139,7 +178,15 @@ impl FileSystem {
             path.display(),
             local_path.display()
         );
-        let local_result = tokio::fs::read(&local_path).await;
+        let local_result = if let Some(bundle) = &self.bundle {
+            bundle.read(&bundle_file_name(access))
+        } else {
+            tokio::fs::read(&local_path).await
+        };
+        let local_result = match (local_result, &self.s3) {
+            (Err(e), Some(s3)) if is_path_missing_error(&e) => s3.read(path).await,
+            (result, _) => result,
+        };
         match (local_result, &self.db_fs_queries) {
             (Ok(f), _) => Ok(f),
             (Err(e), Some(db_fs)) if is_path_missing_error(&e) => {
@@ -159,19 +206,29 @@ impl FileSystem {
         }
     }
 
+    /// The release of `sqlpage_file_versions` that files are read from, as last seen by this server.
+    pub(crate) fn active_release(&self) -> Option<i32> {
+        self.db_fs_queries.as_ref()?.release.as_ref()?.active()
+    }
+
+    /// Whether files can also be loaded from the `sqlpage_files` table.
+    pub(crate) fn uses_database(&self) -> bool {
+        self.db_fs_queries.is_some()
+    }
+
     fn safe_local_path(&self, app_state: &AppState, access: FileAccess<'_>) -> PathBuf {
         let path = access.path();
         if access.privileged {
-            // Templates requests are always made to the static TEMPLATES_DIR, because this is where they are stored in the database
-            // but when serving them from the filesystem, we need to serve them from the `SQLPAGE_CONFIGURATION_DIRECTORY/templates` directory
-            if let Ok(template_path) = path.strip_prefix(TEMPLATES_DIR) {
+            // Templates and helpers requests are always made to their directory in `sqlpage/`, because this is where they are stored in the database
+            // but when serving them from the filesystem, we need to serve them from the `SQLPAGE_CONFIGURATION_DIRECTORY` directory
+            if let Some((dir, relative)) = configuration_file(path) {
                 let normalized = app_state
                     .config
                     .configuration_directory
-                    .join("templates")
-                    .join(template_path);
+                    .join(dir)
+                    .join(relative);
                 log::trace!(
-                    "Normalizing template path {} to {}",
+                    "Normalizing configuration file path {} to {}",
                     path.display(),
                     normalized.display()
                 );
@@ -188,7 +245,17 @@ impl FileSystem {
     ) -> anyhow::Result<bool> {
         let path = access.path();
         let safe_path = self.safe_local_path(app_state, access);
-        let local_exists = match tokio::fs::try_exists(safe_path).await {
+        let local_result = if let Some(bundle) = &self.bundle {
+            Ok(bundle.contains(&bundle_file_name(access)))
+        } else {
+            tokio::fs::try_exists(safe_path).await
+        };
+        let local_result = match (local_result, &self.s3) {
+            (Ok(false), Some(s3)) => s3.exists(path).await,
+            (Err(e), Some(s3)) if is_path_missing_error(&e) => s3.exists(path).await,
+            (result, _) => result,
+        };
+        let local_exists = match local_result {
             Ok(exists) => exists,
             Err(e) if is_path_missing_error(&e) => false,
             Err(e) => {
@@ -214,6 +281,25 @@ impl FileSystem {
     }
 }
 
+/// Splits the path of a template or helper into its directory in the configuration directory,
+/// and its path relative to that directory.
+pub(crate) fn configuration_file(path: &Path) -> Option<(&'static str, &Path)> {
+    [(TEMPLATES_DIR, "templates"), (HELPERS_DIR, "helpers")]
+        .into_iter()
+        .find_map(|(prefix, dir)| Some((dir, path.strip_prefix(prefix).ok()?)))
+}
+
+/// The name of a file in a bundle. Like local templates and helpers, bundled ones are in the configuration directory.
+fn bundle_file_name(access: FileAccess<'_>) -> String {
+    let path = access.path();
+    if access.privileged
+        && let Some((dir, relative)) = configuration_file(path)
+    {
+        return archive_name(&format!("{CONFIG_DIR}{dir}/"), relative);
+    }
+    archive_name(WEB_DIR, path)
+}
+
 /// Rejects paths that an untrusted HTTP request must never reach: the reserved
 /// `sqlpage/` prefix, dotfiles, parent-directory traversal and absolute/root paths.
 fn validate_unprivileged_path(path: &Path) -> anyhow::Result<()> {
@@ -273,6 +359,8 @@ pub struct DbFsQueries {
     was_modified: AnyStatement<'static>,
     read_file: AnyStatement<'static>,
     exists: AnyStatement<'static>,
+    /// Set when the files are read from the active release of `sqlpage_file_versions`
+    release: Option<ReleaseTracker>,
 }
 
 impl DbFsQueries {
@@ -296,11 +384,41 @@ impl DbFsQueries {
 
     async fn init(db: &Database) -> anyhow::Result<Self> {
         log::debug!("Initializing database filesystem queries");
+        if releases::tables_available(db).await {
+            log::info!("Serving the active release of sqlpage_file_versions");
+            if Self::check_table_available(db).await.is_ok() {
+                log::warn!(
+                    "The sqlpage_files table is ignored, because versioned releases are stored in sqlpage_file_versions"
+                );
+            }
+            let param_types: &[AnyTypeInfo; 1] = &[<str as Type<Postgres>>::type_info().into()];
+            let path = make_placeholder(db.info.kind, 1);
+            let active_files = releases::ACTIVE_FILES;
+            return Ok(Self {
+                was_modified: db.prepare_with(releases::ACTIVE_RELEASE_QUERY, &[]).await?,
+                read_file: db
+                    .prepare_with(
+                        &format!("SELECT f.contents FROM {active_files} WHERE f.path = {path}"),
+                        param_types,
+                    )
+                    .await?,
+                exists: db
+                    .prepare_with(
+                        &format!("SELECT 1 FROM {active_files} WHERE f.path = {path}"),
+                        param_types,
+                    )
+                    .await?,
+                release: Some(ReleaseTracker::new(
+                    releases::active_version(&db.connection).await?,
+                )),
+            });
+        }
         Self::check_table_available(db).await?;
         Ok(Self {
             was_modified: Self::make_was_modified_query(db).await?,
             read_file: Self::make_read_file_query(db).await?,
             exists: Self::make_exists_query(db).await?,
+            release: None,
         })
     }
 
@@ -352,6 +470,16 @@ impl DbFsQueries {
         path: &Path,
         since: DateTime<Utc>,
     ) -> anyhow::Result<bool> {
+        if let Some(release) = &self.release {
+            // Publishing or rolling back a release changes all the files at once
+            let active = self
+                .was_modified
+                .query_as::<(i32,)>()
+                .fetch_optional(&app_state.db.connection)
+                .await
+                .context("Unable to read the active release")?;
+            return Ok(release.changed_since(active.map(|(v,)| v), since));
+        }
         let query = self
             .was_modified
             .query_as::<(i32,)>()
@@ -427,6 +555,65 @@ impl DbFsQueries {
     }
 }
 
+/// Lists the files of the `sqlpage_files` table that changed recently.
+/// Used to reload them in development, since the database does not notify us of changes.
+pub(crate) struct DbFileChanges {
+    modified_since: AnyStatement<'static>,
+    pool: sqlx::any::AnyPool,
+    /// When releases are used, all the files of the new release change when it is activated
+    release: Option<ReleaseTracker>,
+}
+
+impl DbFileChanges {
+    pub(crate) async fn init(db: &Database) -> anyhow::Result<Self> {
+        if releases::tables_available(db).await {
+            let query = format!("SELECT f.path FROM {}", releases::ACTIVE_FILES);
+            return Ok(Self {
+                modified_since: db.prepare_with(&query, &[]).await?,
+                pool: db.connection.clone(),
+                release: Some(ReleaseTracker::new(
+                    releases::active_version(&db.connection).await?,
+                )),
+            });
+        }
+        let query = format!(
+            "SELECT path from sqlpage_files WHERE last_modified >= {}",
+            make_placeholder(db.info.kind, 1),
+        );
+        let param_types: &[AnyTypeInfo; 1] = &[PgTimeTz::type_info().into()];
+        log::debug!("Preparing the database filesystem changes query: {query}");
+        Ok(Self {
+            modified_since: db.prepare_with(&query, param_types).await?,
+            pool: db.connection.clone(),
+            release: None,
+        })
+    }
+
+    pub(crate) async fn modified_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let query = self.modified_since.query_as::<(String,)>();
+        let query = if let Some(release) = &self.release {
+            let active = releases::active_version(&self.pool).await?;
+            if !release.changed_since(active, since) {
+                return Ok(Vec::new());
+            }
+            query
+        } else {
+            query.bind(since)
+        };
+        let paths = query
+            .fetch_all(&self.pool)
+            .await
+            .context("Unable to list the files modified in sqlpage_files")?;
+        Ok(paths
+            .into_iter()
+            .map(|(path,)| PathBuf::from(path))
+            .collect())
+    }
+}
+
 #[actix_web::test]
 async fn test_sql_file_read_utf8() -> anyhow::Result<()> {
     use crate::app_config;
@@ -504,5 +691,20 @@ async fn test_sql_file_read_utf8() -> anyhow::Result<()> {
         "File should not be modified since one hour in the future"
     );
 
+    let test_file = PathBuf::from("unit test file.txt");
+    let changes = DbFileChanges::init(db).await?;
+    assert!(
+        changes
+            .modified_since(one_hour_ago)
+            .await?
+            .contains(&test_file)
+    );
+    assert!(
+        !changes
+            .modified_since(one_hour_future)
+            .await?
+            .contains(&test_file)
+    );
+
     Ok(())
 }
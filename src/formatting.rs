@@ -0,0 +1,537 @@
+//! Locale-aware formatting of dates, numbers, amounts of money and file sizes,
+//! used by the `format_*` handlebars helpers.
+//!
+//! Separators, digit grouping, date patterns, month names and currency symbols follow the
+//! [POSIX locale](crate::i18n::Locale::posix) of the language of the request.
+//! Relative dates ("3 days ago") and compact numbers ("1.2k") are messages of the translation
+//! catalogs, under the `sqlpage.format.` prefix, so that applications can change them.
+
+use crate::i18n::Locale;
+use anyhow::Context;
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::format::StrftimeItems;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use pure_rust_locales::locale_match;
+use serde_json::{Map, Value as JsonValue};
+use std::fmt::Write;
+
+/// How many fraction digits to show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decimals {
+    Exactly(usize),
+    /// Trailing zeros are removed
+    AtMost(usize),
+}
+
+/// Numbers without an explicit precision are shown with at most 3 fraction digits
+const DEFAULT_DECIMALS: Decimals = Decimals::AtMost(3);
+
+const BYTE_UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+
+/// Symbols of common currencies, used when the locale uses another currency
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("CNY", "¥"),
+    ("EUR", "€"),
+    ("GBP", "£"),
+    ("INR", "₹"),
+    ("JPY", "¥"),
+    ("KRW", "₩"),
+    ("USD", "$"),
+];
+
+/// Parses a date given as an ISO 8601 string (with or without time) or a unix timestamp.
+/// Dates without a time zone are in UTC.
+pub fn parse_date(value: &JsonValue) -> anyhow::Result<DateTime<FixedOffset>> {
+    match value {
+        JsonValue::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .or_else(|| {
+                ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                    .iter()
+                    .find_map(|pattern| NaiveDateTime::parse_from_str(s, pattern).ok())
+                    .or_else(|| {
+                        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                            .ok()
+                            .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    })
+                    .map(|d| d.and_utc().fixed_offset())
+            })
+            .with_context(|| format!("invalid date: {s}")),
+        JsonValue::Number(n) => {
+            DateTime::from_timestamp(n.as_i64().with_context(|| "not a timestamp")?, 0)
+                .with_context(|| "invalid timestamp")
+                .map(Into::into)
+        }
+        other => anyhow::bail!("expected a date, got {other}"),
+    }
+}
+
+/// Parses a number given as a JSON number or as a string, like the decimals of some databases.
+pub fn parse_number(value: &JsonValue) -> anyhow::Result<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .with_context(|| format!("expected a number, got {value}"))
+}
+
+/// Parses an amount of money without going through floating point numbers,
+/// so that decimal values from the database are rounded exactly.
+pub fn parse_decimal(value: &JsonValue) -> anyhow::Result<BigDecimal> {
+    match value {
+        JsonValue::Number(n) => n.to_string().parse().ok(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .with_context(|| format!("expected a number, got {value}"))
+}
+
+/// Formats a date with a strftime pattern, like `%d %B %Y`.
+/// Without a pattern, the usual date format of the locale is used.
+pub fn format_date(
+    date: &DateTime<FixedOffset>,
+    pattern: Option<&str>,
+    locale: &Locale,
+) -> anyhow::Result<String> {
+    let posix = locale.posix();
+    let pattern = pattern.unwrap_or(locale_match!(posix => LC_TIME::D_FMT));
+    let items = StrftimeItems::new_with_locale(pattern, posix)
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid date format: {pattern}"))?;
+    let mut formatted = String::new();
+    write!(
+        formatted,
+        "{}",
+        date.format_localized_with_items(items.iter(), posix)
+    )
+    .with_context(|| format!("unable to format the date with {pattern}"))?;
+    Ok(formatted)
+}
+
+/// Formats the time between `date` and `now`, like "3 days ago" or "in 2 hours".
+#[must_use]
+pub fn format_relative_date(
+    date: &DateTime<FixedOffset>,
+    now: DateTime<Utc>,
+    locale: &Locale,
+) -> String {
+    let seconds = now.signed_duration_since(date).num_seconds();
+    #[allow(clippy::cast_precision_loss)]
+    let minutes = seconds.abs() as f64 / 60.;
+    let hours = minutes / 60.;
+    let days = hours / 24.;
+    let (count, unit) = if minutes < 0.75 {
+        return locale.translate("sqlpage.format.now", &Map::new());
+    } else if minutes < 45. {
+        (minutes, "minute")
+    } else if hours < 22. {
+        (hours, "hour")
+    } else if days < 26. {
+        (days, "day")
+    } else if days < 320. {
+        (days / 30.4, "month")
+    } else {
+        (days / 365.25, "year")
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let count = (count.round() as i64).max(1);
+    let key = if count == 1 {
+        format!("sqlpage.format.{unit}")
+    } else {
+        format!("sqlpage.format.{unit}s")
+    };
+    let time = locale.translate(&key, &message_args("count", count.into()));
+    let key = if seconds >= 0 {
+        "sqlpage.format.ago"
+    } else {
+        "sqlpage.format.in"
+    };
+    locale.translate(key, &message_args("time", time.into()))
+}
+
+/// Formats a number with the decimal point and digit grouping of the locale: `1,234.5` or `1 234,5`.
+#[must_use]
+pub fn format_number(number: f64, decimals: Decimals, locale: &Locale) -> String {
+    let posix = locale.posix();
+    format_with_separators(
+        number,
+        decimals,
+        locale_match!(posix => LC_NUMERIC::DECIMAL_POINT),
+        locale_match!(posix => LC_NUMERIC::THOUSANDS_SEP),
+        locale_match!(posix => LC_NUMERIC::GROUPING),
+    )
+}
+
+/// Formats a number in a short form, like `1.2k` or `35M`.
+#[must_use]
+pub fn format_compact(number: f64, locale: &Locale) -> String {
+    const UNITS: [&str; 3] = ["thousands", "millions", "billions"];
+    let mut scaled = number.abs();
+    let mut unit = None;
+    while scaled >= 999.95 && unit.is_none_or(|u| u + 1 < UNITS.len()) {
+        scaled /= 1000.;
+        unit = Some(unit.map_or(0, |u| u + 1));
+    }
+    let decimals = Decimals::AtMost(usize::from(scaled < 9.95));
+    let formatted = format_number(scaled.copysign(number), decimals, locale);
+    match unit {
+        None => formatted,
+        Some(unit) => locale.translate(
+            &format!("sqlpage.format.{}", UNITS[unit]),
+            &message_args("number", formatted.into()),
+        ),
+    }
+}
+
+/// Formats an amount of money. `currency` is an ISO 4217 code, like `EUR`.
+/// Without a currency, the currency of the locale is used.
+#[must_use]
+pub fn format_currency(
+    amount: &BigDecimal,
+    currency: Option<&str>,
+    decimals: Option<usize>,
+    locale: &Locale,
+) -> String {
+    let posix = locale.posix();
+    let local_code = locale_match!(posix => LC_MONETARY::INT_CURR_SYMBOL).trim();
+    let local_symbol = locale_match!(posix => LC_MONETARY::CURRENCY_SYMBOL);
+    let symbol = match currency {
+        None => local_symbol,
+        Some(code) if code.eq_ignore_ascii_case(local_code) => local_symbol,
+        Some(code) => CURRENCY_SYMBOLS
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(code))
+            .map_or(code, |(_, symbol)| symbol),
+    };
+    let frac_digits = locale_match!(posix => LC_MONETARY::FRAC_DIGITS);
+    let decimals = decimals.unwrap_or_else(|| usize::try_from(frac_digits).unwrap_or(2));
+    let scale = i64::try_from(decimals).unwrap_or(i64::MAX);
+    let fixed = amount
+        .abs()
+        .with_scale_round(scale, RoundingMode::HalfUp)
+        .to_plain_string();
+    let number = separate_digits(
+        &fixed,
+        locale_match!(posix => LC_MONETARY::MON_DECIMAL_POINT),
+        locale_match!(posix => LC_MONETARY::MON_THOUSANDS_SEP),
+        locale_match!(posix => LC_MONETARY::MON_GROUPING),
+    );
+    let sign = if amount.sign() == bigdecimal::num_bigint::Sign::Minus
+        && number.bytes().any(|b| matches!(b, b'1'..=b'9'))
+    {
+        "-"
+    } else {
+        ""
+    };
+    let space = if locale_match!(posix => LC_MONETARY::P_SEP_BY_SPACE) == 1 {
+        "\u{a0}"
+    } else {
+        ""
+    };
+    if symbol.is_empty() {
+        format!("{sign}{number}")
+    } else if locale_match!(posix => LC_MONETARY::P_CS_PRECEDES) == 1 {
+        format!("{sign}{symbol}{space}{number}")
+    } else {
+        format!("{sign}{number}{space}{symbol}")
+    }
+}
+
+/// Formats a number of bytes with decimal units, like `1.5 MB`.
+#[must_use]
+pub fn format_bytes(bytes: f64, locale: &Locale) -> String {
+    let mut scaled = bytes;
+    let mut unit = 0;
+    while scaled.abs() >= 999.95 && unit + 1 < BYTE_UNITS.len() {
+        scaled /= 1000.;
+        unit += 1;
+    }
+    let decimals = Decimals::AtMost(usize::from(unit != 0));
+    format!(
+        "{}\u{a0}{}",
+        format_number(scaled, decimals, locale),
+        BYTE_UNITS[unit]
+    )
+}
+
+/// Formats a value according to a format specification, as given in the `format` option of components:
+///  - `date`, or `date:<strftime pattern>`,
+///  - `relative_date`,
+///  - `number`, or `number:<decimals>`,
+///  - `compact`,
+///  - `currency`, or `currency:<ISO 4217 code>`,
+///  - `bytes`.
+///
+/// Null values are left empty.
+pub fn format(value: &JsonValue, spec: &str, locale: &Locale) -> anyhow::Result<String> {
+    if value.is_null() {
+        return Ok(String::new());
+    }
+    let (kind, argument) = match spec.split_once(':') {
+        Some((kind, argument)) => (kind.trim(), Some(argument)),
+        None => (spec.trim(), None),
+    };
+    let decimals = || {
+        argument
+            .map(|a| a.trim().parse::<usize>())
+            .transpose()
+            .with_context(|| format!("invalid number of decimals in {spec}"))
+    };
+    Ok(match kind {
+        "date" => format_date(&parse_date(value)?, argument, locale)?,
+        "relative_date" => format_relative_date(&parse_date(value)?, Utc::now(), locale),
+        "number" => format_number(
+            parse_number(value)?,
+            decimals()?.map_or(DEFAULT_DECIMALS, Decimals::Exactly),
+            locale,
+        ),
+        "compact" => format_compact(parse_number(value)?, locale),
+        "currency" => format_currency(
+            &parse_decimal(value)?,
+            argument.map(str::trim),
+            None,
+            locale,
+        ),
+        "bytes" => format_bytes(parse_number(value)?, locale),
+        other => anyhow::bail!(
+            "unknown format {other:?}. Expected date, relative_date, number, compact, currency or bytes"
+        ),
+    })
+}
+
+fn message_args(name: &str, value: JsonValue) -> Map<String, JsonValue> {
+    Map::from_iter([(name.to_owned(), value)])
+}
+
+fn format_with_separators(
+    number: f64,
+    decimals: Decimals,
+    decimal_point: &str,
+    thousands_sep: &str,
+    grouping: &[i64],
+) -> String {
+    let fixed = match decimals {
+        Decimals::Exactly(decimals) => format!("{:.decimals$}", number.abs()),
+        Decimals::AtMost(decimals) => {
+            let fixed = format!("{:.decimals$}", number.abs());
+            if fixed.contains('.') {
+                fixed.trim_end_matches('0').trim_end_matches('.').to_owned()
+            } else {
+                fixed
+            }
+        }
+    };
+    let mut formatted = String::with_capacity(fixed.len() + 8);
+    // -0.001 rounded to 0 is shown as 0
+    if number < 0. && fixed.bytes().any(|b| matches!(b, b'1'..=b'9')) {
+        formatted.push('-');
+    }
+    formatted.push_str(&separate_digits(
+        &fixed,
+        decimal_point,
+        thousands_sep,
+        grouping,
+    ));
+    formatted
+}
+
+/// Replaces the decimal point of a positive number written with digits, like `1234.50`,
+/// and groups the digits of its integer part.
+fn separate_digits(
+    fixed: &str,
+    decimal_point: &str,
+    thousands_sep: &str,
+    grouping: &[i64],
+) -> String {
+    let (integer, fraction) = fixed.split_once('.').unwrap_or((fixed, ""));
+    let mut formatted = group_digits(integer, thousands_sep, grouping);
+    if !fraction.is_empty() {
+        formatted.push_str(decimal_point);
+        formatted.push_str(fraction);
+    }
+    formatted
+}
+
+/// Inserts the thousands separator in a string of digits. `grouping` gives the sizes of the groups
+/// from the right, the last one being repeated, like the POSIX `LC_NUMERIC` grouping:
+/// `[3]` gives `1,234,567` and `[3, 2]` gives `12,34,567`. A negative size stops the grouping.
+fn group_digits(digits: &str, separator: &str, grouping: &[i64]) -> String {
+    let mut groups = Vec::new();
+    let mut end = digits.len();
+    for i in 0.. {
+        let size = grouping.get(i).or(grouping.last()).copied().unwrap_or(-1);
+        match usize::try_from(size) {
+            Ok(size) if size > 0 && end > size => {
+                groups.push(&digits[end - size..end]);
+                end -= size;
+            }
+            _ => {
+                groups.push(&digits[..end]);
+                break;
+            }
+        }
+    }
+    groups.reverse();
+    groups.join(separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Translations;
+    use serde_json::json;
+
+    fn locale(tag: &str) -> std::sync::Arc<Locale> {
+        let translations = Translations::new(tag, Vec::new()).unwrap();
+        std::sync::Arc::clone(translations.default_locale())
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits("1234567", ",", &[3]), "1,234,567");
+        assert_eq!(group_digits("1234567", ",", &[3, 2]), "12,34,567");
+        assert_eq!(group_digits("123", ",", &[3]), "123");
+        assert_eq!(group_digits("1234567", "", &[-1]), "1234567");
+        assert_eq!(group_digits("1234567", ",", &[]), "1234567");
+    }
+
+    #[test]
+    fn test_format_number() {
+        let en = locale("en");
+        let fr = locale("fr");
+        assert_eq!(
+            format_number(1_234_567.891, DEFAULT_DECIMALS, &en),
+            "1,234,567.891"
+        );
+        assert_eq!(
+            format_number(-1234.5, Decimals::Exactly(2), &en),
+            "-1,234.50"
+        );
+        assert_eq!(
+            format_number(1234.5, DEFAULT_DECIMALS, &fr),
+            "1\u{202f}234,5"
+        );
+        assert_eq!(format_number(-0.0001, Decimals::Exactly(2), &en), "0.00");
+        assert_eq!(format_number(12.0, DEFAULT_DECIMALS, &en), "12");
+    }
+
+    #[test]
+    fn test_format_compact() {
+        let en = locale("en");
+        assert_eq!(format_compact(999.0, &en), "999");
+        assert_eq!(format_compact(1234.0, &en), "1.2k");
+        assert_eq!(format_compact(-12_345.0, &en), "-12k");
+        assert_eq!(format_compact(999_999.0, &en), "1M");
+        assert_eq!(format_compact(2.5e12, &en), "2,500B");
+        assert_eq!(format_compact(1_500_000.0, &locale("fr")), "1,5\u{a0}M");
+    }
+
+    #[test]
+    fn test_format_currency() {
+        let en = locale("en");
+        let fr = locale("fr");
+        let amount = |value: &str| value.parse::<BigDecimal>().unwrap();
+        assert_eq!(
+            format_currency(&amount("1234.5"), None, None, &en),
+            "$1,234.50"
+        );
+        assert_eq!(
+            format_currency(&amount("-3"), Some("EUR"), None, &en),
+            "-€3.00"
+        );
+        assert_eq!(
+            format_currency(&amount("1234.5"), None, None, &fr),
+            "1\u{202f}234,50\u{a0}€"
+        );
+        assert_eq!(
+            format_currency(&amount("12"), Some("CHF"), Some(0), &fr),
+            "12\u{a0}CHF"
+        );
+        // Amounts are rounded exactly, even beyond the precision of floating point numbers
+        assert_eq!(
+            format_currency(&amount("0.125"), Some("USD"), None, &en),
+            "$0.13"
+        );
+        assert_eq!(
+            format_currency(&amount("90071992547409.93"), Some("USD"), None, &en),
+            "$90,071,992,547,409.93"
+        );
+        assert_eq!(
+            format_currency(&amount("-0.001"), Some("USD"), None, &en),
+            "$0.00"
+        );
+        assert_eq!(
+            format_currency(
+                &parse_decimal(&json!(1.005)).unwrap(),
+                Some("USD"),
+                None,
+                &en
+            ),
+            "$1.01"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        let en = locale("en");
+        assert_eq!(format_bytes(512.0, &en), "512\u{a0}B");
+        assert_eq!(format_bytes(1536.0, &en), "1.5\u{a0}kB");
+        assert_eq!(format_bytes(3e9, &en), "3\u{a0}GB");
+    }
+
+    #[test]
+    fn test_format_date() {
+        let date = parse_date(&json!("2024-03-05 14:30:00")).unwrap();
+        assert_eq!(
+            format_date(&date, None, &locale("en")).unwrap(),
+            "03/05/2024"
+        );
+        assert_eq!(
+            format_date(&date, None, &locale("fr")).unwrap(),
+            "05/03/2024"
+        );
+        assert_eq!(
+            format_date(&date, Some("%e %B %Y %H:%M"), &locale("de")).unwrap(),
+            " 5 März 2024 14:30"
+        );
+        assert!(format_date(&date, Some("%Q"), &locale("en")).is_err());
+        assert!(parse_date(&json!("not a date")).is_err());
+        assert_eq!(parse_date(&json!(0)).unwrap().timestamp(), 0);
+    }
+
+    #[test]
+    fn test_format_relative_date() {
+        let now = Utc::now();
+        let relative = |seconds: i64, tag: &str| {
+            let date = (now - chrono::Duration::seconds(seconds)).fixed_offset();
+            format_relative_date(&date, now, &locale(tag))
+        };
+        assert_eq!(relative(10, "en"), "just now");
+        assert_eq!(relative(60, "en"), "1 minute ago");
+        assert_eq!(relative(3 * 86_400, "en"), "3 days ago");
+        assert_eq!(relative(-2 * 3600, "en"), "in 2 hours");
+        assert_eq!(relative(3 * 86_400, "fr"), "il y a 3 jours");
+        assert_eq!(relative(-400 * 86_400, "de"), "in 1 Jahr");
+    }
+
+    #[test]
+    fn test_format_spec() {
+        let en = locale("en");
+        assert_eq!(
+            format(&json!(1234.567), "number:1", &en).unwrap(),
+            "1,234.6"
+        );
+        assert_eq!(
+            format(&json!("1500"), "currency:USD", &en).unwrap(),
+            "$1,500.00"
+        );
+        assert_eq!(
+            format(&json!("2024-03-05"), "date:%Y", &en).unwrap(),
+            "2024"
+        );
+        assert_eq!(format(&JsonValue::Null, "bytes", &en).unwrap(), "");
+        assert!(format(&json!(1), "unknown", &en).is_err());
+        assert!(format(&json!("abc"), "number", &en).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Translation catalogs and locale negotiation.
+//!
+//! Catalogs are JSON files named after a language tag, like `fr.json` or `pt-BR.json`, in the
+//! `locales` folder of the configuration directory. They are loaded when the server starts.
+//! Nested objects are flattened into dotted keys: `{"menu": {"home": "Accueil"}}` defines the
+//! `menu.home` message. Messages can contain `{name}` placeholders, replaced by named arguments.
+//!
+//! `SQLPage` embeds catalogs for the strings of its built-in components, under the `sqlpage.`
+//! prefix. Application catalogs can override them, and add translations for other languages.
+//! Without application catalogs, the locale is not negotiated: built-in components always use
+//! the `default_locale`.
+//!
+//! The locale of a request is the first locale that has a catalog among the locale cookie,
+//! the `locale` claim of OIDC users, and the languages of the `Accept-Language` header.
+//! When none matches, the `default_locale` configuration option is used.
+//! A missing message falls back to the language without its region (`fr` for `fr-CA`),
+//! then to the default locale, then to English.
+
+use crate::app_config::AppConfig;
+use anyhow::Context;
+use include_dir::{Dir, include_dir};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Folder of the configuration directory that contains the translation catalogs
+pub const LOCALES_DIR: &str = "locales";
+
+const BUILTIN_CATALOGS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/sqlpage/locales");
+
+const FALLBACK_LOCALE: &str = "en";
+
+type Catalog = HashMap<String, String>;
+
+/// The messages of a locale, merged with those of the locales it falls back to.
+#[derive(Debug)]
+pub struct Locale {
+    name: String,
+    messages: Catalog,
+    localized: bool,
+    posix: chrono::Locale,
+}
+
+impl Locale {
+    /// The language tag of the locale, as written in the name of its catalog file.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the application has translation catalogs.
+    /// Pages of applications without catalogs keep the formatting conventions of the browser.
+    #[must_use]
+    pub fn is_localized(&self) -> bool {
+        self.localized
+    }
+
+    /// The POSIX locale that gives the conventions to format dates, numbers and amounts of money,
+    /// like `fr_FR` for `fr`.
+    #[must_use]
+    pub fn posix(&self) -> chrono::Locale {
+        self.posix
+    }
+
+    /// Translates `key`, replacing `{name}` placeholders with the matching values in `args`.
+    /// When no catalog defines the key, the key itself is used as the message.
+    #[must_use]
+    pub fn translate(&self, key: &str, args: &Map<String, JsonValue>) -> String {
+        let message = self.messages.get(key).map_or(key, String::as_str);
+        if args.is_empty() {
+            message.to_owned()
+        } else {
+            interpolate(message, args)
+        }
+    }
+}
+
+fn interpolate(message: &str, args: &Map<String, JsonValue>) -> String {
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after
+            .find('}')
+            .and_then(|end| Some((end, args.get(&after[..end])?)));
+        if let Some((end, value)) = value {
+            match value {
+                JsonValue::String(s) => result.push_str(s),
+                JsonValue::Null => {}
+                other => result.push_str(&other.to_string()),
+            }
+            rest = &after[end + 1..];
+        } else {
+            result.push('{');
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// All the locales of the application, indexed by lowercase language tag.
+#[derive(Debug)]
+pub struct Translations {
+    locales: HashMap<String, Arc<Locale>>,
+    default: Arc<Locale>,
+}
+
+impl Translations {
+    /// Loads the catalogs of the `locales` folder of the configuration directory.
+    pub fn init(config: &AppConfig) -> anyhow::Result<Self> {
+        let dir = config.configuration_directory.join(LOCALES_DIR);
+        let catalogs = read_catalogs(&dir)
+            .with_context(|| format!("Unable to load the translations in {}", dir.display()))?;
+        Self::new(&config.default_locale, catalogs)
+    }
+
+    pub(crate) fn new(
+        default_locale: &str,
+        app_catalogs: Vec<(String, Catalog)>,
+    ) -> anyhow::Result<Self> {
+        let mut builtin = HashMap::new();
+        for file in BUILTIN_CATALOGS.files() {
+            let tag = file_language_tag(file.path()).context("invalid built-in catalog name")?;
+            let json = serde_json::from_slice(file.contents())
+                .with_context(|| format!("Invalid built-in catalog {tag}"))?;
+            builtin.insert(tag.to_ascii_lowercase(), flatten(json));
+        }
+        let localized = !app_catalogs.is_empty();
+        let mut app: HashMap<String, (String, Catalog)> = app_catalogs
+            .into_iter()
+            .map(|(tag, catalog)| (tag.to_ascii_lowercase(), (tag, catalog)))
+            .collect();
+        let default_key = default_locale.to_ascii_lowercase();
+        app.entry(default_key.clone())
+            .or_insert_with(|| (default_locale.to_owned(), Catalog::new()));
+
+        let locales: HashMap<String, Arc<Locale>> = app
+            .iter()
+            .map(|(key, (name, _))| {
+                let mut messages = Catalog::new();
+                // Merge the least specific catalogs first, so that more specific ones override them
+                for tag in fallback_chain(key, &default_key).iter().rev() {
+                    if let Some(catalog) = builtin.get(*tag) {
+                        messages.extend(catalog.clone());
+                    }
+                    if let Some((_, catalog)) = app.get(*tag) {
+                        messages.extend(catalog.clone());
+                    }
+                }
+                let locale = Locale {
+                    posix: posix_locale(name),
+                    name: name.clone(),
+                    messages,
+                    localized,
+                };
+                (key.clone(), Arc::new(locale))
+            })
+            .collect();
+        let default = Arc::clone(&locales[&default_key]);
+        Ok(Self { locales, default })
+    }
+
+    #[must_use]
+    pub fn default_locale(&self) -> &Arc<Locale> {
+        &self.default
+    }
+
+    /// Whether the application has translation catalogs, so that the locale depends on the request.
+    #[must_use]
+    pub fn is_localized(&self) -> bool {
+        self.default.is_localized()
+    }
+
+    /// The first of the preferred language tags that has a catalog, or the default locale.
+    /// A tag with a region (`fr-CA`) matches the catalog of its language (`fr`).
+    pub fn negotiate<'a>(&self, preferred: impl IntoIterator<Item = &'a str>) -> Arc<Locale> {
+        preferred
+            .into_iter()
+            .find_map(|tag| self.get(tag))
+            .unwrap_or(&self.default)
+            .clone()
+    }
+
+    fn get(&self, tag: &str) -> Option<&Arc<Locale>> {
+        let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+        self.locales
+            .get(&tag)
+            .or_else(|| self.locales.get(primary_language(&tag)))
+    }
+}
+
+/// Countries of the languages whose POSIX locale is not named like `fr_FR`
+const DEFAULT_REGIONS: &[(&str, &str)] = &[
+    ("ar", "ar_SA"),
+    ("bn", "bn_BD"),
+    ("ca", "ca_ES"),
+    ("cs", "cs_CZ"),
+    ("da", "da_DK"),
+    ("el", "el_GR"),
+    ("en", "en_US"),
+    ("et", "et_EE"),
+    ("fa", "fa_IR"),
+    ("he", "he_IL"),
+    ("hi", "hi_IN"),
+    ("ja", "ja_JP"),
+    ("ko", "ko_KR"),
+    ("ms", "ms_MY"),
+    ("nb", "nb_NO"),
+    ("sl", "sl_SI"),
+    ("sq", "sq_AL"),
+    ("sr", "sr_RS"),
+    ("sv", "sv_SE"),
+    ("uk", "uk_UA"),
+    ("ur", "ur_PK"),
+    ("vi", "vi_VN"),
+    ("zh", "zh_CN"),
+];
+
+/// The POSIX locale of a language tag: `pt_BR` for `pt-BR`, and `de_DE` for `de`.
+fn posix_locale(tag: &str) -> chrono::Locale {
+    let tag = tag.replace('_', "-").to_ascii_lowercase();
+    let language = primary_language(&tag);
+    let with_region = tag
+        .split_once('-')
+        .map(|(language, region)| format!("{language}_{}", region.to_ascii_uppercase()));
+    let candidates = [
+        with_region,
+        Some(format!("{language}_{}", language.to_ascii_uppercase())),
+        DEFAULT_REGIONS
+            .iter()
+            .find(|(l, _)| *l == language)
+            .map(|(_, posix)| (*posix).to_owned()),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|name| chrono::Locale::try_from(name.as_str()).ok())
+        .unwrap_or(chrono::Locale::POSIX)
+}
+
+fn primary_language(tag: &str) -> &str {
+    tag.split('-').next().unwrap_or(tag)
+}
+
+/// The lowercase language tags whose messages a locale uses, from the most specific.
+fn fallback_chain<'a>(tag: &'a str, default: &'a str) -> Vec<&'a str> {
+    let mut chain = Vec::with_capacity(5);
+    for tag in [
+        tag,
+        primary_language(tag),
+        default,
+        primary_language(default),
+        FALLBACK_LOCALE,
+    ] {
+        if !chain.contains(&tag) {
+            chain.push(tag);
+        }
+    }
+    chain
+}
+
+fn file_language_tag(path: &Path) -> Option<&str> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    path.file_stem()?.to_str()
+}
+
+fn read_catalogs(dir: &Path) -> anyhow::Result<Vec<(String, Catalog)>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut catalogs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(tag) = file_language_tag(&path) else {
+            continue;
+        };
+        let contents = std::fs::read(&path)?;
+        let json = serde_json::from_slice(&contents)
+            .with_context(|| format!("{} is not a valid JSON file", path.display()))?;
+        log::debug!("Loaded the {tag} translations from {}", path.display());
+        catalogs.push((tag.to_owned(), flatten(json)));
+    }
+    Ok(catalogs)
+}
+
+/// Flattens nested objects into a map of dotted keys to messages
+fn flatten(json: JsonValue) -> Catalog {
+    fn flatten_into(prefix: &str, json: JsonValue, catalog: &mut Catalog) {
+        match json {
+            JsonValue::Object(object) => {
+                for (key, value) in object {
+                    let key = if prefix.is_empty() {
+                        key
+                    } else {
+                        format!("{prefix}.{key}")
+                    };
+                    flatten_into(&key, value, catalog);
+                }
+            }
+            JsonValue::String(message) => {
+                catalog.insert(prefix.to_owned(), message);
+            }
+            JsonValue::Null => {}
+            other => {
+                catalog.insert(prefix.to_owned(), other.to_string());
+            }
+        }
+    }
+    let mut catalog = Catalog::new();
+    flatten_into("", json, &mut catalog);
+    catalog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn translations() -> Translations {
+        let catalog = |json| flatten(json);
+        Translations::new(
+            "en",
+            vec![
+                ("en".into(), catalog(json!({"greeting": "Hello {name}!"}))),
+                (
+                    "fr".into(),
+                    catalog(json!({"greeting": "Bonjour {name} !", "menu": {"home": "Accueil"}})),
+                ),
+                (
+                    "fr-CA".into(),
+                    catalog(json!({"greeting": "Allô {name} !"})),
+                ),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn args(json: JsonValue) -> Map<String, JsonValue> {
+        match json {
+            JsonValue::Object(args) => args,
+            other => panic!("expected an object, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let t = translations();
+        assert_eq!(t.negotiate(["de", "fr-FR", "en"]).name(), "fr");
+        assert_eq!(t.negotiate(["fr_ca"]).name(), "fr-CA");
+        assert_eq!(t.negotiate(["es"]).name(), "en");
+        assert_eq!(t.negotiate([]).name(), "en");
+    }
+
+    #[test]
+    fn test_translate_with_fallbacks() {
+        let t = translations();
+        let fr_ca = t.negotiate(["fr-CA"]);
+        let name = args(json!({"name": "Lovelace"}));
+        assert_eq!(fr_ca.translate("greeting", &name), "Allô Lovelace !");
+        assert_eq!(fr_ca.translate("menu.home", &Map::new()), "Accueil");
+        assert_eq!(
+            fr_ca.translate("sqlpage.no_data", &Map::new()),
+            "Aucune donnée"
+        );
+        let en = t.negotiate(["en"]);
+        assert_eq!(en.translate("menu.home", &Map::new()), "menu.home");
+        assert_eq!(en.translate("sqlpage.no_data", &Map::new()), "No data");
+    }
+
+    #[test]
+    fn test_posix_locale() {
+        assert_eq!(posix_locale("fr"), chrono::Locale::fr_FR);
+        assert_eq!(posix_locale("pt-br"), chrono::Locale::pt_BR);
+        assert_eq!(posix_locale("fr_CA"), chrono::Locale::fr_CA);
+        assert_eq!(posix_locale("en"), chrono::Locale::en_US);
+        assert_eq!(posix_locale("de-XX"), chrono::Locale::de_DE);
+        assert_eq!(posix_locale("xx"), chrono::Locale::POSIX);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = args(json!({"n": 3, "x": null, "s": "str"}));
+        assert_eq!(
+            interpolate("{n} {x}{s} {unknown} {", &a),
+            "3 str {unknown} {"
+        );
+    }
+
+    #[test]
+    fn test_not_localized_without_catalogs() {
+        let t = Translations::new("de", Vec::new()).unwrap();
+        let locale = t.negotiate(["fr"]);
+        assert_eq!(locale.name(), "de");
+        assert!(!locale.is_localized());
+        assert_eq!(locale.translate("sqlpage.search", &Map::new()), "Suchen");
+    }
+}
@@ -25,9 +25,16 @@
 //! - [`webserver`]: Core HTTP server implementation using actix-web
 //! - [`render`]: Component rendering system, streaming rendering of the handlebars templates with data
 //! - [`templates`]: Pre-defined UI component definitions
+//! - [`custom_helpers`]: Handlebars helpers written in Rhai by users
 //! - [`file_cache`]: Caching layer for SQL file parsing
+//! - [`bundle`]: Single-file deployment archives of a site
+//! - [`file_watcher`]: Reloads changed files and refreshes the browser in development
 //! - [`filesystem`]: Abstract interface for disk and DB-stored files
+//! - [`releases`]: Versioned releases of the files stored in the database
+//! - [`s3`]: Files stored in an S3-compatible bucket
 //! - [`app_config`]: Configuration and environment handling
+//! - [`i18n`]: Translation catalogs and locale negotiation
+//! - [`formatting`]: Locale-aware formatting of dates, numbers and amounts of money
 //!
 //! # Query Processing Pipeline
 //!
@@ -68,11 +75,18 @@
 extern crate core;
 
 pub mod app_config;
+pub mod bundle;
 pub mod cli;
+pub mod custom_helpers;
 pub mod dynamic_component;
 pub mod file_cache;
+pub mod file_watcher;
 pub mod filesystem;
+pub mod formatting;
+pub mod i18n;
+pub mod releases;
 pub mod render;
+pub mod s3;
 pub mod telemetry;
 pub mod telemetry_metrics;
 pub mod template_helpers;
@@ -81,10 +95,18 @@ pub mod utils;
 pub mod webserver;
 
 use crate::app_config::AppConfig;
+use crate::bundle::Bundle;
 use crate::filesystem::FileSystem;
+use crate::i18n::Translations;
+use crate::s3::S3Bucket;
 use crate::webserver::database::SqlFile;
+use crate::webserver::fetch_cache::FetchCache;
+use crate::webserver::http_client::HttpClients;
+use crate::webserver::image_endpoint::ImageCache;
 use crate::webserver::oidc::OidcState;
+use crate::webserver::resumable_uploads::PendingUploads;
 use file_cache::FileCache;
+use file_watcher::FileWatcher;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use telemetry_metrics::TelemetryMetrics;
@@ -95,9 +117,16 @@ use webserver::Database;
 /// When a template is requested, it is looked up in `sqlpage/templates/component_name.handlebars` in the database,
 /// or in `$SQLPAGE_CONFIGURATION_DIRECTORY/templates/component_name.handlebars` in the filesystem.
 pub const TEMPLATES_DIR: &str = "sqlpage/templates/";
+/// `HELPERS_DIR` is the directory where custom handlebars helpers (.rhai files) are stored,
+/// in the database or in `$SQLPAGE_CONFIGURATION_DIRECTORY/helpers/` in the filesystem.
+pub const HELPERS_DIR: &str = "sqlpage/helpers/";
 pub const MIGRATIONS_DIR: &str = "migrations";
 pub const ON_CONNECT_FILE: &str = "on_connect.sql";
 pub const ON_RESET_FILE: &str = "on_reset.sql";
+/// Runs when a resumable upload is complete. Resumable uploads are disabled when it does not exist.
+pub const ON_UPLOAD_FILE: &str = "on_upload.sql";
+/// Runs before a resumable upload is created, and refuses it when its response is not a success.
+pub const ON_UPLOAD_START_FILE: &str = "on_upload_start.sql";
 pub const DEFAULT_404_FILE: &str = "default_404.sql";
 
 pub struct AppState {
@@ -105,9 +134,15 @@ pub struct AppState {
     all_templates: AllTemplates,
     sql_file_cache: FileCache<SqlFile>,
     file_system: FileSystem,
+    file_watcher: Option<FileWatcher>,
     config: AppConfig,
+    translations: Translations,
     pub oidc_state: Option<Arc<OidcState>>,
     pub telemetry_metrics: TelemetryMetrics,
+    fetch_cache: FetchCache,
+    http_clients: HttpClients,
+    pending_uploads: Arc<PendingUploads>,
+    image_cache: ImageCache,
 }
 
 impl AppState {
@@ -116,9 +151,14 @@ impl AppState {
         Self::init_with_db(config, db).await
     }
     pub async fn init_with_db(config: &AppConfig, db: Database) -> anyhow::Result<Self> {
-        let all_templates = AllTemplates::init(config)?;
-        let mut sql_file_cache = FileCache::new();
-        let file_system = FileSystem::init(&config.web_root, &db).await;
+        let translations = Translations::init(config)?;
+        let all_templates = AllTemplates::init(config, translations.default_locale())?;
+        let mut sql_file_cache = FileCache::new("sql_files", config);
+        let bundle = config.bundle.as_deref().map(Bundle::open).transpose()?;
+        let file_system = FileSystem::init(&config.web_root, &db)
+            .await
+            .with_bundle(bundle)
+            .with_s3(S3Bucket::from_config(config)?);
         sql_file_cache.add_static(
             PathBuf::from("index.sql"),
             SqlFile::new(&db, include_str!("index.sql"), Path::new("index.sql")),
@@ -132,6 +172,15 @@ impl AppState {
             ),
         );
 
+        let file_watcher = if config.live_reload() {
+            FileWatcher::start(config, &file_system, &db)
+                .await
+                .inspect_err(|e| log::warn!("Live reload is disabled: {e:#}"))
+                .ok()
+        } else {
+            None
+        };
+
         let oidc_state = webserver::oidc::initialize_oidc_state(config).await?;
         let telemetry_metrics =
             TelemetryMetrics::new(&db.connection, db.info.database_type.otel_name());
@@ -141,9 +190,15 @@ impl AppState {
             all_templates,
             sql_file_cache,
             file_system,
+            file_watcher,
             config: config.clone(),
+            translations,
             oidc_state,
             telemetry_metrics,
+            fetch_cache: FetchCache::new(config.max_fetch_cache_size),
+            http_clients: HttpClients::new(config)?,
+            pending_uploads: PendingUploads::start(config),
+            image_cache: ImageCache::new(config),
         })
     }
 }
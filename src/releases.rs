@@ -0,0 +1,379 @@
+//! Versioned releases of the files stored in the database.
+//!
+//! `sqlpage publish` stores a snapshot of a directory in the `sqlpage_file_versions` table,
+//! as a new release, and makes it the active release.
+//! The single row of the `sqlpage_active_release` table points to the release that is served.
+//! Since it is changed in the same transaction as the files are published,
+//! all the servers that share the database switch to the new release at once,
+//! and `sqlpage rollback` switches them back to a previous release just as atomically.
+//!
+//! When these tables exist, they replace the unversioned `sqlpage_files` table.
+//! Servers check whether they exist when they start, so they are created in a migration.
+
+use crate::bundle::archive_name;
+use crate::file_watcher::is_ignored;
+use crate::webserver::database::SupportedDatabase;
+use crate::webserver::{Database, make_placeholder};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::any::{Any, AnyKind, AnyPool};
+use sqlx::executor::Executor;
+use sqlx::query::query;
+use sqlx::query_as::query_as;
+use sqlx::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Component, Path};
+use std::sync::Mutex;
+
+pub const ACTIVE_RELEASE_QUERY: &str = "SELECT version FROM sqlpage_active_release";
+
+/// The files of the active release, joined by `path`
+pub const ACTIVE_FILES: &str = "sqlpage_file_versions f \
+    INNER JOIN sqlpage_active_release r ON f.version = r.version";
+
+#[must_use]
+pub fn get_create_tables_sql(dbms: SupportedDatabase) -> [&'static str; 2] {
+    match dbms {
+        SupportedDatabase::Mssql => [
+            "CREATE TABLE sqlpage_file_versions(path NVARCHAR(255) NOT NULL, version INT NOT NULL, contents VARBINARY(MAX), author NVARCHAR(255), created_at DATETIME2(3) NOT NULL DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (path, version));",
+            "CREATE TABLE sqlpage_active_release(version INT NOT NULL, activated_at DATETIME2(3) NOT NULL DEFAULT CURRENT_TIMESTAMP);",
+        ],
+        SupportedDatabase::Postgres => [
+            "CREATE TABLE IF NOT EXISTS sqlpage_file_versions(path VARCHAR(255) NOT NULL, version INTEGER NOT NULL, contents BYTEA, author VARCHAR(255), created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (path, version));",
+            "CREATE TABLE IF NOT EXISTS sqlpage_active_release(version INTEGER NOT NULL, activated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP);",
+        ],
+        SupportedDatabase::Snowflake => [
+            "CREATE TABLE IF NOT EXISTS sqlpage_file_versions(path VARCHAR(255) NOT NULL, version INTEGER NOT NULL, contents VARBINARY, author VARCHAR(255), created_at TIMESTAMP_TZ DEFAULT CONVERT_TIMEZONE('UTC', CURRENT_TIMESTAMP()), PRIMARY KEY (path, version));",
+            "CREATE TABLE IF NOT EXISTS sqlpage_active_release(version INTEGER NOT NULL, activated_at TIMESTAMP_TZ DEFAULT CONVERT_TIMEZONE('UTC', CURRENT_TIMESTAMP()));",
+        ],
+        _ => [
+            "CREATE TABLE IF NOT EXISTS sqlpage_file_versions(path VARCHAR(255) NOT NULL, version INTEGER NOT NULL, contents BLOB, author VARCHAR(255), created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (path, version));",
+            "CREATE TABLE IF NOT EXISTS sqlpage_active_release(version INTEGER NOT NULL, activated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP);",
+        ],
+    }
+}
+
+/// Whether the database contains the tables of versioned releases.
+pub(crate) async fn tables_available(db: &Database) -> bool {
+    db.connection
+        .execute("SELECT 1 FROM sqlpage_file_versions, sqlpage_active_release WHERE 1 = 0")
+        .await
+        .is_ok()
+}
+
+async fn ensure_tables_available(db: &Database) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        tables_available(db).await,
+        "The tables of versioned releases do not exist. \
+        Create them in a migration in sqlpage/migrations/, so that all the servers use them when they start:\n{}",
+        get_create_tables_sql(db.info.database_type).join("\n")
+    );
+    Ok(())
+}
+
+/// How many times a release is published when other releases take its version concurrently
+const PUBLISH_ATTEMPTS: usize = 5;
+
+/// The version of the active release, if a release was published.
+pub(crate) async fn active_version(pool: &AnyPool) -> anyhow::Result<Option<i32>> {
+    let version = query_as::<_, (i32,)>(ACTIVE_RELEASE_QUERY)
+        .fetch_optional(pool)
+        .await
+        .context("Unable to read the active release")?;
+    Ok(version.map(|(version,)| version))
+}
+
+/// Publishes the files of `directory` as a new release, and activates it.
+/// Returns the version of the release, and the number of files it contains.
+pub async fn publish(
+    db: &Database,
+    directory: &Path,
+    author: Option<&str>,
+) -> anyhow::Result<(i32, usize)> {
+    let mut files = Vec::new();
+    collect_files(directory, Path::new(""), &mut files)?;
+    // An empty release would make every page of the site disappear
+    anyhow::ensure!(
+        !files.is_empty(),
+        "{} contains no files to publish",
+        directory.display()
+    );
+    ensure_tables_available(db).await?;
+    let mut attempt = 1;
+    loop {
+        let mut tx = db.connection.begin().await?;
+        let (version,) = query_as::<_, (i32,)>(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM sqlpage_file_versions",
+        )
+        .fetch_one(&mut tx)
+        .await
+        .context("Unable to compute the version of the new release")?;
+        match publish_release(tx, db.info.kind, version, &files, author).await {
+            Ok(()) => return Ok((version, files.len())),
+            Err(e)
+                if attempt < PUBLISH_ATTEMPTS
+                    && release_exists(&db.connection, db.info.kind, version).await? =>
+            {
+                log::info!("Release {version} was published concurrently, retrying: {e:#}");
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn publish_release(
+    mut tx: Transaction<'_, Any>,
+    kind: AnyKind,
+    version: i32,
+    files: &[(String, Vec<u8>)],
+    author: Option<&str>,
+) -> anyhow::Result<()> {
+    let insert_file = format!(
+        "INSERT INTO sqlpage_file_versions(path, version, contents, author) VALUES ({}, {}, {}, {})",
+        make_placeholder(kind, 1),
+        make_placeholder(kind, 2),
+        make_placeholder(kind, 3),
+        make_placeholder(kind, 4),
+    );
+    for (path, contents) in files {
+        log::debug!("Publishing {path} in release {version}");
+        query(&insert_file)
+            .bind(path.as_str())
+            .bind(version)
+            .bind(contents.as_slice())
+            .bind(author)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("Unable to publish {path}"))?;
+    }
+    activate(&mut tx, kind, version).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn release_exists<'e>(
+    executor: impl Executor<'e, Database = Any>,
+    kind: AnyKind,
+    version: i32,
+) -> anyhow::Result<bool> {
+    let exists = query_as::<_, (i32,)>(&format!(
+        "SELECT 1 FROM sqlpage_file_versions WHERE version = {}",
+        make_placeholder(kind, 1)
+    ))
+    .bind(version)
+    .fetch_optional(executor)
+    .await
+    .with_context(|| format!("Unable to check whether release {version} exists"))?;
+    Ok(exists.is_some())
+}
+
+/// Activates a previous release: the one with the given version,
+/// or else the latest release before the active one. Returns the activated version.
+pub async fn rollback(db: &Database, version: Option<i32>) -> anyhow::Result<i32> {
+    anyhow::ensure!(
+        tables_available(db).await,
+        "No release was published in this database"
+    );
+    let mut tx = db.connection.begin().await?;
+    let version = if let Some(version) = version {
+        version
+    } else {
+        let (previous,) = query_as::<_, (Option<i32>,)>(
+            "SELECT MAX(f.version) FROM sqlpage_file_versions f, sqlpage_active_release r \
+            WHERE f.version < r.version",
+        )
+        .fetch_one(&mut tx)
+        .await
+        .context("Unable to find the previous release")?;
+        previous.context("There is no release before the active one")?
+    };
+    anyhow::ensure!(
+        release_exists(&mut tx, db.info.kind, version).await?,
+        "Release {version} does not exist"
+    );
+    activate(&mut tx, db.info.kind, version).await?;
+    tx.commit().await?;
+    Ok(version)
+}
+
+async fn activate(
+    tx: &mut Transaction<'_, Any>,
+    kind: AnyKind,
+    version: i32,
+) -> anyhow::Result<()> {
+    tx.execute("DELETE FROM sqlpage_active_release").await?;
+    query(&format!(
+        "INSERT INTO sqlpage_active_release(version) VALUES ({})",
+        make_placeholder(kind, 1)
+    ))
+    .bind(version)
+    .execute(&mut *tx)
+    .await
+    .with_context(|| format!("Unable to activate release {version}"))?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Deleted,
+    Modified,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::Added => "A",
+            Change::Deleted => "D",
+            Change::Modified => "M",
+        })
+    }
+}
+
+/// The files that changed between two releases, sorted by path.
+/// When `to` is not given, compares with the active release.
+pub async fn diff(
+    db: &Database,
+    from: i32,
+    to: Option<i32>,
+) -> anyhow::Result<Vec<(Change, String)>> {
+    let to = match to {
+        Some(to) => to,
+        None => active_version(&db.connection)
+            .await?
+            .context("No release is active")?,
+    };
+    let old = release_files(db, from).await?;
+    let mut new = release_files(db, to).await?;
+    let mut changes = Vec::new();
+    for (path, contents) in old {
+        match new.remove(&path) {
+            None => changes.push((Change::Deleted, path)),
+            Some(new_contents) if new_contents != contents => {
+                changes.push((Change::Modified, path));
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(new.into_keys().map(|path| (Change::Added, path)));
+    changes.sort_by(|(_, a), (_, b)| a.cmp(b));
+    Ok(changes)
+}
+
+async fn release_files(db: &Database, version: i32) -> anyhow::Result<BTreeMap<String, Vec<u8>>> {
+    let files = query_as::<_, (String, Vec<u8>)>(&format!(
+        "SELECT path, contents FROM sqlpage_file_versions WHERE version = {}",
+        make_placeholder(db.info.kind, 1)
+    ))
+    .bind(version)
+    .fetch_all(&db.connection)
+    .await
+    .with_context(|| format!("Unable to read release {version}"))?;
+    anyhow::ensure!(!files.is_empty(), "Release {version} does not exist");
+    Ok(files.into_iter().collect())
+}
+
+/// Lists the files to publish. The configuration directory is not published, except for templates
+/// and helpers, which are stored in the database under `sqlpage/templates/` and `sqlpage/helpers/`.
+fn collect_files(
+    root: &Path,
+    relative: &Path,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> anyhow::Result<()> {
+    let dir = root.join(relative);
+    let entries =
+        std::fs::read_dir(&dir).with_context(|| format!("Unable to read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let relative = relative.join(entry.file_name());
+        let path = entry.path();
+        if is_ignored(&relative) || !is_published(&relative) {
+            log::debug!("Not publishing {}", path.display());
+        } else if path.is_dir() {
+            collect_files(root, &relative, files)?;
+        } else {
+            let contents = std::fs::read(&path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            files.push((archive_name("", &relative), contents));
+        }
+    }
+    Ok(())
+}
+
+fn is_published(relative: &Path) -> bool {
+    let mut components = relative.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first == "sqlpage" => match components.next() {
+            None => true,
+            Some(second) => matches!(second.as_os_str().to_str(), Some("templates" | "helpers")),
+        },
+        _ => true,
+    }
+}
+
+/// Remembers when this server noticed that the active release changed.
+/// Comparing versions instead of the activation date in the database
+/// is immune to the difference between the clocks of the servers and the database.
+pub(crate) struct ReleaseTracker {
+    seen: Mutex<(Option<i32>, DateTime<Utc>)>,
+}
+
+impl ReleaseTracker {
+    pub(crate) fn new(active: Option<i32>) -> Self {
+        Self {
+            seen: Mutex::new((active, Utc::now())),
+        }
+    }
+
+    /// The active version, when this server last checked it.
+    pub(crate) fn active(&self) -> Option<i32> {
+        self.seen.lock().expect("release tracker lock poisoned").0
+    }
+
+    /// Whether the active release changed after `since`, given the currently active version.
+    pub(crate) fn changed_since(&self, active: Option<i32>, since: DateTime<Utc>) -> bool {
+        let mut seen = self.seen.lock().expect("release tracker lock poisoned");
+        if seen.0 != active {
+            log::info!("The active release changed from {:?} to {active:?}", seen.0);
+            *seen = (active, Utc::now());
+        }
+        seen.1 >= since
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_published_files() {
+        for path in [
+            "index.sql",
+            "sqlpage",
+            "sqlpage/templates/card.handlebars",
+            "sqlpage/helpers/money.rhai",
+        ] {
+            assert!(is_published(Path::new(path)), "{path} should be published");
+        }
+        for path in ["sqlpage/sqlpage.json", "sqlpage/migrations/0001_init.sql"] {
+            assert!(
+                !is_published(Path::new(path)),
+                "{path} should not be published"
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_tracker() {
+        let before = Utc::now();
+        let tracker = ReleaseTracker::new(Some(1));
+        let after = Utc::now() + chrono::Duration::milliseconds(1);
+        assert!(!tracker.changed_since(Some(1), after));
+        assert!(tracker.changed_since(Some(1), before));
+        let now = Utc::now();
+        assert!(tracker.changed_since(Some(2), now));
+        assert!(!tracker.changed_since(Some(2), Utc::now() + chrono::Duration::seconds(1)));
+    }
+}
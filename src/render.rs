@@ -2,7 +2,7 @@
 //!
 //! This module is responsible for transforming database query results into formatted HTTP responses
 //! by utilizing a component-based rendering system. It supports multiple output formats including HTML,
-//! JSON, and CSV.
+//! JSON, CSV, XLSX, Parquet and PDF.
 //!
 //! # Components
 //!
@@ -37,13 +37,19 @@
 //! * HTML: Renders templated HTML output using components
 //! * JSON: Generates JSON responses for API endpoints
 //! * CSV: Creates downloadable CSV files
+//! * XLSX and Parquet: Create downloadable spreadsheets and data files
+//! * PDF: Lays out printable documents, without a browser
 //!
 //! For more details on available components and their usage, see the
 //! [SQLPage documentation](https://sql-page.com/documentation.sql).
 
 use crate::AppState;
+use crate::custom_helpers::NamedCustomHelper;
+use crate::i18n::Locale;
+use crate::template_helpers::{ColumnFormatHelper, FORMAT_HELPERS, FormatHelper, TranslateHelper};
 use crate::templates::SplitTemplate;
 use crate::webserver::ErrorWithStatus;
+use crate::webserver::database::Blob;
 use crate::webserver::error::ClientError;
 use crate::webserver::http::{RequestContext, ResponseFormat};
 use crate::webserver::response_writer::{AsyncResponseWriter, ResponseWriter};
@@ -66,7 +72,15 @@ use std::fmt::Write as _;
 use std::io::Write;
 use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+mod parquet_file;
+pub(crate) mod pdf;
+mod xlsx;
+
+use parquet_file::ParquetBodyRenderer;
+use pdf::{PageSize, PdfBodyRenderer};
+use xlsx::XlsxBodyRenderer;
 
 pub enum PageContext {
     /// Indicates that we should stay in the header context
@@ -100,6 +114,10 @@ impl HeaderContext {
     ) -> Self {
         let mut response = HttpResponseBuilder::new(StatusCode::OK);
         response.content_type(request_context.response_format.content_type());
+        if app_state.translations.is_localized() {
+            // the locale of the page is negotiated from these headers
+            response.append_header((header::VARY, "Accept-Language, Cookie"));
+        }
         if request_context.response_format == ResponseFormat::Html {
             let tpl = &app_state.config.content_security_policy;
             request_context
@@ -126,9 +144,20 @@ impl HeaderContext {
             Some(HeaderComponent::Redirect) => self.redirect(&data),
             Some(HeaderComponent::Json) => self.json(&data),
             Some(HeaderComponent::Csv) => self.csv(&data).await,
+            Some(HeaderComponent::Xlsx) => self.xlsx(&data),
+            Some(HeaderComponent::Parquet) => self.parquet(&data),
+            Some(HeaderComponent::Pdf) => self.pdf(&data),
             Some(HeaderComponent::Cookie) => self.add_cookie(&data).map(PageContext::Header),
             Some(HeaderComponent::Authentication) => self.authentication(data).await,
             Some(HeaderComponent::Download) => self.download(&data),
+            Some(HeaderComponent::Blob) => self.blob(Blob {
+                contents: get_object_str(&data, "contents")
+                    .unwrap_or_default()
+                    .as_bytes()
+                    .to_vec(),
+                content_type: get_object_str(&data, "content_type").map(str::to_owned),
+                filename: get_object_str(&data, "filename").map(str::to_owned),
+            }),
             Some(HeaderComponent::Log) => self.log(&data),
             None => self.start_body(data).await,
         }
@@ -324,6 +353,84 @@ impl HeaderContext {
         })
     }
 
+    fn xlsx(mut self, options: &JsonValue) -> anyhow::Result<PageContext> {
+        self.insert_header((
+            header::CONTENT_TYPE,
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        ))?;
+        self.insert_download_filename(options, "xlsx")?;
+        let sheet_name = get_object_str(options, "sheet_name").unwrap_or("Sheet1");
+        let xlsx_renderer = XlsxBodyRenderer::new(self.writer, sheet_name)?;
+        let renderer = AnyRenderBodyContext::new(
+            BodyRenderer::Xlsx(Box::new(xlsx_renderer)),
+            self.app_state.config.environment,
+        );
+        let http_response = self.response.take();
+        Ok(PageContext::Body {
+            renderer,
+            http_response,
+        })
+    }
+
+    fn parquet(mut self, options: &JsonValue) -> anyhow::Result<PageContext> {
+        self.insert_header((header::CONTENT_TYPE, "application/vnd.apache.parquet"))?;
+        self.insert_download_filename(options, "parquet")?;
+        let row_group_size = match options.get("row_group_size") {
+            None | Some(JsonValue::Null) => parquet_file::DEFAULT_ROW_GROUP_SIZE,
+            Some(size) => size
+                .as_u64()
+                .and_then(|size| usize::try_from(size).ok())
+                .with_context(|| format!("Invalid parquet row_group_size: {size}"))?,
+        };
+        let parquet_renderer = ParquetBodyRenderer::new(self.writer, row_group_size)?;
+        let renderer = AnyRenderBodyContext::new(
+            BodyRenderer::Parquet(Box::new(parquet_renderer)),
+            self.app_state.config.environment,
+        );
+        let http_response = self.response.take();
+        Ok(PageContext::Body {
+            renderer,
+            http_response,
+        })
+    }
+
+    fn pdf(mut self, options: &JsonValue) -> anyhow::Result<PageContext> {
+        self.insert_header((header::CONTENT_TYPE, "application/pdf"))?;
+        self.insert_download_filename(options, "pdf")?;
+        let mut pdf_renderer = PdfBodyRenderer::new(self.writer, PageSize::from_options(options)?)?;
+        if let Some(title) = get_object_str(options, "title") {
+            pdf_renderer.set_title(title);
+        }
+        let renderer = AnyRenderBodyContext::new(
+            BodyRenderer::Pdf(Box::new(pdf_renderer)),
+            self.app_state.config.environment,
+        );
+        let http_response = self.response.take();
+        Ok(PageContext::Body {
+            renderer,
+            http_response,
+        })
+    }
+
+    /// Makes the browser download the response as a file, if the component has a file name.
+    fn insert_download_filename(
+        &mut self,
+        options: &JsonValue,
+        extension: &str,
+    ) -> anyhow::Result<()> {
+        if let Some(filename) =
+            get_object_str(options, "filename").or_else(|| get_object_str(options, "title"))
+        {
+            let extension = if filename.contains('.') {
+                String::new()
+            } else {
+                format!(".{extension}")
+            };
+            self.insert_header(attachment_with_filename(&format!("{filename}{extension}")))?;
+        }
+        Ok(())
+    }
+
     async fn authentication(mut self, mut data: JsonValue) -> anyhow::Result<PageContext> {
         let password_hash = take_object_str(&mut data, "password_hash");
         let password = take_object_str(&mut data, "password");
@@ -366,6 +473,18 @@ impl HeaderContext {
         self.close_with_body(body_bytes)
     }
 
+    /// Sends a file decoded from a binary column, without converting it to a data URL first
+    pub fn blob(mut self, blob: Blob) -> anyhow::Result<PageContext> {
+        if let Some(filename) = &blob.filename {
+            self.insert_header(attachment_with_filename(filename))?;
+        }
+        let content_type = blob.content_type.as_deref().unwrap_or_else(|| {
+            crate::webserver::database::blob_to_data_url::detect_mime_type(&blob.contents)
+        });
+        self.insert_header((header::CONTENT_TYPE, content_type))?;
+        self.close_with_body(blob.contents)
+    }
+
     fn log(self, data: &JsonValue) -> anyhow::Result<PageContext> {
         handle_log_component(&self.request_context.source_path, None, data)?;
         Ok(PageContext::Header(self))
@@ -412,6 +531,11 @@ impl HeaderContext {
                         )?;
                 BodyRenderer::Html(html_renderer)
             }
+            ResponseFormat::Pdf => {
+                let mut pdf_renderer = PdfBodyRenderer::new(self.writer, PageSize::A4)?;
+                pdf_renderer.handle_row(&data)?;
+                BodyRenderer::Pdf(Box::new(pdf_renderer))
+            }
         };
         let renderer = AnyRenderBodyContext::new(body_renderer, environment);
         let http_response = self.response;
@@ -439,6 +563,15 @@ async fn verify_password_async(
     .await?
 }
 
+pub(crate) fn header_component_after_body(component_name: &str) -> anyhow::Error {
+    format_err!(
+        "The {component_name} component cannot be used after data has already been sent to the client's browser. \n\
+        This component must be used before any other component. \n\
+        To fix this, either move the call to the '{component_name}' component to the top of the SQL file, \n\
+        or create a new SQL file where '{component_name}' is the first component."
+    )
+}
+
 /// Builds an `attachment` `Content-Disposition` header with the given filename,
 /// using actix-web's structured [`ContentDisposition`] type so the filename is
 /// properly quoted and escaped. This prevents a user-supplied filename
@@ -470,6 +603,9 @@ pub enum BodyRenderer {
     Html(HtmlRenderContext<ResponseWriter>),
     Json(JsonBodyRenderer<ResponseWriter>),
     Csv(CsvBodyRenderer),
+    Xlsx(Box<XlsxBodyRenderer>),
+    Parquet(Box<ParquetBodyRenderer>),
+    Pdf(Box<PdfBodyRenderer>),
 }
 
 /// Wraps a [`BodyRenderer`] together with the environment, so errors can be
@@ -503,6 +639,16 @@ impl AnyRenderBodyContext {
             BodyRenderer::Html(render_context) => render_context.handle_row(data).await,
             BodyRenderer::Json(json_body_renderer) => json_body_renderer.handle_row(data),
             BodyRenderer::Csv(csv_renderer) => csv_renderer.handle_row(data).await,
+            BodyRenderer::Xlsx(xlsx_renderer) => xlsx_renderer.handle_row(data),
+            BodyRenderer::Parquet(parquet_renderer) => parquet_renderer.handle_row(data),
+            BodyRenderer::Pdf(pdf_renderer) => pdf_renderer.handle_row(data),
+        }
+    }
+
+    /// Only spreadsheets format dates differently from text.
+    pub fn set_date_columns(&mut self, columns: Vec<String>) {
+        if let BodyRenderer::Xlsx(xlsx_renderer) = &mut self.renderer {
+            xlsx_renderer.set_date_columns(columns);
         }
     }
 
@@ -515,7 +661,11 @@ impl AnyRenderBodyContext {
         // path, the SQL statement, the raw database error, and the backtrace.
         let query_number = match &self.renderer {
             BodyRenderer::Html(html) => Some(html.current_statement),
-            BodyRenderer::Json(_) | BodyRenderer::Csv(_) => None,
+            BodyRenderer::Json(_)
+            | BodyRenderer::Csv(_)
+            | BodyRenderer::Xlsx(_)
+            | BodyRenderer::Parquet(_)
+            | BodyRenderer::Pdf(_) => None,
         };
         let client_error = ClientError::new(error, self.environment, query_number);
         match &mut self.renderer {
@@ -524,6 +674,12 @@ impl AnyRenderBodyContext {
                 json_body_renderer.handle_error(&client_error)
             }
             BodyRenderer::Csv(csv_renderer) => csv_renderer.handle_error(&client_error).await,
+            BodyRenderer::Xlsx(xlsx_renderer) => xlsx_renderer.handle_error(&client_error),
+            BodyRenderer::Parquet(parquet_renderer) => {
+                parquet_renderer.handle_error(&client_error).await;
+                Ok(())
+            }
+            BodyRenderer::Pdf(pdf_renderer) => pdf_renderer.handle_error(&client_error),
         }
     }
 
@@ -531,7 +687,10 @@ impl AnyRenderBodyContext {
         match &mut self.renderer {
             BodyRenderer::Html(render_context) => render_context.finish_query().await,
             BodyRenderer::Json(_json_body_renderer) => Ok(()),
-            BodyRenderer::Csv(_csv_renderer) => Ok(()),
+            BodyRenderer::Csv(_)
+            | BodyRenderer::Xlsx(_)
+            | BodyRenderer::Parquet(_)
+            | BodyRenderer::Pdf(_) => Ok(()),
         }
     }
 
@@ -542,6 +701,9 @@ impl AnyRenderBodyContext {
                 writer.async_flush().await?;
             }
             BodyRenderer::Csv(csv_renderer) => csv_renderer.flush().await?,
+            BodyRenderer::Xlsx(xlsx_renderer) => xlsx_renderer.flush().await?,
+            BodyRenderer::Parquet(parquet_renderer) => parquet_renderer.flush().await?,
+            BodyRenderer::Pdf(pdf_renderer) => pdf_renderer.flush().await?,
         }
         Ok(())
     }
@@ -551,6 +713,9 @@ impl AnyRenderBodyContext {
             BodyRenderer::Html(render_context) => render_context.close().await,
             BodyRenderer::Json(json_body_renderer) => json_body_renderer.close(),
             BodyRenderer::Csv(csv_renderer) => csv_renderer.close().await,
+            BodyRenderer::Xlsx(xlsx_renderer) => xlsx_renderer.close(),
+            BodyRenderer::Parquet(parquet_renderer) => parquet_renderer.close().await,
+            BodyRenderer::Pdf(pdf_renderer) => pdf_renderer.close(),
         }
     }
 }
@@ -794,14 +959,10 @@ impl<W: Write> HtmlRenderContext<W> {
             );
             shell_component = FRAGMENT_SHELL_COMPONENT;
         }
-        let mut shell_renderer = Self::create_renderer(
-            shell_component,
-            Arc::clone(&app_state),
-            0,
-            request_context.content_security_policy.nonce,
-        )
-        .await
-        .with_context(|| "The shell component should always exist")?;
+        let mut shell_renderer =
+            Self::create_renderer(shell_component, Arc::clone(&app_state), 0, &request_context)
+                .await
+                .with_context(|| "The shell component should always exist")?;
         log::debug!("Rendering the shell with properties: {shell_row}");
         shell_renderer.render_start(&mut writer, shell_row)?;
 
@@ -847,12 +1008,7 @@ impl<W: Write> HtmlRenderContext<W> {
         match self.open_component_with_data(component_name, &data).await {
             Ok(_) => Ok(()),
             Err(err) => match HeaderComponent::try_from(component_name) {
-                Ok(_) => bail!(
-                    "The {component_name} component cannot be used after data has already been sent to the client's browser. \n\
-                                This component must be used before any other component. \n\
-                                    To fix this, either move the call to the '{component_name}' component to the top of the SQL file, \n\
-                                or create a new SQL file where '{component_name}' is the first component."
-                ),
+                Ok(_) => Err(header_component_after_body(component_name)),
                 Err(()) => Err(err),
             },
         }
@@ -935,17 +1091,23 @@ impl<W: Write> HtmlRenderContext<W> {
         component: &str,
         app_state: Arc<AppState>,
         component_index: usize,
-        nonce: u64,
+        request_context: &RequestContext,
     ) -> anyhow::Result<SplitTemplateRenderer> {
         let split_template = app_state
             .all_templates
             .get_template(&app_state, component)
             .await?;
+        let custom_helpers = app_state
+            .all_templates
+            .get_custom_helpers(&app_state, &split_template)
+            .await?;
         Ok(SplitTemplateRenderer::new(
             split_template,
             app_state,
             component_index,
-            nonce,
+            request_context.content_security_policy.nonce,
+            Arc::clone(&request_context.locale),
+            custom_helpers,
         ))
     }
 
@@ -962,7 +1124,7 @@ impl<W: Write> HtmlRenderContext<W> {
             component,
             Arc::clone(&self.app_state),
             current_component_index + 1,
-            self.request_context.content_security_policy.nonce,
+            &self.request_context,
         )
         .await?;
         Ok(self.current_component.replace(new_component))
@@ -1040,6 +1202,9 @@ pub struct SplitTemplateRenderer {
     row_index: usize,
     component_index: usize,
     nonce: u64,
+    /// Has the helpers and variables that are the same for the whole component.
+    /// It is cloned to render each part of the component.
+    base_render_context: Box<handlebars::RenderContext<'static, 'static>>,
 }
 
 const _: () = assert!(
@@ -1053,6 +1218,8 @@ impl SplitTemplateRenderer {
         app_state: Arc<AppState>,
         component_index: usize,
         nonce: u64,
+        locale: Arc<Locale>,
+        custom_helpers: Vec<NamedCustomHelper>,
     ) -> Self {
         Self {
             split_template,
@@ -1062,8 +1229,15 @@ impl SplitTemplateRenderer {
             ctx: Box::new(handlebars::Context::null()),
             component_index,
             nonce,
+            base_render_context: Box::new(base_render_context(
+                locale,
+                custom_helpers,
+                component_index,
+                nonce,
+            )),
         }
     }
+
     fn name(&self) -> &str {
         self.split_template
             .list_content
@@ -1080,13 +1254,7 @@ impl SplitTemplateRenderer {
                 .map(|n| format!(" ('{n}')"))
                 .unwrap_or_default(),
         );
-        let mut render_context = handlebars::RenderContext::new(None);
-        let blk = render_context
-            .block_mut()
-            .expect("context created without block");
-        blk.set_local_var("component_index", self.component_index.into());
-        blk.set_local_var("csp_nonce", self.nonce.into());
-
+        let mut render_context = (*self.base_render_context).clone();
         *self.ctx.data_mut() = data;
         let mut output = HandlebarWriterOutput(writer);
         self.split_template.before_list.render(
@@ -1107,7 +1275,7 @@ impl SplitTemplateRenderer {
     fn render_item<W: Write>(&mut self, writer: W, data: JsonValue) -> Result<(), RenderError> {
         log::trace!("Rendering a new item in the page: {data:?}");
         if let Some(local_vars) = self.local_vars.take() {
-            let mut render_context = handlebars::RenderContext::new(None);
+            let mut render_context = (*self.base_render_context).clone();
             let blk = render_context
                 .block_mut()
                 .expect("context created without block");
@@ -1145,7 +1313,7 @@ impl SplitTemplateRenderer {
                 .unwrap_or_default(),
         );
         if let Some(mut local_vars) = self.local_vars.take() {
-            let mut render_context = handlebars::RenderContext::new(None);
+            let mut render_context = (*self.base_render_context).clone();
             local_vars.put("row_index", self.row_index.into());
             local_vars.put("component_index", self.component_index.into());
             local_vars.put("csp_nonce", self.nonce.into());
@@ -1168,6 +1336,41 @@ impl SplitTemplateRenderer {
     }
 }
 
+/// Makes the `t` and `format_*` helpers use the locale of the request, makes `column_format`
+/// parse the format of the component once, and registers the custom helpers
+fn base_render_context(
+    locale: Arc<Locale>,
+    custom_helpers: Vec<NamedCustomHelper>,
+    component_index: usize,
+    nonce: u64,
+) -> handlebars::RenderContext<'static, 'static> {
+    let mut render_context = handlebars::RenderContext::new(None);
+    let blk = render_context
+        .block_mut()
+        .expect("context created without block");
+    blk.set_local_var("component_index", component_index.into());
+    blk.set_local_var("csp_nonce", nonce.into());
+    if locale.is_localized() {
+        blk.set_local_var("locale", locale.name().into());
+    }
+    for (name, kind) in FORMAT_HELPERS {
+        render_context
+            .register_local_helper(name, Box::new(FormatHelper(kind, Arc::clone(&locale))));
+    }
+    render_context.register_local_helper("t", Box::new(TranslateHelper(locale)));
+    // The parsed `format` parameter of the component, shared between its rows
+    let column_formats: Arc<OnceLock<JsonValue>> = Arc::default();
+    render_context.register_local_helper(
+        "column_format",
+        Box::new(ColumnFormatHelper(Some(column_formats))),
+    );
+    for helper in custom_helpers {
+        let name = helper.0.clone();
+        render_context.register_local_helper(&name, Box::new(helper));
+    }
+    render_context
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1186,7 +1389,9 @@ mod tests {
         let mut output = Vec::new();
         let config = app_config::tests::test_config();
         let app_state = Arc::new(AppState::init(&config).await.unwrap());
-        let mut rdr = SplitTemplateRenderer::new(Arc::new(split), app_state, 0, 0);
+        let locale = Arc::clone(app_state.translations.default_locale());
+        let mut rdr =
+            SplitTemplateRenderer::new(Arc::new(split), app_state, 0, 0, locale, Vec::new());
         rdr.render_start(&mut output, json!({"name": "SQL"}))?;
         rdr.render_item(&mut output, json!({"x": 1}))?;
         rdr.render_item(&mut output, json!({"x": 2}))?;
@@ -1207,7 +1412,9 @@ mod tests {
         let mut output = Vec::new();
         let config = app_config::tests::test_config();
         let app_state = Arc::new(AppState::init(&config).await.unwrap());
-        let mut rdr = SplitTemplateRenderer::new(Arc::new(split), app_state, 0, 0);
+        let locale = Arc::clone(app_state.translations.default_locale());
+        let mut rdr =
+            SplitTemplateRenderer::new(Arc::new(split), app_state, 0, 0, locale, Vec::new());
         rdr.render_start(&mut output, json!(null))?;
         rdr.render_item(&mut output, json!({"x": 1}))?;
         rdr.render_item(&mut output, json!({"x": 2}))?;
@@ -1227,9 +1434,13 @@ enum HeaderComponent {
     Redirect,
     Json,
     Csv,
+    Xlsx,
+    Parquet,
+    Pdf,
     Cookie,
     Authentication,
     Download,
+    Blob,
     Log,
 }
 
@@ -1242,9 +1453,13 @@ impl TryFrom<&str> for HeaderComponent {
             "redirect" => Ok(Self::Redirect),
             "json" => Ok(Self::Json),
             "csv" => Ok(Self::Csv),
+            "xlsx" => Ok(Self::Xlsx),
+            "parquet" => Ok(Self::Parquet),
+            "pdf" => Ok(Self::Pdf),
             "cookie" => Ok(Self::Cookie),
             "authentication" => Ok(Self::Authentication),
             "download" => Ok(Self::Download),
+            "blob" => Ok(Self::Blob),
             "log" => Ok(Self::Log),
             _ => Err(()),
         }
@@ -0,0 +1,277 @@
+//! Streams rows into an Apache Parquet file.
+//!
+//! Parquet stores data by column, in row groups. Rows are buffered until a row group is full,
+//! then the group is encoded and sent, so memory usage depends on the row group size and not
+//! on the total number of rows. The type of each column is inferred from the values in the first
+//! row group. When a later value does not match, or on any other error, the download is interrupted,
+//! so that clients never receive an incomplete file as if it was complete.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{Context, bail};
+use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedColumnWriter, SerializedFileWriter};
+use parquet::schema::types::Type;
+use serde_json::Value;
+
+use crate::webserver::error::ClientError;
+use crate::webserver::response_writer::{ResponseWriter, SharedBuffer};
+
+pub(super) const DEFAULT_ROW_GROUP_SIZE: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Boolean,
+    Int64,
+    Double,
+    Text,
+}
+
+impl ColumnType {
+    /// The narrowest type that can represent all the given values.
+    fn infer<'a>(values: impl Iterator<Item = &'a Value>) -> Self {
+        let mut inferred = None;
+        for value in values {
+            let value_type = match value {
+                Value::Null => continue,
+                Value::Bool(_) => Self::Boolean,
+                Value::Number(n) if n.is_i64() => Self::Int64,
+                Value::Number(_) => Self::Double,
+                Value::String(_) | Value::Array(_) | Value::Object(_) => Self::Text,
+            };
+            inferred = Some(match (inferred, value_type) {
+                (None, t) => t,
+                (Some(a), b) if a == b => a,
+                (Some(Self::Int64 | Self::Double), Self::Int64 | Self::Double) => Self::Double,
+                _ => Self::Text,
+            });
+        }
+        inferred.unwrap_or(Self::Text)
+    }
+
+    fn schema_field(self, name: &str) -> parquet::errors::Result<Type> {
+        let (physical_type, logical_type) = match self {
+            Self::Boolean => (PhysicalType::BOOLEAN, None),
+            Self::Int64 => (PhysicalType::INT64, None),
+            Self::Double => (PhysicalType::DOUBLE, None),
+            Self::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        };
+        Type::primitive_type_builder(name, physical_type)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical_type)
+            .build()
+    }
+}
+
+pub struct ParquetBodyRenderer {
+    writer: ResponseWriter,
+    buffer: SharedBuffer,
+    file: Option<SerializedFileWriter<SharedBuffer>>,
+    row_group_size: usize,
+    columns: Vec<String>,
+    column_types: Vec<ColumnType>,
+    /// Values of the current row group, column by column.
+    pending: Vec<Vec<Value>>,
+    pending_rows: usize,
+    /// A parquet file cannot contain an error message: after an error, the response is interrupted.
+    failed: bool,
+}
+
+impl ParquetBodyRenderer {
+    pub fn new(writer: ResponseWriter, row_group_size: usize) -> anyhow::Result<Self> {
+        if row_group_size == 0 {
+            bail!("The parquet row_group_size must be a positive number of rows.");
+        }
+        Ok(Self {
+            writer,
+            buffer: SharedBuffer::default(),
+            file: None,
+            row_group_size,
+            columns: Vec::new(),
+            column_types: Vec::new(),
+            pending: Vec::new(),
+            pending_rows: 0,
+            failed: false,
+        })
+    }
+
+    pub fn handle_row(&mut self, data: &Value) -> anyhow::Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+        let Some(obj) = data.as_object() else {
+            return Ok(());
+        };
+        if self.columns.is_empty() {
+            self.columns = obj.keys().cloned().collect();
+            self.pending = vec![Vec::with_capacity(self.row_group_size); self.columns.len()];
+        }
+        for (column, values) in self.columns.iter().zip(&mut self.pending) {
+            values.push(obj.get(column).cloned().unwrap_or(Value::Null));
+        }
+        self.pending_rows += 1;
+        if self.pending_rows >= self.row_group_size {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_error(&mut self, error: &ClientError) {
+        if !self.failed {
+            self.failed = true;
+            let message = format!("Unable to generate the parquet file: {}", error.message());
+            log::error!("{message}");
+            self.writer.abort(message).await;
+        }
+    }
+
+    fn file_writer(&mut self) -> anyhow::Result<&mut SerializedFileWriter<SharedBuffer>> {
+        if self.file.is_none() {
+            self.column_types = self
+                .pending
+                .iter()
+                .map(|values| ColumnType::infer(values.iter()))
+                .collect();
+            let fields = self
+                .columns
+                .iter()
+                .zip(&self.column_types)
+                .map(|(name, column_type)| column_type.schema_field(name).map(Arc::new))
+                .collect::<Result<Vec<_>, _>>()?;
+            let schema = Type::group_type_builder("schema")
+                .with_fields(fields)
+                .build()?;
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .set_created_by(format!("SQLPage {}", env!("CARGO_PKG_VERSION")))
+                .build();
+            self.file = Some(SerializedFileWriter::new(
+                self.buffer.clone(),
+                Arc::new(schema),
+                Arc::new(properties),
+            )?);
+        }
+        Ok(self
+            .file
+            .as_mut()
+            .expect("the file writer was just created"))
+    }
+
+    fn write_row_group(&mut self) -> anyhow::Result<()> {
+        // The first row group determines the column types
+        self.file_writer()?;
+        let pending = std::mem::replace(
+            &mut self.pending,
+            vec![Vec::with_capacity(self.row_group_size); self.columns.len()],
+        );
+        self.pending_rows = 0;
+        let column_types = &self.column_types;
+        let columns = &self.columns;
+        let file = self.file.as_mut().expect("the file writer was created");
+        let mut row_group = file.next_row_group()?;
+        for ((values, column_type), name) in pending.into_iter().zip(column_types).zip(columns) {
+            let mut column = row_group
+                .next_column()?
+                .context("The parquet schema has fewer columns than the rows")?;
+            write_column(&mut column, *column_type, values)
+                .with_context(|| format!("Unable to write the parquet column {name:?}"))?;
+            column.close()?;
+        }
+        row_group.close()?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.write_all(&self.buffer.take())?;
+        self.writer.async_flush().await?;
+        Ok(())
+    }
+
+    pub async fn close(mut self) -> ResponseWriter {
+        if self.failed {
+            return self.writer;
+        }
+        let finished = if self.pending_rows > 0 || self.file.is_none() {
+            self.write_row_group()
+        } else {
+            Ok(())
+        }
+        .and_then(|()| Ok(self.file_writer()?.finish()?));
+        if let Err(e) = finished {
+            let message = format!("Unable to finish the parquet file: {e:#}");
+            log::error!("{message}");
+            self.writer.abort(message).await;
+            return self.writer;
+        }
+        let _ = self.writer.write_all(&self.buffer.take());
+        self.writer
+    }
+}
+
+fn write_column(
+    column: &mut SerializedColumnWriter<'_>,
+    column_type: ColumnType,
+    values: Vec<Value>,
+) -> anyhow::Result<()> {
+    let definition_levels: Vec<i16> = values.iter().map(|v| i16::from(!v.is_null())).collect();
+    let present = values.into_iter().filter(|v| !v.is_null());
+    let mismatch = |value: &Value| {
+        anyhow::anyhow!(
+            "The value {value} does not match the column type {column_type:?}, inferred from the first rows. \
+            Cast the column in SQL so that all its values have the same type."
+        )
+    };
+    match column_type {
+        ColumnType::Boolean => {
+            let values = present
+                .map(|v| v.as_bool().ok_or_else(|| mismatch(&v)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            column
+                .typed::<BoolType>()
+                .write_batch(&values, Some(&definition_levels), None)?;
+        }
+        ColumnType::Int64 => {
+            let values = present
+                .map(|v| v.as_i64().ok_or_else(|| mismatch(&v)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            column
+                .typed::<Int64Type>()
+                .write_batch(&values, Some(&definition_levels), None)?;
+        }
+        ColumnType::Double => {
+            let values = present
+                .map(|v| v.as_f64().ok_or_else(|| mismatch(&v)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            column
+                .typed::<DoubleType>()
+                .write_batch(&values, Some(&definition_levels), None)?;
+        }
+        ColumnType::Text => {
+            let values = present
+                .map(|v| match v {
+                    Value::String(s) => ByteArray::from(s.into_bytes()),
+                    other => ByteArray::from(other.to_string().into_bytes()),
+                })
+                .collect::<Vec<_>>();
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&values, Some(&definition_levels), None)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_infer_column_type() {
+    use serde_json::json;
+    let infer = |values: Value| ColumnType::infer(values.as_array().unwrap().iter());
+    assert_eq!(infer(json!([1, null, 2])), ColumnType::Int64);
+    assert_eq!(infer(json!([1, 2.5])), ColumnType::Double);
+    assert_eq!(infer(json!([true, false])), ColumnType::Boolean);
+    assert_eq!(infer(json!([1, "a"])), ColumnType::Text);
+    assert_eq!(infer(json!([null])), ColumnType::Text);
+}
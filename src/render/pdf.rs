@@ -0,0 +1,948 @@
+//! Lays out pages as PDF documents, without a browser.
+//!
+//! The `text`, `title`, `table`, `list`, `card` and `big_number` components are converted directly
+//! into PDF drawing operations, using the Helvetica fonts that every PDF reader provides.
+//! Other components are skipped. Each page is written as soon as it is full, so that long tables
+//! are streamed to the client instead of being kept in memory.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use anyhow::bail;
+use serde_json::{Map, Value};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::webserver::error::ClientError;
+use crate::webserver::response_writer::{ResponseWriter, SharedBuffer};
+
+const MARGIN: f32 = 50.0;
+const FONT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 1.3;
+const CELL_PADDING: f32 = 4.0;
+const FOOTER_SIZE: f32 = 8.0;
+
+const BLACK: [f32; 3] = [0.0, 0.0, 0.0];
+const GRAY: [f32; 3] = [0.4, 0.4, 0.4];
+const RED: [f32; 3] = [0.8, 0.0, 0.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageSize {
+    width: f32,
+    height: f32,
+}
+
+impl PageSize {
+    pub const A4: Self = Self {
+        width: 595.0,
+        height: 842.0,
+    };
+
+    /// Reads the `page_size` (A3, A4, A5, letter or legal) and `orientation` options.
+    pub fn from_options(options: &Value) -> anyhow::Result<Self> {
+        let size = match options.get("page_size").and_then(Value::as_str) {
+            None => Self::A4,
+            Some(name) => match name.to_ascii_lowercase().as_str() {
+                "a3" => Self::new(842.0, 1191.0),
+                "a4" => Self::A4,
+                "a5" => Self::new(420.0, 595.0),
+                "letter" => Self::new(612.0, 792.0),
+                "legal" => Self::new(612.0, 1008.0),
+                _ => {
+                    bail!("Invalid page_size: {name:?}. Supported sizes: A3, A4, A5, letter, legal")
+                }
+            },
+        };
+        match options.get("orientation").and_then(Value::as_str) {
+            None | Some("portrait") => Ok(size),
+            Some("landscape") => Ok(Self::new(size.height, size.width)),
+            Some(other) => {
+                bail!("Invalid orientation: {other:?}. Expected 'portrait' or 'landscape'")
+            }
+        }
+    }
+
+    const fn new(width: f32, height: f32) -> Self {
+        Self { width, height }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+#[rustfmt::skip]
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[rustfmt::skip]
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+impl Font {
+    fn resource_name(self) -> &'static str {
+        match self {
+            Self::Regular => "F1",
+            Self::Bold => "F2",
+        }
+    }
+
+    /// Width of a character, in thousandths of the font size.
+    fn char_width(self, c: char) -> u16 {
+        let widths = match self {
+            Self::Regular => &HELVETICA_WIDTHS,
+            Self::Bold => &HELVETICA_BOLD_WIDTHS,
+        };
+        let ascii_width = |c: char| widths.get((c as usize).wrapping_sub(32)).copied();
+        match c {
+            '—' | '…' | '‰' => 1000,
+            '•' => 350,
+            '‘' | '’' | '‚' => 278,
+            '“' | '”' | '„' => 500,
+            // Accented letters are as wide as their base letter
+            _ => ascii_width(c)
+                .or_else(|| c.nfd().next().and_then(ascii_width))
+                .unwrap_or(556),
+        }
+    }
+
+    fn text_width(self, text: &str, size: f32) -> f32 {
+        let thousandths: f32 = text.chars().map(|c| f32::from(self.char_width(c))).sum();
+        thousandths * size / 1000.0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TextStyle {
+    font: Font,
+    size: f32,
+    color: [f32; 3],
+}
+
+impl TextStyle {
+    const BODY: Self = Self::new(Font::Regular, FONT_SIZE, BLACK);
+    const BOLD: Self = Self::new(Font::Bold, FONT_SIZE, BLACK);
+    const MUTED: Self = Self::new(Font::Regular, FONT_SIZE, GRAY);
+    const ERROR: Self = Self::new(Font::Regular, FONT_SIZE, RED);
+
+    const fn new(font: Font, size: f32, color: [f32; 3]) -> Self {
+        Self { font, size, color }
+    }
+
+    const fn heading(size: f32) -> Self {
+        Self::new(Font::Bold, size, BLACK)
+    }
+
+    fn line_height(self) -> f32 {
+        self.size * LINE_HEIGHT
+    }
+}
+
+/// Maps a character to its code in the `WinAnsiEncoding` of the standard fonts.
+fn win_ansi_byte(c: char) -> Option<u8> {
+    Some(match c {
+        '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => u8::try_from(u32::from(c)).ok()?,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '™' => 0x99,
+        'š' => 0x9a,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        '\t' => b' ',
+        _ => return None,
+    })
+}
+
+/// Appends `text` as a PDF string literal.
+/// Characters that the standard fonts cannot show are replaced with `?`, and the first one is returned.
+fn push_pdf_string(out: &mut Vec<u8>, text: &str) -> Option<char> {
+    let mut unsupported = None;
+    out.push(b'(');
+    for c in text.chars() {
+        let byte = win_ansi_byte(c).unwrap_or_else(|| {
+            unsupported.get_or_insert(c);
+            b'?'
+        });
+        match byte {
+            b'\\' | b'(' | b')' => out.extend_from_slice(&[b'\\', byte]),
+            0x80.. => {
+                write!(out, "\\{byte:03o}").expect("writing to a Vec cannot fail");
+            }
+            _ => out.push(byte),
+        }
+    }
+    out.push(b')');
+    unsupported
+}
+
+/// Splits `text` into lines that fit in `width`. Words longer than a line are cut.
+fn wrap(text: &str, style: TextStyle, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{line} {word}")
+            };
+            if style.font.text_width(&candidate, style.size) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                line.push(c);
+                if style.font.text_width(&line, style.size) > width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// The number of lines of a table row: the number of lines of its tallest cell.
+fn line_count(cells: &[Vec<String>]) -> usize {
+    cells.iter().map(Vec::len).max().unwrap_or(1)
+}
+
+fn row_height(line_count: usize, style: TextStyle) -> f32 {
+    count_f32(line_count) * style.line_height() + 2.0 * CELL_PADDING
+}
+
+fn count_f32(count: usize) -> f32 {
+    f32::from(u16::try_from(count).unwrap_or(u16::MAX))
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn text_property(row: &Map<String, Value>, key: &str) -> Option<String> {
+    row.get(key)
+        .filter(|value| !value.is_null())
+        .map(value_text)
+}
+
+/// Writes the objects of the PDF file as they are produced, and the cross-reference table at the end.
+struct PdfWriter<W: Write> {
+    out: W,
+    position: usize,
+    /// Byte offset of each object, by object number minus one
+    offsets: Vec<usize>,
+    page_ids: Vec<usize>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    const CATALOG_ID: usize = 1;
+    const PAGES_ID: usize = 2;
+
+    fn new(out: W) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            position: 0,
+            // The catalog and the page tree are written last, but their numbers are reserved
+            offsets: vec![0, 0],
+            page_ids: Vec::new(),
+        };
+        writer.write_raw(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        for font in ["Helvetica", "Helvetica-Bold"] {
+            let font = format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{font} /Encoding /WinAnsiEncoding >>"
+            );
+            writer.add_object(font.as_bytes())?;
+        }
+        Ok(writer)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+
+    fn write_object(&mut self, id: usize, body: &[u8]) -> io::Result<()> {
+        self.offsets[id - 1] = self.position;
+        self.write_raw(format!("{id} 0 obj\n").as_bytes())?;
+        self.write_raw(body)?;
+        self.write_raw(b"\nendobj\n")
+    }
+
+    fn add_object(&mut self, body: &[u8]) -> io::Result<usize> {
+        self.offsets.push(0);
+        let id = self.offsets.len();
+        self.write_object(id, body)?;
+        Ok(id)
+    }
+
+    fn add_page(&mut self, size: PageSize, content: &[u8]) -> io::Result<()> {
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(content);
+        stream.extend_from_slice(b"\nendstream");
+        let content_id = self.add_object(&stream)?;
+        let page = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+            /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {content_id} 0 R >>",
+            Self::PAGES_ID,
+            size.width,
+            size.height
+        );
+        let page_id = self.add_object(page.as_bytes())?;
+        self.page_ids.push(page_id);
+        Ok(())
+    }
+
+    fn finish(mut self, title: Option<&str>) -> io::Result<W> {
+        let mut kids = String::new();
+        for id in &self.page_ids {
+            write!(kids, "{id} 0 R ").expect("writing to a String cannot fail");
+        }
+        let pages = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.trim_end(),
+            self.page_ids.len()
+        );
+        self.write_object(Self::PAGES_ID, pages.as_bytes())?;
+        let catalog = format!("<< /Type /Catalog /Pages {} 0 R >>", Self::PAGES_ID);
+        self.write_object(Self::CATALOG_ID, catalog.as_bytes())?;
+        let mut info = b"<< /Producer ".to_vec();
+        push_pdf_string(&mut info, "SQLPage");
+        if let Some(title) = title {
+            info.extend_from_slice(b" /Title ");
+            if let Some(c) = push_pdf_string(&mut info, title) {
+                log::warn!("The character {c:?} of the PDF title cannot be shown in PDF documents");
+            }
+        }
+        info.extend_from_slice(b" >>");
+        let info_id = self.add_object(&info)?;
+
+        let xref_position = self.position;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            writeln!(xref, "{offset:010} 00000 n ").expect("writing to a String cannot fail");
+        }
+        write!(
+            xref,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {info_id} 0 R >>\nstartxref\n{xref_position}\n%%EOF\n",
+            self.offsets.len() + 1,
+            Self::CATALOG_ID
+        )
+        .expect("writing to a String cannot fail");
+        self.write_raw(xref.as_bytes())?;
+        Ok(self.out)
+    }
+}
+
+/// The component whose rows are being laid out.
+enum Component {
+    Text {
+        paragraph: String,
+    },
+    Table {
+        columns: Vec<String>,
+    },
+    List,
+    Card,
+    BigNumber,
+    /// Components that have no rows, or that cannot be printed
+    Ignored,
+}
+
+/// Lays out rows of components on pages, and writes each full page to `W`.
+pub(crate) struct PdfDocument<W: Write> {
+    writer: PdfWriter<W>,
+    page_size: PageSize,
+    content: Vec<u8>,
+    page_number: usize,
+    /// Vertical position of the top of the next line, from the bottom of the page
+    y: f32,
+    title: Option<String>,
+    component: Component,
+    /// Whether a warning was already logged about characters the fonts cannot show
+    warned_unsupported_characters: bool,
+}
+
+impl<W: Write> PdfDocument<W> {
+    pub(crate) fn new(out: W, page_size: PageSize) -> anyhow::Result<Self> {
+        if page_size.width <= 3.0 * MARGIN || page_size.height <= 3.0 * MARGIN {
+            bail!("The PDF page size is too small");
+        }
+        Ok(Self {
+            writer: PdfWriter::new(out)?,
+            page_size,
+            content: Vec::new(),
+            page_number: 1,
+            y: page_size.height - MARGIN,
+            title: None,
+            // Like in HTML pages, rows without a component are displayed in a table
+            component: Component::Table {
+                columns: Vec::new(),
+            },
+            warned_unsupported_characters: false,
+        })
+    }
+
+    /// Sets the title shown by PDF readers. A `shell` component can also set it.
+    pub(crate) fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+    }
+
+    pub(crate) fn handle_row(&mut self, data: &Value) -> anyhow::Result<()> {
+        let Some(row) = data.as_object() else {
+            return Ok(());
+        };
+        if let Some(name) = row.get("component").and_then(Value::as_str) {
+            self.end_component()?;
+            self.start_component(name, row)?;
+        } else {
+            self.handle_item(row)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_error(&mut self, message: &str) -> anyhow::Result<()> {
+        self.end_component()?;
+        self.paragraph(message, TextStyle::ERROR, 0.0)?;
+        self.gap(FONT_SIZE);
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> anyhow::Result<W> {
+        self.end_component()?;
+        self.finish_page()?;
+        Ok(self.writer.finish(self.title.as_deref())?)
+    }
+
+    fn start_component(&mut self, name: &str, row: &Map<String, Value>) -> io::Result<()> {
+        self.component = match name {
+            "shell" | "shell-empty" | "pdf" => {
+                if let Some(title) = text_property(row, "title") {
+                    self.title = Some(title);
+                }
+                Component::Ignored
+            }
+            "title" => {
+                let size = match row.get("level").and_then(Value::as_u64) {
+                    None | Some(0 | 1) => 20.0,
+                    Some(2) => 18.0,
+                    Some(3) => 16.0,
+                    Some(_) => 14.0,
+                };
+                if let Some(contents) = text_property(row, "contents") {
+                    self.heading(&contents, size)?;
+                }
+                Component::Ignored
+            }
+            "text" => {
+                if let Some(title) = text_property(row, "title") {
+                    self.heading(&title, 16.0)?;
+                }
+                let contents = text_property(row, "contents")
+                    .or_else(|| text_property(row, "contents_md"))
+                    .unwrap_or_default();
+                Component::Text {
+                    paragraph: contents,
+                }
+            }
+            "table" => Component::Table {
+                columns: Vec::new(),
+            },
+            "list" | "card" | "big_number" => {
+                if let Some(title) = text_property(row, "title") {
+                    self.heading(&title, 14.0)?;
+                }
+                match name {
+                    "list" => Component::List,
+                    "card" => Component::Card,
+                    _ => Component::BigNumber,
+                }
+            }
+            _ => {
+                log::debug!("The {name} component is not displayed in PDF documents");
+                Component::Ignored
+            }
+        };
+        Ok(())
+    }
+
+    fn handle_item(&mut self, row: &Map<String, Value>) -> io::Result<()> {
+        match &mut self.component {
+            Component::Text { paragraph } => {
+                if let Some(contents) =
+                    text_property(row, "contents").or_else(|| text_property(row, "contents_md"))
+                {
+                    paragraph.push_str(&contents);
+                }
+                if row.get("break").is_some_and(|b| b == &Value::Bool(true)) {
+                    paragraph.push('\n');
+                }
+                Ok(())
+            }
+            Component::Table { columns } => {
+                let is_first_row = columns.is_empty();
+                if is_first_row {
+                    *columns = row
+                        .keys()
+                        .filter(|key| !key.starts_with("_sqlpage"))
+                        .cloned()
+                        .collect();
+                }
+                let columns = columns.clone();
+                let cells: Vec<String> = columns
+                    .iter()
+                    .map(|column| row.get(column).map(value_text).unwrap_or_default())
+                    .collect();
+                self.table_row(&columns, &cells, is_first_row)
+            }
+            Component::List => self.list_item(row),
+            Component::Card => self.card(row),
+            Component::BigNumber => self.big_number(row),
+            Component::Ignored => Ok(()),
+        }
+    }
+
+    fn end_component(&mut self) -> io::Result<()> {
+        match std::mem::replace(&mut self.component, Component::Ignored) {
+            Component::Text { paragraph } => {
+                if !paragraph.is_empty() {
+                    self.paragraph(&paragraph, TextStyle::BODY, 0.0)?;
+                    self.gap(FONT_SIZE);
+                }
+            }
+            Component::Table { columns } if !columns.is_empty() => self.gap(FONT_SIZE),
+            Component::List | Component::BigNumber => self.gap(FONT_SIZE),
+            Component::Table { .. } | Component::Card | Component::Ignored => {}
+        }
+        Ok(())
+    }
+
+    fn content_width(&self) -> f32 {
+        self.page_size.width - 2.0 * MARGIN
+    }
+
+    fn page_top(&self) -> f32 {
+        self.page_size.height - MARGIN
+    }
+
+    /// Starts a new page if `height` does not fit on the current one. Returns whether it did.
+    fn ensure_space(&mut self, height: f32) -> io::Result<bool> {
+        let page_is_empty = self.y >= self.page_top();
+        if self.y - height < MARGIN && !page_is_empty {
+            self.new_page()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn new_page(&mut self) -> io::Result<()> {
+        self.finish_page()?;
+        self.page_number += 1;
+        self.y = self.page_top();
+        Ok(())
+    }
+
+    fn finish_page(&mut self) -> io::Result<()> {
+        let footer = format!("{}", self.page_number);
+        let style = TextStyle::new(Font::Regular, FOOTER_SIZE, GRAY);
+        let x = (self.page_size.width - style.font.text_width(&footer, style.size)) / 2.0;
+        self.draw_text(x, MARGIN / 2.0, style, &footer);
+        let content = std::mem::take(&mut self.content);
+        self.writer.add_page(self.page_size, &content)
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y = (self.y - height).max(MARGIN);
+    }
+
+    fn draw_text(&mut self, x: f32, baseline: f32, style: TextStyle, text: &str) {
+        let [r, g, b] = style.color;
+        write!(
+            self.content,
+            "BT {r:.2} {g:.2} {b:.2} rg /{} {:.1} Tf {x:.2} {baseline:.2} Td ",
+            style.font.resource_name(),
+            style.size
+        )
+        .expect("writing to a Vec cannot fail");
+        let unsupported = push_pdf_string(&mut self.content, text);
+        self.content.extend_from_slice(b" Tj ET\n");
+        if let Some(c) = unsupported.filter(|_| !self.warned_unsupported_characters) {
+            self.warned_unsupported_characters = true;
+            log::warn!(
+                "The PDF fonts cannot show the character {c:?}. It is replaced with '?'. \
+                 Only the characters of the Windows-1252 encoding are supported."
+            );
+        }
+    }
+
+    /// Draws a rectangle, filled with the given gray level or stroked when `fill` is `None`.
+    fn rectangle(&mut self, x: f32, y: f32, width: f32, height: f32, fill: Option<f32>) {
+        let operation = match fill {
+            Some(level) => format!("{level:.2} g {x:.2} {y:.2} {width:.2} {height:.2} re f\n"),
+            None => format!("0.8 G 0.5 w {x:.2} {y:.2} {width:.2} {height:.2} re S\n"),
+        };
+        self.content.extend_from_slice(operation.as_bytes());
+    }
+
+    fn horizontal_line(&mut self, y: f32) {
+        let (start, end) = (MARGIN, self.page_size.width - MARGIN);
+        writeln!(
+            self.content,
+            "0.8 G 0.5 w {start:.2} {y:.2} m {end:.2} {y:.2} l S"
+        )
+        .expect("writing to a Vec cannot fail");
+    }
+
+    /// Draws wrapped text at the current position, continuing on new pages when needed.
+    fn paragraph(&mut self, text: &str, style: TextStyle, indent: f32) -> io::Result<()> {
+        for line in wrap(text, style, self.content_width() - indent) {
+            self.ensure_space(style.line_height())?;
+            self.draw_text(MARGIN + indent, self.y - style.size, style, &line);
+            self.y -= style.line_height();
+        }
+        Ok(())
+    }
+
+    fn heading(&mut self, text: &str, size: f32) -> io::Result<()> {
+        let style = TextStyle::heading(size);
+        // Keep the heading on the same page as the first line that follows it
+        self.ensure_space(style.line_height() + 2.0 * FONT_SIZE * LINE_HEIGHT)?;
+        self.paragraph(text, style, 0.0)?;
+        self.gap(size / 2.0);
+        Ok(())
+    }
+
+    /// Draws a table row, preceded by the header at the top of each page.
+    /// Rows taller than a page are split across pages.
+    fn table_row(
+        &mut self,
+        columns: &[String],
+        cells: &[String],
+        with_header: bool,
+    ) -> io::Result<()> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let header = self.wrap_cells(columns, TextStyle::BOLD);
+        let header_height = row_height(line_count(&header), TextStyle::BOLD);
+        let cells = self.wrap_cells(cells, TextStyle::BODY);
+        let total_lines = line_count(&cells);
+        let mut draw_header = with_header;
+        let mut start = 0;
+        loop {
+            let remaining = total_lines - start;
+            let header_space = |draw_header| if draw_header { header_height } else { 0.0 };
+            let height = header_space(draw_header) + row_height(remaining, TextStyle::BODY);
+            // The header is repeated at the top of each page
+            draw_header |= self.ensure_space(height)?;
+            let available = self.y - MARGIN - header_space(draw_header);
+            let mut count = remaining.min(1);
+            while count < remaining && row_height(count + 1, TextStyle::BODY) <= available {
+                count += 1;
+            }
+            if draw_header {
+                let header_lines = 0..line_count(&header);
+                self.draw_table_row(&header, header_lines, TextStyle::BOLD, Some(0.9));
+            }
+            self.draw_table_row(&cells, start..start + count, TextStyle::BODY, None);
+            start += count;
+            if start >= total_lines {
+                return Ok(());
+            }
+            self.new_page()?;
+            draw_header = true;
+        }
+    }
+
+    fn column_width(&self, column_count: usize) -> f32 {
+        self.content_width() / count_f32(column_count)
+    }
+
+    fn wrap_cells(&self, cells: &[String], style: TextStyle) -> Vec<Vec<String>> {
+        let inner_width = self.column_width(cells.len()) - 2.0 * CELL_PADDING;
+        cells
+            .iter()
+            .map(|cell| wrap(cell, style, inner_width))
+            .collect()
+    }
+
+    /// Draws the given range of the wrapped lines of each cell.
+    fn draw_table_row(
+        &mut self,
+        cells: &[Vec<String>],
+        lines: Range<usize>,
+        style: TextStyle,
+        fill: Option<f32>,
+    ) {
+        let height = row_height(lines.len(), style);
+        let column_width = self.column_width(cells.len());
+        if let Some(level) = fill {
+            let width = self.content_width();
+            self.rectangle(MARGIN, self.y - height, width, height, Some(level));
+        }
+        for (index, cell) in cells.iter().enumerate() {
+            let x = MARGIN + count_f32(index) * column_width + CELL_PADDING;
+            let mut baseline = self.y - CELL_PADDING - style.size;
+            for line in cell.iter().skip(lines.start).take(lines.len()) {
+                self.draw_text(x, baseline, style, line);
+                baseline -= style.line_height();
+            }
+        }
+        self.y -= height;
+        self.horizontal_line(self.y);
+    }
+
+    fn list_item(&mut self, row: &Map<String, Value>) -> io::Result<()> {
+        let indent = 12.0;
+        if let Some(title) = text_property(row, "title") {
+            self.ensure_space(2.0 * TextStyle::BOLD.line_height())?;
+            self.draw_text(MARGIN, self.y - FONT_SIZE, TextStyle::BODY, "•");
+            self.paragraph(&title, TextStyle::BOLD, indent)?;
+        }
+        if let Some(description) =
+            text_property(row, "description").or_else(|| text_property(row, "description_md"))
+        {
+            self.paragraph(&description, TextStyle::MUTED, indent)?;
+        }
+        self.gap(FONT_SIZE / 2.0);
+        Ok(())
+    }
+
+    fn card(&mut self, row: &Map<String, Value>) -> io::Result<()> {
+        let inner_width = self.content_width() - 4.0 * CELL_PADDING;
+        let mut lines = Vec::new();
+        let parts = [
+            ("title", TextStyle::heading(12.0)),
+            ("description", TextStyle::BODY),
+            ("description_md", TextStyle::BODY),
+            ("footer", TextStyle::new(Font::Regular, FOOTER_SIZE, GRAY)),
+            (
+                "footer_md",
+                TextStyle::new(Font::Regular, FOOTER_SIZE, GRAY),
+            ),
+        ];
+        for (key, style) in parts {
+            if let Some(text) = text_property(row, key) {
+                lines.extend(
+                    wrap(&text, style, inner_width)
+                        .into_iter()
+                        .map(|l| (l, style)),
+                );
+            }
+        }
+        let height = lines
+            .iter()
+            .map(|(_, style)| style.line_height())
+            .sum::<f32>()
+            + 4.0 * CELL_PADDING;
+        self.ensure_space(height)?;
+        let width = self.content_width();
+        self.rectangle(MARGIN, self.y - height, width, height, None);
+        let mut top = self.y - 2.0 * CELL_PADDING;
+        for (line, style) in lines {
+            self.draw_text(MARGIN + 2.0 * CELL_PADDING, top - style.size, style, &line);
+            top -= style.line_height();
+        }
+        self.y -= height;
+        self.gap(FONT_SIZE);
+        Ok(())
+    }
+
+    fn big_number(&mut self, row: &Map<String, Value>) -> io::Result<()> {
+        let value_style = TextStyle::heading(24.0);
+        self.ensure_space(value_style.line_height() + 2.0 * FONT_SIZE * LINE_HEIGHT)?;
+        if let Some(title) = text_property(row, "title") {
+            self.paragraph(&title, TextStyle::MUTED, 0.0)?;
+        }
+        let mut value = text_property(row, "value").unwrap_or_default();
+        if let Some(unit) = text_property(row, "unit") {
+            write!(value, " {unit}").expect("writing to a String cannot fail");
+        }
+        self.paragraph(&value, value_style, 0.0)?;
+        if let Some(description) = text_property(row, "description") {
+            self.paragraph(&description, TextStyle::BODY, 0.0)?;
+        }
+        self.gap(FONT_SIZE);
+        Ok(())
+    }
+}
+
+/// Streams a PDF document to the client, one page at a time.
+pub struct PdfBodyRenderer {
+    writer: ResponseWriter,
+    buffer: SharedBuffer,
+    document: PdfDocument<SharedBuffer>,
+}
+
+impl PdfBodyRenderer {
+    pub fn new(writer: ResponseWriter, page_size: PageSize) -> anyhow::Result<Self> {
+        let buffer = SharedBuffer::default();
+        Ok(Self {
+            writer,
+            document: PdfDocument::new(buffer.clone(), page_size)?,
+            buffer,
+        })
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.document.set_title(title);
+    }
+
+    pub fn handle_row(&mut self, data: &Value) -> anyhow::Result<()> {
+        self.document.handle_row(data)
+    }
+
+    pub fn handle_error(&mut self, error: &ClientError) -> anyhow::Result<()> {
+        self.document.handle_error(error.message())
+    }
+
+    pub async fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.write_all(&self.buffer.take())?;
+        self.writer.async_flush().await?;
+        Ok(())
+    }
+
+    pub fn close(mut self) -> ResponseWriter {
+        if let Err(e) = self.document.finish() {
+            log::error!("Unable to finish the PDF document: {e:#}");
+        }
+        let _ = self.writer.write_all(&self.buffer.take());
+        self.writer
+    }
+}
+
+#[test]
+fn test_wrap() {
+    let lines = wrap("hello world, this is a test", TextStyle::BODY, 60.0);
+    assert_eq!(lines, ["hello world,", "this is a test"]);
+    assert!(
+        wrap("abcdefghijklmnopqrstuvwxyz", TextStyle::BODY, 30.0)
+            .iter()
+            .all(|line| TextStyle::BODY.font.text_width(line, FONT_SIZE) <= 30.0)
+    );
+    assert_eq!(wrap("a\n\nb", TextStyle::BODY, 100.0), ["a", "", "b"]);
+}
+
+#[test]
+fn test_pdf_string() {
+    let mut out = Vec::new();
+    let unsupported = push_pdf_string(&mut out, "(a\\b) é€ 日");
+    assert_eq!(out, b"(\\(a\\\\b\\) \\351\\200 ?)");
+    assert_eq!(unsupported, Some('日'));
+    assert_eq!(push_pdf_string(&mut out, "é€"), None);
+}
+
+#[test]
+fn test_table_row_taller_than_a_page() {
+    let mut document = PdfDocument::new(Vec::new(), PageSize::A4).unwrap();
+    document
+        .handle_row(&serde_json::json!({"component": "table"}))
+        .unwrap();
+    let long_text = (0..200)
+        .map(|i| format!("line {i}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    document
+        .handle_row(&serde_json::json!({"id": 1, "text": long_text}))
+        .unwrap();
+    let pdf = String::from_utf8_lossy(&document.finish().unwrap()).into_owned();
+    let page_count = pdf.matches("/Type /Page ").count();
+    assert!(page_count > 1, "expected several pages, got {page_count}");
+    assert_eq!(pdf.matches("(text) Tj").count(), page_count);
+    assert!(pdf.contains("(line 199) Tj"));
+    // The lines of the cell stay above the bottom margin
+    for line in pdf.lines().filter(|line| line.contains("(line ")) {
+        let operators = line.split(" Td ").next().unwrap();
+        let baseline: f32 = operators.rsplit(' ').next().unwrap().parse().unwrap();
+        assert!(baseline >= MARGIN, "{line}");
+    }
+    for line in pdf.lines().filter(|line| line.ends_with(" re f")) {
+        let y: f32 = line.split(' ').nth(3).unwrap().parse().unwrap();
+        assert!(y >= MARGIN, "{line}");
+    }
+}
+
+#[test]
+fn test_pdf_document_structure() {
+    let mut document = PdfDocument::new(Vec::new(), PageSize::A4).unwrap();
+    document
+        .handle_row(&serde_json::json!({"component": "shell", "title": "Report"}))
+        .unwrap();
+    document
+        .handle_row(&serde_json::json!({"component": "table"}))
+        .unwrap();
+    for i in 0..200 {
+        document
+            .handle_row(&serde_json::json!({"id": i, "name": format!("item {i}")}))
+            .unwrap();
+    }
+    let bytes = document.finish().unwrap();
+    let pdf = String::from_utf8_lossy(&bytes);
+    assert!(pdf.starts_with("%PDF-1.4"));
+    assert!(pdf.ends_with("%%EOF\n"));
+    assert!(pdf.contains("(item 199) Tj"));
+    assert!(pdf.contains("/Title (Report)"));
+    let page_count = pdf.matches("/Type /Page ").count();
+    assert!(page_count > 1, "expected several pages, got {page_count}");
+    // The header is repeated on every page
+    assert_eq!(pdf.matches("(name) Tj").count(), page_count);
+    let startxref: usize = pdf
+        .rsplit("startxref\n")
+        .next()
+        .unwrap()
+        .lines()
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(bytes[startxref..].starts_with(b"xref"));
+    // Every entry of the cross-reference table points to its object
+    let xref = &pdf[pdf.rfind("\nxref\n").unwrap()..pdf.rfind("trailer").unwrap()];
+    for (id, entry) in xref.lines().skip(4).enumerate() {
+        let offset: usize = entry[..10].parse().unwrap();
+        let object_header = format!("{} 0 obj", id + 1);
+        assert!(bytes[offset..].starts_with(object_header.as_bytes()));
+    }
+}
@@ -0,0 +1,375 @@
+//! Streams rows into an Excel (`.xlsx`) workbook with a single sheet.
+//!
+//! An xlsx file is a zip archive of XML documents. The sheet is written row by row into a
+//! deflated zip entry, and the archive is streamed without ever being held in memory.
+//! Cells use inline strings, so no shared string table has to be built.
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::{Context, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use zip::ZipWriter;
+use zip::write::{SimpleFileOptions, StreamWriter};
+
+use crate::webserver::error::ClientError;
+use crate::webserver::response_writer::{ResponseWriter, SharedBuffer};
+
+const MAX_SHEET_NAME_LENGTH: usize = 31;
+/// Limits of the xlsx format, as documented by Microsoft
+const MAX_ROWS: u32 = 1_048_576;
+const MAX_COLUMNS: usize = 16_384;
+const MAX_CELL_LENGTH: usize = 32_767;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const ROOT_RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK_RELATIONSHIPS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+/// Cell formats: 0 is the default, 1 is the bold header, 2 is a date, 3 is a date and time.
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy\-mm\-dd"/><numFmt numFmtId="165" formatCode="yyyy\-mm\-dd\ hh:mm:ss"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#;
+
+const SHEET_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><sheetData>"#;
+
+const SHEET_END: &str = "</sheetData></worksheet>";
+
+const HEADER_STYLE: u8 = 1;
+const DATE_STYLE: u8 = 2;
+const DATE_TIME_STYLE: u8 = 3;
+
+pub struct XlsxBodyRenderer {
+    writer: ResponseWriter,
+    buffer: SharedBuffer,
+    zip: ZipWriter<StreamWriter<SharedBuffer>>,
+    columns: Vec<String>,
+    /// Columns that have a date or timestamp type in the database
+    date_columns: Vec<String>,
+    row_count: u32,
+    truncated_cells: bool,
+    /// Reused between rows to build the XML of one row.
+    row_xml: String,
+}
+
+impl XlsxBodyRenderer {
+    pub fn new(writer: ResponseWriter, sheet_name: &str) -> anyhow::Result<Self> {
+        validate_sheet_name(sheet_name)?;
+        let buffer = SharedBuffer::default();
+        let mut zip = ZipWriter::new_stream(buffer.clone());
+        let options = SimpleFileOptions::default();
+        let workbook = format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            xml_escape(sheet_name)
+        );
+        for (path, contents) in [
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("_rels/.rels", ROOT_RELATIONSHIPS),
+            ("xl/workbook.xml", workbook.as_str()),
+            ("xl/_rels/workbook.xml.rels", WORKBOOK_RELATIONSHIPS),
+            ("xl/styles.xml", STYLES),
+        ] {
+            zip.start_file(path, options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        // The sheet can grow past the 4GB limit of zip files without the zip64 extension
+        zip.start_file("xl/worksheets/sheet1.xml", options.large_file(true))?;
+        zip.write_all(SHEET_START.as_bytes())?;
+        Ok(Self {
+            writer,
+            buffer,
+            zip,
+            columns: Vec::new(),
+            date_columns: Vec::new(),
+            row_count: 0,
+            truncated_cells: false,
+            row_xml: String::new(),
+        })
+    }
+
+    pub fn set_date_columns(&mut self, columns: Vec<String>) {
+        self.date_columns = columns;
+    }
+
+    pub fn handle_row(&mut self, data: &Value) -> anyhow::Result<()> {
+        let Some(obj) = data.as_object() else {
+            return Ok(());
+        };
+        if self.columns.is_empty() {
+            if obj.len() > MAX_COLUMNS {
+                bail!(
+                    "The xlsx file cannot have {} columns: Excel supports at most {MAX_COLUMNS} columns.",
+                    obj.len()
+                );
+            }
+            let header = obj.keys().map(|name| Cell::Text(name)).collect();
+            self.write_row(header, HEADER_STYLE)?;
+            self.columns = obj.keys().cloned().collect();
+        }
+        let cells = self
+            .columns
+            .iter()
+            .map(|column| {
+                let is_date = self.date_columns.contains(column);
+                obj.get(column)
+                    .map_or(Cell::Empty, |value| Cell::from_json(value, is_date))
+            })
+            .collect();
+        self.write_row(cells, 0)
+    }
+
+    pub fn handle_error(&mut self, error: &ClientError) -> anyhow::Result<()> {
+        self.write_row(vec![Cell::Text(error.message())], 0)
+    }
+
+    fn write_row(&mut self, cells: Vec<Cell<'_>>, style: u8) -> anyhow::Result<()> {
+        if self.row_count >= MAX_ROWS {
+            bail!(
+                "The xlsx file cannot have more than {MAX_ROWS} rows, the maximum supported by Excel."
+            );
+        }
+        self.row_count += 1;
+        let row = self.row_count;
+        let xml = &mut self.row_xml;
+        xml.clear();
+        let _ = write!(xml, "<row r=\"{row}\">");
+        for (index, cell) in cells.into_iter().enumerate() {
+            let reference = cell_reference(index, row);
+            if !self.truncated_cells && cell.text().and_then(truncate_cell_text).is_some() {
+                // Logged once per file, to avoid flooding the logs
+                self.truncated_cells = true;
+                log::warn!(
+                    "Truncating the text of cell {reference} of the xlsx file to {MAX_CELL_LENGTH} characters, the maximum supported by Excel."
+                );
+            }
+            cell.write_xml(xml, &reference, style);
+        }
+        xml.push_str("</row>");
+        self.zip
+            .write_all(xml.as_bytes())
+            .with_context(|| format!("Unable to write row {row} of the xlsx file"))
+    }
+
+    pub async fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.write_all(&self.buffer.take())?;
+        self.writer.async_flush().await?;
+        Ok(())
+    }
+
+    pub fn close(mut self) -> ResponseWriter {
+        let finished = self
+            .zip
+            .write_all(SHEET_END.as_bytes())
+            .map_err(anyhow::Error::from)
+            .and_then(|()| Ok(self.zip.finish()?));
+        if let Err(e) = finished {
+            log::error!("Unable to finish the xlsx file: {e:#}");
+        }
+        let _ = self.writer.write_all(&self.buffer.take());
+        self.writer
+    }
+}
+
+/// The value of one cell, with its Excel type.
+enum Cell<'a> {
+    Empty,
+    Bool(bool),
+    Number(String),
+    /// A number of days since 1899-12-30, displayed as a date or a date and time.
+    Date {
+        serial: f64,
+        has_time: bool,
+    },
+    Text(&'a str),
+    OwnedText(String),
+}
+
+impl<'a> Cell<'a> {
+    /// Only values from date columns become date cells: text that looks like a date stays text.
+    fn from_json(value: &'a Value, is_date: bool) -> Self {
+        match value {
+            Value::Null => Self::Empty,
+            Value::Bool(b) => Self::Bool(*b),
+            Value::Number(n) => Self::Number(n.to_string()),
+            Value::String(s) if is_date => {
+                parse_date(s).map_or(Self::Text(s), |(serial, has_time)| Self::Date {
+                    serial,
+                    has_time,
+                })
+            }
+            Value::String(s) => Self::Text(s),
+            Value::Array(_) | Value::Object(_) => Self::OwnedText(value.to_string()),
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::OwnedText(text) => Some(text),
+            Self::Empty | Self::Bool(_) | Self::Number(_) | Self::Date { .. } => None,
+        }
+    }
+
+    fn write_xml(self, xml: &mut String, reference: &str, style: u8) {
+        let style_attribute = |style: u8| {
+            if style == 0 {
+                String::new()
+            } else {
+                format!(" s=\"{style}\"")
+            }
+        };
+        match self {
+            Self::Empty => {}
+            Self::Bool(b) => {
+                let _ = write!(
+                    xml,
+                    "<c r=\"{reference}\" t=\"b\"{}><v>{}</v></c>",
+                    style_attribute(style),
+                    u8::from(b)
+                );
+            }
+            Self::Number(n) => {
+                let _ = write!(
+                    xml,
+                    "<c r=\"{reference}\"{}><v>{n}</v></c>",
+                    style_attribute(style)
+                );
+            }
+            Self::Date { serial, has_time } => {
+                let date_style = if has_time {
+                    DATE_TIME_STYLE
+                } else {
+                    DATE_STYLE
+                };
+                let _ = write!(
+                    xml,
+                    "<c r=\"{reference}\"{}><v>{serial}</v></c>",
+                    style_attribute(date_style)
+                );
+            }
+            Self::Text(text) => write_text_cell(xml, reference, &style_attribute(style), text),
+            Self::OwnedText(text) => {
+                write_text_cell(xml, reference, &style_attribute(style), &text);
+            }
+        }
+    }
+}
+
+fn write_text_cell(xml: &mut String, reference: &str, style_attribute: &str, text: &str) {
+    let text = truncate_cell_text(text).unwrap_or(text);
+    let _ = write!(
+        xml,
+        "<c r=\"{reference}\" t=\"inlineStr\"{style_attribute}><is><t xml:space=\"preserve\">{}</t></is></c>",
+        xml_escape(text)
+    );
+}
+
+/// Cuts texts that are longer than what an Excel cell can hold.
+fn truncate_cell_text(text: &str) -> Option<&str> {
+    if text.len() <= MAX_CELL_LENGTH {
+        return None;
+    }
+    text.char_indices()
+        .nth(MAX_CELL_LENGTH)
+        .map(|(end, _)| &text[..end])
+}
+
+/// Recognizes the date formats produced for database date and timestamp columns.
+fn parse_date(s: &str) -> Option<(f64, bool)> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_hms_opt(0, 0, 0)?;
+    let (datetime, has_time) = if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        (date.and_hms_opt(0, 0, 0)?, false)
+    } else if let Ok(datetime) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+    {
+        (datetime, true)
+    } else {
+        // Excel has no time zones: keep the local time written in the value
+        (DateTime::parse_from_rfc3339(s).ok()?.naive_local(), true)
+    };
+    #[allow(clippy::cast_precision_loss)] // Milliseconds since 1899 fit in an f64 mantissa
+    let serial = (datetime - epoch).num_milliseconds() as f64 / 86_400_000.0;
+    // Excel does not display dates before 1900
+    (serial >= 1.0).then_some((serial, has_time))
+}
+
+/// The name of a cell, like `A1` or `AB12`.
+fn cell_reference(column_index: usize, row: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = column_index + 1;
+    while n > 0 {
+        let remainder = (n - 1) % 26;
+        letters.push(char::from(b'A' + u8::try_from(remainder).unwrap_or(0)));
+        n = (n - 1) / 26;
+    }
+    letters.iter().rev().collect::<String>() + row.to_string().as_str()
+}
+
+fn validate_sheet_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.chars().count() > MAX_SHEET_NAME_LENGTH {
+        bail!(
+            "Invalid xlsx sheet name {name:?}: it must be between 1 and {MAX_SHEET_NAME_LENGTH} characters long."
+        );
+    }
+    if let Some(c) = name.chars().find(|c| r"[]:*?/\".contains(*c)) {
+        bail!("Invalid xlsx sheet name {name:?}: it cannot contain {c:?}.");
+    }
+    Ok(())
+}
+
+/// Escapes XML special characters, and drops the control characters XML cannot represent.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[test]
+fn test_cell_reference() {
+    assert_eq!(cell_reference(0, 1), "A1");
+    assert_eq!(cell_reference(25, 2), "Z2");
+    assert_eq!(cell_reference(26, 3), "AA3");
+    assert_eq!(cell_reference(701, 4), "ZZ4");
+    assert_eq!(cell_reference(702, 5), "AAA5");
+}
+
+#[test]
+fn test_truncate_cell_text() {
+    assert_eq!(truncate_cell_text("short"), None);
+    let long = "é".repeat(MAX_CELL_LENGTH);
+    assert_eq!(truncate_cell_text(&long), None);
+    let too_long = long.clone() + "x";
+    assert_eq!(truncate_cell_text(&too_long), Some(long.as_str()));
+}
+
+#[test]
+fn test_parse_date() {
+    assert_eq!(parse_date("2024-01-01"), Some((45292.0, false)));
+    assert_eq!(parse_date("2024-01-01T12:00:00"), Some((45292.5, true)));
+    assert_eq!(
+        parse_date("2024-01-01 06:00:00.000"),
+        Some((45292.25, true))
+    );
+    assert_eq!(
+        parse_date("2024-01-01T12:00:00+02:00"),
+        Some((45292.5, true))
+    );
+    assert_eq!(parse_date("hello"), None);
+    assert_eq!(parse_date("20240101"), None);
+}
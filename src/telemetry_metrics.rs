@@ -1,5 +1,5 @@
 use opentelemetry::global;
-use opentelemetry::metrics::{Histogram, ObservableGauge};
+use opentelemetry::metrics::{Counter, Histogram, Meter, ObservableGauge};
 use opentelemetry_semantic_conventions::attribute as otel;
 use opentelemetry_semantic_conventions::metric as otel_metric;
 use sqlx::any::AnyPool;
@@ -7,6 +7,7 @@ use sqlx::any::AnyPool;
 pub struct TelemetryMetrics {
     pub http_request_duration: Histogram<f64>,
     pub db_query_duration: Histogram<f64>,
+    pub file_cache: FileCacheMetrics,
     _pool_connection_count: ObservableGauge<i64>,
 }
 
@@ -34,6 +35,7 @@ impl Default for TelemetryMetrics {
         Self {
             http_request_duration,
             db_query_duration,
+            file_cache: FileCacheMetrics::new(&meter),
             _pool_connection_count: pool_connection_count,
         }
     }
@@ -91,7 +93,40 @@ impl TelemetryMetrics {
         Self {
             http_request_duration,
             db_query_duration,
+            file_cache: FileCacheMetrics::new(&meter),
             _pool_connection_count: pool_connection_count,
         }
     }
 }
+
+/// Counters of the caches of parsed SQL files and templates,
+/// with a `sqlpage.file_cache.name` attribute that identifies the cache.
+pub struct FileCacheMetrics {
+    pub hits: Counter<u64>,
+    pub misses: Counter<u64>,
+    pub evictions: Counter<u64>,
+}
+
+impl FileCacheMetrics {
+    fn new(meter: &Meter) -> Self {
+        Self {
+            hits: meter
+                .u64_counter("sqlpage.file_cache.hits")
+                .with_unit("{file}")
+                .with_description("Number of files served from the file cache.")
+                .build(),
+            misses: meter
+                .u64_counter("sqlpage.file_cache.misses")
+                .with_unit("{file}")
+                .with_description("Number of files that had to be loaded into the file cache.")
+                .build(),
+            evictions: meter
+                .u64_counter("sqlpage.file_cache.evictions")
+                .with_unit("{file}")
+                .with_description(
+                    "Number of files removed from the file cache to respect its capacity.",
+                )
+                .build(),
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use std::{borrow::Cow, collections::HashMap, sync::LazyLock};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::{Arc, LazyLock, OnceLock},
+};
 
-use crate::{app_config::AppConfig, utils::static_filename};
+use crate::{app_config::AppConfig, i18n::Locale, utils::static_filename};
 use anyhow::Context as _;
 use handlebars::{
     Context, Handlebars, HelperDef, JsonTruthy, PathAndJson, RenderError, RenderErrorReason,
@@ -20,7 +24,48 @@ type HH = fn(&JsonValue, &JsonValue) -> JsonValue;
 #[allow(clippy::upper_case_acronyms)]
 type HHH = fn(&JsonValue, &JsonValue, &JsonValue) -> JsonValue;
 
-pub fn register_all_helpers(h: &mut Handlebars<'_>, config: &AppConfig) {
+/// Helpers that handlebars registers itself
+const HANDLEBARS_HELPERS: [&str; 17] = [
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte",
+    "and", "or", "not", "len",
+];
+
+/// Handlebars does not list its helpers, so their names are recorded when they are registered
+struct HelperRegistry<'a, 'reg> {
+    handlebars: &'a mut Handlebars<'reg>,
+    names: HashSet<&'static str>,
+}
+
+impl<'reg> HelperRegistry<'_, 'reg> {
+    fn register_helper(
+        &mut self,
+        name: &'static str,
+        def: Box<dyn HelperDef + Send + Sync + 'reg>,
+    ) {
+        self.handlebars.register_helper(name, def);
+        self.names.insert(name);
+    }
+}
+
+/// Registers the helpers of `SQLPage`, and returns the names of all the helpers of `handlebars`
+pub fn register_all_helpers(
+    handlebars: &mut Handlebars<'_>,
+    config: &AppConfig,
+    default_locale: &Arc<Locale>,
+) -> HashSet<&'static str> {
+    let mut registry = HelperRegistry {
+        handlebars,
+        names: HashSet::from(HANDLEBARS_HELPERS),
+    };
+    register_sqlpage_helpers(&mut registry, config, default_locale);
+    registry.names
+}
+
+fn register_sqlpage_helpers(
+    h: &mut HelperRegistry<'_, '_>,
+    config: &AppConfig,
+    default_locale: &Arc<Locale>,
+) {
     let site_prefix = config.site_prefix.clone();
 
     register_helper(h, "all", HelperCheckTruthy(false));
@@ -64,6 +109,7 @@ pub fn register_all_helpers(h: &mut Handlebars<'_>, config: &AppConfig) {
 
     // static_path helper: generate a path to a static file. Replaces sqpage.js by sqlpage.<hash>.js
     register_helper(h, "static_path", StaticPathHelper(site_prefix.clone()));
+    register_helper(h, "image_srcset", ImageSrcsetHelper(site_prefix.clone()));
     register_helper(h, "app_config", AppConfigHelper(config.clone()));
 
     // icon helper: generate an image with the specified icon
@@ -74,6 +120,17 @@ pub fn register_all_helpers(h: &mut Handlebars<'_>, config: &AppConfig) {
     register_helper(h, "rfc2822_date", rfc2822_date_helper as EH);
     register_helper(h, "url_encode", url_encode_helper as H);
     register_helper(h, "csv_escape", csv_escape_helper as HH);
+    // t: translate a message. Pages register their own instance, with the locale of the request
+    h.register_helper("t", Box::new(TranslateHelper(Arc::clone(default_locale))));
+    // format_date, format_number, ...: format values like the locale of the request, which pages also register
+    for (name, kind) in FORMAT_HELPERS {
+        h.register_helper(
+            name,
+            Box::new(FormatHelper(kind, Arc::clone(default_locale))),
+        );
+    }
+    // column_format: the format of a table column. Pages register an instance that parses the format once per component
+    h.register_helper("column_format", Box::new(ColumnFormatHelper(None)));
 }
 
 fn json_eq_case_insensitive(a: &JsonValue, b: &JsonValue) -> bool {
@@ -188,6 +245,23 @@ impl CanHelp for StaticPathHelper {
     }
 }
 
+/// The `srcset` attribute of an image of the site, or null when the image cannot be resized.
+/// Struct Param is the site prefix
+struct ImageSrcsetHelper(String);
+
+impl CanHelp for ImageSrcsetHelper {
+    fn call(&self, args: &[PathAndJson<'_>]) -> Result<JsonValue, String> {
+        match args {
+            [v] => Ok(v
+                .value()
+                .as_str()
+                .and_then(|url| crate::webserver::images::srcset(&self.0, url))
+                .map_or(JsonValue::Null, JsonValue::String)),
+            _ => Err("expected one argument".to_string()),
+        }
+    }
+}
+
 /// Generate the full path to a builtin sqlpage asset. Struct Param is the site prefix
 struct AppConfigHelper(AppConfig);
 
@@ -202,8 +276,12 @@ impl CanHelp for AppConfigHelper {
             .ok_or_else(|| format!("app_config: not a string: {static_file}"))?;
         match name {
             "max_uploaded_file_size" => Ok(JsonValue::Number(self.0.max_uploaded_file_size.into())),
+            "max_resumable_upload_size" => {
+                Ok(JsonValue::Number(self.0.max_resumable_upload_size.into()))
+            }
             "environment" => serde_json::to_value(self.0.environment).map_err(|e| e.to_string()),
             "site_prefix" => Ok(self.0.site_prefix.clone().into()),
+            "live_reload" => Ok(self.0.live_reload().into()),
             other => Err(format!("unknown app config property: {other:?}")),
         }
     }
@@ -240,6 +318,170 @@ impl HelperDef for IconImgHelper {
     }
 }
 
+/// Translates a message key to a locale: `{{t "menu.home"}}`, `{{t "greeting" name=user_name}}`.
+/// Arguments can also be passed as an object: `{{t "greeting" this}}`.
+pub struct TranslateHelper(pub Arc<Locale>);
+
+impl HelperDef for TranslateHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        helper: &handlebars::Helper<'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _rc: &mut handlebars::RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let key = helper
+            .param(0)
+            .and_then(|key| key.value().as_str())
+            .ok_or_else(|| RenderErrorReason::Other("t: expected a message key".to_string()))?;
+        let mut args = match helper.param(1).map(PathAndJson::value) {
+            Some(JsonValue::Object(args)) => args.clone(),
+            _ => serde_json::Map::new(),
+        };
+        for (name, value) in helper.hash() {
+            args.insert((*name).to_string(), value.value().clone());
+        }
+        Ok(ScopedJson::Derived(self.0.translate(key, &args).into()))
+    }
+}
+
+/// Looks up the format of a column in the `format` parameter of the component, which can be a JSON string.
+/// When given a cache, the parameter is parsed only once, and shared between the rows of the component.
+pub struct ColumnFormatHelper(pub Option<Arc<OnceLock<JsonValue>>>);
+
+impl ColumnFormatHelper {
+    fn column_format(&self, column: &str, ctx: &Context) -> anyhow::Result<JsonValue> {
+        let parse = || {
+            let formats = ctx.data().get("format").unwrap_or(&JsonValue::Null);
+            parse_json_helper(formats).context("invalid format parameter")
+        };
+        let parsed;
+        let formats = if let Some(formats) = self.0.as_deref().and_then(OnceLock::get) {
+            formats
+        } else {
+            parsed = parse()?;
+            match &self.0 {
+                Some(cache) => cache.get_or_init(|| parsed),
+                None => &parsed,
+            }
+        };
+        Ok(formats.get(column).cloned().unwrap_or(JsonValue::Null))
+    }
+}
+
+impl HelperDef for ColumnFormatHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        helper: &handlebars::Helper<'rc>,
+        _r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _rc: &mut handlebars::RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let column = helper
+            .param(0)
+            .and_then(|column| column.value().as_str())
+            .ok_or_else(|| {
+                RenderErrorReason::Other("column_format: expected a column name".to_string())
+            })?;
+        let format = self
+            .column_format(column, ctx)
+            .map_err(|e| RenderErrorReason::Other(format!("column_format: {e:#}")))?;
+        Ok(ScopedJson::Derived(format))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FormatKind {
+    Date,
+    Number,
+    Currency,
+    Bytes,
+    /// Formats a value according to a format specification, like `currency:EUR`
+    Any,
+}
+
+pub const FORMAT_HELPERS: [(&str, FormatKind); 5] = [
+    ("format_date", FormatKind::Date),
+    ("format_number", FormatKind::Number),
+    ("format_currency", FormatKind::Currency),
+    ("format_bytes", FormatKind::Bytes),
+    ("format", FormatKind::Any),
+];
+
+/// Formats dates and numbers like the given locale. Null values are formatted as empty strings.
+pub struct FormatHelper(pub FormatKind, pub Arc<Locale>);
+
+impl FormatHelper {
+    fn format(&self, helper: &handlebars::Helper<'_>) -> anyhow::Result<JsonValue> {
+        use crate::formatting::{self, Decimals};
+        let value = helper.param(0).map_or(&JsonValue::Null, PathAndJson::value);
+        let argument = helper.param(1).map(PathAndJson::value);
+        let option = |name| helper.hash_get(name).map(PathAndJson::value);
+        let decimals = option("decimals")
+            .or(argument.filter(|_| matches!(self.0, FormatKind::Number)))
+            .filter(|d| !d.is_null())
+            .map(|d| {
+                d.as_u64()
+                    .and_then(|d| usize::try_from(d).ok())
+                    .with_context(|| format!("invalid number of decimals: {d}"))
+            })
+            .transpose()?;
+        let locale = &self.1;
+        if value.is_null() {
+            return Ok(JsonValue::String(String::new()));
+        }
+        let formatted = match self.0 {
+            FormatKind::Date if option("relative").is_some_and(|r| r.is_truthy(false)) => {
+                formatting::format_relative_date(
+                    &formatting::parse_date(value)?,
+                    chrono::Utc::now(),
+                    locale,
+                )
+            }
+            FormatKind::Date => formatting::format_date(
+                &formatting::parse_date(value)?,
+                argument.and_then(JsonValue::as_str),
+                locale,
+            )?,
+            FormatKind::Number if option("compact").is_some_and(|c| c.is_truthy(false)) => {
+                formatting::format_compact(formatting::parse_number(value)?, locale)
+            }
+            FormatKind::Number => formatting::format_number(
+                formatting::parse_number(value)?,
+                decimals.map_or(Decimals::AtMost(3), Decimals::Exactly),
+                locale,
+            ),
+            FormatKind::Currency => formatting::format_currency(
+                &formatting::parse_decimal(value)?,
+                argument.and_then(JsonValue::as_str),
+                decimals,
+                locale,
+            ),
+            FormatKind::Bytes => formatting::format_bytes(formatting::parse_number(value)?, locale),
+            FormatKind::Any => match argument.and_then(JsonValue::as_str) {
+                Some(spec) => formatting::format(value, spec, locale)?,
+                None => return Ok(value.clone()),
+            },
+        };
+        Ok(JsonValue::String(formatted))
+    }
+}
+
+impl HelperDef for FormatHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        helper: &handlebars::Helper<'rc>,
+        _r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _rc: &mut handlebars::RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let formatted = self
+            .format(helper)
+            .map_err(|e| RenderErrorReason::Other(format!("{}: {e:#}", helper.name())))?;
+        Ok(ScopedJson::Derived(formatted))
+    }
+}
+
 fn typeof_helper(v: &JsonValue) -> JsonValue {
     match v {
         JsonValue::Null => "null",
@@ -356,23 +598,8 @@ fn buildinfo_helper(x: &JsonValue) -> anyhow::Result<JsonValue> {
 
 // rfc2822_date: take an ISO date and convert it to an RFC 2822 date
 fn rfc2822_date_helper(v: &JsonValue) -> anyhow::Result<JsonValue> {
-    let date: chrono::DateTime<chrono::FixedOffset> = match v {
-        JsonValue::String(s) => {
-            // we accept both dates with and without time
-            chrono::DateTime::parse_from_rfc3339(s)
-                .or_else(|_| {
-                    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
-                        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().fixed_offset())
-                })
-                .with_context(|| format!("invalid date: {s}"))?
-        }
-        JsonValue::Number(n) => {
-            chrono::DateTime::from_timestamp(n.as_i64().with_context(|| "not a timestamp")?, 0)
-                .with_context(|| "invalid timestamp")?
-                .into()
-        }
-        other => anyhow::bail!("expected a date, got {other:?}"),
-    };
+    // we accept both dates with and without time
+    let date = crate::formatting::parse_date(v)?;
     // format: Thu, 01 Jan 1970 00:00:00 +0000
     Ok(date.format("%a, %d %b %Y %T %z").to_string().into())
 }
@@ -585,7 +812,7 @@ impl<F: CanHelp> HelperDef for JFun<F> {
     }
 }
 
-fn register_helper(h: &mut Handlebars<'_>, name: &'static str, fun: impl CanHelp) {
+fn register_helper(h: &mut HelperRegistry<'_, '_>, name: &'static str, fun: impl CanHelp) {
     h.register_helper(name, Box::new(JFun { name, fun }));
 }
 
@@ -632,6 +859,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_column_format_is_parsed_once() {
+        use super::ColumnFormatHelper;
+        use handlebars::Context;
+        use serde_json::json;
+
+        let helper = ColumnFormatHelper(Some(std::sync::Arc::default()));
+        let ctx = Context::wraps(json!({"format": r#"{"price": "currency:EUR"}"#})).unwrap();
+        assert_eq!(helper.column_format("price", &ctx).unwrap(), "currency:EUR");
+        assert_eq!(helper.column_format("name", &ctx).unwrap(), Value::Null);
+        // The rows of a component share the parsed format
+        let other = Context::wraps(json!({"format": "not json"})).unwrap();
+        assert_eq!(
+            helper.column_format("price", &other).unwrap(),
+            "currency:EUR"
+        );
+        assert!(
+            ColumnFormatHelper(None)
+                .column_format("price", &other)
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_basic_gfm_markdown() {
         let helper = MarkdownHelper::default();
@@ -1,11 +1,16 @@
 use crate::app_config::AppConfig;
+use crate::custom_helpers::{CustomHelper, NamedCustomHelper, helper_names};
 use crate::file_cache::AsyncFromStrWithState;
 use crate::filesystem::FileAccess;
+use crate::i18n::Locale;
 use crate::template_helpers::register_all_helpers;
-use crate::{AppState, FileCache, TEMPLATES_DIR};
+use crate::webserver::ErrorWithStatus;
+use crate::{AppState, FileCache, HELPERS_DIR, TEMPLATES_DIR};
 use async_trait::async_trait;
-use handlebars::{Handlebars, Template, template::TemplateElement};
+use handlebars::template::{Parameter, TemplateElement};
+use handlebars::{Handlebars, Template};
 use include_dir::{Dir, include_dir};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -13,6 +18,8 @@ pub struct SplitTemplate {
     pub before_list: Template,
     pub list_content: Template,
     pub after_list: Template,
+    /// Names of the helpers used by the template that are not built-in, and can be custom helpers
+    pub custom_helpers: Vec<String>,
 }
 
 impl SplitTemplate {
@@ -48,25 +55,194 @@ pub fn split_template(mut original: Template) -> SplitTemplate {
         before_list,
         list_content,
         after_list,
+        custom_helpers: Vec::new(),
     }
 }
 
 #[async_trait(? Send)]
 impl AsyncFromStrWithState for SplitTemplate {
     async fn from_str_with_state(
-        _app_state: &AppState,
+        app_state: &AppState,
         source: &str,
         source_path: &Path,
     ) -> anyhow::Result<Self> {
         log::debug!("Compiling template \"{}\"", source_path.display());
-        let tpl = Template::compile_with_name(source, "SQLPage component".to_string())?;
-        Ok(split_template(tpl))
+        let mut tpl = Template::compile_with_name(source, "SQLPage component".to_string())?;
+        if is_builtin_source(source_path, source) {
+            resolve_default_blocks(&mut tpl);
+        } else {
+            tpl = resolve_inheritance(tpl)?;
+        }
+        let builtin_helpers = &app_state.all_templates.builtin_helpers;
+        let mut custom_helpers = helper_names(&tpl);
+        custom_helpers.retain(|name| !builtin_helpers.contains(name.as_str()));
+        Ok(SplitTemplate {
+            custom_helpers,
+            ..split_template(tpl)
+        })
+    }
+}
+
+/// Custom templates can extend a built-in component instead of copying it,
+/// by including it with `{{> table}}` after overriding some of its named blocks with inline partials:
+/// `{{#*inline "cell"}}<b>{{> @partial-block}}</b>{{/inline}}`.
+/// The built-in template is inlined when the custom template is compiled,
+/// and its named blocks (`{{#> cell}}default content{{/cell}}`) are replaced by their overrides.
+/// Partial blocks written in the custom template itself are left to handlebars.
+fn resolve_inheritance(mut template: Template) -> anyhow::Result<Template> {
+    let overrides: HashMap<String, Template> = template
+        .elements
+        .iter()
+        .filter_map(inline_partial)
+        .collect();
+    let mut extended = Vec::new();
+    let mut used = HashSet::new();
+    let mut i = 0;
+    while i < template.elements.len() {
+        let Some((base_name, file)) = included_builtin_template(&template.elements[i], &overrides)
+        else {
+            i += 1;
+            continue;
+        };
+        log::debug!("Extending the built-in {base_name} component");
+        let source = String::from_utf8_lossy(file.contents());
+        let mut base = Template::compile_with_name(&source, base_name.clone())?;
+        resolve_blocks(&mut base, &overrides, &mut used);
+        let len = base.elements.len();
+        template.elements.splice(i..=i, base.elements);
+        template.mapping.splice(i..=i, base.mapping);
+        extended.push(base_name);
+        i += len;
+    }
+    if !extended.is_empty() {
+        for name in overrides.keys().filter(|name| !used.contains(*name)) {
+            log::warn!(
+                "The inline partial {name:?} does not override any block of {}",
+                extended.join(", ")
+            );
+        }
+    }
+    Ok(template)
+}
+
+/// Built-in templates have no overrides: their named blocks render their default content
+fn resolve_default_blocks(template: &mut Template) {
+    resolve_blocks(template, &HashMap::new(), &mut HashSet::new());
+}
+
+/// Built-in templates are read from the disk in development, when running from the source tree
+fn is_builtin_source(source_path: &Path, source: &str) -> bool {
+    source_path
+        .file_name()
+        .and_then(|name| STATIC_TEMPLATES.get_file(name))
+        .is_some_and(|file| file.contents() == source.as_bytes())
+}
+
+/// `{{> name}}`, where `name` is a built-in component that the template does not define as an inline partial
+fn included_builtin_template(
+    element: &TemplateElement,
+    overrides: &HashMap<String, Template>,
+) -> Option<(String, &'static include_dir::File<'static>)> {
+    let TemplateElement::PartialExpression(partial) = element else {
+        return None;
+    };
+    let Parameter::Name(name) = &partial.name else {
+        return None;
+    };
+    if overrides.contains_key(name) {
+        return None;
+    }
+    let file = STATIC_TEMPLATES.get_file(format!("{name}.handlebars"))?;
+    Some((name.clone(), file))
+}
+
+/// `{{#*inline "name"}}...{{/inline}}`
+fn inline_partial(element: &TemplateElement) -> Option<(String, Template)> {
+    let TemplateElement::DecoratorBlock(decorator) = element else {
+        return None;
+    };
+    match (&decorator.name, decorator.params.as_slice()) {
+        (Parameter::Name(inline), [Parameter::Literal(serde_json::Value::String(name))])
+            if inline == "inline" =>
+        {
+            Some((name.clone(), decorator.template.clone().unwrap_or_default()))
+        }
+        _ => None,
+    }
+}
+
+/// Replaces the named blocks of a template (`{{#> name}}default content{{/name}}`)
+/// with the overrides of the same name, or with their default content.
+/// In overrides, `{{> @partial-block}}` stands for the default content.
+fn resolve_blocks(
+    template: &mut Template,
+    overrides: &HashMap<String, Template>,
+    used: &mut HashSet<String>,
+) {
+    splice_elements(template, &mut |element| {
+        let TemplateElement::PartialBlock(block) = element else {
+            return None;
+        };
+        let Parameter::Name(name) = &block.name else {
+            return None;
+        };
+        let mut default = block.template.take().unwrap_or_default();
+        resolve_blocks(&mut default, overrides, used);
+        let Some(overridden) = overrides.get(name) else {
+            return Some(default);
+        };
+        used.insert(name.clone());
+        let mut overridden = overridden.clone();
+        splice_elements(&mut overridden, &mut |element| match element {
+            TemplateElement::PartialExpression(partial) if matches!(&partial.name, Parameter::Name(name) if name == "@partial-block") => {
+                Some(default.clone())
+            }
+            _ => None,
+        });
+        Some(overridden)
+    });
+}
+
+/// Replaces, at any depth, the elements for which `replace` returns a template by the elements of that template
+fn splice_elements(
+    template: &mut Template,
+    replace: &mut impl FnMut(&mut TemplateElement) -> Option<Template>,
+) {
+    let mut i = 0;
+    while i < template.elements.len() {
+        if let Some(replacement) = replace(&mut template.elements[i]) {
+            let len = replacement.elements.len();
+            template.elements.splice(i..=i, replacement.elements);
+            template.mapping.splice(i..=i, replacement.mapping);
+            i += len;
+            continue;
+        }
+        match &mut template.elements[i] {
+            TemplateElement::HelperBlock(helper) => {
+                for inner in helper.template.iter_mut().chain(helper.inverse.iter_mut()) {
+                    splice_elements(inner, replace);
+                }
+            }
+            TemplateElement::DecoratorBlock(decorator)
+            | TemplateElement::PartialBlock(decorator) => {
+                if let Some(inner) = &mut decorator.template {
+                    splice_elements(inner, replace);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
     }
 }
 
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<ErrorWithStatus>()
+        .is_some_and(|e| e.status == actix_web::http::StatusCode::NOT_FOUND)
+}
+
 fn is_template_list_item(element: &TemplateElement) -> bool {
     use Parameter::Name;
-    use handlebars::template::Parameter;
     matches!(element,
                     TemplateElement::HelperBlock(tpl)
                         if matches!(&tpl.name, Name(name) if name == "each_row"))
@@ -75,18 +251,23 @@ fn is_template_list_item(element: &TemplateElement) -> bool {
 #[allow(clippy::module_name_repetitions)]
 pub struct AllTemplates {
     pub handlebars: Handlebars<'static>,
+    /// Helpers of `handlebars`, that are never looked up in the helpers directory
+    builtin_helpers: HashSet<&'static str>,
     split_templates: FileCache<SplitTemplate>,
+    custom_helpers: FileCache<CustomHelper>,
 }
 
 const STATIC_TEMPLATES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/sqlpage/templates");
 
 impl AllTemplates {
-    pub fn init(config: &AppConfig) -> anyhow::Result<Self> {
+    pub fn init(config: &AppConfig, default_locale: &Arc<Locale>) -> anyhow::Result<Self> {
         let mut handlebars = Handlebars::new();
-        register_all_helpers(&mut handlebars, config);
+        let builtin_helpers = register_all_helpers(&mut handlebars, config, default_locale);
         let mut this = Self {
             handlebars,
-            split_templates: FileCache::new(),
+            builtin_helpers,
+            split_templates: FileCache::new("templates", config),
+            custom_helpers: FileCache::new("helpers", config),
         };
         this.preregister_static_templates()?;
         Ok(this)
@@ -104,7 +285,8 @@ impl AllTemplates {
                 .to_string_lossy()
                 .to_string();
             let source = String::from_utf8_lossy(file.contents());
-            let tpl = Template::compile_with_name(&source, name)?;
+            let mut tpl = Template::compile_with_name(&source, name)?;
+            resolve_default_blocks(&mut tpl);
             let split_template = split_template(tpl);
             self.split_templates.add_static(path, split_template);
         }
@@ -133,6 +315,32 @@ impl AllTemplates {
             .with_context(|| format!("Unable to get the component '{name}'"))
     }
 
+    /// Loads the custom helpers used by a template, from `sqlpage/helpers/<name>.rhai`.
+    /// Helpers that do not exist there are built-in, or are reported by handlebars when rendering.
+    pub async fn get_custom_helpers(
+        &self,
+        app_state: &AppState,
+        template: &SplitTemplate,
+    ) -> anyhow::Result<Vec<NamedCustomHelper>> {
+        use anyhow::Context;
+        let mut helpers = Vec::with_capacity(template.custom_helpers.len());
+        for name in &template.custom_helpers {
+            let path = PathBuf::from(format!("{HELPERS_DIR}{name}.rhai"));
+            match self
+                .custom_helpers
+                .get(app_state, FileAccess::privileged(&path))
+                .await
+            {
+                Ok(helper) => helpers.push(NamedCustomHelper(name.as_str().into(), helper)),
+                Err(e) if is_not_found(&e) => log::trace!("No custom helper named {name}"),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Unable to load the helper '{name}'"));
+                }
+            }
+        }
+        Ok(helpers)
+    }
+
     pub fn get_static_template(&self, name: &str) -> anyhow::Result<Arc<SplitTemplate>> {
         let path = Self::template_path(name);
         self.split_templates.get_static(&path)
@@ -160,3 +368,22 @@ fn test_split_template() {
         Template::compile("end").unwrap().elements
     );
 }
+
+#[test]
+fn test_resolve_blocks() {
+    let mut template =
+        Template::compile("a{{#> outer}}b{{#if x}}{{#> inner}}c{{/inner}}{{/if}}{{/outer}}d")
+            .unwrap();
+    let overrides = HashMap::from([(
+        "inner".to_string(),
+        Template::compile("[{{> @partial-block}}]").unwrap(),
+    )]);
+    let mut used = HashSet::new();
+    resolve_blocks(&mut template, &overrides, &mut used);
+    assert_eq!(template.elements.len(), template.mapping.len());
+    assert_eq!(used, HashSet::from(["inner".to_string()]));
+    let mut handlebars = Handlebars::new();
+    handlebars.register_template("t", template);
+    let rendered = handlebars.render("t", &serde_json::json!({"x": true}));
+    assert_eq!(rendered.unwrap(), "ab[c]d");
+}
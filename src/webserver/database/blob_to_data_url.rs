@@ -22,6 +22,10 @@ pub fn detect_mime_type(bytes: &[u8]) -> &'static str {
     if bytes.starts_with(b"RIFF") && bytes.len() >= 12 && &bytes[8..12] == b"WEBP" {
         return "image/webp";
     }
+    // AVIF: ....ftypavif (or ftypavis for image sequences)
+    if bytes.len() >= 12 && (&bytes[4..12] == b"ftypavif" || &bytes[4..12] == b"ftypavis") {
+        return "image/avif";
+    }
     // PDF: %PDF
     if bytes.starts_with(b"%PDF") {
         return "application/pdf";
@@ -160,6 +164,10 @@ mod tests {
 
         // Test PDF
         assert_eq!(detect_mime_type(b"%PDF-"), "application/pdf");
+        assert_eq!(
+            detect_mime_type(b"\x00\x00\x00\x1cftypavif\x00\x00"),
+            "image/avif"
+        );
 
         // Test SVG
         assert_eq!(
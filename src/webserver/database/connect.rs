@@ -1,6 +1,6 @@
 use std::{mem::take, time::Duration};
 
-use super::Database;
+use super::{Database, native_functions};
 use crate::{
     ON_CONNECT_FILE, ON_RESET_FILE,
     app_config::AppConfig,
@@ -14,7 +14,7 @@ use sqlx::odbc::OdbcConnectOptions;
 use sqlx::{
     any::{Any, AnyConnectOptions, AnyConnection, AnyKind},
     pool::PoolOptions,
-    sqlite::{Function, SqliteConnectOptions, SqliteFunctionCtx},
+    sqlite::SqliteConnectOptions,
 };
 
 impl Database {
@@ -56,7 +56,7 @@ impl Database {
         drop(conn);
 
         let db_kind = connect_options.kind();
-        let pool = Self::create_pool_options(config, db_kind)
+        let pool = Self::create_pool_options(config, db_kind, database_type)
             .connect_with(connect_options)
             .await
             .with_context(|| format!("Unable to open connection pool to {database_url}"))?;
@@ -68,11 +68,16 @@ impl Database {
                 dbms_name,
                 database_type,
                 kind: db_kind,
+                precompute_sqlpage_functions: config.precompute_sqlpage_functions,
             },
         })
     }
 
-    fn create_pool_options(config: &AppConfig, kind: AnyKind) -> PoolOptions<Any> {
+    fn create_pool_options(
+        config: &AppConfig,
+        kind: AnyKind,
+        database_type: SupportedDatabase,
+    ) -> PoolOptions<Any> {
         let mut pool_options = PoolOptions::new()
             .max_connections(if let Some(max) = config.max_database_pool_connections {
                 max
@@ -97,7 +102,7 @@ impl Database {
                 config.database_connection_acquire_timeout_seconds,
             ));
         pool_options = add_on_return_to_pool(config, pool_options);
-        pool_options = add_on_connection_handler(config, pool_options);
+        pool_options = add_on_connection_handler(config, database_type, pool_options);
         pool_options
     }
 }
@@ -165,6 +170,7 @@ fn on_return_to_pool(
 
 fn add_on_connection_handler(
     config: &AppConfig,
+    database_type: SupportedDatabase,
     pool_options: PoolOptions<Any>,
 ) -> PoolOptions<Any> {
     let on_connect_file = config.configuration_directory.join(ON_CONNECT_FILE);
@@ -200,6 +206,9 @@ fn add_on_connection_handler(
         let sql = sql.clone();
         let on_connect_file_display = on_connect_file_display.clone();
         Box::pin(async move {
+            if database_type == SupportedDatabase::Duckdb {
+                create_duckdb_macros(conn).await;
+            }
             if let Some(sql) = sql {
                 log::debug!("Running {on_connect_file_display} on new connection");
                 let r = conn.execute(sql.as_str()).await?;
@@ -210,6 +219,15 @@ fn add_on_connection_handler(
     })
 }
 
+/// Older `DuckDB` versions lack some of the functions the macros use: the connection stays usable without them.
+async fn create_duckdb_macros(conn: &mut AnyConnection) {
+    for sql in native_functions::DUCKDB_MACROS {
+        if let Err(e) = conn.execute(sql).await {
+            log::warn!("Unable to create a DuckDB macro with {sql}: {e}");
+        }
+    }
+}
+
 fn set_custom_connect_options(options: &mut AnyConnectOptions, config: &AppConfig) {
     if let Some(sqlite_options) = options.as_sqlite_mut() {
         set_custom_connect_options_sqlite(sqlite_options, config);
@@ -228,21 +246,11 @@ fn set_custom_connect_options_sqlite(
         log::info!("Loading SQLite extension: {extension_name}");
         *sqlite_options = take(sqlite_options).extension(extension_name.clone());
     }
-    *sqlite_options = take(sqlite_options)
-        .collation("NOCASE", |a, b| a.to_lowercase().cmp(&b.to_lowercase()))
-        .function(make_sqlite_fun("upper", str::to_uppercase))
-        .function(make_sqlite_fun("lower", str::to_lowercase));
-}
-
-fn make_sqlite_fun(name: &str, f: fn(&str) -> String) -> Function {
-    Function::new(name, move |ctx: &SqliteFunctionCtx| {
-        let arg = ctx.try_get_arg::<Option<&str>>(0);
-        match arg {
-            Ok(Some(s)) => ctx.set_result(f(s)),
-            Ok(None) => ctx.set_result(None::<String>),
-            Err(e) => ctx.set_error(&e.to_string()),
-        }
-    })
+    *sqlite_options =
+        take(sqlite_options).collation("NOCASE", |a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+    for function in native_functions::sqlite_functions() {
+        *sqlite_options = take(sqlite_options).function(function);
+    }
 }
 
 fn set_custom_connect_options_odbc(odbc_options: &mut OdbcConnectOptions, config: &AppConfig) {
@@ -1,15 +1,22 @@
 use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
 
-use anyhow::Context;
+use anyhow::{Context, bail};
+use calamine::{Data, DataType, Reader};
 use futures_util::StreamExt;
+use serde_json::Value;
 use sqlparser::ast::{
-    CopyLegacyCsvOption, CopyLegacyOption, CopyOption, CopySource, CopyTarget, Statement,
+    CopyLegacyCsvOption, CopyLegacyOption, CopyOption, CopySource, CopyTarget, Ident, Statement,
 };
+use sqlparser::keywords::Keyword;
+use sqlparser::tokenizer::{Location, Token, TokenWithSpan};
 use sqlx::any::{AnyArguments, AnyConnection, AnyConnectionKind, AnyKind};
 use sqlx::arguments::Arguments;
 use sqlx::executor::Executor;
 use sqlx::postgres::PgConnection;
 use tokio::io::AsyncRead;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::webserver::http_request_info::RequestInfo;
 
@@ -20,7 +27,8 @@ pub(super) struct CsvImport {
     /// Used only in postgres
     pub query: String,
     pub table_name: String,
-    pub columns: Vec<String>,
+    /// As written in the `COPY` statement
+    pub columns: Vec<Ident>,
     pub delimiter: Option<char>,
     pub quote: Option<char>,
     // If true, the first line of the CSV file will be interpreted as a header
@@ -31,6 +39,48 @@ pub(super) struct CsvImport {
     pub escape: Option<char>,
     /// Reference the the uploaded file name
     pub uploaded_file: String,
+    pub format: ImportFormat,
+}
+
+/// The format of the uploaded file, from the `FORMAT` option of the `COPY` statement.
+#[derive(Debug, PartialEq, Clone)]
+pub(super) enum ImportFormat {
+    Csv,
+    /// An Excel workbook. Without a sheet name, the first sheet is imported.
+    Xlsx {
+        sheet: Option<String>,
+    },
+    /// A JSON array of objects
+    Json,
+    /// One JSON object per line
+    JsonLines,
+}
+
+impl ImportFormat {
+    fn from_options(format: Option<&str>, sheet: Option<String>) -> Self {
+        match format.map(str::to_ascii_lowercase).as_deref() {
+            Some("xlsx") => Self::Xlsx { sheet },
+            Some("json") => Self::Json,
+            Some("jsonlines" | "ndjson") => Self::JsonLines,
+            _ => Self::Csv,
+        }
+    }
+
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::Csv => "CSV",
+            Self::Xlsx { .. } => "XLSX",
+            Self::Json | Self::JsonLines => "JSON",
+        }
+    }
+
+    /// How records are called in error messages
+    fn record_name(&self) -> &'static str {
+        match self {
+            Self::JsonLines => "line",
+            _ => "row",
+        }
+    }
 }
 
 enum CopyCsvOption<'a> {
@@ -78,9 +128,78 @@ impl CopyCsvOption<'_> {
             _ => None,
         }
     }
+
+    fn format(&self) -> Option<&str> {
+        match self {
+            CopyCsvOption::New(CopyOption::Format(ident)) => Some(&ident.value),
+            _ => None,
+        }
+    }
 }
 
-pub(super) fn extract_csv_copy_statement(stmt: &mut Statement) -> Option<CsvImport> {
+/// The `SHEET 'name'` options of `COPY ... (FORMAT xlsx, SHEET 'name')` statements,
+/// with the location of their `COPY` keyword
+pub(super) type SheetOptions = Vec<(Location, String)>;
+
+/// `sqlparser` does not know the `SHEET` option of `COPY`, so it is removed from the tokens
+/// before parsing, and given back to [`extract_csv_copy_statement`] by the location of the statement.
+pub(super) fn extract_sheet_options(tokens: &mut Vec<TokenWithSpan>) -> SheetOptions {
+    let mut sheets = SheetOptions::new();
+    let mut removed = vec![false; tokens.len()];
+    let mut copy_start = None;
+    let mut statement_start = true;
+    let non_whitespace = |i: usize| !matches!(tokens[i].token, Token::Whitespace(_));
+    for i in 0..tokens.len() {
+        match &tokens[i].token {
+            Token::Whitespace(_) => continue,
+            Token::SemiColon => {
+                statement_start = true;
+                copy_start = None;
+                continue;
+            }
+            Token::Word(word) if statement_start => {
+                copy_start = (word.keyword == Keyword::COPY).then_some(tokens[i].span.start);
+            }
+            Token::Word(word)
+                if word.quote_style.is_none() && word.value.eq_ignore_ascii_case("sheet") =>
+            {
+                let Some(copy_start) = copy_start else {
+                    continue;
+                };
+                let Some(value) = (i + 1..tokens.len()).find(|&j| non_whitespace(j)) else {
+                    continue;
+                };
+                let Token::SingleQuotedString(sheet) = &tokens[value].token else {
+                    continue;
+                };
+                sheets.push((copy_start, sheet.clone()));
+                removed[i..=value].fill(true);
+                // The option is separated from the others by a comma before it, or after it if it comes first
+                let previous = (0..i).rev().find(|&j| non_whitespace(j));
+                let next = (value + 1..tokens.len()).find(|&j| non_whitespace(j));
+                if let Some(comma) = [previous, next]
+                    .into_iter()
+                    .flatten()
+                    .find(|&j| tokens[j].token == Token::Comma && !removed[j])
+                {
+                    removed[comma] = true;
+                }
+            }
+            _ => {}
+        }
+        statement_start = false;
+    }
+    if !sheets.is_empty() {
+        let mut removed = removed.into_iter();
+        tokens.retain(|_| !removed.next().unwrap_or(false));
+    }
+    sheets
+}
+
+pub(super) fn extract_csv_copy_statement(
+    stmt: &mut Statement,
+    sheet: Option<String>,
+) -> Option<CsvImport> {
     if let Statement::Copy {
         source: CopySource::Table {
             table_name,
@@ -117,12 +236,14 @@ pub(super) fn extract_csv_copy_statement(stmt: &mut Statement) -> Option<CsvImpo
             .collect();
 
         let table_name = table_name.to_string();
-        let columns = columns.iter().map(|ident| ident.value.clone()).collect();
+        let columns = columns.clone();
         let delimiter = all_options.iter().find_map(CopyCsvOption::delimiter);
         let quote = all_options.iter().find_map(CopyCsvOption::quote);
         let header = all_options.iter().find_map(CopyCsvOption::header);
         let null = all_options.iter().find_map(CopyCsvOption::null);
         let escape = all_options.iter().find_map(CopyCsvOption::escape);
+        let format =
+            ImportFormat::from_options(all_options.iter().find_map(CopyCsvOption::format), sheet);
         let query = stmt.to_string();
 
         Some(CsvImport {
@@ -135,6 +256,7 @@ pub(super) fn extract_csv_copy_statement(stmt: &mut Statement) -> Option<CsvImpo
             null_str: null,
             escape,
             uploaded_file,
+            format,
         })
     } else {
         None
@@ -160,6 +282,9 @@ pub(super) async fn run_csv_import(
         })?
         .file;
     let file_path = named_temp_file.path();
+    if csv_import.format != ImportFormat::Csv {
+        return run_converted_import(db, csv_import, file_path).await;
+    }
     let file = tokio::fs::File::open(file_path).await.with_context(|| {
         format!(
             "The CSV file {} was uploaded correctly, but could not be opened",
@@ -185,6 +310,508 @@ pub(super) async fn run_csv_import(
     })
 }
 
+/// Records of xlsx and JSON files waiting to be imported
+const CONVERTED_RECORDS_BUFFER: usize = 256;
+/// Size of the chunks of CSV data sent to postgres
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Imports an xlsx or JSON file. The file is converted in a blocking task that sends its records
+/// one at a time, so large files are never loaded in memory entirely.
+async fn run_converted_import(
+    db: &mut AnyConnection,
+    import: &CsvImport,
+    file_path: &Path,
+) -> anyhow::Result<()> {
+    let format_name = import.format.name();
+    if import.columns.is_empty() && import.header == Some(false) {
+        bail!(
+            "Without a header row, the columns of the {format_name} file cannot be known. \
+             Write the list of columns in the COPY statement: COPY {}(column1, column2) FROM ...",
+            import.table_name
+        );
+    }
+    let (columns_sender, columns_receiver) = oneshot::channel();
+    let (records_sender, mut records) = mpsc::channel(CONVERTED_RECORDS_BUFFER);
+    let mut sender = RecordSender {
+        columns: Some(columns_sender),
+        records: records_sender,
+    };
+    let path = file_path.to_path_buf();
+    let format = import.format.clone();
+    let header = import.header.unwrap_or(true);
+    let requested_columns = import.columns.iter().map(|c| c.value.clone()).collect();
+    let converter = tokio::task::spawn_blocking(move || {
+        let converted = match &format {
+            ImportFormat::Xlsx { sheet } => convert_xlsx(
+                &path,
+                sheet.as_deref(),
+                header,
+                requested_columns,
+                &mut sender,
+            ),
+            ImportFormat::Json | ImportFormat::JsonLines => open_json_file(
+                &path,
+                format == ImportFormat::JsonLines,
+                requested_columns,
+                &mut sender,
+            ),
+            ImportFormat::Csv => unreachable!("CSV files are imported without conversion"),
+        };
+        match converted {
+            Ok(()) => sender.columns(Vec::new()),
+            Err(e) => sender.fail(e),
+        }
+    });
+    let imported = async {
+        let file_columns = columns_receiver
+            .await?
+            .with_context(|| format!("Invalid {format_name} file"))?;
+        let columns: Vec<Ident> = if import.columns.is_empty() {
+            file_columns
+                .iter()
+                .map(|column| quoted_column(db.kind(), column))
+                .collect::<anyhow::Result<_>>()?
+        } else {
+            import.columns.clone()
+        };
+        if columns.is_empty() {
+            log::debug!("The {format_name} file does not contain any column to import");
+            return Ok(());
+        }
+        import_converted_records(db, import, columns, &mut records).await
+    }
+    .await;
+    // stops the conversion if the import failed, then waits for the file to be closed
+    drop(records);
+    converter.await?;
+    imported
+}
+
+/// A column name read from an uploaded file, quoted so that it cannot be used to inject SQL
+fn quoted_column(db_kind: AnyKind, name: &str) -> anyhow::Result<Ident> {
+    let (quote, closing_quote) = match db_kind {
+        AnyKind::MySql => ('`', '`'),
+        AnyKind::Mssql => ('[', ']'),
+        _ => ('"', '"'),
+    };
+    if name.contains(closing_quote) {
+        bail!(
+            "The column name {name:?} contains the character {closing_quote}, which is not allowed in column names read from uploaded files"
+        );
+    }
+    Ok(Ident::with_quote(quote, name))
+}
+
+async fn import_converted_records(
+    db: &mut AnyConnection,
+    import: &CsvImport,
+    columns: Vec<Ident>,
+    records: &mut mpsc::Receiver<anyhow::Result<SourceRecord>>,
+) -> anyhow::Result<()> {
+    let format_name = import.format.name();
+    let record_name = import.format.record_name();
+    let csv_import = CsvImport {
+        query: format!(
+            "COPY {} ({}) FROM STDIN (FORMAT csv)",
+            import.table_name,
+            join_columns(&columns)
+        ),
+        table_name: import.table_name.clone(),
+        columns,
+        delimiter: None,
+        quote: None,
+        header: Some(false),
+        null_str: None,
+        escape: None,
+        uploaded_file: import.uploaded_file.clone(),
+        format: ImportFormat::Csv,
+    };
+    if let AnyConnectionKind::Postgres(pg_connection) = db.private_get_mut() {
+        log::debug!("Running {format_name} import with postgres");
+        let mut copy_transact = pg_connection
+            .copy_in_raw(csv_import.query.as_str())
+            .await
+            .with_context(|| "The postgres COPY FROM STDIN command failed.")?;
+        let sent = async {
+            let mut csv = Vec::with_capacity(COPY_CHUNK_SIZE);
+            while let Some(record) = next_record(records, format_name).await? {
+                write_csv_line(&mut csv, &record.values);
+                if csv.len() >= COPY_CHUNK_SIZE {
+                    copy_transact.send(std::mem::take(&mut csv)).await?;
+                }
+            }
+            if !csv.is_empty() {
+                copy_transact.send(csv).await?;
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        return match sent {
+            Ok(()) => {
+                copy_transact.finish().await?;
+                Ok(())
+            }
+            Err(e) => {
+                copy_transact
+                    .abort("The COPY FROM STDIN command failed.")
+                    .await?;
+                Err(e)
+            }
+        };
+    }
+    let insert_stmt = create_insert_stmt(db.kind(), &csv_import);
+    log::debug!("{format_name} data insert statement: {insert_stmt}");
+    while let Some(SourceRecord { number, values }) = next_record(records, format_name).await? {
+        let mut arguments = AnyArguments::default();
+        for value in values {
+            arguments.add(value);
+        }
+        db.execute((insert_stmt.as_str(), Some(arguments)))
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to insert the {record_name} {number} of the {format_name} file into the table {}",
+                    import.table_name
+                )
+            })?;
+    }
+    Ok(())
+}
+
+async fn next_record(
+    records: &mut mpsc::Receiver<anyhow::Result<SourceRecord>>,
+    format_name: &str,
+) -> anyhow::Result<Option<SourceRecord>> {
+    records
+        .recv()
+        .await
+        .transpose()
+        .with_context(|| format!("Invalid {format_name} file"))
+}
+
+/// A record of an xlsx or JSON file
+#[derive(Debug, PartialEq)]
+struct SourceRecord {
+    /// The row number displayed by spreadsheet applications, or the line or row number in a JSON file
+    number: usize,
+    values: Vec<Option<String>>,
+}
+
+/// Receives the columns, then the records of a file being converted
+trait RecordSink {
+    /// Called once, before the first record. Later calls are ignored.
+    fn columns(&mut self, columns: Vec<String>);
+    /// Returns false when no more records are needed
+    fn record(&mut self, record: SourceRecord) -> bool;
+}
+
+/// Sends the records of a file from the blocking conversion task to the import
+struct RecordSender {
+    columns: Option<oneshot::Sender<anyhow::Result<Vec<String>>>>,
+    records: mpsc::Sender<anyhow::Result<SourceRecord>>,
+}
+
+impl RecordSender {
+    fn fail(mut self, error: anyhow::Error) {
+        if let Some(columns) = self.columns.take() {
+            let _ = columns.send(Err(error));
+        } else {
+            let _ = self.records.blocking_send(Err(error));
+        }
+    }
+}
+
+impl RecordSink for RecordSender {
+    fn columns(&mut self, columns: Vec<String>) {
+        if let Some(sender) = self.columns.take() {
+            let _ = sender.send(Ok(columns));
+        }
+    }
+
+    fn record(&mut self, record: SourceRecord) -> bool {
+        self.records.blocking_send(Ok(record)).is_ok()
+    }
+}
+
+/// Appends a CSV line. `None` is written as an empty unquoted field, which means `NULL`.
+fn write_csv_line(csv: &mut Vec<u8>, values: &[Option<String>]) {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            csv.push(b',');
+        }
+        if let Some(value) = value {
+            csv.push(b'"');
+            for byte in value.bytes() {
+                if byte == b'"' {
+                    csv.push(b'"');
+                }
+                csv.push(byte);
+            }
+            csv.push(b'"');
+        }
+    }
+    csv.push(b'\n');
+}
+
+fn convert_xlsx(
+    path: &Path,
+    sheet: Option<&str>,
+    header: bool,
+    columns: Vec<String>,
+    sink: &mut impl RecordSink,
+) -> anyhow::Result<()> {
+    let mut workbook: calamine::Xlsx<_> =
+        calamine::open_workbook(path).with_context(|| "Unable to open the xlsx workbook")?;
+    let sheet_names = workbook.sheet_names();
+    let sheet = match sheet {
+        Some(name) => name.to_string(),
+        None => sheet_names
+            .first()
+            .cloned()
+            .context("The workbook does not contain any sheet")?,
+    };
+    let mut cells = workbook.worksheet_cells_reader(&sheet).with_context(|| {
+        format!(
+            "Unable to read the sheet {sheet:?}. The workbook contains the sheets {sheet_names:?}"
+        )
+    })?;
+    let mut converter = SheetConverter {
+        column_indices: None,
+        requested_columns: columns,
+    };
+    if !header {
+        let first_column = cells.dimensions().start.1 as usize;
+        let columns = std::mem::take(&mut converter.requested_columns);
+        converter.column_indices = Some((first_column..first_column + columns.len()).collect());
+        sink.columns(columns);
+    }
+    // cells are read row by row, and only the current row is kept in memory
+    let mut row_index = None;
+    let mut row: Vec<Data> = Vec::new();
+    while let Some(cell) = cells.next_cell()? {
+        if cell.get_value().is_empty() {
+            continue;
+        }
+        let (cell_row, cell_column) = cell.get_position();
+        if row_index != Some(cell_row) {
+            if let Some(index) = row_index
+                && !converter.row(index as usize + 1, &row, sink)?
+            {
+                return Ok(());
+            }
+            row.clear();
+            row_index = Some(cell_row);
+        }
+        let cell_column = cell_column as usize;
+        if row.len() <= cell_column {
+            row.resize(cell_column + 1, Data::Empty);
+        }
+        row[cell_column] = cell.get_value().clone().into();
+    }
+    if let Some(index) = row_index {
+        converter.row(index as usize + 1, &row, sink)?;
+    }
+    Ok(())
+}
+
+/// Converts the rows of a sheet, one at a time
+struct SheetConverter {
+    /// The position of each imported column in the rows, once known
+    column_indices: Option<Vec<usize>>,
+    /// The columns of the `COPY` statement, matched with the header row
+    requested_columns: Vec<String>,
+}
+
+impl SheetConverter {
+    /// Returns false when no more rows are needed
+    fn row(
+        &mut self,
+        row_number: usize,
+        cells: &[Data],
+        sink: &mut impl RecordSink,
+    ) -> anyhow::Result<bool> {
+        let Some(column_indices) = &self.column_indices else {
+            self.header_row(cells, sink)?;
+            return Ok(true);
+        };
+        let values = column_indices
+            .iter()
+            .map(|&i| {
+                cell_text(cells.get(i).unwrap_or(&Data::Empty))
+                    .with_context(|| format!("Invalid cell in row {row_number}, column {}", i + 1))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if values.iter().all(Option::is_none) {
+            return Ok(true);
+        }
+        Ok(sink.record(SourceRecord {
+            number: row_number,
+            values,
+        }))
+    }
+
+    fn header_row(&mut self, cells: &[Data], sink: &mut impl RecordSink) -> anyhow::Result<()> {
+        let headers: Vec<String> = cells
+            .iter()
+            .map(|cell| cell.to_string().trim().to_string())
+            .collect();
+        let columns = if self.requested_columns.is_empty() {
+            headers.iter().filter(|h| !h.is_empty()).cloned().collect()
+        } else {
+            std::mem::take(&mut self.requested_columns)
+        };
+        let column_indices = columns
+            .iter()
+            .map(|column| {
+                headers.iter().position(|h| h == column).ok_or_else(|| {
+                    anyhow::anyhow!("Column not found in the sheet header: {column}")
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        self.column_indices = Some(column_indices);
+        sink.columns(columns);
+        Ok(())
+    }
+}
+
+fn cell_text(cell: &Data) -> anyhow::Result<Option<String>> {
+    Ok(match cell {
+        Data::Empty => None,
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => Some(s.clone()),
+        Data::Int(i) => Some(i.to_string()),
+        // Whole numbers are stored as floats in xlsx files, but should be imported as integers
+        #[allow(clippy::cast_possible_truncation)]
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => Some((*f as i64).to_string()),
+        Data::Float(f) => Some(f.to_string()),
+        Data::Bool(b) => Some(b.to_string()),
+        Data::DateTime(date) => match date.as_datetime() {
+            Some(datetime) if date.is_datetime() => Some(
+                if datetime.time() == chrono::NaiveTime::MIN {
+                    datetime.format("%Y-%m-%d")
+                } else {
+                    datetime.format("%Y-%m-%d %H:%M:%S")
+                }
+                .to_string(),
+            ),
+            _ => Some(date.as_f64().to_string()),
+        },
+        Data::Error(e) => bail!("The cell contains the error {e}"),
+    })
+}
+
+fn open_json_file(
+    path: &Path,
+    lines: bool,
+    columns: Vec<String>,
+    sink: &mut impl RecordSink,
+) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path).with_context(|| {
+        format!(
+            "The file {} was uploaded correctly, but could not be opened",
+            path.display()
+        )
+    })?;
+    convert_json(std::io::BufReader::new(file), lines, columns, sink)
+}
+
+fn convert_json(
+    reader: impl BufRead,
+    lines: bool,
+    columns: Vec<String>,
+    sink: &mut impl RecordSink,
+) -> anyhow::Result<()> {
+    let mut converter = JsonConverter {
+        columns,
+        started: false,
+        item: if lines {
+            ImportFormat::JsonLines.record_name()
+        } else {
+            ImportFormat::Json.record_name()
+        },
+        sink,
+    };
+    if lines {
+        for (i, line) in reader.split(b'\n').enumerate() {
+            let line = line?;
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            let value = serde_json::from_slice(&line)
+                .with_context(|| format!("Invalid JSON on line {}", i + 1))?;
+            if !converter.object(i + 1, value)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    serde::Deserializer::deserialize_seq(&mut deserializer, JsonArrayVisitor(&mut converter))
+        .context("Invalid JSON")?;
+    deserializer.end().context("Invalid JSON")
+}
+
+/// Converts JSON objects, one at a time
+struct JsonConverter<'s, S> {
+    columns: Vec<String>,
+    /// Whether the columns were sent to the sink
+    started: bool,
+    /// How records are called in error messages
+    item: &'static str,
+    sink: &'s mut S,
+}
+
+impl<S: RecordSink> JsonConverter<'_, S> {
+    /// Returns false when no more objects are needed
+    fn object(&mut self, number: usize, value: Value) -> anyhow::Result<bool> {
+        let Value::Object(object) = value else {
+            bail!("The {} {number} is not a JSON object: {value}", self.item);
+        };
+        if !self.started {
+            if self.columns.is_empty() {
+                self.columns = object.keys().cloned().collect();
+            }
+            self.sink.columns(self.columns.clone());
+            self.started = true;
+        }
+        let values = self
+            .columns
+            .iter()
+            .map(|column| match object.get(column) {
+                None | Some(Value::Null) => None,
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(other) => Some(other.to_string()),
+            })
+            .collect();
+        Ok(self.sink.record(SourceRecord { number, values }))
+    }
+}
+
+/// Reads the elements of a JSON array one at a time, instead of loading the whole array
+struct JsonArrayVisitor<'c, 's, S>(&'c mut JsonConverter<'s, S>);
+
+impl<'de, S: RecordSink> serde::de::Visitor<'de> for JsonArrayVisitor<'_, '_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("an array of objects")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut number = 0;
+        while let Some(value) = seq.next_element::<Value>()? {
+            number += 1;
+            let more = self
+                .0
+                .object(number, value)
+                .map_err(|e| serde::de::Error::custom(format!("{e:#}")))?;
+            if !more {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// This function does not parse the CSV file, it only sends it to postgres.
 /// This is the fastest way to import a CSV file into postgres
 async fn run_csv_import_postgres(
@@ -224,9 +851,13 @@ async fn run_csv_import_insert(
     let mut reader = make_csv_reader(csv_import, file);
     let col_idxs = compute_column_indices(&mut reader, csv_import).await?;
     let mut records = reader.into_records();
+    let mut record_number = 0;
     while let Some(record) = records.next().await {
+        record_number += 1;
         let r = record.with_context(|| "reading csv record")?;
-        process_csv_record(r, db, &insert_stmt, csv_import, &col_idxs).await?;
+        process_csv_record(r, db, &insert_stmt, csv_import, &col_idxs)
+            .await
+            .with_context(|| format!("Unable to insert record {record_number}"))?;
     }
     Ok(())
 }
@@ -246,7 +877,7 @@ async fn compute_column_indices<R: AsyncRead + Unpin + Send>(
             .collect::<HashMap<&str, usize>>();
         for column in &csv_import.columns {
             let &idx = headers
-                .get(column.as_str())
+                .get(column.value.as_str())
                 .ok_or_else(|| anyhow::anyhow!("CSV Column not found: {column}"))?;
             col_idxs.push(idx);
         }
@@ -256,8 +887,16 @@ async fn compute_column_indices<R: AsyncRead + Unpin + Send>(
     Ok(col_idxs)
 }
 
+fn join_columns(columns: &[Ident]) -> String {
+    columns
+        .iter()
+        .map(Ident::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 fn create_insert_stmt(db_kind: AnyKind, csv_import: &CsvImport) -> String {
-    let columns = csv_import.columns.join(", ");
+    let columns = join_columns(&csv_import.columns);
     let placeholders = csv_import
         .columns
         .iter()
@@ -327,6 +966,7 @@ fn test_make_statement() {
         null_str: None,
         escape: None,
         uploaded_file: "my_file.csv".into(),
+        format: ImportFormat::Csv,
     };
     let insert_stmt = create_insert_stmt(AnyKind::Postgres, &csv_import);
     assert_eq!(
@@ -347,7 +987,7 @@ async fn test_end_to_end() {
     .into_iter()
     .next()
     .unwrap();
-    let csv_import = extract_csv_copy_statement(&mut copy_stmt).unwrap();
+    let csv_import = extract_csv_copy_statement(&mut copy_stmt, None).unwrap();
     assert_eq!(
         csv_import,
         CsvImport {
@@ -360,6 +1000,7 @@ async fn test_end_to_end() {
             null_str: None,
             escape: None,
             uploaded_file: "my_file.csv".into(),
+            format: ImportFormat::Csv,
         }
     );
     let mut conn = "sqlite::memory:"
@@ -385,3 +1026,209 @@ async fn test_end_to_end() {
         vec![("b".into(), "a".into()), ("d".into(), "c".into())]
     );
 }
+
+#[actix_web::test]
+async fn test_import_without_header_requires_columns() {
+    use sqlx::connection::ConnectOptions;
+    let mut conn = "sqlite::memory:"
+        .parse::<sqlx::any::AnyConnectOptions>()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let (mut statements, _) =
+        parse_copy_statements("COPY my_table FROM 'f' (FORMAT xlsx, HEADER false)");
+    let csv_import = extract_csv_copy_statement(&mut statements[0], None).unwrap();
+    let error = run_converted_import(&mut conn, &csv_import, Path::new("missing.xlsx"))
+        .await
+        .unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("COPY my_table(column1, column2)"),
+        "{error}"
+    );
+}
+
+#[cfg(test)]
+fn parse_copy_statements(sql: &str) -> (Vec<Statement>, SheetOptions) {
+    let dialect = sqlparser::dialect::PostgreSqlDialect {};
+    let mut tokens = sqlparser::tokenizer::Tokenizer::new(&dialect, sql)
+        .tokenize_with_location()
+        .unwrap();
+    let sheets = extract_sheet_options(&mut tokens);
+    let statements = sqlparser::parser::Parser::new(&dialect)
+        .with_tokens_with_locations(tokens)
+        .parse_statements()
+        .unwrap();
+    (statements, sheets)
+}
+
+#[test]
+fn test_extract_xlsx_copy_statement() {
+    let sql = "COPY t (a, b) FROM 'f' (FORMAT xlsx, SHEET 'My sheet'); SELECT 'sheet' AS sheet";
+    let (mut statements, sheets) = parse_copy_statements(sql);
+    assert_eq!(sheets, vec![(Location::new(1, 1), "My sheet".to_string())]);
+    let csv_import =
+        extract_csv_copy_statement(&mut statements[0], Some("My sheet".into())).unwrap();
+    assert_eq!(
+        csv_import.format,
+        ImportFormat::Xlsx {
+            sheet: Some("My sheet".into())
+        }
+    );
+    assert_eq!(csv_import.query, "COPY t (a, b) FROM STDIN (FORMAT xlsx)");
+    assert_eq!(statements[1].to_string(), "SELECT 'sheet' AS sheet");
+
+    // SHEET can come first, and is independent from ENCODING
+    let sql = "SELECT 1;\nCOPY t (a) FROM 'f' (SHEET 'S', FORMAT csv, ENCODING 'LATIN1')";
+    let (mut statements, sheets) = parse_copy_statements(sql);
+    assert_eq!(sheets, vec![(Location::new(2, 1), "S".to_string())]);
+    let csv_import = extract_csv_copy_statement(&mut statements[1], None).unwrap();
+    assert_eq!(
+        csv_import.query,
+        "COPY t (a) FROM STDIN (FORMAT csv, ENCODING 'LATIN1')"
+    );
+    let (mut statements, _) =
+        parse_copy_statements("COPY t (a) FROM 'f' (FORMAT xlsx, ENCODING 'LATIN1')");
+    let csv_import = extract_csv_copy_statement(&mut statements[0], None).unwrap();
+    assert_eq!(csv_import.format, ImportFormat::Xlsx { sheet: None });
+}
+
+#[cfg(test)]
+#[derive(Default, Debug)]
+struct CollectedRecords {
+    columns: Vec<String>,
+    records: Vec<SourceRecord>,
+}
+
+#[cfg(test)]
+impl RecordSink for CollectedRecords {
+    fn columns(&mut self, columns: Vec<String>) {
+        self.columns = columns;
+    }
+
+    fn record(&mut self, record: SourceRecord) -> bool {
+        self.records.push(record);
+        true
+    }
+}
+
+#[cfg(test)]
+fn collect_json(
+    json: &[u8],
+    lines: bool,
+    columns: Vec<String>,
+) -> anyhow::Result<CollectedRecords> {
+    let mut collected = CollectedRecords::default();
+    convert_json(json, lines, columns, &mut collected)?;
+    Ok(collected)
+}
+
+#[test]
+fn test_convert_json() {
+    let json = br#"[{"b": 1.50, "a": "x\"y"}, {"a": null, "c": true}]"#;
+    let collected = collect_json(json, false, vec![]).unwrap();
+    assert_eq!(collected.columns, ["b", "a"]);
+    let mut csv = Vec::new();
+    for record in &collected.records {
+        write_csv_line(&mut csv, &record.values);
+    }
+    assert_eq!(String::from_utf8(csv).unwrap(), "\"1.50\",\"x\"\"y\"\n,\n");
+
+    let lines = b"{\"a\": 1}\n\n{\"a\": [2]}\n";
+    let collected = collect_json(lines, true, vec!["a".into()]).unwrap();
+    assert_eq!(
+        collected.records,
+        [
+            SourceRecord {
+                number: 1,
+                values: vec![Some("1".into())]
+            },
+            SourceRecord {
+                number: 3,
+                values: vec![Some("[2]".into())]
+            },
+        ]
+    );
+
+    let error = collect_json(b"{\"a\": 1}\n[3]", true, vec![]).unwrap_err();
+    assert_eq!(error.to_string(), "The line 2 is not a JSON object: [3]");
+    let error = collect_json(b"{\"a\": 1}\n{", true, vec![]).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid JSON on line 2");
+    let error = collect_json(b"{\"a\": 1}", false, vec![]).unwrap_err();
+    assert!(
+        format!("{error:#}").contains("expected an array of objects"),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn test_columns_read_from_files_are_quoted() {
+    let column = quoted_column(AnyKind::Postgres, "a) VALUES (1); DROP TABLE t; --").unwrap();
+    let csv_import = CsvImport {
+        query: String::new(),
+        table_name: "t".into(),
+        columns: vec![column, quoted_column(AnyKind::Postgres, "b c").unwrap()],
+        delimiter: None,
+        quote: None,
+        header: None,
+        null_str: None,
+        escape: None,
+        uploaded_file: "f".into(),
+        format: ImportFormat::Json,
+    };
+    assert_eq!(
+        create_insert_stmt(AnyKind::Postgres, &csv_import),
+        r#"INSERT INTO t ("a) VALUES (1); DROP TABLE t; --", "b c") VALUES ($1, $2)"#
+    );
+    assert_eq!(
+        quoted_column(AnyKind::Mssql, "a b").unwrap().to_string(),
+        "[a b]"
+    );
+    assert_eq!(
+        quoted_column(AnyKind::MySql, "a b").unwrap().to_string(),
+        "`a b`"
+    );
+    assert!(quoted_column(AnyKind::Postgres, "a\"b").is_err());
+    assert!(quoted_column(AnyKind::Mssql, "a]b").is_err());
+    assert!(quoted_column(AnyKind::MySql, "a`b").is_err());
+}
+
+#[actix_web::test]
+async fn test_converted_import_errors_give_source_line_numbers() {
+    use sqlx::connection::ConnectOptions;
+    use std::io::Write;
+
+    let mut conn = "sqlite::memory:"
+        .parse::<sqlx::any::AnyConnectOptions>()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    conn.execute("CREATE TABLE t (a TEXT NOT NULL)")
+        .await
+        .unwrap();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"{\"a\": \"x\"}\n\n{\"a\": null}\n")
+        .unwrap();
+    let import = CsvImport {
+        query: String::new(),
+        table_name: "t".into(),
+        columns: vec![],
+        delimiter: None,
+        quote: None,
+        header: None,
+        null_str: None,
+        escape: None,
+        uploaded_file: "f".into(),
+        format: ImportFormat::JsonLines,
+    };
+    let error = run_converted_import(&mut conn, &import, file.path())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Unable to insert the line 3 of the JSON file into the table t"
+    );
+}
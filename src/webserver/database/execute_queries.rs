@@ -10,8 +10,8 @@ use tracing::Instrument;
 use super::csv_import::run_csv_import;
 use super::error_highlighting::{display_stmt_db_error, display_stmt_error};
 use super::sql::{
-    DatabaseQuery, FileStatement, OutputColumn, Query, QueryBody, SingleRowQuery, SourceSpan,
-    SqlFile,
+    DatabaseQuery, FileStatement, Lookup, LookupArguments, LookupTable, MAX_LOOKUP_VALUES,
+    OutputColumn, Query, QueryBody, SingleRowQuery, SourceSpan, SqlFile, expand_lookups,
 };
 use super::sqlpage_expr::{NoInputs, RowExpr, RowInputs};
 use crate::dynamic_component::parse_dynamic_rows;
@@ -20,7 +20,10 @@ use crate::webserver::ErrorWithStatus;
 use crate::webserver::http_request_info::ExecutionContext;
 use crate::webserver::single_or_vec::SingleOrVec;
 
-use super::{Database, DbItem, ScalarSubqueryBehavior, error_highlighting::display_db_error};
+use super::{
+    Database, DbItem, ScalarSubqueryBehavior, SupportedDatabase,
+    error_highlighting::display_db_error,
+};
 use sqlx::Either;
 use sqlx::any::{
     Any, AnyArguments, AnyConnection, AnyQueryResult, AnyRow, AnyStatement, AnyTypeInfo,
@@ -198,7 +201,7 @@ pub fn stream_query_results_with_conn<'a>(
                 FileStatement::CsvImport(csv_import) => {
                     let connection = take_connection(&request.app_state.db, db_connection, request).await?;
                     log::debug!("Executing CSV import: {csv_import:?}");
-                    run_csv_import(connection, csv_import, request).await.with_context(|| format!("Failed to import the CSV file {:?} into the table {:?}", csv_import.uploaded_file, csv_import.table_name))?;
+                    run_csv_import(connection, csv_import, request).await.with_context(|| format!("Failed to import the {} file {:?} into the table {:?}", csv_import.format.name(), csv_import.uploaded_file, csv_import.table_name))?;
                 },
                 FileStatement::Query(statement) => match &statement.body {
                   QueryBody::SingleRow(query) => {
@@ -210,7 +213,10 @@ pub fn stream_query_results_with_conn<'a>(
                     }
                   }
                   QueryBody::Database(stmt) => {
-                    let query = bind_query(stmt, request, db_connection)
+                    let expanded = expand_query(stmt, request, db_connection)
+                        .await
+                        .map_err(|error| with_stmt_position(source_file, statement.source_span, error))?;
+                    let query = bind_query(stmt, &expanded, request, db_connection)
                         .await
                         .map_err(|error| with_stmt_position(source_file, statement.source_span, error))?;
                     request.server_timing.record("bind_params");
@@ -220,6 +226,7 @@ pub fn stream_query_results_with_conn<'a>(
                     let mut returned_rows: i64 = 0;
                     let buffer_rows = stmt.must_buffer_rows();
                     let mut deferred_query_results = Vec::new();
+                    let mut sent_date_columns = false;
                     {
                         let connection = take_connection(&request.app_state.db, db_connection, request).await?;
                         let mut stream = connection.fetch_many(query);
@@ -229,6 +236,42 @@ pub fn stream_query_results_with_conn<'a>(
                         query_metrics.add_duration(start_next.elapsed());
                         let Some(elem) = next_elem else { break; };
 
+                        if stmt.blob
+                            && let Ok(Either::Right(row)) = &elem
+                            && let Some(blob) = super::sql_to_json::row_to_blob(row)
+                        {
+                            match blob {
+                                Ok(blob) => {
+                                    returned_rows += 1;
+                                    yield DbItem::Blob(blob);
+                                    continue;
+                                }
+                                Err(e) => {
+                                    error = Some(e);
+                                    break;
+                                }
+                            }
+                        }
+                        match &elem {
+                            Ok(Either::Right(row)) if !sent_date_columns => {
+                                sent_date_columns = true;
+                                let date_columns = super::sql_to_json::date_column_names(row);
+                                if !date_columns.is_empty() {
+                                    let item = DbItem::DateColumns(date_columns);
+                                    if buffer_rows {
+                                        deferred_query_results.push(QueryResult {
+                                            item,
+                                            inputs: RowInputs::new(Vec::new()),
+                                            output_column_count: 0,
+                                        });
+                                    } else {
+                                        yield item;
+                                    }
+                                }
+                            }
+                            Ok(Either::Left(_)) => sent_date_columns = false,
+                            _ => {}
+                        }
                         let mut query_result = parse_single_sql_result(source_file, stmt, statement.source_span, elem);
                         if let DbItem::Error(e) = query_result.item {
                             error = Some(e);
@@ -405,7 +448,8 @@ async fn execute_scalar_query<'a>(
         let row = execute_single_row(single_row, request, db_connection).await?;
         return scalar_value_from_row(DbItem::Row(row));
     };
-    let query = bind_query(database_query, request, db_connection).await?;
+    let expanded = expand_query(database_query, request, db_connection).await?;
+    let query = bind_query(database_query, &expanded, request, db_connection).await?;
     log::debug!("Executing scalar query: {:?}", query.sql);
     let (query_span, mut query_metrics) =
         create_query_metrics(request, source_file, statement.source_span, &query);
@@ -453,7 +497,7 @@ async fn execute_scalar_query<'a>(
                         break;
                     }
                 }
-                DbItem::FinishedQuery => {}
+                DbItem::FinishedQuery | DbItem::Blob(_) | DbItem::DateColumns(_) => {}
                 DbItem::Error(err) => {
                     error = Some(err);
                     break;
@@ -689,18 +733,133 @@ fn clone_anyhow_err(source_file: &Path, err: &anyhow::Error) -> anyhow::Error {
     e
 }
 
+/// The SQL of a query, with the results of its precomputed `SQLPage` functions spliced in.
+struct ExpandedQuery<'a> {
+    sql: Cow<'a, str>,
+    lookup_arguments: LookupArguments,
+}
+
+impl<'a> ExpandedQuery<'a> {
+    fn without_lookups(query: &'a DatabaseQuery) -> Self {
+        Self {
+            sql: Cow::Borrowed(&query.sql),
+            lookup_arguments: Vec::new(),
+        }
+    }
+}
+
+async fn expand_query<'a>(
+    query: &'a DatabaseQuery,
+    request: &ExecutionContext,
+    db_connection: &mut DbConn,
+) -> anyhow::Result<ExpandedQuery<'a>> {
+    if query.lookups.is_empty() {
+        return Ok(ExpandedQuery::without_lookups(query));
+    }
+    let max_values = max_lookup_values(query, request.app_state.db.info.database_type);
+    let mut tables = Vec::with_capacity(query.lookups.len());
+    for lookup in &query.lookups {
+        // boxed, to keep the futures of queries without lookups small
+        let table = Box::pin(compute_lookup(lookup, max_values, request, db_connection))
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to compute a SQLPage function for each value returned by {}",
+                    lookup.keys.sql
+                )
+            })?;
+        tables.push(table);
+    }
+    let (sql, lookup_arguments) = expand_lookups(query, &request.app_state.db.info, tables);
+    Ok(ExpandedQuery {
+        sql: Cow::Owned(sql),
+        lookup_arguments,
+    })
+}
+
+/// How many distinct values each lookup of `query` can have, so that the query
+/// stays below the number of parameters the database accepts.
+fn max_lookup_values(query: &DatabaseQuery, database: SupportedDatabase) -> usize {
+    let max_parameters: usize = match database {
+        SupportedDatabase::Postgres | SupportedDatabase::MySql => 65_535,
+        SupportedDatabase::Sqlite => 32_766,
+        _ => 2_100,
+    };
+    let lookups = query.lookups.len();
+    // each lookup takes two parameters per value, and one for the NULL value
+    let available = max_parameters.saturating_sub(query.bindings.len() + lookups);
+    (available / (2 * lookups)).min(MAX_LOOKUP_VALUES)
+}
+
+/// Lists the distinct values a lookup argument takes, and evaluates the function for each of them.
+async fn compute_lookup(
+    lookup: &Lookup,
+    max_values: usize,
+    request: &ExecutionContext,
+    db_connection: &mut DbConn,
+) -> anyhow::Result<LookupTable> {
+    let expanded = ExpandedQuery::without_lookups(&lookup.keys);
+    let keys_query = bind_query(&lookup.keys, &expanded, request, db_connection).await?;
+    let connection = take_connection(&request.app_state.db, db_connection, request).await?;
+    let rows = connection.fetch_all(keys_query).await?;
+    if rows.len() > max_values {
+        anyhow::bail!(
+            "The function is called with more than {max_values} different values, which is the maximum for this query. Filter the rows before calling it, or call it in the SELECT clause."
+        );
+    }
+    let mut table = LookupTable::default();
+    let keys = rows.iter().filter_map(|row| {
+        let column = row.columns().first()?;
+        json_to_scalar_string(super::sql_to_json::sql_to_json(row, column))
+    });
+    // The result for NULL is always computed, because the keys query can miss the NULL values of outer joins
+    for key in keys.map(Some).chain([None]) {
+        let mut inputs = RowInputs::new(vec![key.clone().map_or(Value::Null, Value::String)]);
+        let result = lookup
+            .call
+            .evaluate(request, db_connection, &mut inputs)
+            .await?
+            .into_function_argument()
+            .map(Cow::into_owned);
+        match key {
+            Some(key) => table.entries.push((key, result)),
+            None => table.null_result = result,
+        }
+    }
+    Ok(table)
+}
+
 async fn bind_query<'a>(
     query: &'a DatabaseQuery,
+    expanded: &'a ExpandedQuery<'a>,
     request: &'a ExecutionContext,
     db_connection: &mut DbConn,
 ) -> anyhow::Result<BoundQuery<'a>> {
-    let sql = query.sql.as_str();
+    let sql = expanded.sql.as_ref();
     log::debug!("Preparing statement: {sql}");
     let mut arguments = AnyArguments::default();
     let mut param_values = Vec::with_capacity(query.bindings.len());
     let mut inputs = NoInputs;
+    let mut lookup_arguments = expanded.lookup_arguments.iter().peekable();
     for (param_idx, binding) in query.bindings.iter().enumerate() {
+        while let Some((_, values)) = lookup_arguments.next_if(|(at, _)| *at == param_idx) {
+            add_lookup_arguments(&mut arguments, &mut param_values, values);
+        }
         log::trace!("\tevaluating binding {}: {:?}", param_idx + 1, binding);
+        if binding.is_binary() {
+            let bytes = binding
+                .evaluate_binary(request, db_connection, &mut inputs)
+                .await?;
+            let description = bytes.as_ref().map(|b| format!("<{} bytes>", b.len()));
+            log::debug!(
+                "\tparameter {}: {}",
+                param_idx + 1,
+                description.as_deref().unwrap_or("NULL")
+            );
+            param_values.push(description);
+            arguments.add(bytes);
+            continue;
+        }
         let argument = binding
             .evaluate(request, db_connection, &mut inputs)
             .await?
@@ -717,7 +876,10 @@ async fn bind_query<'a>(
             Some(Cow::Borrowed(v)) => arguments.add(v),
         }
     }
-    let has_arguments = !query.bindings.is_empty();
+    for (_, values) in lookup_arguments {
+        add_lookup_arguments(&mut arguments, &mut param_values, values);
+    }
+    let has_arguments = !param_values.is_empty();
     Ok(BoundQuery {
         sql,
         arguments,
@@ -726,6 +888,17 @@ async fn bind_query<'a>(
     })
 }
 
+fn add_lookup_arguments<'a>(
+    arguments: &mut AnyArguments<'a>,
+    param_values: &mut Vec<Option<String>>,
+    values: &'a [Option<String>],
+) {
+    for value in values {
+        arguments.add(value.as_deref());
+        param_values.push(value.clone());
+    }
+}
+
 async fn evaluate_computed_columns(
     request: &ExecutionContext,
     columns: &[OutputColumn<RowExpr>],
@@ -3,6 +3,7 @@ mod connect;
 mod csv_import;
 pub mod execute_queries;
 pub mod migrations;
+mod native_functions;
 mod sql;
 mod sqlpage_expr;
 mod sqlpage_functions;
@@ -136,6 +137,9 @@ pub struct DbInfo {
     pub database_type: SupportedDatabase,
     /// The sqlx database backend we are using. Can be "Odbc", in which case we need to use `database_type` to know what database we are actually using.
     pub kind: AnyKind,
+    /// Whether pure `SQLPage` functions of database values are computed in advance, from the
+    /// `precompute_sqlpage_functions` configuration option.
+    pub precompute_sqlpage_functions: bool,
 }
 
 impl Database {
@@ -149,10 +153,34 @@ impl Database {
 #[derive(Debug)]
 pub enum DbItem {
     Row(serde_json::Value),
+    /// A row of the `blob` component, with its binary contents decoded directly from the database
+    Blob(Blob),
+    /// The columns of the following rows that hold dates or timestamps in the database.
+    /// Sent before the first row of a query, only when it has such columns.
+    DateColumns(Vec<String>),
     FinishedQuery,
     Error(anyhow::Error),
 }
 
+/// A file sent to the client as it is, without being converted to a data URL
+pub struct Blob {
+    pub contents: Vec<u8>,
+    /// Detected from the contents when the query does not set it
+    pub content_type: Option<String>,
+    /// Makes the browser download the file instead of displaying it
+    pub filename: Option<String>,
+}
+
+impl std::fmt::Debug for Blob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Blob")
+            .field("contents", &format_args!("{} bytes", self.contents.len()))
+            .field("content_type", &self.content_type)
+            .field("filename", &self.filename)
+            .finish()
+    }
+}
+
 impl std::fmt::Display for Database {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.connection.any_kind())
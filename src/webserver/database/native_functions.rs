@@ -0,0 +1,302 @@
+//! Scalar functions that run inside the database engine.
+//!
+//! `sqlpage.*` calls are evaluated by `SQLPage` outside the database, so they cannot filter rows in a
+//! `WHERE` clause or feed an aggregate. The pure helpers in this module are registered as native
+//! `SQLite` functions on every new connection by [`sqlite_functions`], and are also used by the
+//! corresponding `sqlpage.*` functions so that both always return the same results.
+//!
+//! `DuckDB` is reached through ODBC, which offers no way to register native functions.
+//! It has built-in `regexp_replace`, `url_encode`, `sha256` and `uuid` functions, and `regexp`,
+//! `unicode_normalize` and `slugify` are created as SQL macros on top of its built-in functions by
+//! [`DUCKDB_MACROS`]. `regexp_match`, `sha512` and `hmac` have no `DuckDB` equivalent.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use anyhow::anyhow;
+use sqlx::encode::Encode;
+use sqlx::sqlite::{Function, Sqlite, SqliteFunctionCtx};
+use sqlx::types::Type;
+use unicode_normalization::UnicodeNormalization;
+
+/// Number of compiled regular expressions kept per thread.
+const REGEX_CACHE_SIZE: usize = 64;
+
+thread_local! {
+    static REGEX_CACHE: RefCell<HashMap<String, regex::Regex>> = RefCell::new(HashMap::new());
+}
+
+/// Compiles `pattern`, reusing a previous compilation when the same pattern is used on many rows.
+fn with_regex<T>(pattern: &str, f: impl FnOnce(&regex::Regex) -> T) -> anyhow::Result<T> {
+    REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(f(regex));
+        }
+        let regex = regex::Regex::new(pattern)?;
+        let result = f(&regex);
+        if cache.len() >= REGEX_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), regex);
+        Ok(result)
+    })
+}
+
+/// Whether `text` contains a match for `pattern`.
+pub(crate) fn regexp(pattern: &str, text: &str) -> anyhow::Result<bool> {
+    with_regex(pattern, |regex| regex.is_match(text))
+}
+
+/// Replaces all matches of `pattern` in `text`. `$1` or `$name` in the replacement refer to capture groups.
+pub(crate) fn regexp_replace(
+    text: &str,
+    pattern: &str,
+    replacement: &str,
+) -> anyhow::Result<String> {
+    with_regex(pattern, |regex| {
+        regex.replace_all(text, replacement).into_owned()
+    })
+}
+
+/// Returns a JSON object with one key per capture group, or `None` if `pattern` does not match.
+/// Named groups (`(?<name>pattern)`) use their name as key, unnamed groups use their index.
+pub(crate) fn regexp_match(pattern: &str, text: &str) -> anyhow::Result<Option<String>> {
+    use serde::{Serializer, ser::SerializeMap};
+    with_regex(pattern, |regex| {
+        let Some(match_obj) = regex.captures(text) else {
+            return Ok(None);
+        };
+        let mut result = Vec::with_capacity(64);
+        let mut ser = serde_json::Serializer::new(&mut result);
+        let mut map = ser.serialize_map(Some(match_obj.len()))?;
+        for (idx, maybe_name) in regex.capture_names().enumerate() {
+            if let Some(match_group) = match_obj.get(idx) {
+                if let Some(name) = maybe_name {
+                    map.serialize_entry(name, match_group.as_str())?;
+                } else {
+                    map.serialize_entry(&idx.to_string(), match_group.as_str())?;
+                }
+            }
+        }
+        map.end()?;
+        Ok(Some(String::from_utf8(result)?))
+    })?
+}
+
+/// Percent-encodes every non-alphanumeric character, for use in a URL.
+pub(crate) fn url_encode(text: &str) -> String {
+    percent_encoding::percent_encode(text.as_bytes(), percent_encoding::NON_ALPHANUMERIC)
+        .to_string()
+}
+
+/// Splits an algorithm name like `sha256-base64` into the hash function and the output encoding.
+fn parse_algorithm(algorithm: &str) -> (String, String) {
+    let (hash, format) = algorithm.split_once('-').unwrap_or((algorithm, "hex"));
+    (hash.to_lowercase(), format.to_lowercase())
+}
+
+fn encode_digest(bytes: &[u8], format: &str) -> anyhow::Result<String> {
+    match format {
+        "hex" => Ok(bytes.iter().fold(String::new(), |mut acc, byte| {
+            write!(&mut acc, "{byte:02x}").unwrap();
+            acc
+        })),
+        "base64" => Ok(base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            bytes,
+        )),
+        _ => anyhow::bail!("Unsupported output format: {format}. Supported formats: hex, base64"),
+    }
+}
+
+/// Hashes `data` with `sha256` or `sha512`, optionally suffixed with the output format (`sha256-base64`).
+pub(crate) fn digest(data: &str, algorithm: &str) -> anyhow::Result<String> {
+    use sha2::{Digest, Sha256, Sha512};
+    let (hash, format) = parse_algorithm(algorithm);
+    let bytes = match hash.as_str() {
+        "sha256" => Sha256::digest(data.as_bytes()).to_vec(),
+        "sha512" => Sha512::digest(data.as_bytes()).to_vec(),
+        _ => anyhow::bail!(
+            "Unsupported hash algorithm: {hash}. Supported algorithms: sha256, sha512"
+        ),
+    };
+    encode_digest(&bytes, &format)
+}
+
+/// Computes the HMAC of `data` with `key`. The algorithm has the same syntax as in [`digest`].
+pub(crate) fn hmac(data: &str, key: &str, algorithm: &str) -> anyhow::Result<String> {
+    use ::hmac::{Hmac, KeyInit, Mac};
+    use sha2::{Sha256, Sha512};
+    let (hash, format) = parse_algorithm(algorithm);
+    let bytes = match hash.as_str() {
+        "sha256" => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                .map_err(|e| anyhow!("Invalid HMAC key: {e}"))?;
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        "sha512" => {
+            let mut mac = Hmac::<Sha512>::new_from_slice(key.as_bytes())
+                .map_err(|e| anyhow!("Invalid HMAC key: {e}"))?;
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        _ => anyhow::bail!(
+            "Unsupported HMAC algorithm: {hash}. Supported algorithms: sha256, sha512"
+        ),
+    };
+    encode_digest(&bytes, &format)
+}
+
+/// A random (version 4) UUID in its hyphenated lowercase form.
+pub(crate) fn uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Applies one of the Unicode normalization forms `NFC`, `NFD`, `NFKC` or `NFKD`.
+pub(crate) fn unicode_normalize(text: &str, form: &str) -> anyhow::Result<String> {
+    Ok(match form.to_ascii_uppercase().as_str() {
+        "NFC" => text.nfc().collect(),
+        "NFD" => text.nfd().collect(),
+        "NFKC" => text.nfkc().collect(),
+        "NFKD" => text.nfkd().collect(),
+        _ => anyhow::bail!(
+            "Unsupported normalization form: {form}. Supported forms: NFC, NFD, NFKC, NFKD"
+        ),
+    })
+}
+
+/// Turns `text` into a lowercase ASCII identifier suitable for a URL: `Crème brûlée !` becomes `creme-brulee`.
+pub(crate) fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.nfkd() {
+        if unicode_normalization::char::is_combining_mark(c) {
+            continue;
+        }
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+/// Functions registered on every `SQLite` connection.
+pub(super) fn sqlite_functions() -> Vec<Function> {
+    vec![
+        text_function("upper", 1..=1, |a| Ok(Some(a[0].to_uppercase()))),
+        text_function("lower", 1..=1, |a| Ok(Some(a[0].to_lowercase()))),
+        text_function("regexp", 2..=2, |a| regexp(a[0], a[1]).map(Some)),
+        text_function("regexp_replace", 3..=3, |a| {
+            regexp_replace(a[0], a[1], a[2]).map(Some)
+        }),
+        text_function("regexp_match", 2..=2, |a| regexp_match(a[0], a[1])),
+        text_function("url_encode", 1..=1, |a| Ok(Some(url_encode(a[0])))),
+        text_function("sha256", 1..=1, |a| digest(a[0], "sha256").map(Some)),
+        text_function("sha512", 1..=1, |a| digest(a[0], "sha512").map(Some)),
+        text_function("hmac", 2..=3, |a| {
+            hmac(a[0], a[1], a.get(2).unwrap_or(&"sha256")).map(Some)
+        }),
+        text_function("unicode_normalize", 1..=2, |a| {
+            unicode_normalize(a[0], a.get(1).unwrap_or(&"NFC")).map(Some)
+        }),
+        text_function("slugify", 1..=1, |a| Ok(Some(slugify(a[0])))),
+        // Not deterministic: every call must return a new value.
+        Function::new("uuid", |ctx: &SqliteFunctionCtx| ctx.set_result(uuid())),
+    ]
+}
+
+/// Created on every `DuckDB` connection, with the same names and arguments as the `SQLite` functions.
+/// `DuckDB` only implements the `NFC` normalization form, and has nothing to build `regexp_match`,
+/// `sha512` and `hmac` from.
+pub(super) const DUCKDB_MACROS: [&str; 3] = [
+    "CREATE OR REPLACE TEMP MACRO regexp(pattern, text) AS regexp_matches(text, pattern)",
+    "CREATE OR REPLACE TEMP MACRO unicode_normalize(text, form := 'NFC') AS \
+        CASE WHEN upper(form) = 'NFC' THEN nfc_normalize(text) \
+        ELSE error('unicode_normalize(): DuckDB only supports the NFC form') END",
+    "CREATE OR REPLACE TEMP MACRO slugify(text) AS \
+        trim(regexp_replace(lower(strip_accents(text)), '[^a-z0-9]+', '-', 'g'), '-')",
+];
+
+/// Wraps a deterministic function of `arity` text arguments.
+/// Like most SQL functions, it returns `NULL` when any of its arguments is `NULL`.
+fn text_function<R>(
+    name: &'static str,
+    arity: RangeInclusive<usize>,
+    f: fn(&[&str]) -> anyhow::Result<Option<R>>,
+) -> Function
+where
+    R: for<'q> Encode<'q, Sqlite> + Type<Sqlite> + 'static,
+{
+    Function::new(name, move |ctx: &SqliteFunctionCtx| {
+        let mut args = Vec::with_capacity(*arity.end());
+        // Any argument can be read as bytes, so this only stops after the last argument
+        while ctx.try_get_arg::<Option<&[u8]>>(args.len()).is_ok() {
+            match ctx.try_get_arg::<Option<&str>>(args.len()) {
+                Ok(arg) => args.push(arg),
+                Err(e) => {
+                    return ctx.set_error(&format!(
+                        "{name}(): argument {} is not valid text: {e}",
+                        args.len() + 1
+                    ));
+                }
+            }
+        }
+        if !arity.contains(&args.len()) {
+            return ctx.set_error(&format!(
+                "{name}() takes between {} and {} arguments, but {} were given",
+                arity.start(),
+                arity.end(),
+                args.len()
+            ));
+        }
+        let Some(args) = args.into_iter().collect::<Option<Vec<&str>>>() else {
+            return ctx.set_result(None::<String>);
+        };
+        match f(&args) {
+            Ok(result) => ctx.set_result(result),
+            Err(e) => ctx.set_error(&format!("{name}(): {e:#}")),
+        }
+    })
+    .deterministic()
+}
+
+#[test]
+fn test_duckdb_macros_parse() {
+    for sql in DUCKDB_MACROS {
+        sqlparser::parser::Parser::parse_sql(&sqlparser::dialect::DuckDbDialect {}, sql)
+            .unwrap_or_else(|e| panic!("{sql}: {e}"));
+    }
+}
+
+#[test]
+fn test_slugify() {
+    assert_eq!(slugify("Crème brûlée !"), "creme-brulee");
+    assert_eq!(slugify("  Hello,   World  "), "hello-world");
+    assert_eq!(slugify("ﬁ 2½"), "fi-21-2");
+    assert_eq!(slugify("日本"), "");
+}
+
+#[test]
+fn test_digest_and_hmac() {
+    assert_eq!(
+        digest("abc", "sha256").unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hmac(
+            "The quick brown fox jumps over the lazy dog",
+            "key",
+            "sha256-base64"
+        )
+        .unwrap(),
+        "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg="
+    );
+}
@@ -29,7 +29,7 @@ use sqlparser::parser::{Parser, ParserError};
 use sqlparser::tokenizer::Token::{self, EOF, SemiColon};
 use sqlparser::tokenizer::{Location, Span, TokenWithSpan, Tokenizer};
 
-use super::csv_import::extract_csv_copy_statement;
+use super::csv_import::{SheetOptions, extract_csv_copy_statement, extract_sheet_options};
 use super::{Database, DbInfo, SupportedDatabase};
 use crate::AppState;
 use crate::file_cache::AsyncFromStrWithState;
@@ -39,12 +39,13 @@ mod dialect;
 mod rewrite;
 mod statement;
 
+pub(super) use rewrite::{LookupArguments, LookupTable, MAX_LOOKUP_VALUES, expand_lookups};
 #[cfg(test)]
 pub(super) use statement::SourceLocation;
 pub use statement::SqlFile;
 pub(super) use statement::{
-    DatabaseQuery, FileStatement, OutputColumn, Query, QueryBody, SingleRowQuery, SourceSpan,
-    VariableName,
+    DatabaseQuery, FileStatement, Lookup, OutputColumn, Query, QueryBody, SingleRowQuery,
+    SourceSpan, VariableName,
 };
 
 impl SqlFile {
@@ -98,7 +99,7 @@ fn parse_sql<'a>(
     sql: &'a str,
 ) -> anyhow::Result<impl Iterator<Item = FileStatement> + 'a> {
     log::trace!("Parsing {} SQL: {sql}", database.dbms_name);
-    let tokens = Tokenizer::new(dialect, sql)
+    let mut tokens = Tokenizer::new(dialect, sql)
         .tokenize_with_location()
         .map_err(|error| {
             let location = error.location;
@@ -107,13 +108,14 @@ fn parse_sql<'a>(
                 quote_source_with_highlight(sql, location.line, location.column)
             ))
         })?;
+    let sheets = extract_sheet_options(&mut tokens);
     let mut parser = Parser::new(dialect).with_tokens_with_locations(tokens);
     let mut has_error = false;
     Ok(std::iter::from_fn(move || {
         if has_error {
             return None;
         }
-        let statement = parse_single_statement(&mut parser, database, sql);
+        let statement = parse_single_statement(&mut parser, database, sql, &sheets);
         if matches!(statement, Some(FileStatement::Error(_))) {
             has_error = true;
         }
@@ -125,10 +127,12 @@ fn parse_single_statement(
     parser: &mut Parser<'_>,
     database: &DbInfo,
     source_sql: &str,
+    sheets: &SheetOptions,
 ) -> Option<FileStatement> {
     if parser.peek_token() == EOF {
         return None;
     }
+    let statement_start = parser.peek_token().span.start;
     let mut statement = match parser.parse_statement() {
         Ok(statement) => statement,
         Err(error) => return Some(syntax_error(error, parser, source_sql)),
@@ -141,7 +145,11 @@ fn parse_single_statement(
     if let Some(statement) = extract_set_variable(&mut statement, database) {
         return Some(statement);
     }
-    if let Some(csv_import) = extract_csv_copy_statement(&mut statement) {
+    let sheet = sheets
+        .iter()
+        .find(|(location, _)| *location == statement_start)
+        .map(|(_, sheet)| sheet.clone());
+    if let Some(csv_import) = extract_csv_copy_statement(&mut statement, sheet) {
         return Some(FileStatement::CsvImport(csv_import));
     }
 
@@ -344,7 +352,7 @@ mod tests {
         ConcatNullBehavior, RowInputId, SqlPageExpr, VariableRef, VariableSource,
     };
     use crate::webserver::database::sqlpage_functions::functions::SqlPageFunctionName;
-    use sqlparser::dialect::{MySqlDialect, PostgreSqlDialect};
+    use sqlparser::dialect::{MsSqlDialect, MySqlDialect, PostgreSqlDialect};
     use sqlx::any::AnyKind;
 
     fn database(database_type: SupportedDatabase) -> DbInfo {
@@ -359,6 +367,7 @@ mod tests {
             dbms_name: database_type.display_name().to_owned(),
             database_type,
             kind,
+            precompute_sqlpage_functions: true,
         }
     }
 
@@ -375,14 +384,14 @@ mod tests {
     }
 
     fn rewrite_database(sql: &str) -> DatabaseQuery {
-        let FileStatement::Query(Query {
-            body: QueryBody::Database(query),
-            ..
-        }) = one(sql)
-        else {
-            panic!("expected database query");
-        };
-        query
+        match one(sql) {
+            FileStatement::Query(Query {
+                body: QueryBody::Database(query),
+                ..
+            }) => query,
+            FileStatement::Error(error) => panic!("expected database query: {error:#}"),
+            other => panic!("expected database query, got {other:?}"),
+        }
     }
 
     fn call<Input, const N: usize>(
@@ -435,6 +444,20 @@ mod tests {
         assert!(query.sql.contains("upper(CAST($1 AS TEXT))"));
     }
 
+    #[test]
+    fn binary_binding_is_not_cast_to_text() {
+        let query = rewrite_database(
+            "insert into files(name, contents) values (sqlpage.uploaded_file_name('f'), sqlpage.uploaded_file_blob('f'))",
+        );
+        assert_eq!(query.bindings.len(), 2);
+        assert!(query.bindings[1].is_binary());
+        assert!(
+            query.sql.ends_with("VALUES (CAST($1 AS TEXT), $2)"),
+            "{}",
+            query.sql
+        );
+    }
+
     #[test]
     fn emulated_parent_keeps_nested_call_per_row() {
         let FileStatement::Query(Query {
@@ -466,12 +489,164 @@ mod tests {
     }
 
     #[test]
-    fn row_value_cannot_cross_database_only_parent() {
-        let FileStatement::Error(error) = one("select upper(sqlpage.url_encode(value)) from t")
+    fn row_value_under_database_only_parent_is_looked_up() {
+        let query = rewrite_database("select upper(sqlpage.url_encode(value)) from t");
+        assert_eq!(
+            query.sql,
+            "SELECT upper(CASE CAST(value AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END) FROM t"
+        );
+        assert_eq!(query.lookups.len(), 1);
+    }
+
+    #[test]
+    fn where_call_on_column_is_looked_up() {
+        let query = rewrite_database(
+            "select id from t join u on sqlpage.url_encode(u.name) = t.code and u.t = t.id \
+             where sqlpage.url_encode(value) = $x and (t.active and t.y > 1)",
+        );
+        assert_eq!(
+            query.sql,
+            "SELECT id FROM t JOIN u ON CASE CAST(u.name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END = t.code AND u.t = t.id \
+             WHERE CASE CAST(value AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_1 THEN NULL END = CAST($1 AS TEXT) AND (t.active AND t.y > 1)"
+        );
+        assert_eq!(query.bindings.as_ref(), [variable("x")]);
+        let [join, filter] = query.lookups.as_ref() else {
+            panic!("expected two lookups");
+        };
+        // The keys are listed from the rows matching the conditions that do not need lookups
+        assert_eq!(
+            join.keys.sql,
+            "SELECT DISTINCT CAST(u.name AS TEXT) COLLATE \"C\" AS \"__sqlpage_lookup_key\" \
+             FROM t JOIN u ON u.t = t.id WHERE (t.active AND t.y > 1) LIMIT 1001"
+        );
+        assert_eq!(filter.call, call(SqlPageFunctionName::url_encode, [row(0)]));
+        assert_eq!(
+            filter.keys.sql,
+            "SELECT DISTINCT CAST(value AS TEXT) COLLATE \"C\" AS \"__sqlpage_lookup_key\" \
+             FROM t JOIN u ON u.t = t.id WHERE (t.active AND t.y > 1) LIMIT 1001"
+        );
+    }
+
+    #[test]
+    fn only_constant_blob_components_are_sent_as_files() {
+        assert!(rewrite_database("select 'blob' as component, contents from t").blob);
+        assert!(!rewrite_database("select component, contents from t").blob);
+        assert!(!rewrite_database("select 'blob' as title, contents from t").blob);
+    }
+
+    #[test]
+    fn mssql_lookup_keys_are_limited_with_top() {
+        let database = database(SupportedDatabase::Mssql);
+        let mut statements = parse_sql(
+            &database,
+            &MsSqlDialect {},
+            "select id from t where sqlpage.url_encode(name) = 'x'",
+        )
+        .unwrap();
+        let FileStatement::Query(Query {
+            body: QueryBody::Database(query),
+            ..
+        }) = statements.next().unwrap()
+        else {
+            panic!("expected database query");
+        };
+        assert_eq!(
+            query.lookups[0].keys.sql,
+            "SELECT DISTINCT TOP 1001 CAST(name AS VARCHAR(MAX)) COLLATE Latin1_General_BIN2 AS \"__sqlpage_lookup_key\" FROM t"
+        );
+    }
+
+    #[test]
+    fn grouped_projection_reuses_group_by_lookup() {
+        let query = rewrite_database(
+            "select sqlpage.url_encode(name) as n, count(*) from t where x > 1 group by sqlpage.url_encode(name)",
+        );
+        assert_eq!(
+            query.sql,
+            "SELECT CASE CAST(name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_1 THEN NULL END AS n, count(*) FROM t WHERE x > 1 \
+             GROUP BY CASE CAST(name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END"
+        );
+        assert!(query.computed_columns.is_empty());
+        assert_eq!(
+            query.lookups[0].keys.sql,
+            "SELECT DISTINCT CAST(name AS TEXT) COLLATE \"C\" AS \"__sqlpage_lookup_key\" FROM t WHERE x > 1 LIMIT 1001"
+        );
+    }
+
+    #[test]
+    fn lookup_branches_are_added_to_the_generated_case() {
+        // An identifier written like a marker in the SQL file is left alone
+        let query = rewrite_database(
+            "select __sqlpage_lookup_0 from t where sqlpage.url_encode(name) = 'a%20b'",
+        );
+        let table = LookupTable {
+            entries: vec![("a b".to_owned(), Some("a%20b".to_owned()))],
+            null_result: None,
+        };
+        let (sql, arguments) =
+            expand_lookups(&query, &database(SupportedDatabase::Postgres), vec![table]);
+        assert_eq!(
+            sql,
+            "SELECT __sqlpage_lookup_0 FROM t WHERE CASE CAST(name AS TEXT) COLLATE \"C\" \
+             WHEN CAST($1 AS TEXT) THEN CAST($2 AS TEXT) ELSE CAST($3 AS TEXT) END = 'a%20b'"
+        );
+        assert_eq!(
+            arguments,
+            [(
+                0,
+                vec![Some("a b".to_owned()), Some("a%20b".to_owned()), None]
+            )]
+        );
+    }
+
+    #[test]
+    fn lookups_require_precompute_sqlpage_functions() {
+        let database = DbInfo {
+            precompute_sqlpage_functions: false,
+            ..database(SupportedDatabase::Postgres)
+        };
+        let mut statements = parse_sql(
+            &database,
+            &PostgreSqlDialect {},
+            "select id from t where sqlpage.url_encode(name) = 'x'",
+        )
+        .unwrap();
+        let Some(FileStatement::Error(error)) = statements.next() else {
+            panic!("expected rewrite error");
+        };
+        assert!(
+            format!("{error:#}").contains("precompute_sqlpage_functions"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn impure_call_on_column_cannot_be_looked_up() {
+        let FileStatement::Error(error) = one("select id from t where sqlpage.fetch(url) = 'x'")
         else {
             panic!("expected rewrite error");
         };
-        assert!(format!("{error:#}").contains("required before the query"));
+        assert!(format!("{error:#}").contains("side effects"), "{error:#}");
+    }
+
+    #[test]
+    fn positional_lookups_record_preceding_bindings() {
+        let database = database(SupportedDatabase::MySql);
+        let mut statements = parse_sql(
+            &database,
+            &MySqlDialect {},
+            "select $a from t where sqlpage.url_encode(name) = $b",
+        )
+        .unwrap();
+        let FileStatement::Query(Query {
+            body: QueryBody::Database(query),
+            ..
+        }) = statements.next().unwrap()
+        else {
+            panic!("expected database query");
+        };
+        assert_eq!(query.lookups[0].preceding_bindings, 1);
+        assert_eq!(query.bindings.as_ref(), [variable("a"), variable("b")]);
     }
 
     #[test]
@@ -555,13 +730,15 @@ mod tests {
     }
 
     #[test]
-    fn database_cannot_order_by_computed_column() {
-        let FileStatement::Error(error) =
-            one("select sqlpage.url_encode(value) as encoded from t order by encoded")
-        else {
-            panic!("expected rewrite error");
-        };
-        assert!(error.to_string().contains("ORDER BY"));
+    fn order_by_computed_alias_is_looked_up() {
+        let query =
+            rewrite_database("select sqlpage.url_encode(value) as encoded from t order by encoded");
+        assert_eq!(
+            query.sql,
+            "SELECT value AS \"__sqlpage_input_0\" FROM t \
+             ORDER BY CASE CAST(value AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END"
+        );
+        assert_eq!(query.computed_columns.len(), 1);
     }
 
     #[test]
@@ -575,13 +752,16 @@ mod tests {
     }
 
     #[test]
-    fn database_cannot_group_by_computed_column() {
-        let FileStatement::Error(error) = one(
-            "select coalesce(sqlpage.url_encode(name), '') as enc, count(*) from users group by enc",
-        ) else {
-            panic!("expected rewrite error");
-        };
-        assert!(error.to_string().contains("GROUP BY"));
+    fn group_by_computed_alias_is_looked_up() {
+        let query = rewrite_database(
+            "select coalesce(sqlpage.url_encode(name), '') as enc, count(*) from users group by 1",
+        );
+        assert_eq!(
+            query.sql,
+            "SELECT coalesce(CASE CAST(name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_1 THEN NULL END, '') AS enc, count(*) FROM users \
+             GROUP BY coalesce(CASE CAST(name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END, '')"
+        );
+        assert!(query.computed_columns.is_empty());
     }
 
     #[test]
@@ -615,13 +795,15 @@ mod tests {
     }
 
     #[test]
-    fn database_cannot_group_by_computed_column_in_expression() {
-        let FileStatement::Error(error) =
-            one("select sqlpage.url_encode(name) as enc, count(*) from users group by lower(enc)")
-        else {
-            panic!("expected rewrite error");
-        };
-        assert!(error.to_string().contains("GROUP BY"));
+    fn order_by_expression_of_computed_alias_is_looked_up() {
+        let query = rewrite_database(
+            "select sqlpage.url_encode(name) as enc from users order by lower(enc)",
+        );
+        assert_eq!(
+            query.sql,
+            "SELECT name AS \"__sqlpage_input_0\" FROM users \
+             ORDER BY lower(CASE CAST(name AS TEXT) COLLATE \"C\" WHEN __sqlpage_lookup_0 THEN NULL END)"
+        );
     }
 
     #[test]
@@ -753,6 +935,9 @@ mod tests {
                     ]),
                 }]),
                 json_columns: Box::new([]),
+                lookups: Box::new([]),
+                lookup_statement: None,
+                blob: false,
             }
         );
     }
@@ -776,6 +961,9 @@ mod tests {
                     value: call(SqlPageFunctionName::url_encode, [row(0)]),
                 }]),
                 json_columns: Box::new([]),
+                lookups: Box::new([]),
+                lookup_statement: None,
+                blob: false,
             }
         );
     }
@@ -801,6 +989,9 @@ mod tests {
                     ]),
                 }]),
                 json_columns: Box::new([]),
+                lookups: Box::new([]),
+                lookup_statement: None,
+                blob: false,
             }
         );
     }
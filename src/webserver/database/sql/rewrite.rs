@@ -23,17 +23,20 @@ use std::str::FromStr as _;
 
 use anyhow::{Context as _, anyhow};
 use serde_json::Value as JsonValue;
+use sqlparser::ast::helpers::attached_token::AttachedToken;
 use sqlparser::ast::{
-    BinaryOperator, CastKind, CharacterLength, DataType, Expr as SqlExpr, Function, FunctionArg,
-    FunctionArgExpr, FunctionArgumentList, FunctionArguments, GroupByExpr, Ident, ObjectName,
-    ObjectNamePart, OrderByKind, SelectItem, SetExpr, Statement as SqlStatement, Value,
+    BinaryOperator, CaseWhen, CastKind, CharacterLength, DataType, Distinct, Expr as SqlExpr,
+    Function, FunctionArg, FunctionArgExpr, FunctionArgumentList, FunctionArguments, GroupByExpr,
+    Ident, JoinConstraint, JoinOperator, LimitClause, ObjectName, ObjectNamePart, OrderByKind,
+    Query as SqlQuery, SelectItem, SetExpr, Statement as SqlStatement, Top, TopQuantity, Value,
     ValueWithSpan, VisitMut, VisitorMut,
 };
 use sqlparser::tokenizer::Span;
 
 use super::dialect::{PlaceholderStyle, placeholder_style};
 use super::statement::{
-    DatabaseQuery, OutputColumn, Query, QueryBody, SingleRowQuery, SourceLocation, SourceSpan,
+    DatabaseQuery, Lookup, OutputColumn, Query, QueryBody, SingleRowQuery, SourceLocation,
+    SourceSpan,
 };
 use super::{extract_json_columns, is_json_expression, is_sqlpage_func};
 use crate::webserver::database::sqlpage_expr::{
@@ -43,6 +46,9 @@ use crate::webserver::database::sqlpage_functions::functions::SqlPageFunctionNam
 use crate::webserver::database::{DbInfo, SupportedDatabase};
 
 const SQLPAGE_INPUT_PREFIX: &str = "__sqlpage_input_";
+const SQLPAGE_LOOKUP_PREFIX: &str = "__sqlpage_lookup_";
+/// Maximum number of distinct database values a `SQLPage` function can be precomputed for in one query.
+pub(in crate::webserver::database) const MAX_LOOKUP_VALUES: usize = 1000;
 
 /// Mutable state used while rewriting one database query.
 struct QueryRewriter<'a> {
@@ -50,9 +56,35 @@ struct QueryRewriter<'a> {
     bindings: Vec<StandaloneExpr>,
     row_input_json: Vec<bool>,
     private_projection: Vec<SelectItem>,
+    /// Set while visiting a clause where pure `SQLPage` functions of database values are precomputed.
+    lookup_site: Option<LookupSite>,
+    lookup_sources: Option<LookupSources>,
+    lookups: Vec<Lookup>,
+    /// `GROUP BY` expressions that were precomputed, so that identical projections can reuse them.
+    grouped_calls: Vec<SqlExpr>,
+    /// Nesting level of subqueries inside the visited expression.
+    query_depth: usize,
     error: Option<anyhow::Error>,
 }
 
+/// The clause in which a `SQLPage` function of database values is used.
+#[derive(Clone, Copy)]
+struct LookupSite {
+    clause: &'static str,
+    /// Whether the argument is computed after grouping, and may contain aggregates.
+    grouped: bool,
+}
+
+/// Copies of the outer query listing the values a precomputed function argument can take.
+/// Both select every distinct value of the argument in the rows (or groups) the query works on,
+/// or a superset of them.
+struct LookupSources {
+    /// The `FROM` and `WHERE` clauses, without the conditions that themselves need lookups.
+    rows: SqlQuery,
+    /// The `FROM`, `WHERE` and `GROUP BY` clauses, if none of them need lookups.
+    groups: Option<SqlQuery>,
+}
+
 struct ComputedAliasFinder<'a> {
     computed_columns: &'a [OutputColumn<RowExpr>],
 }
@@ -146,16 +178,32 @@ enum EmulatedFunction {
 /// Rewrites one parsed statement into database SQL plus the `SQLPage`
 /// expressions evaluated around it.
 pub(super) fn rewrite_query(
+    statement: SqlStatement,
+    database: &DbInfo,
+    semicolon: bool,
+) -> anyhow::Result<Query> {
+    rewrite_statement(statement, database, semicolon, true)
+}
+
+/// Rewrites one statement. Queries listing lookup keys are rewritten without lookups of their own.
+fn rewrite_statement(
     mut statement: SqlStatement,
     database: &DbInfo,
     semicolon: bool,
+    allow_lookups: bool,
 ) -> anyhow::Result<Query> {
     let source_span = source_span(&statement);
+    let blob = selects_blob_component(&statement);
     let mut rewriter = QueryRewriter {
         database,
         bindings: Vec::new(),
         row_input_json: Vec::new(),
         private_projection: Vec::new(),
+        lookup_site: None,
+        lookup_sources: None,
+        lookups: Vec::new(),
+        grouped_calls: Vec::new(),
+        query_depth: 0,
         error: None,
     };
     if let Some(single_row) = rewrite_single_row(&mut statement, &mut rewriter)? {
@@ -164,6 +212,9 @@ pub(super) fn rewrite_query(
             source_span,
         });
     }
+    if allow_lookups {
+        rewrite_clause_lookups(&mut statement, &mut rewriter)?;
+    }
     let computed_columns = rewrite_top_level_projection(&mut statement, &mut rewriter)?;
 
     let _ = statement.visit(&mut rewriter);
@@ -191,6 +242,7 @@ pub(super) fn rewrite_query(
         "{statement}{semicolon}",
         semicolon = if semicolon { ";" } else { "" }
     );
+    let lookup_statement = (!rewriter.lookups.is_empty()).then(|| Box::new(statement));
 
     Ok(Query {
         body: QueryBody::Database(DatabaseQuery {
@@ -199,11 +251,312 @@ pub(super) fn rewrite_query(
             row_input_json: rewriter.row_input_json.into_boxed_slice(),
             computed_columns: computed_columns.into_boxed_slice(),
             json_columns,
+            lookups: rewriter.lookups.into_boxed_slice(),
+            lookup_statement,
+            blob,
         }),
         source_span,
     })
 }
 
+/// Whether the statement is a `SELECT 'blob' AS component, ...`
+fn selects_blob_component(statement: &SqlStatement) -> bool {
+    let SqlStatement::Query(query) = statement else {
+        return false;
+    };
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return false;
+    };
+    select.projection.iter().any(|item| {
+        matches!(
+            item,
+            SelectItem::ExprWithAlias {
+                expr: SqlExpr::Value(ValueWithSpan {
+                    value: Value::SingleQuotedString(component),
+                    ..
+                }),
+                alias,
+            } if component == "blob" && alias.value.eq_ignore_ascii_case("component")
+        )
+    })
+}
+
+/// Precomputes the pure `SQLPage` functions of database values that are used in the `JOIN`,
+/// `WHERE`, `GROUP BY`, `HAVING` and `ORDER BY` clauses of a `SELECT`.
+fn rewrite_clause_lookups(
+    statement: &mut SqlStatement,
+    rewriter: &mut QueryRewriter<'_>,
+) -> anyhow::Result<()> {
+    let SqlStatement::Query(query) = statement else {
+        return Ok(());
+    };
+    if !depends_on_sqlpage_row_function(query.as_ref()) {
+        return Ok(());
+    }
+    let SetExpr::Select(select) = query.body.as_mut() else {
+        return Ok(());
+    };
+    if let GroupByExpr::Expressions(expressions, _) = &mut select.group_by {
+        for expression in expressions {
+            inline_computed_aliases(expression, &select.projection);
+        }
+    }
+    if let Some(order_by) = &mut query.order_by
+        && let OrderByKind::Expressions(expressions) = &mut order_by.kind
+    {
+        for ordering in expressions {
+            inline_computed_aliases(&mut ordering.expr, &select.projection);
+        }
+    }
+    let grouped = select.having.is_some()
+        || !matches!(&select.group_by, GroupByExpr::Expressions(expressions, _) if expressions.is_empty());
+    rewriter.lookup_sources = Some(LookupSources::new(query.as_ref()));
+    let SetExpr::Select(select) = query.body.as_mut() else {
+        unreachable!("the query body was checked")
+    };
+    for table in &mut select.from {
+        for join in &mut table.joins {
+            if let Some(JoinConstraint::On(condition)) =
+                join_constraint_mut(&mut join.join_operator)
+            {
+                rewriter.rewrite_lookup_clause("JOIN", false, condition)?;
+            }
+        }
+    }
+    if let Some(selection) = &mut select.selection {
+        rewriter.rewrite_lookup_clause("WHERE", false, selection)?;
+    }
+    if let GroupByExpr::Expressions(expressions, _) = &mut select.group_by {
+        for expression in expressions {
+            if depends_on_sqlpage_row_function(expression) {
+                rewriter.grouped_calls.push(expression.clone());
+            }
+            rewriter.rewrite_lookup_clause("GROUP BY", false, expression)?;
+        }
+    }
+    if let Some(having) = &mut select.having {
+        rewriter.rewrite_lookup_clause("HAVING", true, having)?;
+    }
+    if let Some(order_by) = &mut query.order_by
+        && let OrderByKind::Expressions(expressions) = &mut order_by.kind
+    {
+        for ordering in expressions {
+            rewriter.rewrite_lookup_clause("ORDER BY", grouped, &mut ordering.expr)?;
+        }
+    }
+    Ok(())
+}
+
+impl LookupSources {
+    fn new(query: &SqlQuery) -> Self {
+        let mut rows = query.clone();
+        rows.order_by = None;
+        rows.limit_clause = None;
+        rows.fetch = None;
+        rows.locks.clear();
+        rows.for_clause = None;
+        rows.settings = None;
+        rows.format_clause = None;
+        rows.pipe_operators.clear();
+        let SetExpr::Select(select) = rows.body.as_mut() else {
+            unreachable!("lookups are only used in SELECT queries")
+        };
+        select.distinct = Some(Distinct::Distinct);
+        select.top = None;
+        select.into = None;
+        select.projection.clear();
+        select.having = None;
+        select.qualify = None;
+        select.sort_by.clear();
+        select.cluster_by.clear();
+        select.distribute_by.clear();
+        let groups = rows.clone();
+
+        let SetExpr::Select(select) = rows.body.as_mut() else {
+            unreachable!("lookups are only used in SELECT queries")
+        };
+        let mut exact = !depends_on_sqlpage_row_function(&select.group_by);
+        select.group_by = GroupByExpr::Expressions(vec![], vec![]);
+        for table in &mut select.from {
+            for join in &mut table.joins {
+                if let Some(JoinConstraint::On(condition)) =
+                    join_constraint_mut(&mut join.join_operator)
+                    && depends_on_sqlpage_row_function(condition)
+                {
+                    let one = || Box::new(SqlExpr::value(Value::Number("1".into(), false)));
+                    let placeholder = SqlExpr::value(Value::Null);
+                    *condition = independent_conjuncts(std::mem::replace(condition, placeholder))
+                        .unwrap_or_else(|| SqlExpr::BinaryOp {
+                            left: one(),
+                            op: BinaryOperator::Eq,
+                            right: one(),
+                        });
+                    exact = false;
+                }
+            }
+        }
+        if let Some(selection) = select.selection.take() {
+            exact &= !depends_on_sqlpage_row_function(&selection);
+            select.selection = independent_conjuncts(selection);
+        }
+        Self {
+            rows,
+            groups: exact.then_some(groups),
+        }
+    }
+
+    /// A query listing the distinct text values of `key`. It returns one more than
+    /// [`MAX_LOOKUP_VALUES`] rows at most, so that larger lookups are detected without listing them all.
+    fn keys_statement(
+        &self,
+        site: LookupSite,
+        key: SqlExpr,
+        database: SupportedDatabase,
+    ) -> Option<SqlStatement> {
+        let mut query = if site.grouped {
+            self.groups.clone()?
+        } else {
+            self.rows.clone()
+        };
+        let SetExpr::Select(select) = query.body.as_mut() else {
+            unreachable!("lookups are only used in SELECT queries")
+        };
+        select.projection = vec![SelectItem::ExprWithAlias {
+            expr: key,
+            alias: Ident::with_quote('"', format!("{SQLPAGE_LOOKUP_PREFIX}key")),
+        }];
+        let limit = MAX_LOOKUP_VALUES as u64 + 1;
+        if database == SupportedDatabase::Mssql {
+            select.top = Some(Top {
+                with_ties: false,
+                percent: false,
+                quantity: Some(TopQuantity::Constant(limit)),
+            });
+        } else {
+            query.limit_clause = Some(LimitClause::LimitOffset {
+                limit: Some(SqlExpr::value(Value::Number(limit.to_string(), false))),
+                offset: None,
+                limit_by: vec![],
+            });
+        }
+        Some(SqlStatement::Query(Box::new(query)))
+    }
+}
+
+/// The conditions of a conjunction that do not call `SQLPage` functions with database values.
+/// Every row matching `condition` also matches the result.
+fn independent_conjuncts(condition: SqlExpr) -> Option<SqlExpr> {
+    match condition {
+        SqlExpr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => match (independent_conjuncts(*left), independent_conjuncts(*right)) {
+            (Some(left), Some(right)) => Some(SqlExpr::BinaryOp {
+                left: Box::new(left),
+                op: BinaryOperator::And,
+                right: Box::new(right),
+            }),
+            (left, right) => left.or(right),
+        },
+        SqlExpr::Nested(inner) => {
+            independent_conjuncts(*inner).map(|e| SqlExpr::Nested(Box::new(e)))
+        }
+        condition if depends_on_sqlpage_row_function(&condition) => None,
+        condition => Some(condition),
+    }
+}
+
+/// Replaces references to projections that call `SQLPage` functions with database values,
+/// by alias or by position, with the projected expression, so that they can be looked up.
+fn inline_computed_aliases(expression: &mut SqlExpr, projection: &[SelectItem]) {
+    let projected = |item: &SelectItem| match item {
+        SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. }
+            if depends_on_sqlpage_row_function(expr) =>
+        {
+            Some(expr.clone())
+        }
+        _ => None,
+    };
+    if let SqlExpr::Value(ValueWithSpan {
+        value: Value::Number(position, _),
+        ..
+    }) = expression
+    {
+        if let Some(expr) = position
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| projection.get(position.checked_sub(1)?))
+            .and_then(projected)
+        {
+            *expression = expr;
+        }
+        return;
+    }
+    let _ = sqlparser::ast::visit_expressions_mut(expression, |expression| {
+        if let SqlExpr::Identifier(identifier) = expression
+            && let Some(expr) = projection.iter().find_map(|item| match item {
+                SelectItem::ExprWithAlias { alias, .. }
+                    if alias.value == identifier.value
+                        || (identifier.quote_style.is_none()
+                            && alias.value.eq_ignore_ascii_case(&identifier.value)) =>
+                {
+                    projected(item)
+                }
+                _ => None,
+            })
+        {
+            *expression = expr;
+        }
+        ControlFlow::<()>::Continue(())
+    });
+}
+
+fn join_constraint_mut(operator: &mut JoinOperator) -> Option<&mut JoinConstraint> {
+    match operator {
+        JoinOperator::Join(constraint)
+        | JoinOperator::Inner(constraint)
+        | JoinOperator::Left(constraint)
+        | JoinOperator::LeftOuter(constraint)
+        | JoinOperator::Right(constraint)
+        | JoinOperator::RightOuter(constraint)
+        | JoinOperator::FullOuter(constraint)
+        | JoinOperator::CrossJoin(constraint)
+        | JoinOperator::Semi(constraint)
+        | JoinOperator::LeftSemi(constraint)
+        | JoinOperator::RightSemi(constraint)
+        | JoinOperator::Anti(constraint)
+        | JoinOperator::LeftAnti(constraint)
+        | JoinOperator::RightAnti(constraint)
+        | JoinOperator::StraightJoin(constraint)
+        | JoinOperator::AsOf { constraint, .. } => Some(constraint),
+        JoinOperator::CrossApply
+        | JoinOperator::OuterApply
+        | JoinOperator::ArrayJoin
+        | JoinOperator::LeftArrayJoin
+        | JoinOperator::InnerArrayJoin => None,
+    }
+}
+
+/// Whether a `SQLPage` function call needs a value computed by the database.
+fn depends_on_sqlpage_row_function(node: &impl sqlparser::ast::Visit) -> bool {
+    struct Finder;
+    impl sqlparser::ast::Visitor for Finder {
+        type Break = ();
+
+        fn pre_visit_expr(&mut self, expression: &SqlExpr) -> ControlFlow<Self::Break> {
+            if let SqlExpr::Function(function) = expression
+                && is_sqlpage_func(&function.name.0)
+                && !can_build_standalone(expression).unwrap_or(true)
+            {
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        }
+    }
+    node.visit(&mut Finder).is_break()
+}
+
 /// Removes SQLPage-owned projection expressions from the database projection
 /// and appends their private database inputs as a trailing suffix.
 fn rewrite_top_level_projection(
@@ -222,8 +575,17 @@ fn rewrite_top_level_projection(
         );
     }
 
-    let (mut database_projection, computed_columns) =
-        rewrite_projection_items(std::mem::take(&mut select.projection), rewriter)?;
+    if rewriter.lookup_sources.is_some() {
+        let grouped = select.having.is_some()
+            || !matches!(&select.group_by, GroupByExpr::Expressions(expressions, _) if expressions.is_empty());
+        rewriter.lookup_site = Some(LookupSite {
+            clause: "SELECT",
+            grouped,
+        });
+    }
+    let projection = rewrite_projection_items(std::mem::take(&mut select.projection), rewriter);
+    rewriter.lookup_site = None;
+    let (mut database_projection, computed_columns) = projection?;
     database_projection.append(&mut rewriter.private_projection);
     select.projection = database_projection;
 
@@ -260,7 +622,22 @@ fn rewrite_projection_items(
         let name = alias
             .as_ref()
             .map_or_else(|| expression.to_string(), |alias| alias.value.clone());
-        match rewriter.rewrite_projection(expression)? {
+        let rewritten = if rewriter.grouped_calls.contains(&expression) {
+            // The database can only return a grouping key it computed itself.
+            // Its arguments take the same values as before grouping.
+            let mut expression = expression;
+            let site = rewriter.lookup_site;
+            if let Some(site) = &mut rewriter.lookup_site {
+                site.grouped = false;
+            }
+            let result = rewriter.rewrite_database_expression(&mut expression);
+            rewriter.lookup_site = site;
+            result?;
+            RewrittenProjection::Database(expression)
+        } else {
+            rewriter.rewrite_projection(expression)?
+        };
+        match rewritten {
             RewrittenProjection::Database(expression) => {
                 database_projection.push(match alias {
                     Some(alias) => SelectItem::ExprWithAlias {
@@ -613,14 +990,125 @@ impl QueryRewriter<'_> {
         self.error.take().map_or(Ok(()), Err)
     }
 
+    /// Rewrites a clause evaluated by the database, precomputing its `SQLPage` functions of
+    /// database values. Clauses without such functions are left for the final statement visit.
+    fn rewrite_lookup_clause(
+        &mut self,
+        clause: &'static str,
+        grouped: bool,
+        expression: &mut SqlExpr,
+    ) -> anyhow::Result<()> {
+        if !depends_on_sqlpage_row_function(expression) {
+            return Ok(());
+        }
+        self.lookup_site = Some(LookupSite { clause, grouped });
+        let result = self.rewrite_database_expression(expression);
+        self.lookup_site = None;
+        result
+    }
+
+    /// Whether a `SQLPage` function call found by the visitor should become a lookup.
+    fn should_look_up(&self, call: &SqlExpr) -> bool {
+        self.lookup_site.is_some()
+            && self.query_depth == 0
+            && !can_build_standalone(call).unwrap_or(true)
+    }
+
+    /// Replaces a pure `SQLPage` function of one database value with a `CASE` expression on that
+    /// value, whose branches are filled with the precomputed results before the query runs.
+    fn add_lookup(&mut self, call: SqlExpr) -> anyhow::Result<SqlExpr> {
+        let mut site = self
+            .lookup_site
+            .expect("lookups are only added inside a lookup site");
+        // Grouping keys take the same values as before grouping
+        site.grouped &= !self.grouped_calls.contains(&call);
+        let SqlExpr::Function(function) = call else {
+            unreachable!("only function calls are looked up")
+        };
+        let function_name =
+            recognize_sqlpage_function(&function)?.expect("only SQLPage functions are looked up");
+        let clause = site.clause;
+        if !self.database.precompute_sqlpage_functions {
+            anyhow::bail!(
+                "{function_name} cannot be called with database values in {clause}. Set precompute_sqlpage_functions to true in the configuration to let SQLPage compute it in advance for every value, with an additional query, or call it in the SELECT clause."
+            );
+        }
+        if !function_name.is_pure() {
+            anyhow::bail!(
+                "{function_name} cannot be called with database values in {clause}: it has side effects, so SQLPage cannot compute it in advance for every row"
+            );
+        }
+        let (arguments, _) = take_expression_arguments(function)?;
+        let mut key = None;
+        let mut call_arguments = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            if can_build_standalone(&argument)? {
+                call_arguments.push(build_sqlpage_expr::<RowEnvironment>(self, argument)?);
+            } else if key.is_none() {
+                key = Some(argument);
+                call_arguments.push(SqlPageExpr::Input(RowInputId::new(0)));
+            } else {
+                anyhow::bail!(
+                    "{function_name} can only take one argument computed by the database when it is used in {clause}. Combine the database values into a single argument."
+                );
+            }
+        }
+        let key = key.expect("a looked up call depends on the database");
+        let key = lookup_key(key, self.database.database_type);
+        let sources = self
+            .lookup_sources
+            .as_ref()
+            .expect("lookup sources are set with the lookup site");
+        let keys_statement = sources
+            .keys_statement(site, key.clone(), self.database.database_type)
+            .ok_or_else(|| {
+            anyhow!(
+                "{function_name} cannot be called with aggregated values in {clause} when the FROM, WHERE or GROUP BY clauses also call SQLPage functions with database values"
+            )
+        })?;
+        let QueryBody::Database(keys) =
+            rewrite_statement(keys_statement, self.database, false, false)?.body
+        else {
+            anyhow::bail!(
+                "The argument of {function_name} in {clause} does not depend on the database"
+            )
+        };
+        let marker = format!("{SQLPAGE_LOOKUP_PREFIX}{}", self.lookups.len());
+        self.lookups.push(Lookup {
+            marker: marker.clone(),
+            preceding_bindings: 0,
+            keys,
+            call: SqlPageExpr::Call {
+                function: function_name,
+                arguments: call_arguments.into_boxed_slice(),
+            },
+        });
+        Ok(SqlExpr::Case {
+            case_token: AttachedToken::empty(),
+            end_token: AttachedToken::empty(),
+            operand: Some(Box::new(key)),
+            conditions: vec![CaseWhen {
+                condition: SqlExpr::Identifier(Ident::new(marker)),
+                result: SqlExpr::value(Value::Null),
+            }],
+            else_result: None,
+        })
+    }
+
     fn add_binding(&mut self, value: StandaloneExpr) -> SqlExpr {
         let sequence = self.bindings.len();
+        let is_binary = value.is_binary();
         self.bindings.push(value);
         let placeholder = match placeholder_style(self.database.kind) {
             PlaceholderStyle::Numbered { prefix } => format!("{prefix}{}", sequence + 1),
             PlaceholderStyle::Positional { .. } => format!("${}", sequence + 1),
         };
-        cast_placeholder(placeholder, self.database.database_type)
+        if is_binary {
+            // Binary parameters keep the type sent by the driver
+            SqlExpr::value(Value::Placeholder(placeholder))
+        } else {
+            cast_placeholder(placeholder, self.database.database_type)
+        }
     }
 
     fn add_row_input(&mut self, mut expression: SqlExpr) -> anyhow::Result<RowInputId> {
@@ -647,12 +1135,27 @@ impl QueryRewriter<'_> {
             token,
             binding_count: self.bindings.len(),
             order: Vec::with_capacity(self.bindings.len()),
+            lookup_markers: Vec::with_capacity(self.lookups.len()),
             error: None,
         };
         let _ = statement.visit(&mut finalizer);
         if let Some(error) = finalizer.error {
             return Err(error);
         }
+        // Lookup arguments are inserted between the other bindings, in SQL text order
+        let mut lookups = std::mem::take(&mut self.lookups)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        for (marker, preceding_bindings) in finalizer.lookup_markers {
+            let index = lookups
+                .iter()
+                .position(|lookup| lookup.as_ref().is_some_and(|l| l.marker == marker))
+                .ok_or_else(|| anyhow!("Generated lookup {marker} was repeated"))?;
+            let mut lookup = lookups[index].take().expect("lookup was found");
+            lookup.preceding_bindings = preceding_bindings;
+            self.lookups.push(lookup);
+        }
         let mut bindings = std::mem::take(&mut self.bindings)
             .into_iter()
             .map(Some)
@@ -673,6 +1176,8 @@ struct PositionalBindingFinalizer {
     token: &'static str,
     binding_count: usize,
     order: Vec<usize>,
+    /// Lookup markers with the number of placeholders preceding them.
+    lookup_markers: Vec<(String, usize)>,
     error: Option<anyhow::Error>,
 }
 
@@ -680,6 +1185,11 @@ impl VisitorMut for PositionalBindingFinalizer {
     type Break = ();
 
     fn pre_visit_expr(&mut self, expression: &mut SqlExpr) -> ControlFlow<Self::Break> {
+        if let Some(marker) = generated_lookup_marker(expression) {
+            self.lookup_markers
+                .push((marker.to_owned(), self.order.len()));
+            return ControlFlow::Continue(());
+        }
         let SqlExpr::Value(ValueWithSpan {
             value: Value::Placeholder(name),
             span,
@@ -710,6 +1220,16 @@ impl VisitorMut for PositionalBindingFinalizer {
 impl VisitorMut for QueryRewriter<'_> {
     type Break = ();
 
+    fn pre_visit_query(&mut self, _query: &mut SqlQuery) -> ControlFlow<Self::Break> {
+        self.query_depth += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, _query: &mut SqlQuery) -> ControlFlow<Self::Break> {
+        self.query_depth -= 1;
+        ControlFlow::Continue(())
+    }
+
     fn pre_visit_expr(&mut self, expression: &mut SqlExpr) -> ControlFlow<Self::Break> {
         if self.error.is_some() {
             return ControlFlow::Break(());
@@ -727,6 +1247,16 @@ impl VisitorMut for QueryRewriter<'_> {
             | SqlExpr::Identifier(_) => variable_from_expr(expression)
                 .map(|variable| self.add_binding(SqlPageExpr::Variable(variable))),
             SqlExpr::Function(function) => match recognize_sqlpage_function(function) {
+                Ok(Some(_)) if self.should_look_up(expression) => {
+                    let owned = std::mem::replace(expression, SqlExpr::value(Value::Null));
+                    match self.add_lookup(owned) {
+                        Ok(lookup) => Some(lookup),
+                        Err(error) => {
+                            self.error = Some(error);
+                            None
+                        }
+                    }
+                }
                 Ok(Some(_)) => {
                     let owned = std::mem::replace(expression, SqlExpr::value(Value::Null));
                     match build_sqlpage_expr::<StandaloneEnvironment>(self, owned) {
@@ -1038,7 +1568,40 @@ fn variable_source(prefix: char) -> VariableSource {
 /// Wraps a generated placeholder in the backend-specific text cast expected
 /// by `SQLPage`'s string-valued binding interface.
 fn cast_placeholder(placeholder: String, database: SupportedDatabase) -> SqlExpr {
-    let data_type = match database {
+    SqlExpr::Cast {
+        expr: Box::new(SqlExpr::value(Value::Placeholder(placeholder))),
+        data_type: text_data_type(database),
+        format: None,
+        kind: CastKind::Cast,
+        array: false,
+    }
+}
+
+/// The text of a lookup argument, compared byte by byte: two keys are only considered equal
+/// when the function receives the same text for both of them.
+fn lookup_key(argument: SqlExpr, database: SupportedDatabase) -> SqlExpr {
+    let text = SqlExpr::Cast {
+        expr: Box::new(argument),
+        data_type: text_data_type(database),
+        format: None,
+        kind: CastKind::Cast,
+        array: false,
+    };
+    let collation = match database {
+        SupportedDatabase::Postgres => Ident::with_quote('"', "C"),
+        SupportedDatabase::Sqlite => Ident::new("BINARY"),
+        SupportedDatabase::MySql => Ident::new("utf8mb4_bin"),
+        SupportedDatabase::Mssql => Ident::new("Latin1_General_BIN2"),
+        _ => return text,
+    };
+    SqlExpr::Collate {
+        expr: Box::new(text),
+        collation: ObjectName(vec![ObjectNamePart::Identifier(collation)]),
+    }
+}
+
+fn text_data_type(database: SupportedDatabase) -> DataType {
+    match database {
         SupportedDatabase::MySql => DataType::Char(None),
         SupportedDatabase::Mssql => DataType::Varchar(Some(CharacterLength::Max)),
         SupportedDatabase::Postgres | SupportedDatabase::Sqlite => DataType::Text,
@@ -1047,13 +1610,129 @@ fn cast_placeholder(placeholder: String, database: SupportedDatabase) -> SqlExpr
             unit: None,
         })),
         _ => DataType::Varchar(None),
+    }
+}
+
+/// Values computed for one [`Lookup`] during one execution of its query.
+#[derive(Default)]
+pub(in crate::webserver::database) struct LookupTable {
+    pub entries: Vec<(String, Option<String>)>,
+    /// The result for a `NULL` argument.
+    pub null_result: Option<String>,
+}
+
+/// Arguments of each lookup, with the index of the query binding they are inserted before.
+pub(in crate::webserver::database) type LookupArguments = Vec<(usize, Vec<Option<String>>)>;
+
+/// Adds the computed lookup tables to the `CASE` expressions of `query`, and returns its SQL
+/// with the arguments they need.
+pub(in crate::webserver::database) fn expand_lookups(
+    query: &DatabaseQuery,
+    database: &DbInfo,
+    tables: Vec<LookupTable>,
+) -> (String, LookupArguments) {
+    let style = placeholder_style(database.kind);
+    let mut statement = query
+        .lookup_statement
+        .as_deref()
+        .expect("queries with lookups keep their statement")
+        .clone();
+    let mut next_placeholder = query.bindings.len() + 1;
+    let mut expander = LookupExpander {
+        cases: Vec::with_capacity(tables.len()),
     };
-    SqlExpr::Cast {
-        expr: Box::new(SqlExpr::value(Value::Placeholder(placeholder))),
-        data_type,
-        format: None,
-        kind: CastKind::Cast,
-        array: false,
+    let mut arguments = Vec::with_capacity(tables.len());
+    for (lookup, table) in query.lookups.iter().zip(tables) {
+        let mut placeholder = || {
+            let placeholder = match style {
+                PlaceholderStyle::Numbered { prefix } => format!("{prefix}{next_placeholder}"),
+                PlaceholderStyle::Positional { token } => token.to_owned(),
+            };
+            next_placeholder += 1;
+            cast_placeholder(placeholder, database.database_type)
+        };
+        let mut conditions = Vec::with_capacity(table.entries.len().max(1));
+        let mut values = Vec::with_capacity(table.entries.len() * 2 + 1);
+        for (key, result) in table.entries {
+            conditions.push(CaseWhen {
+                condition: placeholder(),
+                result: placeholder(),
+            });
+            values.extend([Some(key), result]);
+        }
+        if conditions.is_empty() {
+            conditions.push(CaseWhen {
+                condition: SqlExpr::value(Value::Null),
+                result: SqlExpr::value(Value::Null),
+            });
+        }
+        let else_result = placeholder();
+        values.push(table.null_result);
+        expander
+            .cases
+            .push((lookup.marker.as_str(), Some((conditions, else_result))));
+        let insert_before = match style {
+            PlaceholderStyle::Numbered { .. } => query.bindings.len(),
+            PlaceholderStyle::Positional { .. } => lookup.preceding_bindings,
+        };
+        arguments.push((insert_before, values));
+    }
+    let _ = statement.visit(&mut expander);
+    let semicolon = if query.sql.ends_with(';') { ";" } else { "" };
+    (format!("{statement}{semicolon}"), arguments)
+}
+
+/// The `WHEN` branches of a lookup, and its `ELSE` result for `NULL` arguments.
+type LookupBranches = (Vec<CaseWhen>, SqlExpr);
+
+/// Replaces the placeholder branch of the `CASE` expression generated for each lookup.
+struct LookupExpander<'a> {
+    cases: Vec<(&'a str, Option<LookupBranches>)>,
+}
+
+impl VisitorMut for LookupExpander<'_> {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expression: &mut SqlExpr) -> ControlFlow<Self::Break> {
+        let SqlExpr::Case {
+            conditions,
+            else_result,
+            ..
+        } = expression
+        else {
+            return ControlFlow::Continue(());
+        };
+        let [CaseWhen { condition, .. }] = conditions.as_slice() else {
+            return ControlFlow::Continue(());
+        };
+        let Some(marker) = generated_lookup_marker(condition) else {
+            return ControlFlow::Continue(());
+        };
+        let case = self
+            .cases
+            .iter_mut()
+            .find(|(name, _)| *name == marker)
+            .and_then(|(_, case)| case.take());
+        if let Some((branches, otherwise)) = case {
+            *conditions = branches;
+            *else_result = Some(Box::new(otherwise));
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// The marker of a lookup `CASE` expression. Markers are generated without a source location,
+/// so identifiers written in the SQL file are never taken for them.
+fn generated_lookup_marker(expression: &SqlExpr) -> Option<&str> {
+    match expression {
+        SqlExpr::Identifier(identifier)
+            if identifier.quote_style.is_none()
+                && identifier.span == Span::empty()
+                && identifier.value.starts_with(SQLPAGE_LOOKUP_PREFIX) =>
+        {
+            Some(&identifier.value)
+        }
+        _ => None,
     }
 }
 
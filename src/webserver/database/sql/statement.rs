@@ -50,6 +50,12 @@ pub(in crate::webserver::database) struct DatabaseQuery {
     /// Evaluated once for every returned database row.
     pub computed_columns: Box<[OutputColumn<RowExpr>]>,
     pub json_columns: Box<[String]>,
+    /// `SQLPage` functions of database values used by the database itself, in SQL text order.
+    pub lookups: Box<[Lookup]>,
+    /// The statement containing the `CASE` expressions of the lookups, completed before every execution.
+    pub lookup_statement: Option<Box<sqlparser::ast::Statement>>,
+    /// Selects `'blob' AS component`: its rows are sent as files, with their binary contents.
+    pub blob: bool,
 }
 
 impl DatabaseQuery {
@@ -62,6 +68,23 @@ impl DatabaseQuery {
     }
 }
 
+/// A pure `SQLPage` function of a database value, used where the database needs its result,
+/// like in a `WHERE` or `GROUP BY` clause.
+///
+/// Before `sql` runs, `keys` lists every value the argument can take, the function is evaluated
+/// once per value, and the results replace the `WHEN <marker> THEN NULL` branch of a
+/// `CASE <argument> ...` expression in the statement.
+#[derive(Debug, PartialEq)]
+pub(in crate::webserver::database) struct Lookup {
+    pub marker: String,
+    /// Generated placeholders that precede the marker, for backends with positional placeholders.
+    pub preceding_bindings: usize,
+    /// Returns the distinct values of the argument, as text, in a single column.
+    pub keys: DatabaseQuery,
+    /// The function call, reading the argument value as row input 0.
+    pub call: RowExpr,
+}
+
 /// Exactly one row generated without querying the database.
 #[derive(Debug, PartialEq)]
 pub(in crate::webserver::database) struct SingleRowQuery {
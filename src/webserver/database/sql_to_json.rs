@@ -16,7 +16,7 @@
 //! owns physical row decoding and does not decide where expressions execute.
 
 use crate::utils::add_value_to_map;
-use crate::webserver::database::blob_to_data_url;
+use crate::webserver::database::{Blob, blob_to_data_url};
 use bigdecimal::BigDecimal;
 use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
 use serde_json::{self, Map, Value};
@@ -70,6 +70,66 @@ pub(super) fn row_to_json_with_inputs(
     Ok((Value::Object(map), inputs))
 }
 
+/// Decodes a row of a query that selects the `blob` component, whose `contents` column is sent to the client as it is.
+/// Returns `None` for rows that set another component.
+pub(super) fn row_to_blob(row: &AnyRow) -> Option<anyhow::Result<Blob>> {
+    let column = |name: &str| {
+        row.columns()
+            .iter()
+            .find(|col| canonical_col_name(col) == name)
+    };
+    let component = sql_to_json(row, column("component")?);
+    if component.as_str()? != "blob" {
+        return None;
+    }
+    let text_column = |name: &str| match column(name).map(|col| sql_to_json(row, col)) {
+        Some(Value::String(s)) => Some(s),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    };
+    let Some(contents_column) = column("contents") else {
+        return Some(Err(anyhow::anyhow!(
+            "The blob component requires a 'contents' column"
+        )));
+    };
+    let contents = match row.try_get_raw(contents_column.ordinal()) {
+        Ok(raw_value) if raw_value.is_null() => Vec::new(),
+        Ok(raw_value) if is_binary_type(raw_value.type_info().name()) => {
+            decode_raw::<Vec<u8>>(raw_value)
+        }
+        Ok(_) => text_column("contents").unwrap_or_default().into_bytes(),
+        Err(e) => return Some(Err(e.into())),
+    };
+    Some(Ok(Blob {
+        contents,
+        content_type: text_column("content_type"),
+        filename: text_column("filename"),
+    }))
+}
+
+fn is_binary_type(type_name: &str) -> bool {
+    matches!(
+        type_name,
+        "BLOB" | "BYTEA" | "FILESTREAM" | "VARBINARY" | "BIGVARBINARY" | "BINARY" | "IMAGE"
+    )
+}
+
+/// The names of the columns that have a date or timestamp type
+pub(super) fn date_column_names(row: &AnyRow) -> Vec<String> {
+    row.columns()
+        .iter()
+        .filter(|col| is_date_type(col.type_info().name()))
+        .map(canonical_col_name)
+        .collect()
+}
+
+fn is_date_type(type_name: &str) -> bool {
+    matches!(
+        type_name,
+        "DATE" | "DATETIME" | "DATETIME2" | "DATETIMEOFFSET" | "TIMESTAMP" | "TIMESTAMPTZ"
+    )
+}
+
 fn canonical_col_name(col: &AnyColumn) -> String {
     // Some databases fold all unquoted identifiers to uppercase but SQLPage uses lowercase property names
     if matches!(col.type_info().0, AnyTypeInfoKind::Odbc(_))
@@ -186,11 +246,9 @@ pub(super) fn sql_nonnull_to_json<'r>(
         "MONEY" | "SMALLMONEY" if matches!(db_type, Mssql(_)) => {
             decode_raw::<f64>(raw_value).into()
         }
-        "UUID" | "UNIQUEIDENTIFIER" => decode_raw::<sqlx::types::uuid::Uuid>(raw_value)
-            .to_string()
-            .into(),
+        "UUID" | "UNIQUEIDENTIFIER" => decode_raw::<uuid::Uuid>(raw_value).to_string().into(),
         "JSON" | "JSON[]" | "JSONB" | "JSONB[]" => decode_raw::<Value>(raw_value),
-        "BLOB" | "BYTEA" | "FILESTREAM" | "VARBINARY" | "BIGVARBINARY" | "BINARY" | "IMAGE" => {
+        _ if is_binary_type(type_name) => {
             blob_to_data_url::vec_to_data_uri_value(&decode_raw::<Vec<u8>>(raw_value))
         }
         "INT4RANGE" => decode_pg_range::<i32>(raw_value),
@@ -10,7 +10,7 @@ use anyhow::Context as _;
 use serde_json::Value;
 
 use super::execute_queries::DbConn;
-use super::sqlpage_functions::functions::SqlPageFunctionName;
+use super::sqlpage_functions::functions::{SqlPageFunctionName, read_uploaded_file};
 use crate::webserver::http_request_info::ExecutionContext;
 use crate::webserver::single_or_vec::SingleOrVec;
 
@@ -299,6 +299,45 @@ impl<Input> SqlPageExpr<Input> {
         }
     }
 
+    /// Whether this expression is bound to the query as bytes instead of text
+    pub(crate) fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            Self::Call {
+                function: SqlPageFunctionName::uploaded_file_blob,
+                ..
+            }
+        )
+    }
+
+    /// Evaluates an expression that [`is_binary`](Self::is_binary)
+    pub(crate) async fn evaluate_binary(
+        &self,
+        request: &ExecutionContext,
+        db_connection: &mut DbConn,
+        inputs: &mut impl ExprInputs<Input>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let Self::Call {
+            function: function @ SqlPageFunctionName::uploaded_file_blob,
+            arguments,
+        } = self
+        else {
+            anyhow::bail!("This expression is not binary");
+        };
+        let [upload_name] = &arguments[..] else {
+            anyhow::bail!(
+                "{function} expects a single argument: the name of the file upload field"
+            );
+        };
+        let upload_name = Box::pin(upload_name.evaluate(request, db_connection, inputs))
+            .await?
+            .into_function_argument();
+        match upload_name {
+            Some(name) => read_uploaded_file(request.into(), &name).await,
+            None => Ok(None),
+        }
+    }
+
     pub(crate) fn contains_function(&self, expected: SqlPageFunctionName) -> bool {
         match self {
             Self::Call {
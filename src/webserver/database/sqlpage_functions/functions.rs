@@ -7,9 +7,12 @@
 //! dispatch are handled generically in [`super::function_traits`].
 
 use std::fmt::Write;
+use std::pin::Pin;
 
 use super::function_traits::sqlpage_functions;
 
+pub(crate) use uploaded_file_blob::read_uploaded_file;
+
 sqlpage_functions! {
     basic_auth_password,
     basic_auth_username,
@@ -19,30 +22,39 @@ sqlpage_functions! {
     current_working_directory,
     environment_variable,
     exec,
+    exec_with_meta,
     fetch,
+    fetch_all,
     fetch_with_meta,
+    graphql,
     hash_password,
     header,
     headers,
     hmac,
+    json_rpc,
     link,
     oidc_logout_url,
     path,
     persist_uploaded_file,
+    process_image,
     protocol,
     random_string,
     read_file_as_data_url,
     read_file_as_text,
     regex_match,
+    render_pdf,
     request_body,
     request_body_base64,
     request_method,
     run_sql,
     send_mail,
     set_variable,
+    t,
+    uploaded_file_blob,
     uploaded_file_mime_type,
     uploaded_file_name,
     uploaded_file_path,
+    uploaded_file_scan_result,
     url_encode,
     user_info,
     user_info_token,
@@ -51,6 +63,48 @@ sqlpage_functions! {
     web_root,
 }
 
+impl SqlPageFunctionName {
+    /// Whether the function has no side effects and returns the same result for the same
+    /// arguments during a request, so it can be evaluated once per distinct argument value.
+    /// Functions are not pure unless they are listed here.
+    pub(crate) fn is_pure(self) -> bool {
+        matches!(
+            self,
+            Self::basic_auth_password
+                | Self::basic_auth_username
+                | Self::client_ip
+                | Self::configuration_directory
+                | Self::cookie
+                | Self::current_working_directory
+                | Self::environment_variable
+                | Self::header
+                | Self::headers
+                | Self::hmac
+                | Self::link
+                | Self::oidc_logout_url
+                | Self::path
+                | Self::protocol
+                | Self::read_file_as_data_url
+                | Self::read_file_as_text
+                | Self::regex_match
+                | Self::request_body
+                | Self::request_body_base64
+                | Self::request_method
+                | Self::t
+                | Self::uploaded_file_mime_type
+                | Self::uploaded_file_name
+                | Self::uploaded_file_path
+                | Self::uploaded_file_scan_result
+                | Self::url_encode
+                | Self::user_info
+                | Self::user_info_token
+                | Self::variables
+                | Self::version
+                | Self::web_root
+        )
+    }
+}
+
 impl ::std::str::FromStr for SqlPageFunctionName {
     type Err = anyhow::Error;
 
@@ -75,6 +129,13 @@ impl ::std::fmt::Display for SqlPageFunctionName {
     }
 }
 
+/// Moves the future of a function to the heap.
+/// The future that dispatches a `sqlpage.*` call is as large as the largest function future,
+/// so the functions that keep a lot of state across `.await` points box it to keep all calls small.
+fn boxed<F: Future>(future: F) -> Pin<Box<F>> {
+    Box::pin(future)
+}
+
 fn supported_function_list() -> String {
     let mut supported = String::new();
     for function in SqlPageFunctionName::ALL {
@@ -1,8 +1,13 @@
 use std::borrow::Cow;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
 
 use anyhow::Context;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
 use tracing::Instrument;
 
+use super::boxed;
 use crate::webserver::http_request_info::RequestInfo;
 
 /// Executes an external command and returns its output.
@@ -11,30 +16,15 @@ pub(super) async fn exec<'a>(
     program_name: Cow<'a, str>,
     args: Vec<Cow<'a, str>>,
 ) -> anyhow::Result<String> {
-    if !request.app_state.config.allow_exec {
-        anyhow::bail!("The sqlpage.exec() function is disabled in the configuration, for security reasons.
-        Make sure you understand the security implications before enabling it, and never allow user input to be passed as the first argument to this function.
-        You can enable it by setting the allow_exec option to true in the sqlpage.json configuration file.")
-    }
-    let exec_span = tracing::info_span!(
-        "subprocess",
-        otel.name = format!("EXEC {program_name}"),
-        process.command = %program_name,
-        process.args_count = args.len(),
-    );
-    let res = tokio::process::Command::new(&*program_name)
-        .args(args.iter().map(|x| &**x))
-        .output()
-        .instrument(exec_span)
-        .await
-        .with_context(|| {
-            let mut s = format!("Unable to execute command: {program_name}");
-            for arg in args {
-                s.push(' ');
-                s.push_str(&arg);
-            }
-            s
-        })?;
+    let command = PreparedCommand::new(request, &program_name, &args)?;
+    let res = command.run(None).await.with_context(|| {
+        let mut s = format!("Unable to execute command: {program_name}");
+        for arg in args {
+            s.push(' ');
+            s.push_str(&arg);
+        }
+        s
+    })?;
     if !res.status.success() {
         anyhow::bail!(
             "Command '{program_name}' failed with exit code {}: {}",
@@ -44,3 +34,154 @@ pub(super) async fn exec<'a>(
     }
     Ok(String::from_utf8_lossy(&res.stdout).into_owned())
 }
+
+/// A command that is allowed to run, either because it is in `exec_commands`, or because `allow_exec` is true
+pub(super) struct PreparedCommand {
+    command: Command,
+    name: String,
+    args_count: usize,
+    timeout: Option<Duration>,
+    max_output_size: Option<usize>,
+}
+
+pub(super) struct ExecOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl PreparedCommand {
+    pub(super) fn new(
+        request: &RequestInfo,
+        program_name: &str,
+        args: &[Cow<'_, str>],
+    ) -> anyhow::Result<Self> {
+        let config = &request.app_state.config;
+        if let Some(allowed) = config.exec_commands.get(program_name) {
+            let patterns = allowed.allowed_args_regexes()?;
+            for arg in args {
+                if !patterns.iter().any(|pattern| pattern.is_match(arg)) {
+                    anyhow::bail!(
+                        "The argument {arg:?} is not allowed for the command '{program_name}'. \
+                        Allowed arguments are defined by allowed_args in the exec_commands configuration."
+                    );
+                }
+            }
+            let mut command = Command::new(&allowed.program);
+            command
+                .args(&allowed.args)
+                .args(args.iter().map(|x| &**x))
+                .env_clear()
+                .envs(&allowed.env);
+            if let Some(dir) = &allowed.working_directory {
+                command.current_dir(dir);
+            }
+            return Ok(Self {
+                command,
+                name: program_name.to_string(),
+                args_count: args.len(),
+                timeout: Some(Duration::from_millis(allowed.timeout_ms)),
+                max_output_size: Some(allowed.max_output_size),
+            });
+        }
+        if !config.allow_exec {
+            let mut aliases: Vec<_> = config.exec_commands.keys().map(String::as_str).collect();
+            aliases.sort_unstable();
+            anyhow::bail!("The sqlpage.exec() function is disabled in the configuration, for security reasons, and '{program_name}' is not in exec_commands.
+        Commands configured in exec_commands can always be executed. Available commands: {aliases:?}.
+        Make sure you understand the security implications before enabling allow_exec, and never allow user input to be passed as the first argument to this function.
+        You can enable it by setting the allow_exec option to true in the sqlpage.json configuration file.")
+        }
+        let mut command = Command::new(program_name);
+        command.args(args.iter().map(|x| &**x));
+        Ok(Self {
+            command,
+            name: program_name.to_string(),
+            args_count: args.len(),
+            timeout: None,
+            max_output_size: None,
+        })
+    }
+
+    /// Runs the command to completion, writing `stdin` to its standard input
+    pub(super) async fn run(mut self, stdin: Option<&str>) -> anyhow::Result<ExecOutput> {
+        let exec_span = tracing::info_span!(
+            "subprocess",
+            otel.name = format!("EXEC {}", self.name),
+            process.command = %self.name,
+            process.args_count = self.args_count,
+            process.exit.code = tracing::field::Empty,
+        );
+        let span = exec_span.clone();
+        boxed(async {
+            let mut child = self
+                .command
+                .stdin(if stdin.is_some() {
+                    Stdio::piped()
+                } else {
+                    Stdio::null()
+                })
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+            let child_stdin = child.stdin.take();
+            let write_stdin = async {
+                if let (Some(mut child_stdin), Some(input)) = (child_stdin, stdin) {
+                    match child_stdin.write_all(input.as_bytes()).await {
+                        // the process does not have to read all of its input
+                        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+                        other => other?,
+                    }
+                }
+                anyhow::Ok(())
+            };
+            let stdout = read_output(child.stdout.take(), self.max_output_size);
+            let stderr = read_output(child.stderr.take(), self.max_output_size);
+            let run = async {
+                let ((), stdout, stderr) = tokio::try_join!(write_stdin, stdout, stderr)?;
+                let status = child.wait().await?;
+                anyhow::Ok(ExecOutput {
+                    status,
+                    stdout,
+                    stderr,
+                })
+            };
+            // the process is killed when the future is dropped, on timeout
+            let output = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, run)
+                    .await
+                    .map_err(|_| anyhow::anyhow!("The command timed out after {timeout:?}"))??,
+                None => run.await?,
+            };
+            if let Some(code) = output.status.code() {
+                span.record("process.exit.code", code);
+            }
+            Ok(output)
+        }
+        .instrument(exec_span))
+        .await
+    }
+}
+
+async fn read_output(
+    reader: Option<impl AsyncRead + Unpin>,
+    max_size: Option<usize>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let Some(reader) = reader else {
+        return Ok(output);
+    };
+    let Some(max_size) = max_size else {
+        let mut reader = reader;
+        reader.read_to_end(&mut output).await?;
+        return Ok(output);
+    };
+    let limit = u64::try_from(max_size)?.saturating_add(1);
+    reader.take(limit).read_to_end(&mut output).await?;
+    anyhow::ensure!(
+        output.len() <= max_size,
+        "The output of the command is larger than max_output_size ({max_size} bytes)"
+    );
+    Ok(output)
+}
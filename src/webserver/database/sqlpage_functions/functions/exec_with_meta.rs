@@ -0,0 +1,58 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+
+use crate::webserver::http_request_info::RequestInfo;
+
+use super::exec::PreparedCommand;
+
+/// `{"command": "pandoc", "args": ["--to=html"], "stdin": "# Title"}`
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ExecRequest {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    stdin: Option<String>,
+}
+
+/// Executes an external command, and returns its exit code and outputs as a JSON object,
+/// even when it fails.
+pub(super) async fn exec_with_meta<'a>(
+    request: &'a RequestInfo,
+    command: Cow<'a, str>,
+    args: Vec<Cow<'a, str>>,
+) -> anyhow::Result<String> {
+    let exec_request = if command.trim_start().starts_with('{') {
+        anyhow::ensure!(
+            args.is_empty(),
+            "When the command is given as a JSON object, its arguments must be in the \"args\" property"
+        );
+        serde_json::from_str(&command)
+            .with_context(|| format!("Invalid command definition: {command}"))?
+    } else {
+        ExecRequest {
+            command: command.into_owned(),
+            args: args.into_iter().map(Cow::into_owned).collect(),
+            stdin: None,
+        }
+    };
+    let args: Vec<Cow<'_, str>> = exec_request
+        .args
+        .iter()
+        .map(|arg| Cow::Borrowed(arg.as_str()))
+        .collect();
+    let prepared = PreparedCommand::new(request, &exec_request.command, &args)?;
+    let result = match prepared.run(exec_request.stdin.as_deref()).await {
+        Ok(output) => serde_json::json!({
+            "exit_code": output.status.code(),
+            "stdout": String::from_utf8_lossy(&output.stdout),
+            "stderr": String::from_utf8_lossy(&output.stderr),
+        }),
+        Err(e) => {
+            log::warn!("Command '{}' failed: {e:#}", exec_request.command);
+            serde_json::json!({ "error": format!("{e:#}") })
+        }
+    };
+    Ok(result.to_string())
+}
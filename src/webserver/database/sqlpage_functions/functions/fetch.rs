@@ -1,28 +1,42 @@
-use std::{fmt::Write, str::FromStr};
+use std::{borrow::Cow, fmt::Write, future::Future, str::FromStr, sync::Arc, time::Duration};
 
-use anyhow::{Context, anyhow};
+use actix_web::http::{
+    Method, StatusCode,
+    header::{self, HeaderMap, HeaderName, HeaderValue, HttpDate},
+};
+use awc::error::PayloadError;
+use sha2::{Digest, Sha256};
+
+use anyhow::Context;
 use opentelemetry_semantic_conventions::attribute as otel;
 use tracing::Instrument;
 
 use crate::webserver::{
     database::sqlpage_functions::http_fetch_request::HttpFetchRequest,
-    http_client::make_http_client,
+    fetch_cache::{CacheLookup, FetchedResponse},
     http_request_info::RequestInfo,
 };
 
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+const MAX_RETRIES: u32 = 10;
+
+fn request_method(http_request: &HttpFetchRequest<'_>) -> anyhow::Result<Method> {
+    if let Some(method) = &http_request.method {
+        Method::from_str(method).with_context(|| format!("Invalid HTTP method: {method}"))
+    } else {
+        Ok(Method::GET)
+    }
+}
+
 pub(super) fn build_request<'a>(
     client: &'a awc::Client,
     http_request: &'a HttpFetchRequest<'_>,
 ) -> anyhow::Result<awc::ClientRequest> {
-    use awc::http::Method;
-    let method = if let Some(method) = &http_request.method {
-        Method::from_str(method).with_context(|| format!("Invalid HTTP method: {method}"))?
-    } else {
-        Method::GET
-    };
+    let method = request_method(http_request)?;
     let mut req = client.request(method, http_request.url.as_ref());
     if let Some(timeout) = http_request.timeout_ms {
-        req = req.timeout(core::time::Duration::from_millis(timeout));
+        req = req.timeout(Duration::from_millis(timeout));
     }
     for (k, v) in &http_request.headers {
         req = req.insert_header((k.as_ref(), v.as_ref()));
@@ -59,17 +73,14 @@ pub(super) fn fetch_span(http_request: &HttpFetchRequest<'_>) -> tracing::Span {
         { otel::URL_FULL } = %http_request.url,
         { otel::HTTP_REQUEST_BODY_SIZE } = tracing::field::Empty,
         { otel::HTTP_RESPONSE_STATUS_CODE } = tracing::field::Empty,
+        { otel::HTTP_REQUEST_RESEND_COUNT } = tracing::field::Empty,
     )
 }
 
-pub(super) fn send_request(
-    request: &RequestInfo,
+fn send_request(
+    req: awc::ClientRequest,
     http_request: &HttpFetchRequest<'_>,
 ) -> anyhow::Result<awc::SendClientRequest> {
-    let client = make_http_client(&request.app_state.config)
-        .with_context(|| "Unable to create an HTTP client")?;
-    let req = build_request(&client, http_request)?;
-
     log::info!("Fetching {}", http_request.url);
     if let Some(body) = &http_request.body {
         let (body, req) = prepare_request_body(body, req)?;
@@ -83,6 +94,200 @@ pub(super) fn send_request(
     }
 }
 
+/// Why a request did not produce a complete response
+pub(super) enum FetchError {
+    /// No response was received
+    Request(awc::error::SendRequestError),
+    /// A response was received, but its body could not be read
+    Body {
+        status: StatusCode,
+        headers: HeaderMap,
+        error: PayloadError,
+    },
+}
+
+impl FetchError {
+    fn into_error(self, url: &str) -> anyhow::Error {
+        match self {
+            Self::Request(e) => anyhow::anyhow!("Unable to fetch {url}: {e}"),
+            Self::Body { error, .. } => anyhow::Error::new(error)
+                .context(format!("Unable to read the body of the response from {url}")),
+        }
+    }
+}
+
+/// Sends the request, and resends it after transient failures when `retries` allows it.
+/// Identical GET and HEAD requests with a `cache_ttl_ms` share their responses.
+pub(super) async fn fetch_response(
+    request: &RequestInfo,
+    http_request: &HttpFetchRequest<'_>,
+) -> anyhow::Result<Result<Arc<FetchedResponse>, FetchError>> {
+    let method = request_method(http_request)?;
+    let cache = &request.app_state.fetch_cache;
+    let cache_key = http_request
+        .cache_ttl_ms
+        .filter(|_| method == Method::GET || method == Method::HEAD)
+        .map(|ttl| (cache_key(&method, http_request), Duration::from_millis(ttl)));
+    let mut stale = None;
+    if let Some((key, ttl)) = &cache_key {
+        match cache.get(key, *ttl) {
+            Some(CacheLookup::Fresh(response)) => {
+                log::debug!("Using the cached response of {}", http_request.url);
+                return Ok(Ok(response));
+            }
+            Some(CacheLookup::Stale(response)) => stale = Some(response),
+            None => {}
+        }
+    }
+
+    let client = request
+        .app_state
+        .http_clients
+        .get(http_request.tls_profile.as_deref())?;
+    let validators = stale.iter().flat_map(|stale| stale.validators()).collect();
+    let response_result = send_with_retries(&client, &method, http_request, validators).await?;
+    let mut response = match response_result {
+        Ok(response) => response,
+        Err(e) => return Ok(Err(FetchError::Request(e))),
+    };
+
+    let status = response.status();
+    tracing::Span::current().record(otel::HTTP_RESPONSE_STATUS_CODE, i64::from(status.as_u16()));
+    log::debug!(
+        "Finished fetching {}. Status: {status}, content_type={:?}",
+        http_request.url,
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+    );
+    if let (StatusCode::NOT_MODIFIED, Some(stale), Some((key, _))) = (status, stale, &cache_key) {
+        log::debug!("The cached response of {} is still valid", http_request.url);
+        cache.revalidated(key, response.headers());
+        return Ok(Ok(stale));
+    }
+    let headers = response.headers().clone();
+    let body = match response.body().await {
+        Ok(body) => body,
+        Err(error) => {
+            return Ok(Err(FetchError::Body {
+                status,
+                headers,
+                error,
+            }));
+        }
+    };
+    log::debug!(
+        "Fetched {} response body: body_len={} bytes",
+        http_request.url,
+        body.len()
+    );
+    let response = Arc::new(FetchedResponse {
+        status,
+        headers,
+        body,
+    });
+    if let Some((key, _)) = cache_key
+        && status.is_success()
+    {
+        cache.insert(key, Arc::clone(&response));
+    }
+    Ok(Ok(response))
+}
+
+/// Resends the request after network errors and transient server errors, with exponential backoff.
+/// A `Retry-After` header of the response replaces the backoff, and the request is not resent
+/// when it asks to wait longer than [`RETRY_MAX_DELAY`].
+async fn send_with_retries(
+    client: &awc::Client,
+    method: &Method,
+    http_request: &HttpFetchRequest<'_>,
+    extra_headers: Vec<(HeaderName, HeaderValue)>,
+) -> anyhow::Result<<awc::SendClientRequest as Future>::Output> {
+    // resending a request that is not idempotent could apply it twice
+    let retries = if method.is_idempotent() {
+        http_request.retries.unwrap_or(0).min(MAX_RETRIES)
+    } else {
+        0
+    };
+    let mut resend_count = 0;
+    loop {
+        let mut req = build_request(client, http_request)?;
+        for header in &extra_headers {
+            req = req.insert_header(header.clone());
+        }
+        let response_result = send_request(req, http_request)?.await;
+        let (failure, retry_after) = match &response_result {
+            Ok(response) if is_transient_status(response.status()) => (
+                format!("status {}", response.status()),
+                retry_after(response.headers()),
+            ),
+            Ok(_) => return Ok(response_result),
+            Err(e) => (e.to_string(), None),
+        };
+        if resend_count >= retries {
+            return Ok(response_result);
+        }
+        let delay = retry_after.unwrap_or_else(|| {
+            RETRY_BASE_DELAY
+                .saturating_mul(2_u32.saturating_pow(resend_count))
+                .min(RETRY_MAX_DELAY)
+        });
+        if delay > RETRY_MAX_DELAY {
+            log::warn!(
+                "Fetching {} failed ({failure}), and the server asks to retry in {delay:?}. Not retrying.",
+                http_request.url
+            );
+            return Ok(response_result);
+        }
+        resend_count += 1;
+        log::warn!(
+            "Fetching {} failed ({failure}). Retrying in {delay:?} (attempt {resend_count} of {retries})",
+            http_request.url
+        );
+        tracing::Span::current().record(otel::HTTP_REQUEST_RESEND_COUNT, resend_count);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The `Retry-After` header, in seconds or as a date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = std::time::SystemTime::from(value.parse::<HttpDate>().ok()?);
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Identifies the requests that get the same response.
+/// It is a hash, so that the credentials of the requests are not kept in memory.
+fn cache_key(method: &Method, http_request: &HttpFetchRequest<'_>) -> String {
+    let request = serde_json::json!([
+        method.as_str(),
+        http_request.url,
+        http_request.headers,
+        http_request.username,
+        http_request.password,
+        http_request.tls_profile,
+        http_request.body.as_deref().map(serde_json::value::RawValue::get),
+    ]);
+    base64::Engine::encode(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+        Sha256::digest(request.to_string().as_bytes()),
+    )
+}
+
 pub(super) async fn fetch(
     request: &RequestInfo,
     http_request: Option<HttpFetchRequest<'_>>,
@@ -93,52 +298,59 @@ pub(super) async fn fetch(
     let fetch_span = fetch_span(&http_request);
 
     async {
-        let response_result = send_request(request, &http_request)?.await;
-        let mut response = response_result
-            .map_err(|e| anyhow!("Unable to fetch {}: {e}", http_request.url))?;
-
-        tracing::Span::current().record(
-            otel::HTTP_RESPONSE_STATUS_CODE,
-            i64::from(response.status().as_u16()),
-        );
-
-        log::debug!(
-            "Finished fetching {}. Status: {}",
-            http_request.url,
-            response.status()
-        );
-        log::debug!(
-            "Fetch response headers for {}: content_type={:?}",
-            http_request.url,
-            response
-                .headers()
-                .get("content-type")
-                .and_then(|value| value.to_str().ok())
-        );
-
-        let body = response
-            .body()
-            .await
-            .with_context(|| {
-                format!(
-                    "Unable to read the body of the response from {}",
-                    http_request.url
-                )
-            })?
-            .to_vec();
-        log::debug!(
-            "Fetched {} response body: body_len={} bytes, encoding={:?}",
-            http_request.url,
-            body.len(),
-            http_request.response_encoding
-        );
-        let response_str = decode_response(body, http_request.response_encoding.as_deref())?;
+        let response = fetch_response(request, &http_request)
+            .await?
+            .map_err(|e| e.into_error(&http_request.url))?;
+        let response_str = decode_response(
+            response.body.to_vec(),
+            http_request.response_encoding.as_deref(),
+        )?;
         Ok(Some(response_str))
     }
     .instrument(fetch_span)
     .await
 }
 
+/// Sends a JSON body to an API like GraphQL or JSON-RPC, with the URL, headers, authentication
+/// and other options of `http_request`. The method defaults to POST.
+pub(super) async fn post_json(
+    request: &RequestInfo,
+    mut http_request: HttpFetchRequest<'_>,
+    body: &serde_json::Value,
+) -> anyhow::Result<Arc<FetchedResponse>> {
+    anyhow::ensure!(
+        http_request.body.is_none(),
+        "The body of the request to {} is built by SQLPage, and cannot be set in its definition",
+        http_request.url
+    );
+    http_request.method.get_or_insert(Cow::Borrowed("POST"));
+    http_request.body = Some(Cow::Owned(serde_json::value::to_raw_value(body)?));
+    let fetch_span = fetch_span(&http_request);
+    async {
+        fetch_response(request, &http_request)
+            .await?
+            .map_err(|e| e.into_error(&http_request.url))
+    }
+    .instrument(fetch_span)
+    .await
+}
+
+/// Parses the JSON response of an API, and shows the beginning of the response when it is not valid
+pub(super) fn parse_json_response<'a, T: serde::Deserialize<'a>>(
+    protocol: &str,
+    url: &str,
+    response: &'a FetchedResponse,
+) -> anyhow::Result<T> {
+    serde_json::from_slice(&response.body).with_context(|| {
+        let body = String::from_utf8_lossy(&response.body);
+        let excerpt: String = body.chars().take(200).collect();
+        format!(
+            "{url} did not return a valid {protocol} response (status {}): {excerpt}",
+            response.status
+        )
+    })
+}
+
 pub(super) fn decode_response(response: Vec<u8>, encoding: Option<&str>) -> anyhow::Result<String> {
     match encoding {
         Some("base64") => Ok(base64::Engine::encode(
@@ -175,3 +387,23 @@ pub(super) fn decode_response(response: Vec<u8>, encoding: Option<&str>) -> anyh
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+}
@@ -0,0 +1,62 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use futures_util::StreamExt;
+use serde_json::value::RawValue;
+
+use crate::webserver::{
+    database::sqlpage_functions::{
+        function_traits::BorrowFromStr, http_fetch_request::HttpFetchRequest,
+    },
+    http_request_info::RequestInfo,
+};
+
+use super::fetch_with_meta::fetch_with_meta_json;
+
+/// Sends the requests of a JSON array concurrently, up to `max_concurrent_fetches` at a time,
+/// and returns a JSON array with the result of each request, in the format of `sqlpage.fetch_with_meta`, in the same order.
+pub(super) async fn fetch_all<'a>(
+    request: &'a RequestInfo,
+    requests: Option<Cow<'a, str>>,
+) -> anyhow::Result<Option<String>> {
+    let Some(requests) = requests else {
+        return Ok(None);
+    };
+    let items: Vec<&RawValue> = serde_json::from_str(&requests).with_context(|| {
+        format!("sqlpage.fetch_all expects a JSON array of requests, but got: {requests}")
+    })?;
+    let max_concurrent_fetches = request.app_state.config.max_concurrent_fetches;
+    log::debug!(
+        "Fetching {} requests, up to {max_concurrent_fetches} at a time",
+        items.len()
+    );
+    let results: Vec<String> = futures_util::stream::iter(items)
+        .map(|item| fetch_item(request, item))
+        .buffered(max_concurrent_fetches)
+        .collect()
+        .await;
+    Ok(Some(format!("[{}]", results.join(","))))
+}
+
+/// Errors in the definition of a request only affect its own result
+async fn fetch_item(request: &RequestInfo, item: &RawValue) -> String {
+    let item = item.get();
+    if item == "null" {
+        return item.to_string();
+    }
+    let result = async {
+        // like in sqlpage.fetch, a request can be a URL, or a JSON object
+        let definition = if item.starts_with('"') {
+            Cow::Owned(serde_json::from_str::<String>(item)?)
+        } else {
+            Cow::Borrowed(item)
+        };
+        let http_request = HttpFetchRequest::borrow_from_str(definition)?;
+        fetch_with_meta_json(request, &http_request).await
+    }
+    .await;
+    result.unwrap_or_else(|e| {
+        log::warn!("Request failed in sqlpage.fetch_all: {e:#}");
+        serde_json::json!({ "error": format!("{e:#}") }).to_string()
+    })
+}
@@ -1,4 +1,5 @@
-use opentelemetry_semantic_conventions::attribute as otel;
+use actix_web::http::{StatusCode, header::HeaderMap};
+use serde::ser::SerializeMap;
 use tracing::Instrument;
 
 use crate::webserver::{
@@ -6,89 +7,98 @@ use crate::webserver::{
     http_request_info::RequestInfo,
 };
 
-use super::fetch::{decode_response, fetch_span, send_request};
+use super::fetch::{FetchError, decode_response, fetch_response, fetch_span};
 
 pub(super) async fn fetch_with_meta(
     request: &RequestInfo,
     http_request: Option<HttpFetchRequest<'_>>,
 ) -> anyhow::Result<Option<String>> {
-    use serde::{Serializer, ser::SerializeMap};
-
     let Some(http_request) = http_request else {
         return Ok(None);
     };
+    fetch_with_meta_json(request, &http_request).await.map(Some)
+}
+
+/// Sends the request, and describes its response, or why it failed, as a JSON object
+pub(super) async fn fetch_with_meta_json(
+    request: &RequestInfo,
+    http_request: &HttpFetchRequest<'_>,
+) -> anyhow::Result<String> {
+    use serde::Serializer;
 
-    let fetch_span = fetch_span(&http_request);
+    let fetch_span = fetch_span(http_request);
 
     async {
-        let response_result = send_request(request, &http_request)?.await;
+        let response_result = fetch_response(request, http_request).await?;
 
         let mut resp_str = Vec::new();
         let mut encoder = serde_json::Serializer::new(&mut resp_str);
         let mut obj = encoder.serialize_map(Some(3))?;
         match response_result {
-            Ok(mut response) => {
-                let status = response.status();
-                tracing::Span::current()
-                    .record(otel::HTTP_RESPONSE_STATUS_CODE, i64::from(status.as_u16()));
-                obj.serialize_entry("status", &status.as_u16())?;
-                let mut has_error = false;
-                if status.is_server_error() {
-                    has_error = true;
-                    obj.serialize_entry("error", &format!("Server error: {status}"))?;
-                }
-
-                let headers = response.headers();
+            Ok(response) => {
+                serialize_status_and_headers(&mut obj, response.status, &response.headers)?;
 
-                let is_json = headers
+                let is_json = response
+                    .headers
                     .get("content-type")
                     .and_then(|v| v.to_str().ok())
                     .unwrap_or_default()
                     .starts_with("application/json");
 
-                obj.serialize_entry(
-                    "headers",
-                    &headers
-                        .iter()
-                        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default()))
-                        .collect::<std::collections::HashMap<_, _>>(),
+                let body_str = decode_response(
+                    response.body.to_vec(),
+                    http_request.response_encoding.as_deref(),
                 )?;
-
-                match response.body().await {
-                    Ok(body) => {
-                        let body_bytes = body.to_vec();
-                        let body_str =
-                            decode_response(body_bytes, http_request.response_encoding.as_deref())?;
-                        if is_json {
-                            obj.serialize_entry(
-                                "json_body",
-                                &serde_json::value::RawValue::from_string(body_str)?,
-                            )?;
-                        } else {
-                            obj.serialize_entry("body", &body_str)?;
-                        }
-                    }
-                    Err(e) => {
-                        log::warn!("Failed to read response body: {e}");
-                        if !has_error {
-                            obj.serialize_entry(
-                                "error",
-                                &format!("Failed to read response body: {e}"),
-                            )?;
-                        }
-                    }
+                if is_json {
+                    obj.serialize_entry(
+                        "json_body",
+                        &serde_json::value::RawValue::from_string(body_str)?,
+                    )?;
+                } else {
+                    obj.serialize_entry("body", &body_str)?;
+                }
+            }
+            Err(FetchError::Body {
+                status,
+                headers,
+                error,
+            }) => {
+                let has_error = serialize_status_and_headers(&mut obj, status, &headers)?;
+                log::warn!("Failed to read response body: {error}");
+                if !has_error {
+                    obj.serialize_entry("error", &format!("Failed to read response body: {error}"))?;
                 }
             }
-            Err(e) => {
+            Err(FetchError::Request(e)) => {
                 log::warn!("Request failed: {e}");
                 obj.serialize_entry("error", &format!("Request failed: {e}"))?;
             }
         }
 
         obj.end()?;
-        let return_value = String::from_utf8(resp_str)?;
-        Ok(Some(return_value))
+        Ok(String::from_utf8(resp_str)?)
     }
     .instrument(fetch_span)
     .await
 }
+
+/// Returns whether an error was serialized, for server errors
+fn serialize_status_and_headers<M: SerializeMap>(
+    obj: &mut M,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> Result<bool, M::Error> {
+    obj.serialize_entry("status", &status.as_u16())?;
+    let has_error = status.is_server_error();
+    if has_error {
+        obj.serialize_entry("error", &format!("Server error: {status}"))?;
+    }
+    obj.serialize_entry(
+        "headers",
+        &headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default()))
+            .collect::<std::collections::HashMap<_, _>>(),
+    )?;
+    Ok(has_error)
+}
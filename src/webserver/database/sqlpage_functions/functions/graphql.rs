@@ -0,0 +1,75 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use serde_json::value::RawValue;
+
+use crate::webserver::{
+    database::sqlpage_functions::{
+        function_traits::BorrowFromStr, http_fetch_request::HttpFetchRequest,
+    },
+    http_request_info::RequestInfo,
+};
+
+use super::boxed;
+use super::fetch::{parse_json_response, post_json};
+
+/// <https://spec.graphql.org/October2021/#sec-Response-Format>
+#[derive(serde::Deserialize)]
+struct GraphQlResponse<'a> {
+    #[serde(borrow)]
+    data: Option<&'a RawValue>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+/// Sends a GraphQL query, and returns the `data` of the response as JSON.
+/// The errors returned by the server, even with a 200 status, make the function fail.
+pub(super) async fn graphql<'a>(
+    request: &'a RequestInfo,
+    endpoint: Option<Cow<'a, str>>,
+    query: Cow<'a, str>,
+    variables: Option<Cow<'a, str>>,
+) -> anyhow::Result<Option<String>> {
+    let Some(endpoint) = endpoint else {
+        return Ok(None);
+    };
+    boxed(async move {
+        let endpoint = HttpFetchRequest::borrow_from_str(endpoint)?;
+        let mut body = serde_json::json!({ "query": query });
+        if let Some(variables) = variables {
+            let variables: serde_json::Value = serde_json::from_str(&variables)
+                .with_context(|| format!("The GraphQL variables are not valid JSON: {variables}"))?;
+            anyhow::ensure!(
+                variables.is_object(),
+                "The GraphQL variables must be a JSON object, but got: {variables}"
+            );
+            body["variables"] = variables;
+        }
+        let url = endpoint.url.to_string();
+        let response = post_json(request, endpoint, &body).await?;
+        let parsed: GraphQlResponse<'_> = parse_json_response("GraphQL", &url, &response)?;
+        if !parsed.errors.is_empty() {
+            let messages: Vec<&str> = parsed.errors.iter().map(|e| e.message.as_str()).collect();
+            anyhow::bail!(
+                "The GraphQL server at {url} returned an error: {}",
+                messages.join("; ")
+            );
+        }
+        anyhow::ensure!(
+            response.status.is_success(),
+            "The GraphQL server at {url} answered with status {}",
+            response.status
+        );
+        Ok(parsed
+            .data
+            .map(RawValue::get)
+            .filter(|data| *data != "null")
+            .map(str::to_string))
+    })
+    .await
+}
@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use serde_json::value::RawValue;
+
+use crate::webserver::{
+    database::sqlpage_functions::{
+        function_traits::BorrowFromStr, http_fetch_request::HttpFetchRequest,
+    },
+    http_request_info::RequestInfo,
+};
+
+use super::boxed;
+use super::fetch::{parse_json_response, post_json};
+
+/// <https://www.jsonrpc.org/specification#response_object>
+#[derive(serde::Deserialize)]
+struct JsonRpcResponse<'a> {
+    #[serde(borrow)]
+    result: Option<&'a RawValue>,
+    #[serde(borrow)]
+    error: Option<JsonRpcError<'a>>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonRpcError<'a> {
+    code: i64,
+    message: String,
+    #[serde(borrow)]
+    data: Option<&'a RawValue>,
+}
+
+/// Calls a JSON-RPC 2.0 method, and returns its `result` as JSON.
+/// The `error` returned by the server makes the function fail.
+pub(super) async fn json_rpc<'a>(
+    request: &'a RequestInfo,
+    endpoint: Option<Cow<'a, str>>,
+    method: Cow<'a, str>,
+    params: Option<Cow<'a, str>>,
+) -> anyhow::Result<Option<String>> {
+    let Some(endpoint) = endpoint else {
+        return Ok(None);
+    };
+    boxed(async move {
+        let endpoint = HttpFetchRequest::borrow_from_str(endpoint)?;
+        let mut body = serde_json::json!({ "jsonrpc": "2.0", "method": method, "id": 1 });
+        if let Some(params) = params {
+            let params: serde_json::Value = serde_json::from_str(&params)
+                .with_context(|| format!("The JSON-RPC params are not valid JSON: {params}"))?;
+            anyhow::ensure!(
+                params.is_object() || params.is_array(),
+                "The JSON-RPC params must be a JSON array or object, but got: {params}"
+            );
+            body["params"] = params;
+        }
+        let url = endpoint.url.to_string();
+        let response = post_json(request, endpoint, &body).await?;
+        let parsed: JsonRpcResponse<'_> = parse_json_response("JSON-RPC", &url, &response)?;
+        if let Some(JsonRpcError {
+            code,
+            message,
+            data,
+        }) = parsed.error
+        {
+            let data = data.map(|data| format!(" ({})", data.get())).unwrap_or_default();
+            anyhow::bail!(
+                "The JSON-RPC method {method:?} of {url} returned error {code}: {message}{data}"
+            );
+        }
+        anyhow::ensure!(
+            response.status.is_success(),
+            "The JSON-RPC server at {url} answered with status {}",
+            response.status
+        );
+        Ok(parsed
+            .result
+            .map(RawValue::get)
+            .filter(|result| *result != "null")
+            .map(str::to_string))
+    })
+    .await
+}
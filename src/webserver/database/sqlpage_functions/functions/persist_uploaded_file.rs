@@ -1,11 +1,17 @@
 use std::borrow::Cow;
+use std::path::Path;
 
 use anyhow::Context;
+use tokio::io::AsyncReadExt;
 
+use crate::webserver::database::blob_to_data_url::detect_mime_type;
 use crate::webserver::http_request_info::RequestInfo;
 
 use super::random_string::random_string_sync;
 
+/// Enough bytes to detect the type of office documents
+const SIGNATURE_LENGTH: usize = 64;
+
 const DEFAULT_ALLOWED_EXTENSIONS: &str =
     "jpg,jpeg,png,gif,bmp,webp,pdf,txt,doc,docx,xls,xlsx,csv,mp3,mp4,wav,avi,mov";
 
@@ -32,6 +38,53 @@ pub(super) async fn persist_uploaded_file<'a>(
         let exts = allowed_extensions.collect::<Vec<_>>().join(", ");
         anyhow::bail!("file extension {extension} is not allowed. Allowed extensions: {exts}");
     }
+    check_file_signature(uploaded_file.file.path(), extension)
+        .await
+        .with_context(|| format!("invalid uploaded file {field_name:?}"))?;
+    store_file(
+        request,
+        &folder,
+        extension,
+        FileContents::Path(uploaded_file.file.path()),
+        mode.as_deref(),
+    )
+    .await
+    .with_context(|| format!("unable to persist uploaded file {field_name:?}"))
+    .map(Some)
+}
+
+/// The contents of a file to store in the uploads folder
+pub(super) enum FileContents<'a> {
+    Path(&'a Path),
+    Bytes(Vec<u8>),
+}
+
+/// Stores a file with a new random name in `folder`, in the web root or in the uploads bucket,
+/// and returns its path, relative to the root of the site.
+pub(super) async fn store_file(
+    request: &RequestInfo,
+    folder: &str,
+    extension: &str,
+    contents: FileContents<'_>,
+    mode: Option<&str>,
+) -> anyhow::Result<String> {
+    let date = chrono::Utc::now().format("%Y-%m-%d_%Hh%Mm%Ss");
+    let random_part = random_string_sync(8);
+    let random_target_name = format!("{date}_{random_part}.{extension}");
+    let app_state = &request.app_state;
+    if let Some(bucket) = app_state.file_system.uploads_bucket(&app_state.config) {
+        // The file is served from the bucket, like the other files that are not in the web root
+        let key = Path::new(folder).join(&random_target_name);
+        let contents = match contents {
+            FileContents::Path(path) => tokio::fs::read(path).await?,
+            FileContents::Bytes(bytes) => bytes,
+        };
+        bucket
+            .write(&key, contents)
+            .await
+            .context("unable to store the file in S3")?;
+        return Ok(format!("/{folder}/{random_target_name}"));
+    }
     // Resolve the folder path relative to the web root.
     // `folder` is trusted application input: it is expected to be a constant chosen by the
     // app author in their SQL code, never attacker-controlled request data. It is joined
@@ -39,24 +92,18 @@ pub(super) async fn persist_uploaded_file<'a>(
     // the caller write the uploaded file outside the web root. Callers must not pass
     // untrusted input (form fields, query parameters, headers, ...) as the folder.
     let web_root = &request.app_state.config.web_root;
-    let target_folder = web_root.join(&*folder);
+    let target_folder = web_root.join(folder);
     // create the folder if it doesn't exist
     tokio::fs::create_dir_all(&target_folder)
         .await
         .with_context(|| format!("unable to create folder {}", target_folder.display()))?;
-    let date = chrono::Utc::now().format("%Y-%m-%d_%Hh%Mm%Ss");
-    let random_part = random_string_sync(8);
-    let random_target_name = format!("{date}_{random_part}.{extension}");
     let target_path = target_folder.join(&random_target_name);
-    tokio::fs::copy(&uploaded_file.file.path(), &target_path)
-        .await
-        .with_context(|| {
-            format!(
-                "unable to copy uploaded file {field_name:?} to \"{}\"",
-                target_path.display()
-            )
-        })?;
-    set_file_mode(&target_path, mode.as_deref()).await?;
+    match contents {
+        FileContents::Path(path) => tokio::fs::copy(path, &target_path).await.map(|_| ()),
+        FileContents::Bytes(bytes) => tokio::fs::write(&target_path, bytes).await,
+    }
+    .with_context(|| format!("unable to write \"{}\"", target_path.display()))?;
+    set_file_mode(&target_path, mode).await?;
     // remove the WEB_ROOT prefix from the path, but keep the leading slash
     let path = "/".to_string()
         + target_path
@@ -68,11 +115,52 @@ pub(super) async fn persist_uploaded_file<'a>(
                     target_path.display()
                 )
             })?;
-    Ok(Some(path))
+    Ok(path)
+}
+
+const ZIP_MIME_TYPES: &[&str] = &[
+    "application/zip",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+];
+
+/// The types that files with a given extension can be detected as, for file types that have a signature
+fn expected_mime_types(extension: &str) -> Option<&'static [&'static str]> {
+    Some(match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => &["image/jpeg"],
+        "png" => &["image/png"],
+        "gif" => &["image/gif"],
+        "bmp" => &["image/bmp"],
+        "webp" => &["image/webp"],
+        "pdf" => &["application/pdf"],
+        "zip" | "docx" | "xlsx" | "pptx" => ZIP_MIME_TYPES,
+        _ => return None,
+    })
+}
+
+/// Checks that the first bytes of a file match its extension, so that the extension cannot be used
+/// to smuggle, for instance, an HTML page or a script with the name of an image.
+async fn check_file_signature(path: &Path, extension: &str) -> anyhow::Result<()> {
+    let Some(expected) = expected_mime_types(extension) else {
+        return Ok(());
+    };
+    let mut header = Vec::with_capacity(SIGNATURE_LENGTH);
+    tokio::fs::File::open(path)
+        .await?
+        .take(SIGNATURE_LENGTH as u64)
+        .read_to_end(&mut header)
+        .await?;
+    let detected = detect_mime_type(&header);
+    anyhow::ensure!(
+        expected.contains(&detected),
+        "the contents of the file do not match its extension: a .{extension} file cannot be {detected}"
+    );
+    Ok(())
 }
 
 #[cfg(unix)]
-pub(super) async fn set_file_mode(path: &std::path::Path, mode: Option<&str>) -> anyhow::Result<()> {
+pub(super) async fn set_file_mode(path: &Path, mode: Option<&str>) -> anyhow::Result<()> {
     use std::os::unix::fs::PermissionsExt;
     let mode = if let Some(mode) = mode {
         u32::from_str_radix(mode, 8)
@@ -87,6 +175,6 @@ pub(super) async fn set_file_mode(path: &std::path::Path, mode: Option<&str>) ->
 }
 
 #[cfg(not(unix))]
-pub(super) async fn set_file_mode(_path: &std::path::Path, _mode: Option<&str>) -> anyhow::Result<()> {
+pub(super) async fn set_file_mode(_path: &Path, _mode: Option<&str>) -> anyhow::Result<()> {
     Ok(())
 }
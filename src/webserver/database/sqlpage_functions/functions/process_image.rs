@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::webserver::http_request_info::RequestInfo;
+use crate::webserver::images::{Crop, Fit, ImageOptions, OutputFormat};
+
+use super::persist_uploaded_file::{FileContents, store_file};
+use super::read_file_as_data_url::read_file_bytes;
+
+/// Where the image to process comes from
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ImageSource {
+    /// The name of a file upload field
+    #[default]
+    Upload,
+    /// The path of a file, relative to the web root
+    File,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ProcessImageOptions<'a> {
+    #[serde(default)]
+    source: ImageSource,
+    #[serde(default)]
+    width: Option<u32>,
+    #[serde(default)]
+    height: Option<u32>,
+    #[serde(default)]
+    fit: Fit,
+    #[serde(default)]
+    crop: Option<Crop>,
+    #[serde(default)]
+    format: Option<OutputFormat>,
+    #[serde(default)]
+    quality: Option<u8>,
+    #[serde(borrow, default)]
+    folder: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    mode: Option<Cow<'a, str>>,
+}
+
+/// Resizes, crops or converts an uploaded image, or an image file, and stores the result in the uploads folder.
+/// Returns NULL when `source` is the name of a file upload field that was left empty.
+/// `source` is only read as a file path when the `source` option is `file`.
+pub(super) async fn process_image<'a>(
+    request: &'a RequestInfo,
+    source: Option<Cow<'a, str>>,
+    options: Option<Cow<'a, str>>,
+) -> anyhow::Result<Option<String>> {
+    let Some(source) = source else {
+        return Ok(None);
+    };
+    let options: ProcessImageOptions<'_> = match &options {
+        Some(options) => serde_json::from_str(options)
+            .with_context(|| format!("invalid image processing options: {options}"))?,
+        None => ProcessImageOptions::default(),
+    };
+    let bytes = match options.source {
+        ImageSource::File => read_file_bytes(request, &source).await?,
+        ImageSource::Upload => {
+            let Some(uploaded_file) = request.uploaded_files.get(&*source) else {
+                log::debug!(
+                    "process_image: no file was uploaded in the field {source:?}, returning NULL"
+                );
+                return Ok(None);
+            };
+            tokio::fs::read(uploaded_file.file.path())
+                .await
+                .with_context(|| format!("unable to read the uploaded file {source:?}"))?
+        }
+    };
+    let image_options = ImageOptions {
+        width: options.width,
+        height: options.height,
+        fit: options.fit,
+        crop: options.crop,
+        format: options.format,
+        quality: options.quality,
+    };
+    let processed = request
+        .app_state
+        .image_cache
+        .process(bytes, image_options)
+        .await
+        .with_context(|| format!("unable to process the image {source:?}"))?;
+    let folder = options.folder.unwrap_or(Cow::Borrowed("uploads"));
+    let path = store_file(
+        request,
+        &folder,
+        processed.format.extension(),
+        FileContents::Bytes(processed.bytes),
+        options.mode.as_deref(),
+    )
+    .await
+    .with_context(|| format!("unable to store the processed image {source:?}"))?;
+    Ok(Some(path))
+}
@@ -1,5 +1,7 @@
 use std::borrow::Cow;
 
+use crate::webserver::database::native_functions;
+
 /// Returns a string containing a JSON-encoded match object, or `null` if no match was found.
 /// The match object contains one key per capture group, with the value being the matched text.
 /// For named capture groups (`(?<name>pattern)`), the key is the name.
@@ -8,29 +10,12 @@ pub(super) async fn regex_match<'a>(
     pattern: Cow<'a, str>,
     text: Option<Cow<'a, str>>,
 ) -> Result<Option<String>, anyhow::Error> {
-    use serde::{Serializer, ser::SerializeMap};
-    let regex = regex::Regex::new(&pattern)?;
     let Some(text) = text else {
+        // Still validate the pattern, so that invalid patterns are reported even on NULL input.
+        regex::Regex::new(&pattern)?;
         return Ok(None);
     };
-    let Some(match_obj) = regex.captures(&text) else {
-        return Ok(None);
-    };
-    let mut result = Vec::with_capacity(64);
-    let mut ser = serde_json::Serializer::new(&mut result);
-    let mut map = ser.serialize_map(Some(match_obj.len()))?;
-    for (idx, maybe_name) in regex.capture_names().enumerate() {
-        if let Some(match_group) = match_obj.get(idx) {
-            if let Some(name) = maybe_name {
-                map.serialize_entry(name, match_group.as_str())?;
-            } else {
-                let key = idx.to_string();
-                map.serialize_entry(&key, match_group.as_str())?;
-            }
-        }
-    }
-    map.end()?;
-    Ok(Some(String::from_utf8(result)?))
+    native_functions::regexp_match(&pattern, &text)
 }
 
 #[tokio::test]
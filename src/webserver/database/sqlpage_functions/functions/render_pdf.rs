@@ -0,0 +1,36 @@
+use std::borrow::Cow;
+
+use crate::{
+    render::pdf::{PageSize, PdfDocument},
+    webserver::{
+        database::{blob_to_data_url::vec_to_data_uri_with_mime, execute_queries::DbConn},
+        http_request_info::ExecutionContext,
+    },
+};
+
+use super::run_sql::for_each_row;
+
+/// Renders the components returned by a SQL file as a PDF document, returned as a data URL.
+pub(super) async fn render_pdf<'a>(
+    request: &'a ExecutionContext,
+    db_connection: &mut DbConn,
+    sql_file_path: Option<Cow<'a, str>>,
+    variables: Option<Cow<'a, str>>,
+) -> anyhow::Result<Option<String>> {
+    let Some(sql_file_path) = sql_file_path else {
+        log::debug!("render_pdf: first argument is NULL, returning NULL");
+        return Ok(None);
+    };
+    let mut document = PdfDocument::new(Vec::new(), PageSize::A4)?;
+    for_each_row(
+        "render_pdf",
+        request,
+        db_connection,
+        &sql_file_path,
+        variables,
+        |row| document.handle_row(&row),
+    )
+    .await?;
+    let bytes = document.finish()?;
+    Ok(Some(vec_to_data_uri_with_mime(&bytes, "application/pdf")))
+}
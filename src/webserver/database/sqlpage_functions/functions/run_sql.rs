@@ -23,6 +23,36 @@ pub(super) async fn run_sql<'a>(
         log::debug!("run_sql: first argument is NULL, returning NULL");
         return Ok(None);
     };
+    let mut json_results_bytes = Vec::new();
+    let mut json_encoder = serde_json::Serializer::new(&mut json_results_bytes);
+    let mut seq = json_encoder.serialize_seq(None)?;
+    for_each_row(
+        "run_sql",
+        request,
+        db_connection,
+        &sql_file_path,
+        variables,
+        |row| {
+            log::debug!("run_sql: row: {row:?}");
+            seq.serialize_element(&row)?;
+            Ok(())
+        },
+    )
+    .await?;
+    seq.end()?;
+    Ok(Some(Cow::Owned(String::from_utf8(json_results_bytes)?)))
+}
+
+/// Executes the SQL file at `sql_file_path` with the given variables, and calls `on_row` for each
+/// row it returns. `function` is the name of the calling function, used in error messages.
+pub(super) async fn for_each_row(
+    function: &str,
+    request: &ExecutionContext,
+    db_connection: &mut DbConn,
+    sql_file_path: &str,
+    variables: Option<Cow<'_, str>>,
+    mut on_row: impl FnMut(serde_json::Value) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
     let run_sql_span = tracing::info_span!(
         "sqlpage.file",
         otel.name = format!("SQL {sql_file_path}"),
@@ -33,14 +63,14 @@ pub(super) async fn run_sql<'a>(
         .sql_file_cache
         .get(
             app_state,
-            FileAccess::privileged(std::path::Path::new(sql_file_path.as_ref())),
+            FileAccess::privileged(std::path::Path::new(sql_file_path)),
         )
         .instrument(run_sql_span.clone())
         .await
-        .with_context(|| format!("run_sql: invalid path {sql_file_path:?}"))?;
+        .with_context(|| format!("{function}: invalid path {sql_file_path:?}"))?;
     let tmp_req = if let Some(variables) = variables {
         let variables: SetVariablesMap = serde_json::from_str(&variables).with_context(|| {
-            format!("run_sql(\'{sql_file_path}\', \'{variables}\'): the second argument should be a JSON object with string keys and values")
+            format!("{function}(\'{sql_file_path}\', \'{variables}\'): the second argument should be a JSON object with string keys and values")
         })?;
         request.fork_with_variables(variables)
     } else {
@@ -49,8 +79,8 @@ pub(super) async fn run_sql<'a>(
     let max_recursion_depth = app_state.config.max_recursion_depth;
     if tmp_req.clone_depth > max_recursion_depth {
         anyhow::bail!(
-            "Too many nested inclusions. run_sql can include a file that includes another file, but the depth is limited to {max_recursion_depth} levels. \n\
-        Executing sqlpage.run_sql('{sql_file_path}') would exceed this limit. \n\
+            "Too many nested inclusions. {function} can include a file that includes another file, but the depth is limited to {max_recursion_depth} levels. \n\
+        Executing sqlpage.{function}('{sql_file_path}') would exceed this limit. \n\
         This is to prevent infinite loops and stack overflows.\n\
         Make sure that your SQL file does not try to run itself, directly or through a chain of other files.\n\
         If you need to include more files, you can increase max_recursion_depth in the configuration file.\
@@ -63,22 +93,17 @@ pub(super) async fn run_sql<'a>(
             &tmp_req,
             db_connection,
         );
-    let mut json_results_bytes = Vec::new();
-    let mut json_encoder = serde_json::Serializer::new(&mut json_results_bytes);
-    let mut seq = json_encoder.serialize_seq(None)?;
     while let Some(db_item) = results_stream.next().instrument(run_sql_span.clone()).await {
-        use crate::webserver::database::DbItem::{Error, FinishedQuery, Row};
+        use crate::webserver::database::DbItem::{Blob, DateColumns, Error, FinishedQuery, Row};
         match db_item {
-            Row(row) => {
-                log::debug!("run_sql: row: {row:?}");
-                seq.serialize_element(&row)?;
-            }
-            FinishedQuery => log::trace!("run_sql: Finished query"),
+            Row(row) => on_row(row)?,
+            Blob(_) => anyhow::bail!("{function}: the blob component cannot be used in {sql_file_path:?}"),
+            FinishedQuery => log::trace!("{function}: Finished query"),
+            DateColumns(_) => {}
             Error(err) => {
-                return Err(err.context(format!("run_sql: unable to run {sql_file_path:?}")));
+                return Err(err.context(format!("{function}: unable to run {sql_file_path:?}")));
             }
         }
     }
-    seq.end()?;
-    Ok(Some(Cow::Owned(String::from_utf8(json_results_bytes)?)))
+    Ok(())
 }
@@ -0,0 +1,22 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+
+use crate::webserver::http_request_info::RequestInfo;
+
+/// Translates a message key to the locale of the request.
+/// Named arguments are passed as a JSON object: `sqlpage.t('greeting', json_object('name', $name))`.
+/// When no translation catalog defines the key, the key itself is used as the message.
+pub(super) async fn t<'a>(
+    request: &'a RequestInfo,
+    key: Cow<'a, str>,
+    arguments: Option<Cow<'a, str>>,
+) -> anyhow::Result<String> {
+    let arguments = match arguments {
+        Some(arguments) => serde_json::from_str(&arguments).with_context(|| {
+            format!("sqlpage.t: {arguments:?} is not a valid JSON object. The message arguments should be passed as a json object with argument names as keys.")
+        })?,
+        None => serde_json::Map::new(),
+    };
+    Ok(request.locale.translate(&key, &arguments))
+}
@@ -0,0 +1,44 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+
+use crate::webserver::http_request_info::RequestInfo;
+
+/// Only reached when the result is not passed directly to the database:
+/// query parameters are read with [`read_uploaded_file`] instead.
+pub(super) async fn uploaded_file_blob(upload_name: Cow<'_, str>) -> anyhow::Result<String> {
+    anyhow::bail!(
+        "sqlpage.uploaded_file_blob('{upload_name}') returns binary data, so it can only be passed directly to the database, \
+        as in INSERT INTO files(contents) VALUES (sqlpage.uploaded_file_blob('{upload_name}')). \n\
+        To use the contents of the file as text, use sqlpage.read_file_as_text(sqlpage.uploaded_file_path('{upload_name}'))."
+    )
+}
+
+/// Reads an uploaded file, to bind its contents to a query as bytes.
+/// Database drivers need the whole value in memory, so files larger than
+/// `max_uploaded_file_size`, such as large resumable uploads, are refused.
+pub(crate) async fn read_uploaded_file(
+    request: &RequestInfo,
+    upload_name: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(uploaded_file) = request.uploaded_files.get(upload_name) else {
+        log::debug!("There is no uploaded file named {upload_name:?}");
+        return Ok(None);
+    };
+    let path = uploaded_file.file.path();
+    let max_size = request.app_state.config.max_uploaded_file_size;
+    let size = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("Unable to read the uploaded file {upload_name:?}"))?
+        .len();
+    if size > max_size as u64 {
+        anyhow::bail!(
+            "The uploaded file {upload_name:?} is {size} bytes long, but sqlpage.uploaded_file_blob only accepts files of up to {max_size} bytes (max_uploaded_file_size). \n\
+            To keep larger files, store them with sqlpage.persist_uploaded_file('{upload_name}') instead."
+        );
+    }
+    let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Unable to read the uploaded file {upload_name:?}"))?;
+    Ok(Some(contents))
+}
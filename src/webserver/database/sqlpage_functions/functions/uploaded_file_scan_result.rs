@@ -0,0 +1,13 @@
+use std::borrow::Cow;
+
+use crate::webserver::http_request_info::RequestInfo;
+
+/// Returns `clean`, or the name of the virus found by the upload scanner.
+/// NULL when no scanner is configured, or when there is no such uploaded file.
+pub(super) async fn uploaded_file_scan_result<'a>(
+    request: &'a RequestInfo,
+    upload_name: Cow<'a, str>,
+) -> Option<String> {
+    let verdict = request.upload_scan_results.get(&*upload_name)?;
+    Some(verdict.to_string())
+}
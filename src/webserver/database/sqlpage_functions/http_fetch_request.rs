@@ -39,6 +39,12 @@ pub(super) struct HttpFetchRequest<'b> {
     pub body: Option<Cow<'b, serde_json::value::RawValue>>,
     pub timeout_ms: Option<u64>,
     pub response_encoding: Option<Cow<'b, str>>,
+    /// Name of the client certificate to present, from the `tls_profiles` configuration
+    pub tls_profile: Option<Cow<'b, str>>,
+    /// How long the response can be reused by identical GET and HEAD requests
+    pub cache_ttl_ms: Option<u64>,
+    /// How many times to resend idempotent requests that fail with a network error or a 502, 503 or 504 status, up to 10
+    pub retries: Option<u32>,
 }
 
 fn deserialize_map_to_vec_pairs<'de, D: serde::Deserializer<'de>>(
@@ -80,6 +86,9 @@ impl<'a> BorrowFromStr<'a> for HttpFetchRequest<'a> {
                 body: None,
                 timeout_ms: None,
                 response_encoding: None,
+                tls_profile: None,
+                cache_ttl_ms: None,
+                retries: None,
             }
         } else {
             match s {
@@ -107,6 +116,9 @@ impl HttpFetchRequest<'_> {
             username: self.username.map(Cow::into_owned).map(Cow::Owned),
             password: self.password.map(Cow::into_owned).map(Cow::Owned),
             response_encoding: self.response_encoding.map(Cow::into_owned).map(Cow::Owned),
+            tls_profile: self.tls_profile.map(Cow::into_owned).map(Cow::Owned),
+            cache_ttl_ms: self.cache_ttl_ms,
+            retries: self.retries,
         }
     }
 }
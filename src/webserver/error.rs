@@ -15,7 +15,6 @@ use std::path::PathBuf;
 use crate::AppState;
 use crate::app_config::DevOrProd;
 use crate::webserver::ErrorWithStatus;
-use actix_web::HttpResponseBuilder;
 use actix_web::error::UrlencodedError;
 use actix_web::http::{StatusCode, header};
 use actix_web::{HttpRequest, HttpResponse};
@@ -172,58 +171,65 @@ fn error_to_html_string(app_state: &AppState, err: &anyhow::Error) -> anyhow::Re
 }
 
 pub(super) fn anyhow_err_to_actix_resp(e: &anyhow::Error, state: &AppState) -> HttpResponse {
-    let mut resp = HttpResponseBuilder::new(StatusCode::INTERNAL_SERVER_ERROR);
-    resp.insert_header((header::CONTENT_TYPE, header::ContentType::plaintext()));
-
-    if let Some(status) = anyhow_error_status(e) {
-        resp.status(status);
-        if status == StatusCode::UNAUTHORIZED {
-            resp.append_header((
-                header::WWW_AUTHENTICATE,
-                "Basic realm=\"Authentication required\", charset=\"UTF-8\"",
-            ));
-        }
-    } else if let Some(sqlx::error::Error::PoolTimedOut) = e.downcast_ref() {
-        use rand::RngExt;
-        resp.status(StatusCode::TOO_MANY_REQUESTS).insert_header((
-            header::RETRY_AFTER,
-            header::HeaderValue::from(rand::rng().random_range(1..=15)),
-        ));
-    }
-    match error_to_html_string(state, e) {
-        Ok(body) => {
-            resp.insert_header((header::CONTENT_TYPE, header::ContentType::html()));
-            resp.body(body)
-        }
+    let mut resp = match error_to_html_string(state, e) {
+        Ok(body) => HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, header::ContentType::html()))
+            .body(body),
         Err(second_err) => {
             log::error!("Unable to render error: {e:#}");
-            resp.body(format!(
-                "A second error occurred while rendering the error page: \n\n\
-                Initial error: \n\
-                {e:#}\n\n\
-                Second error: \n\
-                {second_err:#}"
-            ))
+            HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, header::ContentType::plaintext()))
+                .body(format!(
+                    "A second error occurred while rendering the error page: \n\n\
+                    Initial error: \n\
+                    {e:#}\n\n\
+                    Second error: \n\
+                    {second_err:#}"
+                ))
         }
-    }
+    };
+    set_error_status(e, &mut resp);
+    resp
 }
 
-fn anyhow_error_status(e: &anyhow::Error) -> Option<StatusCode> {
+/// The HTTP status code of the response to a failed request.
+pub(super) fn error_status(e: &anyhow::Error) -> StatusCode {
     if let Some(&ErrorWithStatus { status }) = e.downcast_ref() {
-        Some(status)
+        status
     } else if let Some(sqlx::error::Error::PoolTimedOut) = e.downcast_ref() {
-        Some(StatusCode::TOO_MANY_REQUESTS)
+        StatusCode::TOO_MANY_REQUESTS
     } else {
-        None
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Sets the status code of a response to a failed request,
+/// together with the headers that come with it.
+pub(super) fn set_error_status(e: &anyhow::Error, resp: &mut HttpResponse) {
+    let status = error_status(e);
+    *resp.status_mut() = status;
+    if status == StatusCode::UNAUTHORIZED {
+        resp.headers_mut().append(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static(
+                "Basic realm=\"Authentication required\", charset=\"UTF-8\"",
+            ),
+        );
+    } else if let Some(sqlx::error::Error::PoolTimedOut) = e.downcast_ref() {
+        use rand::RngExt;
+        resp.headers_mut().insert(
+            header::RETRY_AFTER,
+            header::HeaderValue::from(rand::rng().random_range(1..=15)),
+        );
     }
 }
 
-pub(super) fn send_anyhow_error(
+/// Sends the default error response. The error is expected to have been logged already.
+pub(super) fn send_error_response(
     e: &anyhow::Error,
     resp_send: tokio::sync::oneshot::Sender<HttpResponse>,
     state: &AppState,
 ) {
-    log::error!("An error occurred before starting to send the response body: {e:#}");
     resp_send
         .send(anyhow_err_to_actix_resp(e, state))
         .unwrap_or_else(|_| log::error!("could not send headers"));
@@ -0,0 +1,325 @@
+//! In-memory cache of the responses of `sqlpage.fetch` and `sqlpage.fetch_with_meta`,
+//! for the requests that have a `cache_ttl_ms`.
+//!
+//! A response is fresh for `cache_ttl_ms`, or less when its `Cache-Control` header has a shorter `max-age`.
+//! Stale responses that have an `ETag` or a `Last-Modified` header are revalidated with a conditional request,
+//! and responses with `Cache-Control: no-store` or `private` are never stored, since they are shared by all users.
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A response that was entirely read
+#[derive(Debug)]
+pub(crate) struct FetchedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl FetchedResponse {
+    /// Approximate memory used by the response, in bytes
+    fn size(&self) -> usize {
+        let headers_size: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        self.body.len() + headers_size
+    }
+
+    /// The headers that make a request conditional on the response having changed
+    pub(crate) fn validators(&self) -> Vec<(HeaderName, HeaderValue)> {
+        let mut validators = Vec::new();
+        if let Some(etag) = self.headers.get(header::ETAG) {
+            validators.push((header::IF_NONE_MATCH, etag.clone()));
+        }
+        if let Some(last_modified) = self.headers.get(header::LAST_MODIFIED) {
+            validators.push((header::IF_MODIFIED_SINCE, last_modified.clone()));
+        }
+        validators
+    }
+}
+
+pub(crate) enum CacheLookup {
+    /// Can be used without contacting the server
+    Fresh(Arc<FetchedResponse>),
+    /// Can be used if the server answers a conditional request with `304 Not Modified`
+    Stale(Arc<FetchedResponse>),
+}
+
+struct Entry {
+    response: Arc<FetchedResponse>,
+    stored_at: Instant,
+    last_used_at: Instant,
+    /// From the `Cache-Control` header of the response. `None` when the header does not limit it.
+    max_age: Option<Duration>,
+    size: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    responses: HashMap<String, Entry>,
+    size: usize,
+}
+
+impl Entries {
+    fn insert(&mut self, key: String, entry: Entry) {
+        self.size += entry.size;
+        if let Some(previous) = self.responses.insert(key, entry) {
+            self.size -= previous.size;
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(previous) = self.responses.remove(key) {
+            self.size -= previous.size;
+        }
+    }
+
+    /// Evicts the least recently used responses until the cache is under its capacity
+    fn evict(&mut self, max_size: usize) {
+        if self.size <= max_size {
+            return;
+        }
+        let mut by_last_use: Vec<(Instant, String)> = self
+            .responses
+            .iter()
+            .map(|(key, entry)| (entry.last_used_at, key.clone()))
+            .collect();
+        by_last_use.sort_unstable_by_key(|(last_used_at, _)| *last_used_at);
+        for (_, key) in by_last_use {
+            if self.size <= max_size {
+                break;
+            }
+            self.remove(&key);
+        }
+    }
+}
+
+pub(crate) struct FetchCache {
+    entries: Mutex<Entries>,
+    max_size: usize,
+}
+
+impl FetchCache {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            entries: Mutex::default(),
+            max_size,
+        }
+    }
+
+    /// Returns the cached response, unless it is stale and cannot be revalidated
+    pub(crate) fn get(&self, key: &str, ttl: Duration) -> Option<CacheLookup> {
+        let mut entries = self.entries.lock().expect("fetch cache lock poisoned");
+        let entry = entries.responses.get_mut(key)?;
+        entry.last_used_at = Instant::now();
+        let fresh_for = entry.max_age.map_or(ttl, |max_age| max_age.min(ttl));
+        if entry.stored_at.elapsed() < fresh_for {
+            return Some(CacheLookup::Fresh(Arc::clone(&entry.response)));
+        }
+        if entry.response.validators().is_empty() {
+            entries.remove(key);
+            return None;
+        }
+        Some(CacheLookup::Stale(Arc::clone(&entry.response)))
+    }
+
+    /// Stores a response, unless it forbids it
+    pub(crate) fn insert(&self, key: String, response: Arc<FetchedResponse>) {
+        let CacheControl { no_store, max_age } = CacheControl::parse(&response.headers);
+        if no_store {
+            return;
+        }
+        let size = key.len() + response.size();
+        if size > self.max_size {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("fetch cache lock poisoned");
+        entries.insert(
+            key,
+            Entry {
+                response,
+                stored_at: now,
+                last_used_at: now,
+                max_age,
+                size,
+            },
+        );
+        entries.evict(self.max_size);
+    }
+
+    /// Marks a stale response as fresh again, after the server answered `304 Not Modified`.
+    /// The `Cache-Control` header of the `304` response replaces the one of the stored response.
+    pub(crate) fn revalidated(&self, key: &str, not_modified_headers: &HeaderMap) {
+        let mut entries = self.entries.lock().expect("fetch cache lock poisoned");
+        let Some(entry) = entries.responses.get_mut(key) else {
+            return;
+        };
+        let headers = if not_modified_headers.contains_key(header::CACHE_CONTROL) {
+            not_modified_headers
+        } else {
+            &entry.response.headers
+        };
+        let CacheControl { no_store, max_age } = CacheControl::parse(headers);
+        if no_store {
+            entries.remove(key);
+        } else {
+            entry.max_age = max_age;
+            entry.stored_at = Instant::now();
+        }
+    }
+}
+
+/// The directives of the `Cache-Control` header of a response that the cache follows
+#[derive(Debug, PartialEq, Eq, Default)]
+struct CacheControl {
+    /// The response must not be stored: `no-store`, or `private`, since the cache is shared by all users
+    no_store: bool,
+    /// Freshness lifetime of the response. `None` when the header does not limit it.
+    max_age: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut max_age = None;
+        let mut shared_max_age = None;
+        for directive in headers
+            .get_all(header::CACHE_CONTROL)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let (name, value) = directive
+                .split_once('=')
+                .map_or((directive, None), |(name, value)| (name, Some(value)));
+            let seconds = || {
+                value
+                    .and_then(|value| value.trim().trim_matches('"').parse().ok())
+                    .map(Duration::from_secs)
+            };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" | "private" => {
+                    return Self {
+                        no_store: true,
+                        max_age: None,
+                    };
+                }
+                "no-cache" => max_age = Some(Duration::ZERO),
+                "max-age" if max_age.is_none() => max_age = seconds(),
+                // responses are shared by all the users of the site, like in a proxy cache
+                "s-maxage" => shared_max_age = seconds(),
+                _ => {}
+            }
+        }
+        Self {
+            no_store: false,
+            max_age: shared_max_age.or(max_age),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&'static str, &'static str)], body: &'static str) -> FetchedResponse {
+        let mut header_map = HeaderMap::new();
+        for &(name, value) in headers {
+            header_map.append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        FetchedResponse {
+            status: StatusCode::OK,
+            headers: header_map,
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    fn max_age(cache_control: &'static str) -> Option<Duration> {
+        let headers = response(&[("cache-control", cache_control)], "").headers;
+        let parsed = CacheControl::parse(&headers);
+        assert!(!parsed.no_store);
+        parsed.max_age
+    }
+
+    #[test]
+    fn test_cache_control() {
+        assert_eq!(
+            CacheControl::parse(&HeaderMap::new()),
+            CacheControl::default()
+        );
+        assert_eq!(max_age("public, max-age=60"), Some(Duration::from_mins(1)));
+        assert_eq!(
+            max_age("max-age=60, s-maxage=10"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(max_age("no-cache, max-age=60"), Some(Duration::ZERO));
+        let headers = response(&[("cache-control", "no-store")], "").headers;
+        assert!(CacheControl::parse(&headers).no_store);
+        let headers = response(&[("cache-control", "private, max-age=60")], "").headers;
+        assert!(CacheControl::parse(&headers).no_store);
+    }
+
+    #[test]
+    fn test_fresh_and_stale_responses() {
+        let cache = FetchCache::new(1024);
+        let ttl = Duration::from_mins(1);
+        cache.insert("fresh".into(), Arc::new(response(&[], "a")));
+        assert!(matches!(
+            cache.get("fresh", ttl),
+            Some(CacheLookup::Fresh(r)) if r.body == "a"
+        ));
+        assert!(cache.get("fresh", Duration::ZERO).is_none());
+        // the stale response could not be revalidated, so it was removed
+        assert!(cache.get("fresh", ttl).is_none());
+
+        cache.insert(
+            "etag".into(),
+            Arc::new(response(
+                &[("etag", "\"v1\""), ("cache-control", "max-age=0")],
+                "b",
+            )),
+        );
+        let Some(CacheLookup::Stale(stale)) = cache.get("etag", ttl) else {
+            panic!("expected a stale response");
+        };
+        assert_eq!(
+            stale.validators(),
+            [(header::IF_NONE_MATCH, HeaderValue::from_static("\"v1\""))]
+        );
+        let not_modified = response(&[("cache-control", "max-age=60")], "").headers;
+        cache.revalidated("etag", &not_modified);
+        assert!(matches!(
+            cache.get("etag", ttl),
+            Some(CacheLookup::Fresh(r)) if r.body == "b"
+        ));
+
+        cache.insert(
+            "no-store".into(),
+            Arc::new(response(&[("cache-control", "no-store")], "c")),
+        );
+        assert!(cache.get("no-store", ttl).is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_responses() {
+        let cache = FetchCache::new(10);
+        cache.insert("a".into(), Arc::new(response(&[], "1234")));
+        cache.insert("b".into(), Arc::new(response(&[], "1234")));
+        let ttl = Duration::from_mins(1);
+        assert!(cache.get("a", ttl).is_some());
+        cache.insert("c".into(), Arc::new(response(&[], "1234")));
+        assert!(cache.get("a", ttl).is_some());
+        assert!(cache.get("b", ttl).is_none());
+        assert!(cache.get("c", ttl).is_some());
+        cache.insert("too big".into(), Arc::new(response(&[], "12345678")));
+        assert!(cache.get("too big", ttl).is_none());
+    }
+}
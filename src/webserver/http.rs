@@ -2,13 +2,17 @@
 //! including rendering SQL files, serving static content, and managing
 //! request contexts and response headers.
 
-use crate::render::{AnyRenderBodyContext, HeaderContext, PageContext};
+use crate::i18n::Locale;
+use crate::render::{
+    AnyRenderBodyContext, HeaderContext, PageContext, header_component_after_body,
+};
 use crate::webserver::ErrorWithStatus;
 use crate::webserver::content_security_policy::ContentSecurityPolicy;
 use crate::webserver::database::execute_queries::stop_at_first_error;
 use crate::webserver::database::{DbItem, execute_queries::stream_query_results_with_conn};
-use crate::webserver::http_request_info::extract_request_info;
+use crate::webserver::http_request_info::{ExecutionContext, extract_request_info};
 use crate::webserver::server_timing::ServerTiming;
+use crate::webserver::single_or_vec::SingleOrVec;
 use crate::{AppConfig, AppState, DEFAULT_404_FILE, SqlFile};
 use actix_web::dev::{ServiceFactory, ServiceRequest, fn_service};
 use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
@@ -16,12 +20,17 @@ use actix_web::http::header::Accept;
 use actix_web::http::header::{ContentType, Header, HttpDate, IfModifiedSince, LastModified};
 use actix_web::http::{StatusCode, header};
 use actix_web::web::PayloadConfig;
-use actix_web::{App, Error, HttpResponse, HttpServer, dev::ServiceResponse, middleware, web};
+use actix_web::{
+    App, Error, HttpMessage, HttpResponse, HttpServer, dev::ServiceResponse, middleware, web,
+};
 use opentelemetry_semantic_conventions::attribute as otel;
 use tracing::{Instrument, Span};
 use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, TracingLogger};
 
-use super::error::{anyhow_err_to_actix, bind_error, send_anyhow_error};
+use super::error::{
+    ClientError, anyhow_err_to_actix, bind_error, error_status, send_error_response,
+    set_error_status,
+};
 use super::http_client::make_http_client;
 use super::https::make_auto_rustls_config;
 use super::oidc::OidcMiddleware;
@@ -31,7 +40,7 @@ use crate::filesystem::FileAccess;
 use crate::webserver::routing::RoutingAction::{
     CustomNotFound, Execute, NotFound, Redirect, Serve,
 };
-use crate::webserver::routing::{AppFileStore, calculate_route};
+use crate::webserver::routing::{AppFileStore, calculate_route, find_error_handler};
 use actix_web::body::MessageBody;
 use anyhow::{Context, bail};
 use chrono::{DateTime, Utc};
@@ -50,6 +59,7 @@ pub enum ResponseFormat {
     Html,
     Json,
     JsonLines,
+    Pdf,
 }
 
 #[derive(Clone)]
@@ -59,6 +69,7 @@ pub struct RequestContext {
     pub content_security_policy: ContentSecurityPolicy,
     pub server_timing: Arc<ServerTiming>,
     pub response_format: ResponseFormat,
+    pub locale: Arc<Locale>,
 }
 
 impl ResponseFormat {
@@ -75,6 +86,7 @@ impl ResponseFormat {
                     return Self::JsonLines;
                 }
                 ("text", "x-ndjson" | "jsonlines" | "x-jsonlines") => return Self::JsonLines,
+                ("application", "pdf") => return Self::Pdf,
                 ("text", "html") | ("*", "*") => return Self::Html,
                 _ => {}
             }
@@ -88,6 +100,7 @@ impl ResponseFormat {
             Self::Html => "text/html; charset=utf-8",
             Self::Json => "application/json",
             Self::JsonLines => "application/x-ndjson",
+            Self::Pdf => "application/pdf",
         }
     }
 }
@@ -105,6 +118,11 @@ async fn stream_response(stream: impl Stream<Item = DbItem>, mut renderer: AnyRe
         let render_result = match item {
             DbItem::FinishedQuery => renderer.finish_query().await,
             DbItem::Row(row) => renderer.handle_row(&row).await,
+            DbItem::Blob(_) => Err(header_component_after_body("blob")),
+            DbItem::DateColumns(columns) => {
+                renderer.set_date_columns(columns);
+                Ok(())
+            }
             DbItem::Error(e) => renderer.handle_error(&e).await,
         };
         if let Err(e) = render_result
@@ -154,10 +172,12 @@ async fn build_response_header_and_stream<S: Stream<Item = DbItem>>(
                 head_context.request_context.server_timing.record("row");
                 head_context.handle_row(data).await?
             }
+            DbItem::Blob(blob) => head_context.blob(blob)?,
             DbItem::FinishedQuery => {
                 log::debug!("finished query");
                 continue;
             }
+            DbItem::DateColumns(_) => continue,
             DbItem::Error(source_err)
                 if matches!(
                     source_err.downcast_ref(),
@@ -177,8 +197,7 @@ async fn build_response_header_and_stream<S: Stream<Item = DbItem>>(
                 renderer,
             } => {
                 let body_stream = tokio_stream::wrappers::ReceiverStream::new(receiver);
-                let result_stream = body_stream.map(Ok::<_, Error>);
-                let http_response = http_response.streaming(result_stream);
+                let http_response = http_response.streaming(body_stream);
                 return Ok(ResponseWithWriter::RenderStream {
                     http_response,
                     renderer,
@@ -207,7 +226,7 @@ enum ResponseWithWriter<S> {
     },
 }
 
-async fn render_sql(
+pub(super) async fn render_sql(
     srv_req: &mut ServiceRequest,
     sql_file: Arc<SqlFile>,
     server_timing: ServerTiming,
@@ -255,6 +274,10 @@ async fn render_sql(
 
     exec_ctx.request().server_timing.record("parse_req");
 
+    let request_id = srv_req
+        .extensions()
+        .get::<tracing_actix_web::RequestId>()
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), ToString::to_string);
     let (resp_send, resp_recv) = tokio::sync::oneshot::channel::<HttpResponse>();
     let source_path: PathBuf = sql_file.source_path.clone();
     let exec_span = tracing::info_span!(
@@ -264,47 +287,37 @@ async fn render_sql(
     );
     actix_web::rt::spawn(Instrument::instrument(
         async move {
-            let request_info = exec_ctx.request();
-            let request_context = RequestContext {
-                is_embedded: request_info.url_params.contains_key("_sqlpage_embed"),
-                source_path,
-                content_security_policy: ContentSecurityPolicy::with_random_nonce(),
-                server_timing: Arc::clone(&request_info.server_timing),
-                response_format,
-            };
-            let mut conn = None;
-            let database_entries_stream =
-                stream_query_results_with_conn(&sql_file, &exec_ctx, &mut conn);
-            let database_entries_stream = stop_at_first_error(database_entries_stream);
-            let response_with_writer = build_response_header_and_stream(
-                Arc::clone(&app_state),
-                database_entries_stream,
-                request_context,
-            )
-            .await;
-            match response_with_writer {
-                Ok(ResponseWithWriter::RenderStream {
-                    http_response,
-                    renderer,
-                    database_entries_stream,
-                }) => {
-                    resp_send
-                        .send(http_response)
-                        .unwrap_or_else(|e| log::error!("could not send headers {e:?}"));
-                    Instrument::instrument(
-                        stream_response(database_entries_stream, renderer),
-                        tracing::info_span!("render"),
-                    )
-                    .await;
-                }
-                Ok(ResponseWithWriter::FinishedResponse { http_response }) => {
-                    resp_send
-                        .send(http_response)
-                        .unwrap_or_else(|e| log::error!("could not send headers {e:?}"));
-                }
-                Err(err) => {
-                    send_anyhow_error(&err, resp_send, &app_state);
+            let run = Box::pin(async {
+                let request_context = RequestContext {
+                    is_embedded: exec_ctx.request().url_params.contains_key("_sqlpage_embed"),
+                    source_path,
+                    content_security_policy: ContentSecurityPolicy::with_random_nonce(),
+                    server_timing: Arc::clone(&exec_ctx.request().server_timing),
+                    response_format,
+                    locale: Arc::clone(&exec_ctx.request().locale),
+                };
+                let result = execute_sql_file(
+                    Arc::clone(&app_state),
+                    &sql_file,
+                    &exec_ctx,
+                    request_context,
+                    resp_send,
+                    None,
+                )
+                .await;
+                if let Err((err, resp_send)) = result {
+                    let failure = FailedRequest {
+                        error: err,
+                        source_path: &sql_file.source_path,
+                        request_id: &request_id,
+                        response_format,
+                    };
+                    send_error_page(&app_state, &exec_ctx, failure, resp_send).await;
                 }
+            });
+            match &app_state.file_watcher {
+                Some(watcher) => watcher.track_dependencies(&sql_file.source_path, run).await,
+                None => run.await,
             }
         },
         exec_span,
@@ -312,6 +325,133 @@ async fn render_sql(
     resp_recv.await.map_err(ErrorInternalServerError)
 }
 
+/// Runs a SQL file and sends its response.
+/// If the file fails before the response headers are sent, the error is returned together with
+/// the unused sender, so that the caller can still respond.
+/// When `original_error` is set, the file is an error page: a successful response takes the
+/// status of the original error.
+async fn execute_sql_file(
+    app_state: Arc<AppState>,
+    sql_file: &SqlFile,
+    exec_ctx: &ExecutionContext,
+    request_context: RequestContext,
+    resp_send: tokio::sync::oneshot::Sender<HttpResponse>,
+    original_error: Option<&anyhow::Error>,
+) -> Result<(), (anyhow::Error, tokio::sync::oneshot::Sender<HttpResponse>)> {
+    let mut conn = None;
+    let database_entries_stream = stream_query_results_with_conn(sql_file, exec_ctx, &mut conn);
+    let database_entries_stream = stop_at_first_error(database_entries_stream);
+    let response_with_writer =
+        build_response_header_and_stream(app_state, database_entries_stream, request_context).await;
+    let (mut http_response, render) = match response_with_writer {
+        Ok(ResponseWithWriter::RenderStream {
+            http_response,
+            renderer,
+            database_entries_stream,
+        }) => (http_response, Some((renderer, database_entries_stream))),
+        Ok(ResponseWithWriter::FinishedResponse { http_response }) => (http_response, None),
+        Err(err) => return Err((err, resp_send)),
+    };
+    if let Some(original_error) = original_error
+        && http_response.status() == StatusCode::OK
+    {
+        set_error_status(original_error, &mut http_response);
+    }
+    resp_send
+        .send(http_response)
+        .unwrap_or_else(|e| log::error!("could not send headers {e:?}"));
+    if let Some((renderer, database_entries_stream)) = render {
+        Instrument::instrument(
+            stream_response(database_entries_stream, renderer),
+            tracing::info_span!("render"),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// A SQL file that failed before its response started.
+struct FailedRequest<'a> {
+    error: anyhow::Error,
+    source_path: &'a std::path::Path,
+    request_id: &'a str,
+    response_format: ResponseFormat,
+}
+
+/// Responds to a failed request with the closest custom error page (`{status}.sql` or `error.sql`),
+/// or with the default error response when there is none or when the error page fails too.
+async fn send_error_page(
+    app_state: &Arc<AppState>,
+    exec_ctx: &ExecutionContext,
+    failure: FailedRequest<'_>,
+    resp_send: tokio::sync::oneshot::Sender<HttpResponse>,
+) {
+    let FailedRequest {
+        error,
+        source_path,
+        request_id,
+        response_format,
+    } = failure;
+    log::error!(
+        "An error occurred before starting to send the response body (request id: {request_id}): {error:#}"
+    );
+    let status = error_status(&error);
+    let store = AppFileStore::new(&app_state.sql_file_cache, &app_state.file_system, app_state);
+    let handler = match find_error_handler(source_path, status, &store).await {
+        Ok(Some(handler)) => handler,
+        Ok(None) => return send_error_response(&error, resp_send, app_state),
+        Err(e) => {
+            log::error!("Unable to look for a custom error page: {e:#}");
+            return send_error_response(&error, resp_send, app_state);
+        }
+    };
+    let handler_file = match FileAccess::unprivileged(&handler) {
+        Ok(access) => app_state.sql_file_cache.get(app_state, access).await,
+        Err(e) => Err(e),
+    };
+    let handler_file = match handler_file {
+        Ok(file) => file,
+        Err(e) => {
+            log::error!("Unable to load the error page {}: {e:#}", handler.display());
+            return send_error_response(&error, resp_send, app_state);
+        }
+    };
+    let client_error = ClientError::new(&error, app_state.config.environment, None);
+    let mut variables = exec_ctx.set_variables.borrow().clone();
+    for (name, value) in [
+        ("error_status", status.as_u16().to_string()),
+        ("error_message", client_error.message().to_owned()),
+        ("request_id", request_id.to_owned()),
+    ] {
+        variables.insert(name.to_owned(), Some(SingleOrVec::Single(value)));
+    }
+    let error_ctx = exec_ctx.fork_with_variables(variables);
+    let request_context = RequestContext {
+        is_embedded: exec_ctx.request().url_params.contains_key("_sqlpage_embed"),
+        source_path: handler.clone(),
+        content_security_policy: ContentSecurityPolicy::with_random_nonce(),
+        server_timing: Arc::clone(&exec_ctx.request().server_timing),
+        response_format,
+        locale: Arc::clone(&exec_ctx.request().locale),
+    };
+    let result = execute_sql_file(
+        Arc::clone(app_state),
+        &handler_file,
+        &error_ctx,
+        request_context,
+        resp_send,
+        Some(&error),
+    )
+    .await;
+    if let Err((handler_error, resp_send)) = result {
+        log::error!(
+            "The error page {} failed too (request id: {request_id}): {handler_error:#}",
+            handler.display()
+        );
+        send_error_response(&error, resp_send, app_state);
+    }
+}
+
 fn request_span_route(request: &ServiceRequest) -> Cow<'_, str> {
     request
         .match_pattern()
@@ -408,7 +548,7 @@ impl RootSpanBuilder for SqlPageRootSpanBuilder {
     }
 }
 
-async fn process_sql_request(
+pub(super) async fn process_sql_request(
     req: &mut ServiceRequest,
     sql_path: PathBuf,
 ) -> actix_web::Result<HttpResponse> {
@@ -576,6 +716,13 @@ pub fn create_app(
                 .service(static_content::tomselect_js())
                 .service(static_content::css())
                 .service(static_content::favicon())
+                .configure(|cfg| {
+                    if app_state.file_watcher.is_some() {
+                        cfg.service(super::live_reload::endpoint());
+                    }
+                })
+                .service(super::image_endpoint::endpoint())
+                .service(super::resumable_uploads::endpoint())
                 .default_service(fn_service(main_handler)),
         )
         // when receiving a request outside of the prefix, redirect to the prefix
@@ -628,6 +775,10 @@ pub async fn run_server(config: &AppConfig, state: AppState) -> anyhow::Result<(
         return Ok(());
     }
     let mut server = HttpServer::new(factory);
+    if config.live_reload() {
+        // Browsers stay connected to the live reload endpoint, which would delay the shutdown
+        server = server.shutdown_timeout(1);
+    }
     #[cfg_attr(
         not(target_family = "unix"),
         expect(
@@ -710,7 +861,10 @@ fn log_welcome_message(config: &AppConfig) {
         ("✨", "🔗", "💻", "🚀")
     };
     let version = env!("CARGO_PKG_VERSION");
-    let web_root = config.web_root.display();
+    let web_root = match &config.bundle {
+        Some(bundle) => bundle.display(),
+        None => config.web_root.display(),
+    };
 
     log::info!(
         "\n{sparkle} SQLPage v{version} started successfully! {sparkle}\n\n\
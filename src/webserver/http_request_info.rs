@@ -1,4 +1,5 @@
 use crate::AppState;
+use crate::i18n::Locale;
 use crate::webserver::request_variables::SetVariablesMap;
 use crate::webserver::server_timing::ServerTiming;
 use actix_multipart::Multipart;
@@ -12,6 +13,7 @@ use actix_web::HttpRequest;
 use actix_web::dev::ServiceRequest;
 use actix_web::http::header::CONTENT_TYPE;
 use actix_web::http::header::Header;
+use actix_web::http::header::{AcceptLanguage, Preference};
 use actix_web::web;
 use actix_web::web::Form;
 use actix_web_httpauth::headers::authorization::Authorization;
@@ -28,6 +30,7 @@ use tokio_stream::StreamExt;
 use super::oidc::OidcClaims;
 use super::request_variables::ParamMap;
 use super::request_variables::param_map;
+use super::upload_scanner::{ScanVerdict, scan_upload};
 use super::{ActixErrorStatusExt, StatusCodeResultExt};
 
 #[derive(Debug)]
@@ -38,6 +41,7 @@ pub struct RequestInfo {
     pub url_params: ParamMap,
     pub post_variables: ParamMap,
     pub uploaded_files: Rc<HashMap<String, TempFile>>,
+    pub upload_scan_results: HashMap<String, ScanVerdict>,
     pub headers: ParamMap,
     pub client_ip: Option<IpAddr>,
     pub cookies: ParamMap,
@@ -46,6 +50,17 @@ pub struct RequestInfo {
     pub raw_body: Option<Vec<u8>>,
     pub oidc_claims: Option<OidcClaims>,
     pub server_timing: Arc<ServerTiming>,
+    pub locale: Arc<Locale>,
+}
+
+/// Form data received before the SQL file runs, such as a completed resumable upload.
+/// When it is in the extensions of a request, it is used instead of the request body.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedPostData {
+    pub post_variables: Vec<(String, String)>,
+    pub uploaded_files: Vec<(String, TempFile)>,
+    /// Verdicts of the upload scanner, by field name
+    pub upload_scan_results: Vec<(String, ScanVerdict)>,
 }
 
 #[derive(Debug)]
@@ -113,12 +128,16 @@ pub(crate) async fn extract_request_info(
     app_state: Arc<AppState>,
     server_timing: ServerTiming,
 ) -> anyhow::Result<ExecutionContext> {
+    let prepared = req.extensions_mut().remove::<PreparedPostData>();
     let (http_req, payload) = req.parts_mut();
     let method = http_req.method().clone();
     let protocol = http_req.connection_info().scheme().to_string();
     let config = &app_state.config;
-    let (post_variables, uploaded_files, raw_body) =
-        extract_post_data(http_req, payload, config).await?;
+    let (post_data, raw_body) = if let Some(prepared) = prepared {
+        (prepared, None)
+    } else {
+        extract_post_data(http_req, payload, config).await?
+    };
     let headers = req.headers().iter().map(|(name, value)| {
         (
             name.to_string(),
@@ -141,14 +160,16 @@ pub(crate) async fn extract_request_info(
         .map(Authorization::into_scheme);
 
     let oidc_claims: Option<OidcClaims> = req.extensions().get::<OidcClaims>().cloned();
+    let locale = negotiate_locale(req, &app_state, oidc_claims.as_ref());
 
     Ok(ExecutionContext::new(RequestInfo {
         method,
         path: req.path().to_string(),
         headers: param_map(headers),
         url_params: param_map(get_variables),
-        post_variables: param_map(post_variables),
-        uploaded_files: Rc::new(HashMap::from_iter(uploaded_files)),
+        post_variables: param_map(post_data.post_variables),
+        uploaded_files: Rc::new(HashMap::from_iter(post_data.uploaded_files)),
+        upload_scan_results: HashMap::from_iter(post_data.upload_scan_results),
         client_ip,
         cookies: param_map(cookies),
         basic_auth,
@@ -157,37 +178,67 @@ pub(crate) async fn extract_request_info(
         raw_body,
         oidc_claims,
         server_timing: Arc::new(server_timing),
+        locale,
     }))
 }
 
+/// Picks the locale of the request from, in order of preference: the locale cookie,
+/// the `locale` claim of the logged-in user, and the `Accept-Language` header.
+fn negotiate_locale(
+    req: &ServiceRequest,
+    app_state: &AppState,
+    oidc_claims: Option<&OidcClaims>,
+) -> Arc<Locale> {
+    if !app_state.translations.is_localized() {
+        return Arc::clone(app_state.translations.default_locale());
+    }
+    let cookie = req.cookie(&app_state.config.locale_cookie);
+    let claim = oidc_claims.and_then(|claims| claims.locale());
+    let accepted = AcceptLanguage::parse(req)
+        .map(|accept| accept.ranked())
+        .unwrap_or_default();
+    let preferred = cookie
+        .as_ref()
+        .map(|cookie| cookie.value().to_string())
+        .into_iter()
+        .chain(claim.map(|claim| claim.as_str().to_string()))
+        .chain(accepted.iter().filter_map(|preference| match preference {
+            Preference::Specific(tag) => Some(tag.to_string()),
+            Preference::Any => None,
+        }))
+        .collect::<Vec<_>>();
+    app_state
+        .translations
+        .negotiate(preferred.iter().map(String::as_str))
+}
+
 async fn extract_post_data(
     http_req: &mut HttpRequest,
     payload: &mut actix_web::dev::Payload,
     config: &crate::app_config::AppConfig,
-) -> anyhow::Result<(
-    Vec<(String, String)>,
-    Vec<(String, TempFile)>,
-    Option<Vec<u8>>,
-)> {
+) -> anyhow::Result<(PreparedPostData, Option<Vec<u8>>)> {
     let content_type = http_req
         .headers()
         .get(&CONTENT_TYPE)
         .map(AsRef::as_ref)
         .unwrap_or_default();
     if content_type.starts_with(b"application/x-www-form-urlencoded") {
-        let vars = extract_urlencoded_post_variables(http_req, payload).await?;
-        Ok((vars, Vec::new(), None))
+        let post_variables = extract_urlencoded_post_variables(http_req, payload).await?;
+        let post_data = PreparedPostData {
+            post_variables,
+            ..PreparedPostData::default()
+        };
+        Ok((post_data, None))
     } else if content_type.starts_with(b"multipart/form-data") {
-        let (vars, files) = extract_multipart_post_data(http_req, payload, config).await?;
-        Ok((vars, files, None))
+        let post_data = extract_multipart_post_data(http_req, payload, config).await?;
+        Ok((post_data, None))
     } else {
         let body = web::Bytes::from_request(http_req, payload)
             .await
             .with_actix_error_status()
             .context("could not read the request body")?;
         Ok((
-            Vec::new(),
-            Vec::new(),
+            PreparedPostData::default(),
             (!body.is_empty()).then(|| body.to_vec()),
         ))
     }
@@ -208,9 +259,8 @@ async fn extract_multipart_post_data(
     http_req: &mut HttpRequest,
     payload: &mut actix_web::dev::Payload,
     config: &crate::app_config::AppConfig,
-) -> anyhow::Result<(Vec<(String, String)>, Vec<(String, TempFile)>)> {
-    let mut post_variables = Vec::new();
-    let mut uploaded_files = Vec::new();
+) -> anyhow::Result<PreparedPostData> {
+    let mut post_data = PreparedPostData::default();
 
     let mut multipart = Multipart::from_request(http_req, payload)
         .await
@@ -240,14 +290,7 @@ async fn extract_multipart_post_data(
         log::trace!("Parsing multipart field: {field_name}");
         if let Some(filename) = filename {
             log::debug!("Extracting file: {field_name} ({filename})");
-            let extracted = extract_file(http_req, field, &mut limits)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to extract file {field_name:?}. Max file size: {} kiB",
-                        config.max_uploaded_file_size / 1_024
-                    )
-                })?;
+            let extracted = extract_file(http_req, field, &field_name, &mut limits, config).await?;
             log::trace!(
                 "Extracted file {field_name} to \"{}\"",
                 extracted.file.path().display()
@@ -256,14 +299,20 @@ async fn extract_multipart_post_data(
                 log::debug!("Ignoring empty file field: {field_name}");
                 continue;
             }
-            uploaded_files.push((field_name, extracted));
+            // SQL files only see the file once it has been scanned
+            if let Some(scan_result) = scan_upload(config, &field_name, &extracted).await? {
+                post_data
+                    .upload_scan_results
+                    .push((field_name.clone(), scan_result));
+            }
+            post_data.uploaded_files.push((field_name, extracted));
         } else {
             let text_contents = extract_text(http_req, field, &mut limits).await?;
             log::trace!("Extracted field as text: {field_name} = {text_contents:?}");
-            post_variables.push((field_name, text_contents));
+            post_data.post_variables.push((field_name, text_contents));
         }
     }
-    Ok((post_variables, uploaded_files))
+    Ok(post_data)
 }
 
 async fn extract_text(
@@ -285,14 +334,21 @@ async fn extract_text(
 async fn extract_file(
     req: &HttpRequest,
     field: actix_multipart::Field,
+    field_name: &str,
     limits: &mut Limits,
+    config: &crate::app_config::AppConfig,
 ) -> anyhow::Result<TempFile> {
     // extract a tempfile from the field
-    let file = TempFile::read_field(req, field, limits)
+    TempFile::read_field(req, field, limits)
         .await
         .with_response_status()
-        .context("failed to save uploaded file")?;
-    Ok(file)
+        .context("failed to save uploaded file")
+        .with_context(|| {
+            format!(
+                "Failed to extract file {field_name:?}. Max file size: {} kiB",
+                config.max_uploaded_file_size / 1_024
+            )
+        })
 }
 
 /// file upload form fields that are left blank result in the browser sending an empty file, with a mime type of application/octet-stream.
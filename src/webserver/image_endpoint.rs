@@ -0,0 +1,483 @@
+//! Resized versions of the images of the site, served from `/_sqlpage/img/<path>?w=<width>&fmt=<format>`.
+//!
+//! The image can be a file of the web root, a file from the `sqlpage_files` table,
+//! or the response of a `.sql` file, which typically selects a BLOB with the `download` component.
+//! Resized images are cached on disk, in [`AppConfig::image_cache_directory`](crate::app_config::AppConfig::image_cache_directory).
+//! Images are protected by `oidc_protected_paths` like the files they come from.
+
+use super::database::blob_to_data_url::detect_mime_type;
+use super::error::anyhow_err_to_actix;
+use super::http::process_sql_request;
+use super::images::{
+    Fit, ImageOptions, OutputFormat, ProcessedImage, RESIZED_WIDTHS, process_image,
+};
+use super::oidc::OidcClaims;
+use super::{ErrorWithStatus, StatusCodeResultExt};
+use crate::AppState;
+use crate::app_config::AppConfig;
+use crate::filesystem::FileAccess;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, ETag, EntityTag, Header, HttpDate, IfModifiedSince,
+    IfNoneMatch, LastModified,
+};
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, Resource};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::{Mutex, Semaphore};
+
+/// How long browsers and proxies may keep resized versions of the files of the site
+const MAX_AGE_SECONDS: u32 = 7 * 24 * 60 * 60;
+/// Images returned by `.sql` files larger than this are not resized
+const MAX_SOURCE_SIZE: usize = 64 * 1024 * 1024;
+/// Number of files of the site whose cache keys are remembered
+const MAX_KNOWN_FILES: usize = 10_000;
+
+pub(super) fn endpoint() -> Resource {
+    web::resource("_sqlpage/img/{path:.*}").get(resized_image)
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ImageQuery {
+    /// Maximal width of the image, rounded up to one of the [`RESIZED_WIDTHS`]
+    w: Option<u32>,
+    fmt: Option<OutputFormat>,
+}
+
+impl ImageQuery {
+    fn options(&self) -> ImageOptions {
+        ImageOptions {
+            width: self.w.map(|w| {
+                RESIZED_WIDTHS
+                    .into_iter()
+                    .find(|&allowed| allowed >= w)
+                    .unwrap_or(RESIZED_WIDTHS[RESIZED_WIDTHS.len() - 1])
+            }),
+            fit: Fit::Contain,
+            format: self.fmt,
+            ..ImageOptions::default()
+        }
+    }
+}
+
+/// Resized images cached on disk, and the number of images that can be processed at the same time
+pub(crate) struct ImageCache {
+    directory: PathBuf,
+    max_size: u64,
+    /// Total size of the cached images, computed when the first image is cached
+    size: Mutex<Option<u64>>,
+    resizes: Semaphore,
+    /// Cache keys of the resized files of the site, and when their source was read,
+    /// so that unchanged files are not read again
+    known_files: std::sync::Mutex<HashMap<String, (String, DateTime<Utc>)>>,
+}
+
+impl ImageCache {
+    pub(crate) fn new(config: &AppConfig) -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        Self {
+            directory: config.image_cache_directory(),
+            max_size: config.max_image_cache_size,
+            size: Mutex::new(None),
+            resizes: Semaphore::new(parallelism),
+            known_files: std::sync::Mutex::default(),
+        }
+    }
+
+    fn known_file(&self, file: &str) -> Option<(String, DateTime<Utc>)> {
+        let known_files = self.known_files.lock().expect("image cache lock poisoned");
+        known_files.get(file).cloned()
+    }
+
+    fn remember_file(&self, file: String, key: String, read_at: DateTime<Utc>) {
+        let mut known_files = self.known_files.lock().expect("image cache lock poisoned");
+        if known_files.len() >= MAX_KNOWN_FILES {
+            known_files.clear();
+        }
+        known_files.insert(file, (key, read_at));
+    }
+
+    async fn cached(&self, key: &str) -> Option<Bytes> {
+        let cache_file = self.directory.join(key);
+        let cached = tokio::fs::read(&cache_file).await.ok()?;
+        log::trace!("Serving the cached image {}", cache_file.display());
+        Some(Bytes::from(cached))
+    }
+
+    /// Processes an image in a blocking task, once one of the processing slots is free.
+    /// Used both for resized images and for `sqlpage.process_image`.
+    pub(crate) async fn process(
+        &self,
+        source: impl AsRef<[u8]> + Send + 'static,
+        options: ImageOptions,
+    ) -> anyhow::Result<ProcessedImage> {
+        let _permit = self.resizes.acquire().await?;
+        tokio::task::spawn_blocking(move || process_image(source.as_ref(), &options)).await?
+    }
+
+    async fn resize(
+        &self,
+        source: Bytes,
+        options: ImageOptions,
+        key: &str,
+    ) -> anyhow::Result<Bytes> {
+        if let Some(cached) = self.cached(key).await {
+            return Ok(cached);
+        }
+        let cache_file = self.directory.join(key);
+        let resized = self
+            .process(source, options)
+            .await
+            .map_err(|e| format!("{e:#}"))
+            .with_status(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+        if let Err(e) = self.insert(&cache_file, &resized.bytes).await {
+            log::warn!(
+                "Unable to cache the resized image in {}: {e:#}",
+                cache_file.display()
+            );
+        }
+        Ok(Bytes::from(resized.bytes))
+    }
+
+    async fn insert(&self, cache_file: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+        write_cache_file(&self.directory, cache_file, bytes).await?;
+        let mut size = self.size.lock().await;
+        let total = match *size {
+            Some(total) => total + bytes.len() as u64,
+            None => cached_files(&self.directory)
+                .await?
+                .iter()
+                .map(|f| f.size)
+                .sum(),
+        };
+        *size = Some(if total > self.max_size {
+            self.remove_oldest().await?
+        } else {
+            total
+        });
+        Ok(())
+    }
+
+    /// Removes the oldest cached images, until they use less than three quarters of the maximal size.
+    /// Returns the size of the remaining images.
+    async fn remove_oldest(&self) -> anyhow::Result<u64> {
+        let mut files = cached_files(&self.directory).await?;
+        files.sort_by_key(|f| std::cmp::Reverse(f.modified));
+        let mut total: u64 = files.iter().map(|f| f.size).sum();
+        let target = self.max_size / 4 * 3;
+        while total > target {
+            let Some(oldest) = files.pop() else { break };
+            match tokio::fs::remove_file(&oldest.path).await {
+                Ok(()) => total -= oldest.size,
+                Err(e) => log::warn!(
+                    "Unable to remove the cached image {}: {e}",
+                    oldest.path.display()
+                ),
+            }
+        }
+        log::debug!(
+            "The image cache exceeded {} bytes. It now uses {total} bytes",
+            self.max_size
+        );
+        Ok(total)
+    }
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+async fn cached_files(directory: &Path) -> anyhow::Result<Vec<CachedFile>> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(directory)
+        .await
+        .with_context(|| format!("Unable to list the image cache {}", directory.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            files.push(CachedFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    Ok(files)
+}
+
+async fn resized_image(
+    req: HttpRequest,
+    payload: web::Payload,
+    path: web::Path<String>,
+    query: web::Query<ImageQuery>,
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let path = PathBuf::from(path.into_inner());
+    let options = query.options();
+    let public = check_access(&req, &app_state)?;
+    if path.extension().is_some_and(|ext| ext == "sql") {
+        resized_sql_image(req, payload, &path, options, &app_state).await
+    } else {
+        resized_file(&req, &path, options, public, &app_state).await
+    }
+}
+
+/// `/_sqlpage/img/private/photo.jpg` serves `/private/photo.jpg`, so it requires the same authentication.
+/// Returns whether the image is public, and can be stored by shared caches.
+fn check_access(req: &HttpRequest, app_state: &AppState) -> actix_web::Result<bool> {
+    let Some(oidc_state) = &app_state.oidc_state else {
+        return Ok(true);
+    };
+    let site_prefix = app_state.config.site_prefix.trim_end_matches('/');
+    let image_path = req
+        .path()
+        .strip_prefix(&format!("{site_prefix}/_sqlpage/img"))
+        .unwrap_or_default();
+    if oidc_state
+        .config
+        .is_public_path(&format!("{site_prefix}{image_path}"))
+    {
+        Ok(true)
+    } else if req.extensions().contains::<OidcClaims>() {
+        Ok(false)
+    } else {
+        let error = anyhow::anyhow!(ErrorWithStatus {
+            status: StatusCode::FORBIDDEN
+        })
+        .context("This image requires authentication");
+        Err(anyhow_err_to_actix(error, app_state))
+    }
+}
+
+/// Files of the site are only read when they changed since the browser last downloaded them,
+/// or since they were last resized
+async fn resized_file(
+    req: &HttpRequest,
+    path: &Path,
+    options: ImageOptions,
+    public: bool,
+    app_state: &AppState,
+) -> actix_web::Result<HttpResponse> {
+    let access = FileAccess::unprivileged(path).map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    if let Ok(IfModifiedSince(date)) = IfModifiedSince::parse(req) {
+        // Last-Modified is rounded down to the second, and the file was read before it was sent
+        let since = DateTime::<Utc>::from(SystemTime::from(date)) + chrono::TimeDelta::seconds(1);
+        let modified = app_state
+            .file_system
+            .modified_since(app_state, access, since)
+            .await
+            .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+        if !modified {
+            return Ok(HttpResponse::NotModified().finish());
+        }
+    }
+    let bytes = if let Some(bytes) = cached_file(path, &options, app_state).await? {
+        bytes
+    } else {
+        let read_at = Utc::now();
+        let source = app_state
+            .file_system
+            .read_file(app_state, access)
+            .await
+            .with_context(|| format!("Unable to read the image {}", path.display()))
+            .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+        let key = cache_key(&source, &options);
+        let file = known_file_name(path, &options);
+        let bytes = resize(app_state, Bytes::from(source), options, &key, path).await?;
+        app_state.image_cache.remember_file(file, key, read_at);
+        bytes
+    };
+    Ok(image_response(&bytes)
+        .insert_header(LastModified(HttpDate::from(SystemTime::now())))
+        .insert_header(CacheControl(vec![
+            if public {
+                CacheDirective::Public
+            } else {
+                CacheDirective::Private
+            },
+            CacheDirective::MaxAge(MAX_AGE_SECONDS),
+        ]))
+        .body(bytes))
+}
+
+fn known_file_name(path: &Path, options: &ImageOptions) -> String {
+    format!(
+        "{} {:?} {:?}",
+        path.display(),
+        options.width,
+        options.format
+    )
+}
+
+/// The resized image of a file that did not change since it was last resized
+async fn cached_file(
+    path: &Path,
+    options: &ImageOptions,
+    app_state: &AppState,
+) -> actix_web::Result<Option<Bytes>> {
+    let cache = &app_state.image_cache;
+    let Some((key, read_at)) = cache.known_file(&known_file_name(path, options)) else {
+        return Ok(None);
+    };
+    let access = FileAccess::unprivileged(path).map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    let modified = app_state
+        .file_system
+        .modified_since(app_state, access, read_at)
+        .await
+        .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    if modified {
+        return Ok(None);
+    }
+    Ok(cache.cached(&key).await)
+}
+
+/// The image returned by a `.sql` file can be different for every request, so it is identified by its contents
+async fn resized_sql_image(
+    req: HttpRequest,
+    payload: web::Payload,
+    path: &Path,
+    options: ImageOptions,
+    app_state: &AppState,
+) -> actix_web::Result<HttpResponse> {
+    let mut service_request = ServiceRequest::from_parts(req.clone(), payload.into_inner());
+    let response = process_sql_request(&mut service_request, path.to_path_buf()).await?;
+    if !response.status().is_success() {
+        // redirections, authentication and error pages of the SQL file are passed through
+        return Ok(response);
+    }
+    let source = actix_web::body::to_bytes_limited(response.into_body(), MAX_SOURCE_SIZE)
+        .await
+        .map_err(|_| format!("The image is larger than {MAX_SOURCE_SIZE} bytes"))
+        .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+        .and_then(|body| body.map_err(|e| anyhow::anyhow!("{e}")))
+        .with_context(|| format!("Unable to read the image returned by {}", path.display()))
+        .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    let key = cache_key(&source, &options);
+    let etag = EntityTag::new_strong(key.clone());
+    let cache_control = CacheControl(vec![CacheDirective::Private, CacheDirective::NoCache]);
+    let not_modified = req
+        .get_header::<IfNoneMatch>()
+        .is_some_and(|if_none_match| match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        });
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(cache_control)
+            .finish());
+    }
+    let bytes = resize(app_state, source, options, &key, path).await?;
+    Ok(image_response(&bytes)
+        .insert_header(ETag(etag))
+        .insert_header(cache_control)
+        .body(bytes))
+}
+
+async fn resize(
+    app_state: &AppState,
+    source: Bytes,
+    options: ImageOptions,
+    key: &str,
+    path: &Path,
+) -> actix_web::Result<Bytes> {
+    app_state
+        .image_cache
+        .resize(source, options, key)
+        .await
+        .with_context(|| format!("Unable to resize the image {}", path.display()))
+        .map_err(|e| anyhow_err_to_actix(e, app_state))
+}
+
+fn image_response(bytes: &[u8]) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response.insert_header(ContentType(
+        detect_mime_type(bytes)
+            .parse()
+            .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM),
+    ));
+    response
+}
+
+/// Identifies a resized version of an image, from the contents of the original image
+fn cache_key(source: &[u8], options: &ImageOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(source);
+    hasher.update(format!("{:?} {:?}", options.width, options.format).as_bytes());
+    base64::Engine::encode(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+        hasher.finalize(),
+    )
+}
+
+/// Writes to a temporary file first, so that concurrent requests never read a partially written image.
+/// The cache directory is only readable by the user running `SQLPage`.
+async fn write_cache_file(directory: &Path, cache_file: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut dir_builder = tokio::fs::DirBuilder::new();
+    dir_builder.recursive(true);
+    #[cfg(unix)]
+    dir_builder.mode(0o700);
+    dir_builder.create(directory).await?;
+    let temp_file = cache_file.with_extension(format!("{}.tmp", rand::random::<u32>()));
+    tokio::fs::write(&temp_file, bytes).await?;
+    if let Err(e) = tokio::fs::rename(&temp_file, cache_file).await {
+        let _ = tokio::fs::remove_file(&temp_file).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width_is_rounded_up() {
+        let width = |w| ImageQuery { w, fmt: None }.options().width;
+        assert_eq!(width(None), None);
+        assert_eq!(width(Some(0)), Some(160));
+        assert_eq!(width(Some(320)), Some(320));
+        assert_eq!(width(Some(321)), Some(480));
+        assert_eq!(width(Some(u32::MAX)), Some(3840));
+        for srcset_width in super::super::images::SRCSET_WIDTHS {
+            assert_eq!(width(Some(srcset_width)), Some(srcset_width));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_oldest_cached_images_are_removed() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = ImageCache {
+            directory: directory.path().join("cache"),
+            max_size: 100,
+            size: Mutex::new(None),
+            resizes: Semaphore::new(1),
+            known_files: std::sync::Mutex::default(),
+        };
+        std::fs::create_dir(&cache.directory).unwrap();
+        for (name, age) in [("a", 20), ("b", 10)] {
+            let file = std::fs::File::create(cache.directory.join(name)).unwrap();
+            std::io::Write::write_all(&mut &file, &[0; 40]).unwrap();
+            let modified = SystemTime::now() - std::time::Duration::from_secs(age);
+            file.set_modified(modified).unwrap();
+        }
+        cache
+            .insert(&cache.directory.join("c"), &[0; 40])
+            .await
+            .unwrap();
+        // a + b + c = 120 bytes > 100, so files are removed until at most 75 bytes are left
+        assert!(!cache.directory.join("a").exists());
+        assert!(!cache.directory.join("b").exists());
+        assert!(cache.directory.join("c").exists());
+        assert_eq!(*cache.size.lock().await, Some(40));
+    }
+}
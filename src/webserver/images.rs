@@ -0,0 +1,428 @@
+//! Resizing, cropping and conversion of images.
+//!
+//! Images are decoded and encoded again, so their metadata (EXIF, including GPS coordinates,
+//! XMP, comments) is never copied to the result. The EXIF orientation is applied to the pixels
+//! before it is dropped. Processing is CPU-bound: call [`process_image`] from a blocking task.
+//!
+//! WebP images are always encoded losslessly, because only a lossless WebP encoder is available:
+//! the quality does not apply to them, and photos are smaller in JPEG or AVIF.
+
+use std::io::Cursor;
+
+use anyhow::Context;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use serde::Deserialize;
+
+/// Images larger than this in any dimension are not produced
+pub const MAX_DIMENSION: u32 = 16_384;
+/// Images with more pixels than this are not decoded, whatever their file size
+pub const MAX_PIXELS: u64 = 50_000_000;
+/// Bytes per pixel of the largest decoded images (16-bit RGBA)
+const MAX_BYTES_PER_PIXEL: u64 = 8;
+const DEFAULT_QUALITY: u8 = 80;
+/// From 1 (slowest, smallest files) to 10 (fastest)
+const AVIF_SPEED: u8 = 8;
+
+/// The widths of the resized images that components list in the `srcset` attribute of their images
+pub const SRCSET_WIDTHS: [u32; 5] = [320, 640, 960, 1280, 1920];
+/// The only widths produced by `/_sqlpage/img/`. Other widths are rounded up to one of these,
+/// so that the number of variants of an image stays small.
+pub const RESIZED_WIDTHS: [u32; 9] = [160, 320, 480, 640, 960, 1280, 1920, 2560, 3840];
+
+const RESIZABLE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "webp", "gif", "bmp"];
+
+/// The `srcset` attribute of an image of the site, with resized versions of the image served by `/_sqlpage/img/`.
+/// Returns `None` for images that cannot be resized: external URLs, data URLs, relative paths and
+/// files that are not raster images.
+#[must_use]
+pub fn srcset(site_prefix: &str, image_url: &str) -> Option<String> {
+    let path = image_url.strip_prefix(site_prefix)?;
+    if path.starts_with('/') || path.contains(['?', '#']) {
+        return None;
+    }
+    let (_, extension) = path.rsplit_once('.')?;
+    if !RESIZABLE_EXTENSIONS
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(extension))
+    {
+        return None;
+    }
+    let candidates: Vec<String> = SRCSET_WIDTHS
+        .iter()
+        .map(|width| format!("{site_prefix}_sqlpage/img/{path}?w={width} {width}w"))
+        .collect();
+    Some(candidates.join(", "))
+}
+
+/// How an image is fitted in the requested width and height
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Keep the aspect ratio, and fit the whole image in the box. Images are never enlarged.
+    #[default]
+    Contain,
+    /// Keep the aspect ratio, fill the whole box, and crop what overflows, around the center
+    Cover,
+    /// Stretch the image to the exact size of the box
+    Fill,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+    Gif,
+}
+
+impl OutputFormat {
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+            Self::Gif => "gif",
+        }
+    }
+
+    #[must_use]
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+            Self::Gif => "image/gif",
+        }
+    }
+
+    /// The format in which an image is written when no format is requested:
+    /// its own format, or PNG for formats that are not meant for the web
+    fn of_input(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Jpeg => Self::Jpeg,
+            ImageFormat::WebP => Self::Webp,
+            ImageFormat::Gif => Self::Gif,
+            _ => Self::Png,
+        }
+    }
+}
+
+/// A rectangle of the original image, in pixels
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImageOptions {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Fit,
+    /// Applied before resizing
+    pub crop: Option<Crop>,
+    /// Defaults to the format of the original image
+    pub format: Option<OutputFormat>,
+    /// From 1 to 100, for JPEG and AVIF. WebP images are always lossless.
+    pub quality: Option<u8>,
+}
+
+pub struct ProcessedImage {
+    pub bytes: Vec<u8>,
+    pub format: OutputFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Decodes an image, whatever its file name, from its contents.
+/// Only the first frame of animated images is kept.
+/// Images with more than [`MAX_PIXELS`] pixels are rejected before they are decoded.
+pub fn decode_image(bytes: &[u8]) -> anyhow::Result<(DynamicImage, ImageFormat)> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .context("The file is not an image in a supported format")?;
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_PIXELS * MAX_BYTES_PER_PIXEL);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().context("Unable to read the image")?;
+    let (width, height) = decoder.dimensions();
+    anyhow::ensure!(
+        u64::from(width) * u64::from(height) <= MAX_PIXELS,
+        "The {width}x{height} image has more than {MAX_PIXELS} pixels"
+    );
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder).context("Unable to decode the image")?;
+    image.apply_orientation(orientation);
+    Ok((image, format))
+}
+
+pub fn process_image(bytes: &[u8], options: &ImageOptions) -> anyhow::Result<ProcessedImage> {
+    let (image, input_format) = decode_image(bytes)?;
+    let format = options
+        .format
+        .unwrap_or_else(|| OutputFormat::of_input(input_format));
+    let image = transform(image, options)?;
+    let bytes = encode(&image, format, options.quality.unwrap_or(DEFAULT_QUALITY))?;
+    Ok(ProcessedImage {
+        bytes,
+        format,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+fn transform(mut image: DynamicImage, options: &ImageOptions) -> anyhow::Result<DynamicImage> {
+    for dimension in [options.width, options.height].into_iter().flatten() {
+        anyhow::ensure!(
+            (1..=MAX_DIMENSION).contains(&dimension),
+            "Image dimensions must be between 1 and {MAX_DIMENSION} pixels, not {dimension}"
+        );
+    }
+    if let Some(Crop {
+        x,
+        y,
+        width,
+        height,
+    }) = options.crop
+    {
+        anyhow::ensure!(
+            width > 0
+                && height > 0
+                && x.saturating_add(width) <= image.width()
+                && y.saturating_add(height) <= image.height(),
+            "The crop area {width}x{height} at ({x}, {y}) is not inside the {}x{} image",
+            image.width(),
+            image.height()
+        );
+        image = image.crop_imm(x, y, width, height);
+    }
+    let image = match (options.fit, options.width, options.height) {
+        (_, None, None) => image,
+        (Fit::Cover, Some(width), Some(height)) => {
+            image.resize_to_fill(width, height, FilterType::Lanczos3)
+        }
+        (Fit::Fill, width, height) => image.resize_exact(
+            width.unwrap_or(image.width()),
+            height.unwrap_or(image.height()),
+            FilterType::Lanczos3,
+        ),
+        (_, width, height) => {
+            let (width, height) = (
+                width.unwrap_or(u32::MAX).min(image.width()),
+                height.unwrap_or(u32::MAX).min(image.height()),
+            );
+            if (width, height) == (image.width(), image.height()) {
+                image
+            } else {
+                image.resize(width, height, FilterType::Lanczos3)
+            }
+        }
+    };
+    Ok(image)
+}
+
+fn encode(image: &DynamicImage, format: OutputFormat, quality: u8) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(
+        (1..=100).contains(&quality),
+        "The quality must be between 1 and 100, not {quality}"
+    );
+    let mut bytes = Vec::new();
+    match format {
+        // JPEG has no transparency
+        OutputFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?,
+        OutputFormat::Avif => to_8_bits(image).write_with_encoder(
+            AvifEncoder::new_with_speed_quality(&mut bytes, AVIF_SPEED, quality),
+        )?,
+        OutputFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?,
+        OutputFormat::Webp => {
+            to_8_bits(image).write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?;
+        }
+        OutputFormat::Gif => {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Gif)?;
+        }
+    }
+    Ok(bytes)
+}
+
+fn to_8_bits(image: &DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => image.clone(),
+        _ if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, Rgb([200, 10, 10]));
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn dimensions(options: &ImageOptions) -> (u32, u32) {
+        let processed = process_image(&png(400, 200), options).unwrap();
+        (processed.width, processed.height)
+    }
+
+    #[test]
+    fn test_resize() {
+        let options = |width, height, fit| ImageOptions {
+            width,
+            height,
+            fit,
+            ..ImageOptions::default()
+        };
+        assert_eq!(
+            dimensions(&options(Some(100), None, Fit::Contain)),
+            (100, 50)
+        );
+        assert_eq!(
+            dimensions(&options(Some(100), Some(100), Fit::Contain)),
+            (100, 50)
+        );
+        assert_eq!(
+            dimensions(&options(Some(100), Some(100), Fit::Cover)),
+            (100, 100)
+        );
+        assert_eq!(
+            dimensions(&options(Some(100), Some(100), Fit::Fill)),
+            (100, 100)
+        );
+        // Images are not enlarged
+        assert_eq!(
+            dimensions(&options(Some(1000), None, Fit::Contain)),
+            (400, 200)
+        );
+        let crop = ImageOptions {
+            crop: Some(Crop {
+                x: 10,
+                y: 10,
+                width: 50,
+                height: 20,
+            }),
+            ..ImageOptions::default()
+        };
+        assert_eq!(dimensions(&crop), (50, 20));
+        let outside = ImageOptions {
+            crop: Some(Crop {
+                x: 390,
+                y: 0,
+                width: 50,
+                height: 20,
+            }),
+            ..ImageOptions::default()
+        };
+        assert!(process_image(&png(400, 200), &outside).is_err());
+    }
+
+    #[test]
+    fn test_srcset() {
+        assert_eq!(
+            srcset("/", "/photos/cat.JPG").unwrap(),
+            "/_sqlpage/img/photos/cat.JPG?w=320 320w, /_sqlpage/img/photos/cat.JPG?w=640 640w, \
+            /_sqlpage/img/photos/cat.JPG?w=960 960w, /_sqlpage/img/photos/cat.JPG?w=1280 1280w, \
+            /_sqlpage/img/photos/cat.JPG?w=1920 1920w"
+        );
+        assert!(
+            srcset("/app/", "/app/cat.png")
+                .unwrap()
+                .starts_with("/app/_sqlpage/img/cat.png?w=320 320w")
+        );
+        for url in [
+            "cat.png",
+            "//cdn.example.com/cat.png",
+            "https://example.com/cat.png",
+            "data:image/png;base64,AAAA",
+            "/cat.svg",
+            "/cat.png?v=2",
+            "/other_site/cat.png",
+        ] {
+            let prefix = if url.starts_with("/other") {
+                "/app/"
+            } else {
+                "/"
+            };
+            assert_eq!(srcset(prefix, url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        for (format, mime_type) in [
+            (OutputFormat::Jpeg, "image/jpeg"),
+            (OutputFormat::Webp, "image/webp"),
+            (OutputFormat::Gif, "image/gif"),
+        ] {
+            let options = ImageOptions {
+                width: Some(20),
+                format: Some(format),
+                ..ImageOptions::default()
+            };
+            let processed = process_image(&png(40, 40), &options).unwrap();
+            assert_eq!(
+                crate::webserver::database::blob_to_data_url::detect_mime_type(&processed.bytes),
+                mime_type
+            );
+        }
+        assert!(process_image(b"not an image", &ImageOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_webp_is_lossless() {
+        let image = RgbImage::from_fn(50, 40, |x, y| {
+            let (x, y) = (u8::try_from(x).unwrap(), u8::try_from(y).unwrap());
+            Rgb([x * 5, y * 6, x ^ y])
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image.clone())
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let options = ImageOptions {
+            format: Some(OutputFormat::Webp),
+            quality: Some(10),
+            ..ImageOptions::default()
+        };
+        let webp = process_image(&png, &options).unwrap().bytes;
+        let decoded = image::load_from_memory_with_format(&webp, ImageFormat::WebP).unwrap();
+        assert_eq!(decoded.to_rgb8(), image);
+    }
+
+    #[test]
+    fn test_pixel_limit() {
+        // A BMP header announcing a 10000x10000 image, without its pixels
+        let image = RgbImage::new(1, 1);
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Bmp)
+            .unwrap();
+        bytes[18..22].copy_from_slice(&10_000i32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&10_000i32.to_le_bytes());
+        let err = decode_image(&bytes).unwrap_err();
+        assert!(err.to_string().contains("pixels"), "{err:#}");
+    }
+}
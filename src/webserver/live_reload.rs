@@ -0,0 +1,86 @@
+//! Server-sent events that tell the pages open in the browser to reload when a file of the site changes.
+//!
+//! The shell component subscribes to this endpoint when `live_reload` is enabled,
+//! with the path of its page, so that it is only told about the files that the page uses.
+//! The endpoint does not exist when the site files are not watched, as in production.
+
+use super::routing::{AppFileStore, RoutingAction, calculate_route};
+use crate::AppState;
+use actix_web::{
+    HttpResponse, Resource,
+    http::{
+        header::{CacheControl, CacheDirective, ContentEncoding},
+        uri::PathAndQuery,
+    },
+    web::{self, Bytes},
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use tokio::sync::broadcast::error::RecvError;
+
+pub(super) fn endpoint() -> Resource {
+    web::resource("_sqlpage/live_reload").get(live_reload)
+}
+
+#[derive(Deserialize)]
+struct LiveReloadParams {
+    /// The URL path of the page open in the browser
+    page: Option<String>,
+}
+
+async fn live_reload(
+    app_state: web::Data<AppState>,
+    params: web::Query<LiveReloadParams>,
+) -> HttpResponse {
+    let Some(watcher) = &app_state.file_watcher else {
+        return HttpResponse::NotFound().body("Live reload is disabled");
+    };
+    let mut changes = watcher.subscribe();
+    // Pages whose SQL file is unknown are refreshed when any file changes
+    let page = match &params.page {
+        Some(page) => page_sql_file(&app_state, page).await,
+        None => None,
+    };
+    let app_state = web::Data::clone(&app_state);
+    let events = async_stream::stream! {
+        yield Ok::<_, Infallible>(Bytes::from_static(b": connected\n\n"));
+        loop {
+            match changes.recv().await {
+                Ok(path) => {
+                    let affected = match (&page, &app_state.file_watcher) {
+                        (Some(page), Some(watcher)) => watcher.affects(page, &path),
+                        _ => true,
+                    };
+                    if !affected {
+                        continue;
+                    }
+                    let path = path.display().to_string().replace(['\r', '\n'], " ");
+                    yield Ok(Bytes::from(format!("event: change\ndata: {path}\n\n")));
+                }
+                Err(RecvError::Lagged(_)) => yield Ok(Bytes::from_static(b"event: change\ndata:\n\n")),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        // Compression would buffer the events
+        .insert_header(ContentEncoding::Identity)
+        .streaming(events)
+}
+
+/// The SQL file that runs for the page at the given URL path
+async fn page_sql_file(app_state: &AppState, page: &str) -> Option<PathBuf> {
+    let path_and_query = PathAndQuery::try_from(page).ok()?;
+    let store = AppFileStore::new(&app_state.sql_file_cache, &app_state.file_system, app_state);
+    match calculate_route(&path_and_query, &store, &app_state.config).await {
+        Ok(RoutingAction::Execute(path) | RoutingAction::CustomNotFound(path)) => Some(path),
+        Ok(_) => None,
+        Err(e) => {
+            log::debug!("Unable to find the SQL file of {page}: {e:#}");
+            None
+        }
+    }
+}
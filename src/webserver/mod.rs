@@ -26,22 +26,34 @@
 //!   - [Content Security Policy](https://sql-page.com/safety.sql) enforcement
 //!
 //! - [`response_writer`]: Streaming response generation
+//! - [`fetch_cache`]: Responses of `sqlpage.fetch` kept in memory for the requests that allow it
+//! - [`images`]: Resizing and conversion of uploaded images
+//! - [`image_endpoint`]: Resized images of the site, for the `srcset` attribute of images
+//! - [`resumable_uploads`]: Large file uploads sent in chunks, that can resume after a network failure
+//! - [`upload_scanner`]: Antivirus scan of uploaded files, with `ClamAV`
 //! - [`static_content`]: Static asset handling (JS, CSS, icons)
+//! - [`live_reload`]: Refreshes the pages open in the browser when files change, in development
 //!
 
 pub mod content_security_policy;
 pub mod database;
 pub(crate) mod error;
 pub mod error_with_status;
+pub(crate) mod fetch_cache;
 pub mod http;
 pub mod http_client;
 pub mod http_metrics;
 pub mod http_request_info;
 mod https;
+pub(crate) mod image_endpoint;
+pub mod images;
 #[cfg(feature = "lambda-web")]
 mod lambda_http;
+mod live_reload;
 pub mod request_variables;
+pub(crate) mod resumable_uploads;
 pub mod server_timing;
+pub mod upload_scanner;
 
 pub use database::Database;
 pub use error_with_status::{ActixErrorStatusExt, ErrorWithStatus, StatusCodeResultExt};
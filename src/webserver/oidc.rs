@@ -1178,7 +1178,8 @@ fn create_tmp_login_flow_state_cookie<'a>(
     initial_url: &'a str,
 ) -> Cookie<'a> {
     let csrf_token = &params.csrf_token;
-    let cookie_name = SQLPAGE_TMP_LOGIN_STATE_COOKIE_PREFIX.to_owned() + csrf_token.secret();
+    let cookie_name =
+        SQLPAGE_TMP_LOGIN_STATE_COOKIE_PREFIX.to_owned() + csrf_token.secret().as_str();
     let cookie_value = serde_json::to_string(&LoginFlowState {
         nonce: params.nonce.clone(),
         redirect_target: initial_url,
@@ -1204,7 +1205,8 @@ fn get_tmp_login_flow_state_cookie(
     request: &ServiceRequest,
     csrf_token: &CsrfToken,
 ) -> anyhow::Result<Cookie<'static>> {
-    let cookie_name = SQLPAGE_TMP_LOGIN_STATE_COOKIE_PREFIX.to_owned() + csrf_token.secret();
+    let cookie_name =
+        SQLPAGE_TMP_LOGIN_STATE_COOKIE_PREFIX.to_owned() + csrf_token.secret().as_str();
     request
         .cookie(&cookie_name)
         .with_context(|| format!("No {cookie_name} cookie found"))
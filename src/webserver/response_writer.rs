@@ -2,6 +2,7 @@ use actix_web::web::Bytes;
 use std::io::Write;
 use std::mem;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
 use tokio::sync::mpsc;
 
 /// The response writer is a buffered async writer that sends data to the client.
@@ -13,12 +14,12 @@ use tokio::sync::mpsc;
 #[derive(Clone)]
 pub struct ResponseWriter {
     buffer: Vec<u8>,
-    response_bytes: mpsc::Sender<Bytes>,
+    response_bytes: mpsc::Sender<std::io::Result<Bytes>>,
 }
 
 impl ResponseWriter {
     #[must_use]
-    pub fn new(response_bytes: mpsc::Sender<Bytes>) -> Self {
+    pub fn new(response_bytes: mpsc::Sender<std::io::Result<Bytes>>) -> Self {
         Self {
             response_bytes,
             buffer: Vec::new(),
@@ -31,12 +32,22 @@ impl ResponseWriter {
                 use std::fmt::Write;
                 write!(&mut msg, "Unable to flush data: {e}").unwrap();
             }
-            if let Err(e) = self.response_bytes.send(msg.into()).await {
+            if let Err(e) = self.response_bytes.send(Ok(msg.into())).await {
                 log::error!("Unable to send error back to client: {e}");
             }
         }
     }
 
+    /// Interrupts the response, for binary formats that cannot contain an error message.
+    /// The client sees a failed download instead of a truncated file.
+    pub async fn abort(&mut self, msg: String) {
+        self.buffer.clear();
+        let error = std::io::Error::other(msg);
+        if let Err(e) = self.response_bytes.send(Err(error)).await {
+            log::debug!("Unable to interrupt the response: {e}");
+        }
+    }
+
     pub async fn async_flush(&mut self) -> std::io::Result<()> {
         if self.buffer.is_empty() {
             return Ok(());
@@ -50,7 +61,7 @@ impl ResponseWriter {
             .reserve()
             .await
             .map_err(|_| std::io::ErrorKind::WouldBlock)?;
-        sender.send(mem::take(&mut self.buffer).into());
+        sender.send(Ok(mem::take(&mut self.buffer).into()));
         Ok(())
     }
 }
@@ -71,7 +82,7 @@ impl Write for ResponseWriter {
             String::from_utf8_lossy(&self.buffer)
         );
         self.response_bytes
-            .try_send(mem::take(&mut self.buffer).into())
+            .try_send(Ok(mem::take(&mut self.buffer).into()))
             .map_err(|e|
                 std::io::Error::new(
                     std::io::ErrorKind::WouldBlock,
@@ -83,7 +94,7 @@ impl Write for ResponseWriter {
 
 #[allow(clippy::module_name_repetitions)]
 pub struct AsyncResponseWriter {
-    poll_sender: tokio_util::sync::PollSender<Bytes>,
+    poll_sender: tokio_util::sync::PollSender<std::io::Result<Bytes>>,
     writer: ResponseWriter,
 }
 
@@ -122,7 +133,7 @@ impl tokio::io::AsyncWrite for AsyncResponseWriter {
         } = self.get_mut();
         match poll_sender.poll_reserve(cx) {
             std::task::Poll::Ready(Ok(())) => {
-                let res = poll_sender.send_item(mem::take(&mut writer.buffer).into());
+                let res = poll_sender.send_item(Ok(mem::take(&mut writer.buffer).into()));
                 std::task::Poll::Ready(res.map_err(|_| std::io::ErrorKind::BrokenPipe.into()))
             }
             std::task::Poll::Pending => std::task::Poll::Pending,
@@ -147,3 +158,31 @@ impl Drop for ResponseWriter {
         }
     }
 }
+
+/// An in-memory buffer for encoders that take ownership of a synchronous writer,
+/// like zip archives and parquet files. A clone of the buffer is kept by the renderer,
+/// which regularly moves the encoded bytes to the [`ResponseWriter`].
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Removes and returns everything written so far.
+    #[must_use]
+    pub fn take(&self) -> Vec<u8> {
+        mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,660 @@
+//! Resumable uploads of large files, using the [tus protocol](https://tus.io/protocols/resumable-upload), at `/_sqlpage/uploads/`.
+//!
+//! Uploads are sent in chunks, stored in the `uploads` folder of the configuration directory,
+//! so an upload interrupted by a network failure can continue where it stopped.
+//! When the last chunk is received, [`ON_UPLOAD_FILE`] runs, and sees the file as if it had been posted in a form:
+//! `sqlpage.uploaded_file_path`, `sqlpage.uploaded_file_name` and `sqlpage.uploaded_file_mime_type` work as usual.
+//! Resumable uploads are disabled when that file does not exist.
+//!
+//! When [`ON_UPLOAD_START_FILE`] exists, it runs before an upload is created, and can refuse it,
+//! for instance with the `authentication` component.
+//! The number and the total size of the incomplete uploads are limited by
+//! `max_pending_resumable_uploads` and `max_pending_resumable_uploads_size`,
+//! and uploads that did not receive any chunk for a day are removed periodically.
+
+use super::error::anyhow_err_to_actix;
+use super::http::render_sql;
+use super::http_request_info::PreparedPostData;
+use super::server_timing::ServerTiming;
+use super::upload_scanner::scan_upload;
+use crate::app_config::AppConfig;
+use crate::filesystem::FileAccess;
+use crate::{AppState, ON_UPLOAD_FILE, ON_UPLOAD_START_FILE};
+use actix_multipart::form::tempfile::TempFile;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderName, HeaderValue, LOCATION};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, Scope, web};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+const UPLOADS_DIR: &str = "uploads";
+const UPLOAD_ID_LENGTH: usize = 32;
+/// Incomplete uploads that did not receive any chunk for this long are deleted
+const EXPIRATION: Duration = Duration::from_hours(24);
+/// How often expired uploads are removed
+const CLEANUP_INTERVAL: Duration = Duration::from_hours(1);
+
+pub(super) fn endpoint() -> Scope {
+    web::scope("_sqlpage/uploads")
+        .service(
+            web::resource("")
+                .route(web::method(Method::OPTIONS).to(options))
+                .route(web::post().to(create)),
+        )
+        .service(
+            web::resource("/{id}")
+                .route(web::method(Method::OPTIONS).to(options))
+                .route(web::head().to(status))
+                .route(web::patch().to(append))
+                .route(web::delete().to(terminate)),
+        )
+}
+
+/// Stored next to the uploaded data, in `<id>.json`
+#[derive(Serialize, Deserialize, Debug)]
+struct UploadInfo {
+    length: u64,
+    /// The `Upload-Metadata` sent by the client when creating the upload
+    metadata: Vec<(String, String)>,
+}
+
+impl UploadInfo {
+    fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find_map(|(k, v)| (k == key).then_some(v.as_str()))
+    }
+}
+
+struct Upload {
+    id: String,
+    info: UploadInfo,
+    data_path: PathBuf,
+    info_path: PathBuf,
+}
+
+impl Upload {
+    async fn open(app_state: &AppState, id: &str) -> actix_web::Result<Self> {
+        let is_valid_id =
+            id.len() == UPLOAD_ID_LENGTH && id.chars().all(|c| c.is_ascii_alphanumeric());
+        if !is_valid_id {
+            return Err(tus_error(StatusCode::NOT_FOUND, "Invalid upload id"));
+        }
+        let dir = uploads_dir(app_state);
+        let info_path = dir.join(format!("{id}.json"));
+        let Ok(info) = tokio::fs::read(&info_path).await else {
+            return Err(tus_error(StatusCode::NOT_FOUND, "Unknown upload"));
+        };
+        let info = serde_json::from_slice(&info)
+            .with_context(|| format!("Invalid upload information in {}", info_path.display()))
+            .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+        Ok(Self {
+            id: id.to_string(),
+            info,
+            data_path: dir.join(id),
+            info_path,
+        })
+    }
+
+    async fn offset(&self) -> std::io::Result<u64> {
+        Ok(tokio::fs::metadata(&self.data_path).await?.len())
+    }
+
+    async fn delete(&self) {
+        for path in [&self.data_path, &self.info_path] {
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                log::debug!("Unable to remove {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Removes the upload from [`PendingUploads::receiving`] when the chunk has been handled
+struct UploadLock(Arc<PendingUploads>, String);
+
+impl UploadLock {
+    fn acquire(pending_uploads: &Arc<PendingUploads>, id: &str) -> Option<Self> {
+        let mut receiving = pending_uploads.receiving.lock().ok()?;
+        receiving
+            .insert(id.to_string())
+            .then(|| Self(Arc::clone(pending_uploads), id.to_string()))
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        if let Ok(mut receiving) = self.0.receiving.lock() {
+            receiving.remove(&self.1);
+        }
+    }
+}
+
+/// The uploads that were created and are not complete yet, to limit their number and their total size
+#[derive(Default)]
+pub(crate) struct PendingUploads {
+    uploads: Mutex<HashMap<String, PendingUpload>>,
+    /// Uploads currently receiving a chunk. Two chunks of the same upload are never written at the same time.
+    receiving: Mutex<HashSet<String>>,
+}
+
+struct PendingUpload {
+    length: u64,
+    created_at: Instant,
+}
+
+impl PendingUploads {
+    /// Also starts removing the expired uploads periodically, until the returned value is dropped
+    pub(crate) fn start(config: &AppConfig) -> Arc<Self> {
+        let pending_uploads = Arc::new(Self::default());
+        let dir = config.configuration_directory.join(UPLOADS_DIR);
+        tokio::spawn(remove_expired_uploads_periodically(
+            dir,
+            Arc::downgrade(&pending_uploads),
+        ));
+        pending_uploads
+    }
+
+    fn reserve(&self, id: &str, length: u64, config: &AppConfig) -> actix_web::Result<()> {
+        let mut uploads = self.uploads.lock().expect("pending uploads lock poisoned");
+        if uploads.len() >= config.max_pending_resumable_uploads {
+            log::warn!(
+                "Refusing a resumable upload: {} uploads are already in progress (max_pending_resumable_uploads)",
+                uploads.len()
+            );
+            return Err(tus_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many uploads are in progress",
+            ));
+        }
+        let total: u64 = uploads.values().map(|upload| upload.length).sum();
+        if total.saturating_add(length) > config.max_pending_resumable_uploads_size {
+            log::warn!(
+                "Refusing a resumable upload of {length} bytes: {total} bytes are already being uploaded (max_pending_resumable_uploads_size)"
+            );
+            return Err(tus_error(
+                StatusCode::INSUFFICIENT_STORAGE,
+                "Too much data is being uploaded",
+            ));
+        }
+        uploads.insert(
+            id.to_string(),
+            PendingUpload {
+                length,
+                created_at: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) {
+        self.uploads
+            .lock()
+            .expect("pending uploads lock poisoned")
+            .remove(id);
+    }
+
+    /// Replaces the pending uploads with the ones found in the uploads directory,
+    /// except those created while the directory was being read
+    fn update(&self, scan_started_at: Instant, on_disk: HashMap<String, u64>) {
+        let mut uploads = self.uploads.lock().expect("pending uploads lock poisoned");
+        uploads.retain(|_, upload| upload.created_at >= scan_started_at);
+        for (id, length) in on_disk {
+            uploads.entry(id).or_insert(PendingUpload {
+                length,
+                created_at: scan_started_at,
+            });
+        }
+    }
+}
+
+fn uploads_dir(app_state: &AppState) -> PathBuf {
+    app_state.config.configuration_directory.join(UPLOADS_DIR)
+}
+
+fn on_upload_file(app_state: &AppState) -> PathBuf {
+    app_state
+        .config
+        .configuration_directory
+        .join(ON_UPLOAD_FILE)
+}
+
+fn tus_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Tus-Resumable", TUS_VERSION));
+    response
+}
+
+fn tus_error(status: StatusCode, message: &'static str) -> actix_web::Error {
+    actix_web::error::InternalError::from_response(
+        message,
+        tus_response(status)
+            .content_type("text/plain")
+            .body(message),
+    )
+    .into()
+}
+
+async fn ensure_enabled(app_state: &AppState) -> actix_web::Result<()> {
+    if tokio::fs::try_exists(on_upload_file(app_state))
+        .await
+        .unwrap_or(false)
+    {
+        Ok(())
+    } else {
+        log::debug!(
+            "Resumable uploads are disabled, because {} does not exist",
+            on_upload_file(app_state).display()
+        );
+        Err(tus_error(
+            StatusCode::NOT_FOUND,
+            "Resumable uploads are not enabled on this server",
+        ))
+    }
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn check_tus_version(req: &HttpRequest) -> actix_web::Result<()> {
+    if header(req, "Tus-Resumable") == Some(TUS_VERSION) {
+        Ok(())
+    } else {
+        Err(actix_web::error::InternalError::from_response(
+            "Unsupported tus version",
+            HttpResponse::PreconditionFailed()
+                .insert_header(("Tus-Version", TUS_VERSION))
+                .finish(),
+        )
+        .into())
+    }
+}
+
+async fn options(app_state: web::Data<AppState>) -> actix_web::Result<HttpResponse> {
+    ensure_enabled(&app_state).await?;
+    Ok(tus_response(StatusCode::NO_CONTENT)
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .insert_header(("Tus-Max-Size", app_state.config.max_resumable_upload_size))
+        .finish())
+}
+
+async fn create(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_enabled(&app_state).await?;
+    check_tus_version(&req)?;
+    let length: u64 = header(&req, "Upload-Length")
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(|| tus_error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Length"))?;
+    if length > app_state.config.max_resumable_upload_size {
+        return Err(tus_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "The file is larger than max_resumable_upload_size",
+        ));
+    }
+    let metadata = parse_metadata(header(&req, "Upload-Metadata").unwrap_or_default())
+        .ok_or_else(|| tus_error(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata"))?;
+    let location_prefix = req.path().trim_end_matches('/').to_string();
+    if let Some(refusal) = run_on_upload_start(req, &app_state, &metadata).await? {
+        return Ok(refusal);
+    }
+
+    let dir = uploads_dir(&app_state);
+    let id = new_upload_id();
+    app_state
+        .pending_uploads
+        .reserve(&id, length, &app_state.config)?;
+    let info = serde_json::to_vec(&UploadInfo { length, metadata })?;
+    let created = async {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join(&id), b"").await?;
+        tokio::fs::write(dir.join(format!("{id}.json")), info).await
+    }
+    .await
+    .with_context(|| format!("Unable to create an upload in {}", dir.display()));
+    if let Err(e) = created {
+        app_state.pending_uploads.remove(&id);
+        return Err(anyhow_err_to_actix(e, &app_state));
+    }
+    log::debug!("Created the resumable upload {id} of {length} bytes");
+
+    let location = format!("{location_prefix}/{id}");
+    Ok(tus_response(StatusCode::CREATED)
+        .insert_header((LOCATION, location))
+        .finish())
+}
+
+async fn status(
+    req: HttpRequest,
+    id: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_enabled(&app_state).await?;
+    check_tus_version(&req)?;
+    let upload = Upload::open(&app_state, &id).await?;
+    let offset = upload.offset().await?;
+    Ok(tus_response(StatusCode::OK)
+        .insert_header(("Upload-Offset", offset))
+        .insert_header(("Upload-Length", upload.info.length))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+async fn append(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    id: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_enabled(&app_state).await?;
+    check_tus_version(&req)?;
+    if header(&req, CONTENT_TYPE.as_str()) != Some("application/offset+octet-stream") {
+        return Err(tus_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Chunks must be sent as application/offset+octet-stream",
+        ));
+    }
+    let upload = Upload::open(&app_state, &id).await?;
+    let Some(_lock) = UploadLock::acquire(&app_state.pending_uploads, &upload.id) else {
+        return Err(tus_error(
+            StatusCode::CONFLICT,
+            "Another chunk of this upload is being received",
+        ));
+    };
+    let mut offset = upload.offset().await?;
+    if header(&req, "Upload-Offset").and_then(|o| o.parse().ok()) != Some(offset) {
+        return Err(tus_error(
+            StatusCode::CONFLICT,
+            "Upload-Offset does not match the size of the upload",
+        ));
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&upload.data_path)
+        .await?;
+    // Everything received before a network failure is kept, so that the client can resume from there
+    let mut received = Ok(());
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                received = Err(e.into());
+                break;
+            }
+        };
+        let chunk_len = chunk.len() as u64;
+        if offset + chunk_len > upload.info.length {
+            received = Err(tus_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "The chunk goes beyond Upload-Length",
+            ));
+            break;
+        }
+        file.write_all(&chunk).await?;
+        offset += chunk_len;
+    }
+    file.flush().await?;
+    drop(file);
+    received?;
+    log::trace!(
+        "Upload {} is at {offset}/{} bytes",
+        upload.id,
+        upload.info.length
+    );
+
+    if offset < upload.info.length {
+        return Ok(tus_response(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", offset))
+            .finish());
+    }
+    let response = run_on_upload(req, &app_state, &upload).await;
+    // The upload is removed once on_upload.sql has finished running, like any uploaded file,
+    // even when it failed: the client cannot send the file again with the same upload
+    upload.delete().await;
+    app_state.pending_uploads.remove(&upload.id);
+    let mut response = response?;
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static("tus-resumable"),
+        HeaderValue::from_static(TUS_VERSION),
+    );
+    headers.insert(HeaderName::from_static("upload-offset"), offset.into());
+    Ok(response)
+}
+
+async fn terminate(
+    req: HttpRequest,
+    id: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_enabled(&app_state).await?;
+    check_tus_version(&req)?;
+    let upload = Upload::open(&app_state, &id).await?;
+    let Some(_lock) = UploadLock::acquire(&app_state.pending_uploads, &upload.id) else {
+        return Err(tus_error(
+            StatusCode::CONFLICT,
+            "A chunk of this upload is being received",
+        ));
+    };
+    upload.delete().await;
+    app_state.pending_uploads.remove(&upload.id);
+    Ok(tus_response(StatusCode::NO_CONTENT).finish())
+}
+
+/// Runs `on_upload.sql` with the completed file, and returns its response to the client that sent the last chunk
+async fn run_on_upload(
+    req: HttpRequest,
+    app_state: &web::Data<AppState>,
+    upload: &Upload,
+) -> actix_web::Result<HttpResponse> {
+    let field = upload.info.metadata("field").unwrap_or("file").to_string();
+    let file = std::fs::File::open(&upload.data_path)?;
+    let uploaded_file = TempFile {
+        // Deleted when the request is finished, like the files uploaded in a form
+        file: tempfile::NamedTempFile::from_parts(
+            file,
+            tempfile::TempPath::try_from_path(&upload.data_path)?,
+        ),
+        content_type: upload
+            .info
+            .metadata("filetype")
+            .and_then(|mime| mime.parse().ok()),
+        file_name: upload.info.metadata("filename").map(ToString::to_string),
+        size: usize::try_from(upload.info.length).unwrap_or(usize::MAX),
+    };
+    let post_variables = upload
+        .info
+        .metadata
+        .iter()
+        .filter(|(k, _)| k != "field")
+        .cloned()
+        .chain([
+            ("field".to_string(), field.clone()),
+            ("upload_id".to_string(), upload.id.clone()),
+        ])
+        .collect();
+    let scan_result = scan_upload(&app_state.config, &field, &uploaded_file)
+        .await
+        .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    req.extensions_mut().insert(PreparedPostData {
+        post_variables,
+        upload_scan_results: scan_result
+            .map(|verdict| (field.clone(), verdict))
+            .into_iter()
+            .collect(),
+        uploaded_files: vec![(field, uploaded_file)],
+    });
+    run_hook(req, app_state, &on_upload_file(app_state)).await
+}
+
+/// Runs `on_upload_start.sql` with the metadata of the upload as POST variables.
+/// Returns its response when it refuses the upload, with a status that is not a success.
+/// Without this file, uploads are refused unless `allow_anonymous_resumable_uploads` is set.
+async fn run_on_upload_start(
+    req: HttpRequest,
+    app_state: &web::Data<AppState>,
+    metadata: &[(String, String)],
+) -> actix_web::Result<Option<HttpResponse>> {
+    let hook_path = app_state
+        .config
+        .configuration_directory
+        .join(ON_UPLOAD_START_FILE);
+    if !tokio::fs::try_exists(&hook_path).await.unwrap_or(false) {
+        if app_state.config.allow_anonymous_resumable_uploads {
+            return Ok(None);
+        }
+        log::warn!(
+            "Refusing a resumable upload, because {} does not exist. \
+             Create it to decide which uploads to accept, or set allow_anonymous_resumable_uploads to true.",
+            hook_path.display()
+        );
+        return Err(tus_error(
+            StatusCode::FORBIDDEN,
+            "Uploads are not accepted without authorization",
+        ));
+    }
+    req.extensions_mut().insert(PreparedPostData {
+        post_variables: metadata.to_vec(),
+        ..PreparedPostData::default()
+    });
+    let mut response = run_hook(req, app_state, &hook_path).await?;
+    if response.status().is_success() {
+        return Ok(None);
+    }
+    log::debug!(
+        "{} refused the upload with the status {}",
+        hook_path.display(),
+        response.status()
+    );
+    response.headers_mut().insert(
+        HeaderName::from_static("tus-resumable"),
+        HeaderValue::from_static(TUS_VERSION),
+    );
+    Ok(Some(response))
+}
+
+async fn run_hook(
+    req: HttpRequest,
+    app_state: &web::Data<AppState>,
+    hook_path: &Path,
+) -> actix_web::Result<HttpResponse> {
+    let sql_file = app_state
+        .sql_file_cache
+        .get(app_state, FileAccess::privileged(hook_path))
+        .await
+        .with_context(|| format!("Unable to read {}", hook_path.display()))
+        .map_err(|e| anyhow_err_to_actix(e, app_state))?;
+    let server_timing = ServerTiming::for_env(app_state.config.environment);
+    let mut service_request = ServiceRequest::from_parts(req, actix_web::dev::Payload::None);
+    render_sql(&mut service_request, sql_file, server_timing).await
+}
+
+fn new_upload_id() -> String {
+    use rand::{RngExt, distr::Alphanumeric};
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(UPLOAD_ID_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Parses `key base64value,key2 base64value2`. The value can be omitted.
+fn parse_metadata(header: &str) -> Option<Vec<(String, String)>> {
+    use base64::Engine;
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+            let value = base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .ok()?;
+            Some((key.to_string(), String::from_utf8(value).ok()?))
+        })
+        .collect()
+}
+
+async fn remove_expired_uploads_periodically(dir: PathBuf, pending_uploads: Weak<PendingUploads>) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let scan_started_at = Instant::now();
+        let on_disk = remove_expired_uploads(&dir).await;
+        let Some(pending_uploads) = pending_uploads.upgrade() else {
+            // The server stopped
+            return;
+        };
+        pending_uploads.update(scan_started_at, on_disk);
+    }
+}
+
+/// Removes the uploads that did not receive any chunk for [`EXPIRATION`],
+/// and returns the length of the remaining ones, by id
+async fn remove_expired_uploads(dir: &Path) -> HashMap<String, u64> {
+    let mut remaining = HashMap::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return remaining;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let info_path = entry.path();
+        if info_path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let data_path = info_path.with_extension("");
+        let is_expired = tokio::fs::metadata(&data_path)
+            .await
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_none_or(|age| age > EXPIRATION);
+        if is_expired {
+            log::debug!("Removing the expired upload {}", data_path.display());
+            let _ = tokio::fs::remove_file(&data_path).await;
+            let _ = tokio::fs::remove_file(&info_path).await;
+            continue;
+        }
+        let info = tokio::fs::read(&info_path)
+            .await
+            .ok()
+            .and_then(|info| serde_json::from_slice::<UploadInfo>(&info).ok());
+        let id = data_path.file_name().and_then(|name| name.to_str());
+        if let (Some(info), Some(id)) = (info, id) {
+            remaining.insert(id.to_string(), info.length);
+        }
+    }
+    remaining
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        assert_eq!(
+            parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential"),
+            Some(vec![
+                (
+                    "filename".to_string(),
+                    "world_domination_plan.pdf".to_string()
+                ),
+                ("is_confidential".to_string(), String::new()),
+            ])
+        );
+        assert_eq!(parse_metadata(""), Some(vec![]));
+        assert_eq!(parse_metadata("filename not-base64!"), None);
+    }
+}
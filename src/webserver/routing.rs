@@ -39,6 +39,16 @@
 //! - If found: **Execute** the custom 404 SQL file
 //! - If no custom 404 found anywhere: Return default **404 Not Found** response
 //!
+//! ### 4. Error Pages
+//!
+//! When a SQL file fails before its response has started, `SQLPage` looks for a custom error page
+//! with [`find_error_handler`]:
+//!
+//! - Starting from the failing file's directory, walk up the directory tree
+//! - In each directory, look for `{status}.sql` (for instance `500.sql`), then `error.sql`
+//! - If found: **Execute** the error page with the original request
+//! - If no error page is found anywhere: Return the default error response
+//!
 //! ## Examples
 //!
 //! ```text
@@ -80,6 +90,7 @@ use crate::filesystem::{FileAccess, FileSystem};
 use crate::webserver::database::SqlFile;
 use crate::{AppState, file_cache::FileCache};
 use RoutingAction::{CustomNotFound, Execute, NotFound, Redirect, Serve};
+use awc::http::StatusCode;
 use awc::http::uri::PathAndQuery;
 use log::debug;
 use percent_encoding;
@@ -87,6 +98,7 @@ use std::path::{Path, PathBuf};
 
 const INDEX: &str = "index.sql";
 const NOT_FOUND: &str = "404.sql";
+const ERROR: &str = "error.sql";
 const SQL_EXTENSION: &str = "sql";
 const FORWARD_SLASH: &str = "/";
 
@@ -132,8 +144,20 @@ impl FileStore for AppFileStore<'_> {
     async fn contains(&self, access: FileAccess<'_>) -> anyhow::Result<bool> {
         if self.cache.contains(access).await? {
             Ok(true)
+        } else if self
+            .cache
+            .is_known_missing(self.app_state, access.path())
+            .await
+        {
+            Ok(false)
         } else {
-            self.filesystem.file_exists(self.app_state, access).await
+            let exists = self.filesystem.file_exists(self.app_state, access).await?;
+            if !exists {
+                self.cache
+                    .record_missing(self.app_state, access.path())
+                    .await;
+            }
+            Ok(exists)
         }
     }
 }
@@ -265,6 +289,35 @@ where
     Ok(NotFound)
 }
 
+/// Finds the custom error page for a SQL file that failed with the given status code,
+/// walking up from the file's directory and preferring `{status}.sql` over `error.sql`.
+pub(crate) async fn find_error_handler<T>(
+    path: &Path,
+    status: StatusCode,
+    store: &T,
+) -> anyhow::Result<Option<PathBuf>>
+where
+    T: FileStore,
+{
+    let status_file = format!("{}.sql", status.as_u16());
+    let mut parent = path.parent();
+    while let Some(p) = parent {
+        for name in [status_file.as_str(), ERROR] {
+            let target = p.join(name);
+            if target != path && store.contains(FileAccess::unprivileged(&target)?).await? {
+                debug!(
+                    "Using {} as the error page for {}",
+                    target.display(),
+                    path.display()
+                );
+                return Ok(Some(target));
+            }
+        }
+        parent = p.parent();
+    }
+    Ok(None)
+}
+
 fn append_to_path(path_and_query: &PathAndQuery, append: &str) -> String {
     let mut full_uri = path_and_query.to_string();
     full_uri.insert_str(path_and_query.path().len(), append);
@@ -484,6 +537,53 @@ mod tests {
         }
     }
 
+    mod error_handler {
+        use super::super::find_error_handler;
+        use super::Store;
+        use awc::http::StatusCode;
+        use std::path::{Path, PathBuf};
+
+        async fn find(path: &str, status: StatusCode, files: &[&str]) -> Option<PathBuf> {
+            find_error_handler(Path::new(path), status, &Store::with_files(files))
+                .await
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn no_error_handler() {
+            let actual = find("a/b.sql", StatusCode::INTERNAL_SERVER_ERROR, &["index.sql"]).await;
+            assert_eq!(None, actual);
+        }
+
+        #[tokio::test]
+        async fn status_file_takes_precedence_over_error_file() {
+            let files = ["a/error.sql", "a/500.sql"];
+            let actual = find("a/b.sql", StatusCode::INTERNAL_SERVER_ERROR, &files).await;
+            assert_eq!(Some(PathBuf::from("a/500.sql")), actual);
+        }
+
+        #[tokio::test]
+        async fn deeper_error_file_takes_precedence() {
+            let files = ["500.sql", "a/error.sql"];
+            let actual = find("a/b.sql", StatusCode::INTERNAL_SERVER_ERROR, &files).await;
+            assert_eq!(Some(PathBuf::from("a/error.sql")), actual);
+        }
+
+        #[tokio::test]
+        async fn walks_up_to_the_root() {
+            let files = ["error.sql", "a/500.sql"];
+            let actual = find("a/b/c.sql", StatusCode::FORBIDDEN, &files).await;
+            assert_eq!(Some(PathBuf::from("error.sql")), actual);
+        }
+
+        #[tokio::test]
+        async fn failing_error_page_does_not_handle_itself() {
+            let files = ["error.sql", "a/error.sql"];
+            let actual = find("a/error.sql", StatusCode::INTERNAL_SERVER_ERROR, &files).await;
+            assert_eq!(Some(PathBuf::from("error.sql")), actual);
+        }
+    }
+
     mod asset {
         use super::StoreConfig::File;
         use super::{do_route, serve};
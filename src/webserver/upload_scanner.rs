@@ -0,0 +1,183 @@
+//! Scans uploaded files with a [`ClamAV`](https://www.clamav.net/) daemon before SQL files can access them.
+//!
+//! Enabled by [`AppConfig::clamd_address`]. Files are streamed to clamd with its
+//! [`INSTREAM`](https://linux.die.net/man/8/clamd) command, so clamd does not need access to the files of `SQLPage`.
+
+use crate::app_config::AppConfig;
+use crate::webserver::{ErrorWithStatus, StatusCodeResultExt};
+use actix_multipart::form::tempfile::TempFile;
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SCAN_TIMEOUT: Duration = Duration::from_mins(1);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The result of the scan of an uploaded file, returned by `sqlpage.uploaded_file_scan_result`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    /// Contains the name of the signature that matched
+    Infected(String),
+}
+
+impl fmt::Display for ScanVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clean => f.write_str("clean"),
+            Self::Infected(signature) => f.write_str(signature),
+        }
+    }
+}
+
+/// Scans an uploaded file, when a scanner is configured.
+/// Infected files are rejected, unless [`AppConfig::reject_infected_uploads`] is false.
+pub(crate) async fn scan_upload(
+    config: &AppConfig,
+    field_name: &str,
+    file: &TempFile,
+) -> anyhow::Result<Option<ScanVerdict>> {
+    let Some(address) = &config.clamd_address else {
+        return Ok(None);
+    };
+    let verdict = tokio::time::timeout(SCAN_TIMEOUT, scan_file(address, file.file.path()))
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("clamd did not answer in {SCAN_TIMEOUT:?}")))
+        .map_err(|e| format!("{e:#}"))
+        // uploads are never accepted without a scan when a scanner is configured
+        .with_status(StatusCode::SERVICE_UNAVAILABLE)
+        .with_context(|| {
+            format!("Unable to scan the uploaded file {field_name:?} with clamd at {address}")
+        })?;
+    log::debug!("Scan result of the uploaded file {field_name:?}: {verdict}");
+    if let ScanVerdict::Infected(signature) = &verdict
+        && config.reject_infected_uploads
+    {
+        return Err(anyhow::Error::new(ErrorWithStatus {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+        }))
+        .with_context(|| {
+            format!("The uploaded file {field_name:?} was rejected, because it contains a virus: {signature}")
+        });
+    }
+    Ok(Some(verdict))
+}
+
+async fn scan_file(address: &str, path: &Path) -> anyhow::Result<ScanVerdict> {
+    let file = tokio::fs::File::open(path).await?;
+    let response = if let Some(socket) = unix_socket_path(address) {
+        #[cfg(unix)]
+        {
+            let stream = tokio::net::UnixStream::connect(socket).await?;
+            send_instream(stream, file).await?
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!("unix sockets are not supported on this platform: {socket}")
+    } else {
+        let stream = tokio::net::TcpStream::connect(address).await?;
+        send_instream(stream, file).await?
+    };
+    parse_response(&response)
+}
+
+/// Unix socket paths are absolute, or start with `unix:`
+fn unix_socket_path(address: &str) -> Option<&str> {
+    address
+        .strip_prefix("unix:")
+        .or_else(|| address.starts_with('/').then_some(address))
+}
+
+/// Sends the file in chunks, each one prefixed by its length, and reads the answer of clamd
+async fn send_instream(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    mut file: impl AsyncRead + Unpin,
+) -> anyhow::Result<String> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let len = file.read(&mut buffer).await?;
+        stream.write_all(&u32::try_from(len)?.to_be_bytes()).await?;
+        if len == 0 {
+            break;
+        }
+        stream.write_all(&buffer[..len]).await?;
+    }
+    stream.flush().await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(String::from_utf8_lossy(&response)
+        .trim_end_matches(['\0', '\n'])
+        .to_string())
+}
+
+/// Parses `stream: OK` or `stream: <signature> FOUND`
+fn parse_response(response: &str) -> anyhow::Result<ScanVerdict> {
+    let result = response.strip_prefix("stream: ").unwrap_or(response);
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(signature.to_string()))
+    } else if result.contains("size limit exceeded") {
+        anyhow::bail!(
+            "the file is larger than the StreamMaxLength of clamd (25 MB by default). \
+             Increase it in clamd.conf to at least max_uploaded_file_size and max_resumable_upload_size"
+        )
+    } else {
+        anyhow::bail!("clamd could not scan the file: {result}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(parse_response("stream: OK").unwrap(), ScanVerdict::Clean);
+        assert_eq!(
+            parse_response("stream: Win.Test.EICAR_HDB-1 FOUND").unwrap(),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        let too_large = parse_response("INSTREAM size limit exceeded. ERROR").unwrap_err();
+        assert!(too_large.to_string().contains("StreamMaxLength"));
+        assert!(parse_response("stream: Can't allocate memory ERROR").is_err());
+    }
+
+    #[test]
+    fn test_unix_socket_path() {
+        assert_eq!(
+            unix_socket_path("/run/clamav/clamd.ctl"),
+            Some("/run/clamav/clamd.ctl")
+        );
+        assert_eq!(unix_socket_path("unix:clamd.sock"), Some("clamd.sock"));
+        assert_eq!(unix_socket_path("127.0.0.1:3310"), None);
+    }
+
+    #[tokio::test]
+    async fn test_instream_protocol() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let clamd = tokio::spawn(async move {
+            let mut command = [0; 10];
+            server.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut contents = Vec::new();
+            loop {
+                let len = server.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; len];
+                server.read_exact(&mut chunk).await.unwrap();
+                contents.extend(chunk);
+            }
+            server.write_all(b"stream: OK\0").await.unwrap();
+            contents
+        });
+        let response = send_instream(client, &b"hello world"[..]).await.unwrap();
+        assert_eq!(response, "stream: OK");
+        assert_eq!(clamd.await.unwrap(), b"hello world");
+    }
+}
@@ -0,0 +1,54 @@
+use actix_web::{http::StatusCode, test};
+use sqlpage::bundle;
+
+use crate::common::{make_app_data_from_config, req_path_with_app_data, test_config};
+
+#[actix_web::test]
+async fn test_site_is_served_from_bundle() {
+    let dir = std::env::temp_dir().join(format!("sqlpage_bundle_site_{}", std::process::id()));
+    let web_root = dir.join("site");
+    let templates = web_root.join("sqlpage").join("templates");
+    std::fs::create_dir_all(&templates).unwrap();
+    std::fs::write(
+        web_root.join("index.sql"),
+        "select 'greeting' as component, 'bundled' as name;",
+    )
+    .unwrap();
+    std::fs::write(web_root.join("style.css"), "body { color: red }").unwrap();
+    std::fs::write(
+        templates.join("greeting.handlebars"),
+        "<p>Hello from the {{name}} template</p>",
+    )
+    .unwrap();
+
+    let mut config = test_config();
+    config.web_root.clone_from(&web_root);
+    config.configuration_directory = web_root.join("sqlpage");
+    let output = dir.join("site.zip");
+    assert_eq!(bundle::create(&config, &output, None).unwrap(), 3);
+    // The bundle does not depend on the original files
+    std::fs::remove_dir_all(&web_root).unwrap();
+
+    config.bundle = Some(output);
+    let app_data = make_app_data_from_config(config).await;
+
+    let resp = req_path_with_app_data("/", app_data.clone()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(
+        body.contains("<p>Hello from the bundled template</p>"),
+        "{body}"
+    );
+
+    let resp = req_path_with_app_data("/style.css", app_data.clone())
+        .await
+        .unwrap();
+    assert_eq!(test::read_body(resp).await, "body { color: red }");
+
+    let resp = req_path_with_app_data("/missing.sql", app_data)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -104,6 +104,7 @@ pub(crate) fn test_config() -> AppConfig {
         "database_connection_retries": 3,
         "database_connection_acquire_timeout_seconds": 15,
         "allow_exec": true,
+        "precompute_sqlpage_functions": true,
         "max_uploaded_file_size": 123456,
         "listen_on": "111.111.111.111:1",
         "system_root_ca_certificates" : false
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use actix_web::{http::StatusCode, test};
+
+use crate::common::{make_app_data_from_config, req_path_with_app_data, test_config};
+
+#[actix_web::test]
+async fn test_custom_helper_in_component() {
+    let web_root = std::env::temp_dir().join(format!("sqlpage_helpers_{}", std::process::id()));
+    let config_dir = web_root.join("sqlpage");
+    std::fs::create_dir_all(config_dir.join("templates")).unwrap();
+    std::fs::create_dir_all(config_dir.join("helpers")).unwrap();
+    std::fs::write(
+        web_root.join("index.sql"),
+        "select 'price' as component, 1234 as amount;",
+    )
+    .unwrap();
+    std::fs::write(
+        config_dir.join("templates/price.handlebars"),
+        r#"<p>{{money amount currency="$"}}</p>"#,
+    )
+    .unwrap();
+    let helper = config_dir.join("helpers/money.rhai");
+    std::fs::write(
+        &helper,
+        "`${params[0] / 100}.${params[0] % 100} ${hash.currency}`",
+    )
+    .unwrap();
+
+    let mut config = test_config();
+    config.web_root.clone_from(&web_root);
+    config.configuration_directory.clone_from(&config_dir);
+    config.cache_stale_duration_ms = Some(0);
+    let app_data = make_app_data_from_config(config).await;
+
+    let body = |resp| async { String::from_utf8(test::read_body(resp).await.to_vec()).unwrap() };
+    let resp = req_path_with_app_data("/", app_data.clone()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let page = body(resp).await;
+    assert!(page.contains("<p>12.34 $</p>"), "{page}");
+
+    // Helpers are reloaded when their file changes
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(&helper, "`${hash.currency}${params[0] / 100}`").unwrap();
+    let resp = req_path_with_app_data("/", app_data.clone()).await.unwrap();
+    let page = body(resp).await;
+    assert!(page.contains("<p>$12</p>"), "{page}");
+
+    // Errors in helpers are reported
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(&helper, "params[0] +").unwrap();
+    let resp = req_path_with_app_data("/", app_data).await.unwrap();
+    let page = body(resp).await;
+    assert!(page.contains("money"), "{page}");
+    assert!(!page.contains("$12"), "{page}");
+
+    std::fs::remove_dir_all(&web_root).unwrap();
+}
@@ -68,6 +68,119 @@ async fn test_csv_body() -> actix_web::Result<()> {
     Ok(())
 }
 
+#[actix_web::test]
+async fn test_xlsx_body() -> actix_web::Result<()> {
+    use std::io::Read;
+
+    let app_data = make_app_data().await;
+    if matches!(
+        app_data.db.info.database_type,
+        sqlpage::webserver::database::SupportedDatabase::Oracle
+    ) {
+        return Ok(());
+    }
+    let req =
+        crate::common::get_request_to_with_data("/tests/data_formats/xlsx_data.sql", app_data)
+            .await?
+            .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+    );
+    assert!(
+        resp.headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("report.xlsx")
+    );
+    let body = test::read_body(resp).await;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body.to_vec())).unwrap();
+    let mut workbook = String::new();
+    archive
+        .by_name("xl/workbook.xml")
+        .unwrap()
+        .read_to_string(&mut workbook)
+        .unwrap();
+    assert!(
+        workbook.contains(r#"<sheet name="People &amp; pets""#),
+        "{workbook}"
+    );
+    let mut sheet = String::new();
+    archive
+        .by_name("xl/worksheets/sheet1.xml")
+        .unwrap()
+        .read_to_string(&mut sheet)
+        .unwrap();
+    for expected in [
+        r#"<c r="A1" t="inlineStr" s="1"><is><t xml:space="preserve">id</t></is></c>"#,
+        r#"<c r="A2"><v>1</v></c>"#,
+        r#"<c r="B2" t="inlineStr"><is><t xml:space="preserve">Hello &lt;World&gt; !</t></is></c>"#,
+        r#"<c r="C2" s="2"><v>45292</v></c>"#,
+        r#"<row r="3"><c r="A3"><v>2</v></c><c r="C3" s="2"><v>45293</v></c></row>"#,
+        // Text that looks like a date is only a date in date columns
+        r#"<c r="B4" t="inlineStr"><is><t xml:space="preserve">2024-12-25</t></is></c>"#,
+    ] {
+        assert!(sheet.contains(expected), "{expected} not found in {sheet}");
+    }
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_parquet_body() -> actix_web::Result<()> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let app_data = make_app_data().await;
+    if matches!(
+        app_data.db.info.database_type,
+        sqlpage::webserver::database::SupportedDatabase::Oracle
+    ) {
+        return Ok(());
+    }
+    let req =
+        crate::common::get_request_to_with_data("/tests/data_formats/parquet_data.sql", app_data)
+            .await?
+            .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/vnd.apache.parquet"
+    );
+    let body = test::read_body(resp).await;
+    let reader = SerializedFileReader::new(body).unwrap();
+    let metadata = reader.metadata();
+    assert_eq!(metadata.num_row_groups(), 2);
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            r#"{id: 1, label: "one", value: 1.5}"#,
+            "{id: 2, label: null, value: 2.5}",
+            r#"{id: 3, label: "three", value: null}"#,
+        ]
+    );
+    Ok(())
+}
+
+/// The first row group fixes the column types: a later mismatch interrupts the download,
+/// instead of sending a truncated file
+#[actix_web::test]
+async fn test_parquet_type_mismatch_interrupts_the_response() -> actix_web::Result<()> {
+    let resp = crate::common::req_path("/tests/data_formats/parquet_type_mismatch.sql").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = actix_web::body::to_bytes(resp.into_body()).await;
+    assert!(body.is_err(), "the response should be interrupted");
+    Ok(())
+}
+
 #[actix_web::test]
 async fn test_csv_filename_header_injection() -> actix_web::Result<()> {
     use actix_web::http::header::ContentDisposition;
@@ -140,6 +253,63 @@ async fn test_json_columns() {
     );
 }
 
+#[actix_web::test]
+async fn test_pdf_body() -> actix_web::Result<()> {
+    let req = get_request_to("/tests/data_formats/pdf_component.sql")
+        .await?
+        .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/pdf"
+    );
+    assert!(
+        resp.headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("invoice.pdf")
+    );
+    let body = test::read_body(resp).await;
+    let pdf = String::from_utf8_lossy(&body);
+    assert!(pdf.starts_with("%PDF-"), "{pdf}");
+    assert!(pdf.trim_end().ends_with("%%EOF"), "{pdf}");
+    assert!(pdf.contains("/MediaBox [0 0 792 612]"), "{pdf}");
+    assert!(pdf.contains("/Title (Invoice 42)"), "{pdf}");
+    assert!(pdf.contains("(Consulting) Tj"), "{pdf}");
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_accept_pdf_renders_components() -> actix_web::Result<()> {
+    let resp = req_with_accept("/tests/data_formats/pdf_report.sql", "application/pdf").await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/pdf"
+    );
+    let body = test::read_body(resp).await;
+    let pdf = String::from_utf8_lossy(&body);
+    assert!(pdf.starts_with("%PDF-"), "{pdf}");
+    assert!(pdf.contains("/Title (Quarterly report)"), "{pdf}");
+    for text in [
+        "(Summary) Tj",
+        "(Sales grew \\(a lot\\) in every r\\351gion.) Tj",
+        "(Revenue) Tj",
+        "(1250 \\200) Tj",
+        "(Highlights) Tj",
+        "(Opened in Lyon) Tj",
+        "(Twelve people) Tj",
+        "(product) Tj",
+        "(Gadget) Tj",
+    ] {
+        assert!(pdf.contains(text), "{text} not found in {pdf}");
+    }
+    Ok(())
+}
+
 #[actix_web::test]
 async fn test_accept_json_returns_json_array() -> actix_web::Result<()> {
     let resp = req_with_accept(
@@ -0,0 +1,10 @@
+select
+    'parquet' as component,
+    'measures' as filename,
+    2 as row_group_size;
+
+select 1 as id, 'one' as label, 1.5 as value
+union all
+select 2 as id, NULL as label, 2.5 as value
+union all
+select 3 as id, 'three' as label, NULL as value;
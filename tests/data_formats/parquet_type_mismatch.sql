@@ -0,0 +1,5 @@
+select 'parquet' as component, 1 as row_group_size;
+
+select 1 as id
+union all
+select 'two' as id;
@@ -0,0 +1,8 @@
+select
+    'pdf' as component,
+    'invoice' as filename,
+    'Invoice 42' as title,
+    'letter' as page_size,
+    'landscape' as orientation;
+
+select 'Consulting' as item, 3 as quantity;
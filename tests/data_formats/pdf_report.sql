@@ -0,0 +1,17 @@
+select 'shell' as component, 'Quarterly report' as title;
+
+select 'text' as component, 'Summary' as title, 'Sales grew (a lot) in every région.' as contents;
+
+select 'big_number' as component;
+select 'Revenue' as title, 1250 as value, '€' as unit;
+
+select 'list' as component, 'Highlights' as title;
+select 'New office' as title, 'Opened in Lyon' as description;
+
+select 'card' as component;
+select 'Team' as title, 'Twelve people' as description;
+
+select 'table' as component;
+select 1 as id, 'Widget' as product
+union all
+select 2 as id, 'Gadget' as product;
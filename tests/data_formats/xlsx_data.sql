@@ -0,0 +1,13 @@
+drop table if exists sqlpage_xlsx_export_test;
+create table sqlpage_xlsx_export_test(id integer, msg varchar(64), since date);
+insert into sqlpage_xlsx_export_test(id, msg, since) values
+    (1, 'Hello <World> !', '2024-01-01'),
+    (2, NULL, '2024-01-02'),
+    (3, '2024-12-25', '2024-01-03');
+
+select
+    'xlsx' as component,
+    'report' as filename,
+    'People & pets' as sheet_name;
+
+select id, msg, since from sqlpage_xlsx_export_test order by id;
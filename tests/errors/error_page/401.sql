@@ -0,0 +1,2 @@
+SELECT 'text' AS component,
+    'Please log in (status ' || $error_status || ')' AS contents;
@@ -0,0 +1 @@
+SELECT 'text' AS component, sqlpage.basic_auth_username() AS contents;
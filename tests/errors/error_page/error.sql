@@ -0,0 +1,3 @@
+SELECT 'alert' AS component,
+    'Custom error page' AS title,
+    'Status ' || $error_status || ', request ' || $request_id AS description;
@@ -0,0 +1 @@
+SELECT 'text' AS component, contents FROM this_table_does_not_exist;
@@ -102,6 +102,35 @@ async fn test_404_fallback() {
     }
 }
 
+#[actix_web::test]
+async fn test_custom_error_page() {
+    // In development, errors are displayed inline in the page instead
+    let mut config = test_config();
+    config.environment = sqlpage::app_config::DevOrProd::Production;
+    let app_data = make_app_data_from_config(config).await;
+    let resp = req_path_with_app_data("/tests/errors/error_page/failing.sql", app_data)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.starts_with("<!DOCTYPE html>"), "{body}");
+    assert!(body.contains("Custom error page"), "{body}");
+    assert!(body.contains("Status 500, request "), "{body}");
+    assert!(!body.contains("this_table_does_not_exist"), "{body}");
+}
+
+#[actix_web::test]
+async fn test_status_specific_error_page() {
+    let resp = req_path("/tests/errors/error_page/basic_auth.sql")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(resp.headers().contains_key("www-authenticate"));
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("Please log in (status 401)"), "{body}");
+    assert!(!body.contains("Custom error page"), "{body}");
+}
+
 #[actix_web::test]
 async fn test_default_404() {
     for f in [
@@ -0,0 +1 @@
+select sqlpage.exec_with_meta($command) as result;
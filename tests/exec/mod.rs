@@ -27,3 +27,96 @@ fn exec_test_uri() -> &'static str {
 fn exec_test_uri() -> &'static str {
     "/tests/exec/exec.sql?exec_program=echo"
 }
+
+#[cfg(unix)]
+async fn exec_with_config(
+    path_and_query: &str,
+    exec_commands: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let mut config = crate::common::test_config();
+    config.allow_exec = false;
+    config.exec_commands = serde_json::from_value(exec_commands).unwrap();
+    let app_data = crate::common::make_app_data_from_config(config).await;
+    let req = TestRequest::get()
+        .uri(path_and_query)
+        .app_data(app_data)
+        .insert_header(header::Accept::json())
+        .to_srv_request();
+    let resp = main_handler(req).await.map_err(|e| e.to_string())?;
+    let body = actix_web::test::read_body(resp).await;
+    let rows: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    match rows[0].get("description") {
+        Some(error) => Err(error.to_string()),
+        None => Ok(rows[0].clone()),
+    }
+}
+
+#[cfg(unix)]
+fn exec_with_meta_uri(command: &serde_json::Value) -> String {
+    format!(
+        "/tests/exec/exec_with_meta.sql?command={}",
+        percent_encoding::utf8_percent_encode(
+            &command.to_string(),
+            percent_encoding::NON_ALPHANUMERIC
+        )
+    )
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+async fn test_exec_commands_allow_list() {
+    let exec_commands = serde_json::json!({
+        "echo": {"program": "/bin/echo", "allowed_args": ["[a-zA-Z!]+"]},
+        "upper": {
+            "program": "/bin/sh",
+            "args": ["-c", "tr a-z A-Z; echo $GREETING >&2; exit 3"],
+            "env": {"GREETING": "hi"}
+        },
+        "sleep": {"program": "/bin/sleep", "args": ["5"], "timeout_ms": 100},
+        "yes": {"program": "/bin/sh", "args": ["-c", "yes | head -c 1000"], "max_output_size": 10}
+    });
+
+    let uri = "/tests/exec/exec.sql?exec_program=echo&exec_arg1=ok";
+    let row = exec_with_config(uri, exec_commands.clone()).await.unwrap();
+    assert_eq!(row["actual"], "ok It works !\n");
+
+    let uri = "/tests/exec/exec.sql?exec_program=echo&exec_arg1=--bad";
+    let err = exec_with_config(uri, exec_commands.clone())
+        .await
+        .unwrap_err();
+    assert!(err.contains("is not allowed"), "{err}");
+
+    let uri = "/tests/exec/exec.sql?exec_program=ls";
+    let err = exec_with_config(uri, exec_commands.clone())
+        .await
+        .unwrap_err();
+    assert!(err.contains("not in exec_commands"), "{err}");
+
+    let command = serde_json::json!({"command": "upper", "stdin": "hello"});
+    let row = exec_with_config(&exec_with_meta_uri(&command), exec_commands.clone())
+        .await
+        .unwrap();
+    let result: serde_json::Value = serde_json::from_str(row["result"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        result,
+        serde_json::json!({"exit_code": 3, "stdout": "HELLO", "stderr": "hi\n"})
+    );
+
+    let command = serde_json::json!({"command": "sleep"});
+    let row = exec_with_config(&exec_with_meta_uri(&command), exec_commands.clone())
+        .await
+        .unwrap();
+    assert!(
+        row["result"].as_str().unwrap().contains("timed out"),
+        "{row}"
+    );
+
+    let command = serde_json::json!({"command": "yes"});
+    let row = exec_with_config(&exec_with_meta_uri(&command), exec_commands)
+        .await
+        .unwrap();
+    assert!(
+        row["result"].as_str().unwrap().contains("max_output_size"),
+        "{row}"
+    );
+}
@@ -0,0 +1 @@
+select sqlpage.fetch($request) as result;
@@ -0,0 +1 @@
+select sqlpage.fetch_all($requests) as result;
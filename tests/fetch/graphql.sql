@@ -0,0 +1 @@
+select sqlpage.graphql($endpoint, $query, $variables) as result;
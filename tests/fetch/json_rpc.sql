@@ -0,0 +1 @@
+select sqlpage.json_rpc($endpoint, $method, $params) as result;
@@ -0,0 +1,244 @@
+use actix_web::{http::header, test::TestRequest, web::Data};
+use sqlpage::{AppState, webserver::http::main_handler};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+
+/// Answers each connection with the next response, and returns the requests it received
+async fn scripted_server(responses: Vec<&'static str>) -> (u16, JoinHandle<Vec<String>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            let headers = String::from_utf8_lossy(&request).to_ascii_lowercase();
+            let content_length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .map_or(0, |length| length.trim().parse().unwrap());
+            let mut body = vec![0; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+            request.extend(body);
+            requests.push(String::from_utf8(request).unwrap().to_ascii_lowercase());
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }
+        requests
+    });
+    (port, server)
+}
+
+/// Runs a test file with the given parameters, and returns the JSON it rendered
+async fn run_file(app_data: &Data<AppState>, file: &str, params: &[(&str, &str)]) -> String {
+    let query: Vec<String> = params
+        .iter()
+        .map(|(name, value)| {
+            let value =
+                percent_encoding::utf8_percent_encode(value, percent_encoding::NON_ALPHANUMERIC);
+            format!("{name}={value}")
+        })
+        .collect();
+    let req = TestRequest::get()
+        .uri(&format!("/tests/fetch/{file}?{}", query.join("&")))
+        .app_data(app_data.clone())
+        .insert_header(header::Accept::json())
+        .to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    let body = actix_web::test::read_body(resp).await;
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn result(body: &str) -> String {
+    let rows: serde_json::Value =
+        serde_json::from_str(body).unwrap_or_else(|_| panic!("unexpected response: {body}"));
+    rows[0]["result"]
+        .as_str()
+        .unwrap_or_else(|| panic!("unexpected response: {rows}"))
+        .to_string()
+}
+
+async fn fetch(app_data: &Data<AppState>, request: &serde_json::Value) -> String {
+    result(&run_file(app_data, "fetch.sql", &[("request", &request.to_string())]).await)
+}
+
+#[actix_web::test]
+async fn test_fetch_retries_transient_errors() {
+    let (port, server) = scripted_server(vec![
+        "HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\nContent-Length: 3\r\n\r\nbad",
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok",
+        "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 11\r\n\r\nunavailable",
+    ])
+    .await;
+    let app_data = crate::common::make_app_data().await;
+    let url = format!("http://127.0.0.1:{port}/");
+
+    let request = serde_json::json!({"url": url, "retries": 2});
+    assert_eq!(fetch(&app_data, &request).await, "ok");
+
+    // POST requests are not idempotent, so they are never resent
+    let request = serde_json::json!({"url": url, "method": "POST", "retries": 2});
+    assert_eq!(fetch(&app_data, &request).await, "unavailable");
+
+    assert_eq!(server.await.unwrap().len(), 3);
+}
+
+#[actix_web::test]
+async fn test_fetch_cache() {
+    let (port, server) = scripted_server(vec![
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nCache-Control: max-age=60\r\nContent-Length: 5\r\n\r\nfresh",
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nETag: \"v1\"\r\nCache-Control: no-cache\r\nContent-Length: 5\r\n\r\nhello",
+        "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+    ])
+    .await;
+    let app_data = crate::common::make_app_data().await;
+
+    let request =
+        serde_json::json!({"url": format!("http://127.0.0.1:{port}/fresh"), "cache_ttl_ms": 60000});
+    assert_eq!(fetch(&app_data, &request).await, "fresh");
+    assert_eq!(fetch(&app_data, &request).await, "fresh");
+
+    let request =
+        serde_json::json!({"url": format!("http://127.0.0.1:{port}/etag"), "cache_ttl_ms": 60000});
+    assert_eq!(fetch(&app_data, &request).await, "hello");
+    assert_eq!(fetch(&app_data, &request).await, "hello");
+
+    let requests = server.await.unwrap();
+    assert_eq!(requests.len(), 3, "{requests:?}");
+    assert!(requests[0].starts_with("get /fresh "), "{requests:?}");
+    assert!(!requests[1].contains("if-none-match"), "{requests:?}");
+    assert!(
+        requests[2].starts_with("get /etag ") && requests[2].contains("if-none-match: \"v1\""),
+        "{requests:?}"
+    );
+}
+
+#[actix_web::test]
+async fn test_fetch_all_is_concurrent_and_ordered() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // both requests must be in flight before the server answers, in the reverse order
+    let server = tokio::spawn(async move {
+        let mut connections = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            let path = String::from_utf8(request).unwrap()[4..]
+                .split(' ')
+                .next()
+                .unwrap()
+                .to_string();
+            connections.push((path, stream));
+        }
+        for (path, mut stream) in connections.into_iter().rev() {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{path}",
+                path.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    let app_data = crate::common::make_app_data().await;
+    let uri = format!(
+        "/tests/fetch/fetch_all.sql?requests={}",
+        percent_encoding::utf8_percent_encode(
+            &serde_json::json!([
+                format!("http://127.0.0.1:{port}/first"),
+                format!("http://127.0.0.1:{port}/second"),
+            ])
+            .to_string(),
+            percent_encoding::NON_ALPHANUMERIC
+        )
+    );
+    let req = TestRequest::get()
+        .uri(&uri)
+        .app_data(app_data)
+        .insert_header(header::Accept::json())
+        .to_srv_request();
+    let resp = tokio::time::timeout(std::time::Duration::from_secs(10), main_handler(req))
+        .await
+        .expect("the requests were not sent concurrently")
+        .unwrap();
+    let body = actix_web::test::read_body(resp).await;
+    let rows: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let results: serde_json::Value =
+        serde_json::from_str(rows[0]["result"].as_str().unwrap()).unwrap();
+    assert_eq!(results[0]["body"], "/first", "{results}");
+    assert_eq!(results[1]["body"], "/second", "{results}");
+    assert_eq!(results[1]["status"], 200, "{results}");
+    server.await.unwrap();
+}
+
+#[actix_web::test]
+async fn test_graphql() {
+    let (port, server) = scripted_server(vec![
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: 31\r\n\r\n{\"data\":{\"user\":{\"id\":\"42\"}}}",
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: 59\r\n\r\n{\"data\":null,\"errors\":[{\"message\":\"Cannot query field\"}]}",
+    ])
+    .await;
+    let app_data = crate::common::make_app_data().await;
+    let endpoint = serde_json::json!({
+        "url": format!("http://127.0.0.1:{port}/graphql"),
+        "headers": {"Authorization": "Bearer secret"}
+    })
+    .to_string();
+    let params = [
+        ("endpoint", endpoint.as_str()),
+        ("query", "query ($id: ID!) { user(id: $id) { id } }"),
+        ("variables", r#"{"id": "42"}"#),
+    ];
+    let body = run_file(&app_data, "graphql.sql", &params).await;
+    assert_eq!(result(&body), r#"{"user":{"id":"42"}}"#);
+
+    let body = run_file(&app_data, "graphql.sql", &params).await;
+    assert!(body.contains("Cannot query field"), "{body}");
+
+    let requests = server.await.unwrap();
+    assert!(requests[0].starts_with("post /graphql "), "{requests:?}");
+    assert!(
+        requests[0].contains("authorization: bearer secret"),
+        "{requests:?}"
+    );
+    assert!(
+        requests[0].ends_with(
+            r#"{"query":"query ($id: id!) { user(id: $id) { id } }","variables":{"id":"42"}}"#
+        ),
+        "{requests:?}"
+    );
+}
+
+#[actix_web::test]
+async fn test_json_rpc() {
+    let (port, server) = scripted_server(vec![
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 36\r\n\r\n{\"jsonrpc\":\"2.0\",\"result\":3,\"id\":1}",
+        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 77\r\n\r\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32601,\"message\":\"Method not found\"},\"id\":1}",
+    ])
+    .await;
+    let app_data = crate::common::make_app_data().await;
+    let endpoint = format!("http://127.0.0.1:{port}/rpc");
+    let params = [
+        ("endpoint", endpoint.as_str()),
+        ("method", "add"),
+        ("params", "[1, 2]"),
+    ];
+    let body = run_file(&app_data, "json_rpc.sql", &params).await;
+    assert_eq!(result(&body), "3");
+
+    let body = run_file(&app_data, "json_rpc.sql", &params).await;
+    assert!(
+        body.contains("returned error -32601: Method not found"),
+        "{body}"
+    );
+
+    let requests = server.await.unwrap();
+    assert!(
+        requests[0].ends_with(r#"{"jsonrpc":"2.0","method":"add","id":1,"params":[1,2]}"#),
+        "{requests:?}"
+    );
+}
@@ -0,0 +1 @@
+select sqlpage.fetch_with_meta($request) as result;
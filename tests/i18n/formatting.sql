@@ -0,0 +1,3 @@
+SELECT 'table' AS component, json_object('price', 'currency:EUR', 'size', 'bytes') AS format;
+SELECT 1234.5 AS price, 1500000 AS size;
+SELECT 0.125 AS price, 2048 AS size;
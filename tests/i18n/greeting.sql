@@ -0,0 +1,2 @@
+SELECT 'text' AS component, sqlpage.t('greeting', '{"name": "Ada"}') AS contents;
+SELECT 'table' AS component;
@@ -0,0 +1,75 @@
+use actix_web::{http::header, test};
+use sqlpage::webserver::http::main_handler;
+
+use crate::common::{make_app_data_from_config, test_config};
+
+async fn render_greeting(request: test::TestRequest) -> String {
+    render(request, "/tests/i18n/greeting.sql").await
+}
+
+async fn render(request: test::TestRequest, path: &str) -> String {
+    let mut config = test_config();
+    config.configuration_directory = "tests/i18n".into();
+    let app_data = make_app_data_from_config(config).await;
+    let req = request.uri(path).app_data(app_data).to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    assert_eq!(
+        resp.headers().get(header::VARY).unwrap(),
+        "Accept-Language, Cookie"
+    );
+    String::from_utf8(test::read_body(resp).await.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn test_accept_language_selects_catalog() {
+    let request = test::TestRequest::get()
+        .insert_header(header::Accept::html())
+        .insert_header((header::ACCEPT_LANGUAGE, "de-CH, fr-FR;q=0.8, en;q=0.5"));
+    let body = render_greeting(request).await;
+    assert!(body.contains(r#"lang="fr""#), "{body}");
+    assert!(body.contains("Bonjour Ada !"), "{body}");
+    assert!(body.contains("Aucune donnée"), "{body}");
+}
+
+#[actix_web::test]
+async fn test_locale_cookie_takes_precedence() {
+    let request = test::TestRequest::get()
+        .insert_header(header::Accept::html())
+        .insert_header((header::ACCEPT_LANGUAGE, "fr"))
+        .insert_header((header::COOKIE, "lang=en"));
+    let body = render_greeting(request).await;
+    assert!(body.contains(r#"lang="en""#), "{body}");
+    assert!(body.contains("Hello Ada!"), "{body}");
+    assert!(body.contains("No data"), "{body}");
+}
+
+#[actix_web::test]
+async fn test_table_format_follows_locale() {
+    let request = test::TestRequest::get()
+        .insert_header(header::Accept::html())
+        .insert_header((header::ACCEPT_LANGUAGE, "fr"));
+    let body = render(request, "/tests/i18n/formatting.sql").await;
+    assert!(body.contains("1\u{202f}234,50\u{a0}€"), "{body}");
+    assert!(body.contains("1,5\u{a0}MB"), "{body}");
+    // Amounts are rounded as decimals: 0.125 is not rounded down to 0.12
+    assert!(body.contains("0,13\u{a0}€"), "{body}");
+    assert!(body.contains(r#"data-sort_value="1234.5""#), "{body}");
+}
+
+#[actix_web::test]
+async fn test_default_locale_without_app_catalogs() {
+    let config_dir = tempfile::tempdir().unwrap();
+    let mut config = test_config();
+    config.configuration_directory = config_dir.path().to_path_buf();
+    let app_data = make_app_data_from_config(config).await;
+    let req = test::TestRequest::get()
+        .uri("/tests/i18n/greeting.sql")
+        .insert_header(header::Accept::html())
+        .insert_header((header::ACCEPT_LANGUAGE, "fr-FR"))
+        .app_data(app_data)
+        .to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    assert!(resp.headers().get(header::VARY).is_none());
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("No data"), "{body}");
+}
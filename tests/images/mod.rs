@@ -0,0 +1,112 @@
+use actix_web::{
+    body::MessageBody,
+    http::{StatusCode, header},
+    test,
+    web::Data,
+};
+use sqlpage::{AppState, webserver::http::create_app};
+
+use crate::common::test_config;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(width, height))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+async fn get(
+    app: &impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+    >,
+    path: &str,
+    condition: Option<(header::HeaderName, &header::HeaderValue)>,
+) -> (StatusCode, header::HeaderMap, Vec<u8>) {
+    let mut req = test::TestRequest::get().uri(path);
+    if let Some((name, value)) = condition {
+        req = req.insert_header((name, value.clone()));
+    }
+    let resp = test::call_service(app, req.to_request()).await;
+    let (status, headers) = (resp.status(), resp.headers().clone());
+    let body = test::read_body(resp).await;
+    (status, headers, body.to_vec())
+}
+
+#[actix_web::test]
+async fn test_resized_images() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let web_root = temp_dir.path().to_path_buf();
+    std::fs::write(web_root.join("photo.png"), png(400, 200)).unwrap();
+    let data_url = format!(
+        "data:image/png;base64,{}",
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, png(64, 64))
+    );
+    std::fs::write(
+        web_root.join("avatar.sql"),
+        format!("select 'download' as component, '{data_url}' as data_url;"),
+    )
+    .unwrap();
+    std::fs::write(
+        web_root.join("cards.sql"),
+        "select 'card' as component; select 'Photo' as title, '/photo.png' as top_image;",
+    )
+    .unwrap();
+
+    let mut config = test_config();
+    config.web_root.clone_from(&web_root);
+    config.image_cache_directory = Some(web_root.join("cache"));
+    let app_state = AppState::init(&config).await.unwrap();
+    let app = test::init_service(create_app(Data::new(app_state))).await;
+
+    let (status, headers, body) = get(&app, "/_sqlpage/img/photo.png?w=20&fmt=webp", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), "image/webp");
+    assert_eq!(
+        headers.get(header::CACHE_CONTROL).unwrap(),
+        "public, max-age=604800"
+    );
+    let resized = image::load_from_memory(&body).unwrap();
+    // widths are rounded up to the nearest allowed width
+    assert_eq!((resized.width(), resized.height()), (160, 80));
+    assert_eq!(
+        std::fs::read_dir(web_root.join("cache")).unwrap().count(),
+        1
+    );
+
+    let last_modified = headers.get(header::LAST_MODIFIED).unwrap();
+    let condition = Some((header::IF_MODIFIED_SINCE, last_modified));
+    let (status, _, body) = get(&app, "/_sqlpage/img/photo.png?w=20&fmt=webp", condition).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert!(body.is_empty());
+
+    // images are never enlarged
+    let (status, headers, body) = get(&app, "/_sqlpage/img/avatar.sql?w=1000", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    assert_eq!(headers.get(header::CONTENT_TYPE).unwrap(), "image/png");
+    assert_eq!(
+        headers.get(header::CACHE_CONTROL).unwrap(),
+        "private, no-cache"
+    );
+    let resized = image::load_from_memory(&body).unwrap();
+    assert_eq!((resized.width(), resized.height()), (64, 64));
+    let etag = headers.get(header::ETAG).unwrap();
+    let condition = Some((header::IF_NONE_MATCH, etag));
+    let (status, _, body) = get(&app, "/_sqlpage/img/avatar.sql?w=1000", condition).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert!(body.is_empty());
+
+    let (status, _, _) = get(&app, "/_sqlpage/img/cards.sql?w=20", None).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let (status, _, _) = get(&app, "/_sqlpage/img/missing.png?w=20", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, _, body) = get(&app, "/cards.sql", None).await;
+    let body = String::from_utf8(body).unwrap();
+    assert!(
+        body.contains(r#"srcset="/_sqlpage/img/photo.png?w&#x3D;320 320w, "#),
+        "{body}"
+    );
+}
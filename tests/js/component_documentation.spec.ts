@@ -95,12 +95,19 @@ const HELPERS = new Set([
   "array_contains",
   "array_contains_case_insensitive",
   "buildinfo",
+  "column_format",
   "csv_escape",
   "default",
   "delay",
   "entries",
   "flush_delayed",
+  "format",
+  "format_bytes",
+  "format_currency",
+  "format_date",
+  "format_number",
   "icon_img",
+  "image_srcset",
   "loose_eq",
   "markdown",
   "minus",
@@ -161,7 +168,11 @@ function properties_of_template(source: string): TemplateProperties {
 
     const is_closing = body.startsWith("/");
     const opens_a_nested_context = /^#(each|with)\b/.test(body);
+    // Partials (`{{> name}}`), named blocks (`{{#> name}}`) and inline partials
+    // (`{{#*inline "name"}}`) are named after a template, not a property.
+    const names_a_partial = /^(#?>|#\*inline\b)/.test(body);
     body = body.replace(/^[#^/>&]+/, "").trim();
+    if (names_a_partial) body = body.replace(/^\S+/, "");
     if (is_closing) {
       if (/^(each|with)\b/.test(body)) contexts.pop();
       continue;
@@ -0,0 +1,78 @@
+use actix_web::{body::MessageBody, http::StatusCode, test, web::Data};
+use sqlpage::{
+    AppState,
+    app_config::{AppConfig, DevOrProd},
+    webserver::http::create_app,
+};
+
+use crate::common::test_config;
+
+async fn live_reload_app(
+    config: AppConfig,
+) -> impl actix_web::dev::Service<
+    actix_http::Request,
+    Response = actix_web::dev::ServiceResponse<impl MessageBody<Error = impl std::fmt::Debug>>,
+    Error = actix_web::Error,
+> {
+    let app_state = AppState::init(&config).await.unwrap();
+    test::init_service(create_app(Data::new(app_state))).await
+}
+
+fn site_config(web_root: &std::path::Path) -> AppConfig {
+    std::fs::write(
+        web_root.join("page.sql"),
+        "select 'text' as component, 'hello' as contents;",
+    )
+    .unwrap();
+    let mut config = test_config();
+    config.web_root = web_root.to_path_buf();
+    config.configuration_directory = web_root.to_path_buf();
+    config.live_reload = true;
+    config
+}
+
+#[actix_web::test]
+async fn test_pages_subscribe_to_their_changes() {
+    let web_root = tempfile::tempdir().unwrap();
+    let app = live_reload_app(site_config(web_root.path())).await;
+
+    let req = test::TestRequest::get().uri("/page.sql").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("hello"), "{body}");
+    assert!(body.contains("_sqlpage/live_reload?page="), "{body}");
+
+    let req = test::TestRequest::get()
+        .uri("/_sqlpage/live_reload?page=%2Fpage.sql")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let mut events = Box::pin(resp.into_body());
+    let event = std::future::poll_fn(|cx| events.as_mut().poll_next(cx))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, ": connected\n\n");
+}
+
+#[actix_web::test]
+async fn test_no_live_reload_in_production() {
+    let web_root = tempfile::tempdir().unwrap();
+    let mut config = site_config(web_root.path());
+    config.environment = DevOrProd::Production;
+    let app = live_reload_app(config).await;
+
+    let req = test::TestRequest::get().uri("/page.sql").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(!body.contains("_sqlpage/live_reload"), "{body}");
+
+    let req = test::TestRequest::get()
+        .uri("/_sqlpage/live_reload")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
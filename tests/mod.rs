@@ -1,12 +1,23 @@
 mod basic;
+mod bundle;
 mod common;
 mod core;
+mod custom_helpers;
 mod data_formats;
 mod errors;
 mod exec;
+mod fetch;
+mod https_proxy;
+mod i18n;
+mod images;
+mod live_reload;
 mod oidc;
+mod releases;
 mod requests;
+mod resumable_uploads;
+mod s3;
 mod server_timing;
 pub mod sql_test_files;
+mod template_inheritance;
 mod transactions;
 mod uploads;
@@ -836,3 +836,60 @@ async fn test_oidc_logout_is_session_bound() {
         "the user's own logout must clear their auth cookie"
     );
 }
+
+/// `/_sqlpage/img/private/...` serves the files of `/private/`, so it must be protected like them.
+#[actix_web::test]
+async fn test_resized_images_of_protected_paths_require_login() {
+    use sqlpage::{
+        AppState,
+        app_config::{AppConfig, test_database_url},
+    };
+
+    crate::common::init_log();
+    let provider = FakeOidcProvider::new();
+    let web_root = tempfile::tempdir().unwrap();
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    std::fs::create_dir(web_root.path().join("private")).unwrap();
+    std::fs::write(web_root.path().join("private/photo.png"), &png).unwrap();
+    std::fs::write(web_root.path().join("photo.png"), &png).unwrap();
+    std::fs::write(
+        web_root.path().join("private/secret.sql"),
+        "select 'download' as component, 'data:text/plain,secret' as data_url;",
+    )
+    .unwrap();
+
+    let db_url = test_database_url();
+    let config_json = json!({
+        "database_url": db_url,
+        "oidc_issuer_url": provider.issuer_url,
+        "oidc_client_id": provider.client_id,
+        "oidc_client_secret": provider.client_secret,
+        "oidc_protected_paths": ["/private"],
+        "host": "localhost:1",
+        "web_root": web_root.path(),
+        "image_cache_directory": web_root.path().join("cache"),
+    });
+    let config: AppConfig = serde_json::from_value(config_json).unwrap();
+    let app_state = AppState::init(&config).await.unwrap();
+    let app = test::init_service(create_app(Data::new(app_state))).await;
+
+    for uri in [
+        "/_sqlpage/img/private/photo.png?w=20",
+        "/_sqlpage/img/%70rivate/photo.png?w=20",
+        "/_sqlpage/img/private/secret.sql",
+    ] {
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    let req = test::TestRequest::get().uri("/_sqlpage/img/photo.png?w=20");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(header::CACHE_CONTROL).unwrap(),
+        "public, max-age=604800"
+    );
+}
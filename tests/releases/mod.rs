@@ -0,0 +1,98 @@
+use actix_web::{http::StatusCode, test};
+use sqlpage::releases::{self, Change};
+use sqlpage::webserver::Database;
+use sqlx::executor::Executor as _;
+
+use crate::common::{make_app_data_from_config, req_path_with_app_data, test_config};
+
+fn write_site(dir: &std::path::Path, files: &[(&str, &str)]) {
+    let _ = std::fs::remove_dir_all(dir);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
+
+async fn body_of(app_data: &actix_web::web::Data<sqlpage::AppState>, path: &str) -> String {
+    let resp = req_path_with_app_data(path, app_data.clone())
+        .await
+        .unwrap();
+    String::from_utf8(test::read_body(resp).await.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn test_publish_diff_and_rollback() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dir = temp_dir.path();
+    let site = dir.join("site");
+    let web_root = dir.join("empty_web_root");
+    std::fs::create_dir_all(&web_root).unwrap();
+    write_site(
+        &site,
+        &[
+            (
+                "index.sql",
+                "select 'text' as component, 'release 1' as contents;",
+            ),
+            ("old.sql", "select 1;"),
+            ("sqlpage/sqlpage.json", "{\"secret\": true}"),
+        ],
+    );
+
+    // A database of its own, so that other tests keep using sqlpage_files
+    let mut config = test_config();
+    config.database_url = format!("sqlite://{}?mode=rwc", dir.join("releases.db").display());
+    config.web_root.clone_from(&web_root);
+    config.cache_stale_duration_ms = Some(0);
+    let db = Database::init(&config).await.unwrap();
+    assert!(releases::publish(&db, &site, None).await.is_err());
+    // what a migration would do
+    for sql in releases::get_create_tables_sql(db.info.database_type) {
+        db.connection.execute(sql).await.unwrap();
+    }
+    let empty_release = releases::publish(&db, &web_root, None).await.unwrap_err();
+    assert!(
+        empty_release.to_string().contains("no files to publish"),
+        "{empty_release:#}"
+    );
+
+    assert_eq!(
+        releases::publish(&db, &site, Some("alice")).await.unwrap(),
+        (1, 2)
+    );
+    let app_data = make_app_data_from_config(config.clone()).await;
+    assert!(body_of(&app_data, "/index.sql").await.contains("release 1"));
+
+    write_site(
+        &site,
+        &[
+            (
+                "index.sql",
+                "select 'text' as component, 'release 2' as contents;",
+            ),
+            ("new.sql", "select 2;"),
+        ],
+    );
+    assert_eq!(releases::publish(&db, &site, None).await.unwrap(), (2, 2));
+    assert_eq!(
+        releases::diff(&db, 1, None).await.unwrap(),
+        [
+            (Change::Modified, "index.sql".to_string()),
+            (Change::Added, "new.sql".to_string()),
+            (Change::Deleted, "old.sql".to_string()),
+        ]
+    );
+    // The server switches to the new release without being restarted
+    assert!(body_of(&app_data, "/index.sql").await.contains("release 2"));
+    let resp = req_path_with_app_data("/old.sql", app_data.clone())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(releases::rollback(&db, None).await.unwrap(), 1);
+    assert!(body_of(&app_data, "/index.sql").await.contains("release 1"));
+    assert!(releases::rollback(&db, None).await.is_err());
+    assert!(releases::rollback(&db, Some(42)).await.is_err());
+    assert!(releases::diff(&db, 1, Some(42)).await.is_err());
+}
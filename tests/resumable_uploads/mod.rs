@@ -0,0 +1,210 @@
+use actix_web::{
+    body::MessageBody,
+    http::{Method, StatusCode, header},
+    test,
+    web::Data,
+};
+use sqlpage::{AppState, webserver::http::create_app};
+
+use crate::common::test_config;
+
+async fn tus_request(
+    app: &impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+    >,
+    req: test::TestRequest,
+) -> (StatusCode, header::HeaderMap, String) {
+    let req = req.insert_header(("Tus-Resumable", "1.0.0")).to_request();
+    let resp = test::call_service(app, req).await;
+    let (status, headers) = (resp.status(), resp.headers().clone());
+    let body = test::read_body(resp).await;
+    (status, headers, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn chunk(offset: usize, data: &[u8]) -> test::TestRequest {
+    test::TestRequest::default()
+        .method(Method::PATCH)
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .insert_header(("Content-Type", "application/offset+octet-stream"))
+        .set_payload(data.to_vec())
+}
+
+#[actix_web::test]
+async fn test_resumable_upload() {
+    let config_dir =
+        std::env::temp_dir().join(format!("sqlpage_resumable_uploads_{}", std::process::id()));
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let mut config = test_config();
+    config.configuration_directory.clone_from(&config_dir);
+    config.max_resumable_upload_size = 1000;
+    config.allow_anonymous_resumable_uploads = true;
+    let app_state = AppState::init(&config).await.unwrap();
+    let app = test::init_service(create_app(Data::new(app_state))).await;
+
+    let create = || {
+        test::TestRequest::post()
+            .uri("/_sqlpage/uploads")
+            .insert_header(("Upload-Length", "11"))
+            // filename: hello.txt, filetype: text/plain
+            .insert_header((
+                "Upload-Metadata",
+                "filename aGVsbG8udHh0,filetype dGV4dC9wbGFpbg==",
+            ))
+    };
+    let (status, _, _) = tus_request(&app, create()).await;
+    assert_eq!(
+        status,
+        StatusCode::NOT_FOUND,
+        "disabled without on_upload.sql"
+    );
+
+    std::fs::write(
+        config_dir.join("on_upload.sql"),
+        "select 'json' as component;
+        select sqlpage.uploaded_file_name('file') as name,
+            sqlpage.uploaded_file_mime_type('file') as type,
+            sqlpage.read_file_as_text(sqlpage.uploaded_file_path('file')) as contents;",
+    )
+    .unwrap();
+
+    let (status, headers, body) = tus_request(&app, create()).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let location = headers.get(header::LOCATION).unwrap().to_str().unwrap();
+    assert!(location.starts_with("/_sqlpage/uploads/"), "{location}");
+
+    let (status, headers, _) = tus_request(&app, chunk(0, b"hello ").uri(location)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(headers.get("Upload-Offset").unwrap(), "6");
+
+    let (status, _, _) = tus_request(&app, chunk(0, b"hello ").uri(location)).await;
+    assert_eq!(status, StatusCode::CONFLICT, "wrong offset");
+
+    let (status, headers, _) = tus_request(
+        &app,
+        test::TestRequest::default()
+            .method(Method::HEAD)
+            .uri(location),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers.get("Upload-Offset").unwrap(), "6");
+    assert_eq!(headers.get("Upload-Length").unwrap(), "11");
+
+    let (status, headers, body) = tus_request(&app, chunk(6, b"world").uri(location)).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(headers.get("Upload-Offset").unwrap(), "11");
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        response,
+        serde_json::json!([{"name": "hello.txt", "type": "text/plain", "contents": "hello world"}])
+    );
+    let (status, _, _) = tus_request(
+        &app,
+        test::TestRequest::default()
+            .method(Method::HEAD)
+            .uri(location),
+    )
+    .await;
+    assert_eq!(
+        status,
+        StatusCode::NOT_FOUND,
+        "completed uploads are removed"
+    );
+    assert_eq!(
+        std::fs::read_dir(config_dir.join("uploads"))
+            .unwrap()
+            .count(),
+        0
+    );
+
+    let (status, _, _) = tus_request(&app, create().insert_header(("Upload-Length", "1001"))).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    std::fs::remove_dir_all(&config_dir).unwrap();
+}
+
+#[actix_web::test]
+async fn test_resumable_upload_limits_and_authorization() {
+    let config_dir = tempfile::tempdir().unwrap();
+    let mut config = test_config();
+    config.configuration_directory = config_dir.path().to_path_buf();
+    config.max_pending_resumable_uploads = 2;
+    config.max_pending_resumable_uploads_size = 15;
+    let app_state = AppState::init(&config).await.unwrap();
+    let app = test::init_service(create_app(Data::new(app_state))).await;
+
+    let head = |location: &str| {
+        test::TestRequest::default()
+            .method(Method::HEAD)
+            .uri(location)
+    };
+    let (status, _, _) = tus_request(
+        &app,
+        head("/_sqlpage/uploads/0123456789abcdef0123456789abcdef"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "disabled");
+
+    // on_upload.sql exists, but cannot be read
+    std::fs::create_dir(config_dir.path().join("on_upload.sql")).unwrap();
+    let (status, _, _) = tus_request(
+        &app,
+        test::TestRequest::post()
+            .uri("/_sqlpage/uploads")
+            .insert_header(("Upload-Length", "5")),
+    )
+    .await;
+    assert_eq!(
+        status,
+        StatusCode::FORBIDDEN,
+        "refused without on_upload_start.sql"
+    );
+    // The metadata of the upload are POST variables of on_upload_start.sql
+    std::fs::write(
+        config_dir.path().join("on_upload_start.sql"),
+        "select 'status_code' as component, 403 as status where :user is null or :user <> 'alice';",
+    )
+    .unwrap();
+    let create = |length: &str, user: &str| {
+        test::TestRequest::post()
+            .uri("/_sqlpage/uploads")
+            .insert_header(("Upload-Length", length))
+            .insert_header(("Upload-Metadata", format!("user {user}")))
+    };
+    // "Ym9i" is "bob", "YWxpY2U=" is "alice"
+    let (status, headers, _) = tus_request(&app, create("5", "Ym9i")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(headers.get("Tus-Resumable").unwrap(), "1.0.0");
+
+    let (status, headers, body) = tus_request(&app, create("5", "YWxpY2U=")).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let first = headers
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let (status, _, _) = tus_request(&app, create("11", "YWxpY2U=")).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE, "15 bytes at most");
+    let (status, _, body) = tus_request(&app, create("10", "YWxpY2U=")).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let (status, _, _) = tus_request(&app, create("0", "YWxpY2U=")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "2 uploads at most");
+
+    // on_upload.sql fails: the upload is removed entirely
+    let (status, _, _) = tus_request(&app, chunk(0, b"hello").uri(&first)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let (status, _, _) = tus_request(&app, head(&first)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        std::fs::read_dir(config_dir.path().join("uploads"))
+            .unwrap()
+            .count(),
+        2,
+        "only the data and information of the second upload remain"
+    );
+    let (status, _, body) = tus_request(&app, create("5", "YWxpY2U=")).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{
+    App, HttpRequest, HttpResponse, HttpServer,
+    http::{Method, StatusCode},
+    test, web,
+};
+use sqlpage::webserver::http::{form_config, main_handler, payload_config};
+
+use crate::common::{make_app_data_from_config, req_path_with_app_data, test_config};
+
+/// Objects of the stand-in bucket, with their version
+type Objects = Arc<Mutex<HashMap<String, (web::Bytes, u32)>>>;
+
+/// A minimal S3-compatible server, like `MinIO`, that stores objects in memory
+fn start_object_storage(objects: Objects) -> u16 {
+    let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = HttpServer::new(move || {
+        let objects = Arc::clone(&objects);
+        App::new().default_service(web::to(move |req: HttpRequest, body: web::Bytes| {
+            let objects = Arc::clone(&objects);
+            async move {
+                let signed = req
+                    .headers()
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.starts_with("AWS4-HMAC-SHA256 Credential=test-key/"));
+                if !signed {
+                    return HttpResponse::Forbidden().finish();
+                }
+                let mut objects = objects.lock().unwrap();
+                let key = req.path().to_string();
+                if req.method() == Method::PUT {
+                    let version = objects.get(&key).map_or(1, |(_, v)| v + 1);
+                    objects.insert(key, (body, version));
+                    return HttpResponse::Ok().finish();
+                }
+                match objects.get(&key) {
+                    Some((contents, version)) => {
+                        let mut resp = HttpResponse::Ok();
+                        resp.insert_header(("etag", format!("\"v{version}\"")));
+                        if req.method() == Method::HEAD {
+                            resp.finish()
+                        } else {
+                            resp.body(contents.clone())
+                        }
+                    }
+                    None => HttpResponse::NotFound().finish(),
+                }
+            }
+        }))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .shutdown_timeout(1)
+    .run();
+    tokio::spawn(server);
+    port
+}
+
+fn put(objects: &Objects, key: &str, contents: &'static str) {
+    let mut objects = objects.lock().unwrap();
+    let version = objects.get(key).map_or(1, |(_, v)| v + 1);
+    objects.insert(key.to_string(), (web::Bytes::from(contents), version));
+}
+
+async fn body_of(resp: actix_web::dev::ServiceResponse) -> String {
+    String::from_utf8(test::read_body(resp).await.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn test_files_are_served_and_uploaded_to_bucket() {
+    let objects = Objects::default();
+    let port = start_object_storage(Arc::clone(&objects));
+    put(
+        &objects,
+        "/site/web/page.sql",
+        "select 'text' as component, 'version 1' as contents;",
+    );
+    put(
+        &objects,
+        "/site/web/upload.sql",
+        "select 'text' as component, sqlpage.persist_uploaded_file('my_file', 'uploads', 'txt') as contents;",
+    );
+
+    let web_root = std::env::temp_dir().join(format!("sqlpage_s3_test_{}", std::process::id()));
+    std::fs::create_dir_all(&web_root).unwrap();
+    let mut config = test_config();
+    config.web_root.clone_from(&web_root);
+    config.cache_stale_duration_ms = Some(0);
+    config.s3_bucket = Some("site".into());
+    config.s3_endpoint = Some(format!("http://localhost:{port}"));
+    config.s3_prefix = "web/".into();
+    config.s3_access_key_id = Some("test-key".into());
+    config.s3_secret_access_key = Some("test-secret".into());
+    config.s3_uploads = true;
+    let app_data = make_app_data_from_config(config).await;
+
+    let resp = req_path_with_app_data("/page.sql", app_data.clone())
+        .await
+        .unwrap();
+    assert!(body_of(resp).await.contains("version 1"));
+
+    // A new ETag means that the file changed
+    put(
+        &objects,
+        "/site/web/page.sql",
+        "select 'text' as component, 'version 2' as contents;",
+    );
+    let resp = req_path_with_app_data("/page.sql", app_data.clone())
+        .await
+        .unwrap();
+    assert!(body_of(resp).await.contains("version 2"));
+
+    let resp = req_path_with_app_data("/missing.sql", app_data.clone())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::post()
+        .uri("/upload.sql")
+        .app_data(payload_config(&app_data))
+        .app_data(form_config(&app_data))
+        .app_data(app_data.clone())
+        .insert_header(("accept", "application/json"))
+        .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+        .set_payload(
+            "--1234567890\r\n\
+            Content-Disposition: form-data; name=\"my_file\"; filename=\"test.txt\"\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            Hello from the bucket\r\n\
+            --1234567890--\r\n",
+        )
+        .to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let json: serde_json::Value = test::read_body_json(resp).await;
+    let uploaded_path = json[0]["contents"].as_str().unwrap().to_string();
+    assert!(uploaded_path.starts_with("/uploads/"), "{json}");
+    assert!(
+        objects
+            .lock()
+            .unwrap()
+            .contains_key(&format!("/site/web{uploaded_path}")),
+        "the uploaded file is not in the bucket"
+    );
+    assert!(
+        std::fs::read_dir(&web_root).unwrap().next().is_none(),
+        "the uploaded file must not be written to the web root"
+    );
+
+    let resp = req_path_with_app_data(&uploaded_path, app_data)
+        .await
+        .unwrap();
+    assert_eq!(body_of(resp).await, "Hello from the bucket");
+
+    std::fs::remove_dir_all(&web_root).unwrap();
+}
@@ -0,0 +1 @@
+select 'blob' as component, '<!DOCTYPE html><body>It works !</body>' as contents, 'text/html' as content_type;
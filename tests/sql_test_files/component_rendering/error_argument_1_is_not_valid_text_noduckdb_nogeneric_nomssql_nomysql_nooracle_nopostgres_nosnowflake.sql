@@ -0,0 +1 @@
+select 'text' as component, sha256(CAST(x'ff' AS BLOB)) as contents;
@@ -1,4 +1,4 @@
-select 'shell' as component, 
-    lower(sqlpage.url_encode(lower('HELLO'))) as title;
+update my_table
+set title = lower(sqlpage.url_encode(lower('HELLO')));
 -- this is invalid, because the sqlpage pseudo-function is sandwiched between two native SQL functions.
--- It can't be executed neither before nor after the query is executed.
\ No newline at end of file
+-- Outside of SELECT queries, it can't be executed neither before nor after the query is executed.
@@ -0,0 +1,15 @@
+set url = 'http://localhost:' || $echo_port || '/hello_world';
+set res = sqlpage.fetch_all('[
+    "' || $url || '?first",
+    {"url": "' || $url || '?second", "method": "POST", "body": "hello"},
+    null,
+    {"url": "http://not-a-real-url"},
+    {"method": "GET"}
+]');
+
+select '[{"status":200' as expected_contains,
+       'GET /hello_world?first' as expected_contains,
+       'POST /hello_world?second' as expected_contains,
+       '|hello"},null,{"error":"Request failed' as expected_contains,
+       '{"error":"Invalid http fetch request definition' as expected_contains,
+       $res as actual;
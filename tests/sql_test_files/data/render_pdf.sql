@@ -0,0 +1,2 @@
+select 'data:application/pdf;base64,JVBERi0xLj' as expected_contains,
+    sqlpage.render_pdf('tests/data_formats/pdf_report.sql') as actual;
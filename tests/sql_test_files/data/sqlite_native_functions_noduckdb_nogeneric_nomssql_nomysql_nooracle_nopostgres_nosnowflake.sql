@@ -0,0 +1,16 @@
+-- SQLPage registers these functions natively in SQLite, so they can be used per row in WHERE clauses.
+with words(word) as (values ('apple'), ('banana'), ('cherry'))
+select 'banana,cherry' as expected,
+    group_concat(word, ',') as actual
+from words
+where word regexp '^[bc]';
+
+select 'b-n-n-' as expected, regexp_replace('banana', 'a', '-') as actual;
+select '{"0":"2024-05","year":"2024"}' as expected, regexp_match('(?<year>\d{4})-\d{2}', 'on 2024-05') as actual;
+select 'a%20b%2Fc' as expected, url_encode('a b/c') as actual;
+select 'ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad' as expected, sha256('abc') as actual;
+select sqlpage.hmac('data', 'key', 'sha512') as expected, hmac('data', 'key', 'sha512') as actual;
+select 'creme-brulee' as expected, slugify('Crème brûlée !') as actual;
+select 'e' || char(769) as expected, unicode_normalize('é', 'NFD') as actual;
+select 36 as expected, length(uuid()) as actual;
+select 'It works !' as expected, coalesce(sha256(NULL), 'It works !') as actual;
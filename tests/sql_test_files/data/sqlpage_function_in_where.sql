@@ -0,0 +1,31 @@
+drop table if exists lookup_test_names;
+create table lookup_test_names(name varchar(100));
+insert into lookup_test_names(name) values ('a b');
+insert into lookup_test_names(name) values ('c');
+insert into lookup_test_names(name) values ('c');
+insert into lookup_test_names(name) values (NULL);
+
+select 'a%20b' as expected, sqlpage.url_encode(name) as actual
+from lookup_test_names
+where sqlpage.url_encode(name) <> name;
+
+select 'c' as expected, sqlpage.url_encode(name) as actual
+from lookup_test_names
+group by sqlpage.url_encode(name)
+having count(*) > 1;
+
+select 'c' as expected, a.name as actual
+from lookup_test_names a
+join lookup_test_names b on sqlpage.url_encode(a.name) = b.name;
+
+select 'a%20b' as expected, sqlpage.url_encode(name) as actual
+from lookup_test_names
+where name = 'a b'
+order by actual;
+
+select 'c' as expected, sqlpage.url_encode(name) as actual
+from lookup_test_names
+group by actual
+having count(*) > 1;
+
+select 'hello' as expected, lower(sqlpage.url_encode(lower('HELLO'))) as actual;
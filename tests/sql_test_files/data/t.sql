@@ -0,0 +1,4 @@
+select 'No data' as expected,
+    sqlpage.t('sqlpage.no_data') as actual;
+select '3 rows' as expected,
+    sqlpage.t('{n} rows', '{"n": 3}') as actual;
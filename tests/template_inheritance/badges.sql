@@ -0,0 +1 @@
+SELECT 'badges' AS component, 'Team' AS title;
@@ -0,0 +1,48 @@
+use actix_web::{http::header, test};
+use sqlpage::webserver::http::main_handler;
+
+use crate::common::{make_app_data_from_config, test_config};
+
+#[actix_web::test]
+async fn test_custom_template_extends_builtin() {
+    let mut config = test_config();
+    config.configuration_directory = "tests/template_inheritance".into();
+    let app_data = make_app_data_from_config(config).await;
+    let req = test::TestRequest::get()
+        .uri("/tests/template_inheritance/tables.sql")
+        .insert_header(header::Accept::html())
+        .app_data(app_data)
+        .to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    // Overridden blocks, with the default content of the block
+    assert!(body.contains("<b>Ada</b>"), "{body}");
+    assert!(body.contains("Nothing to see here"), "{body}");
+    assert!(body.contains("Powered by My site"), "{body}");
+    // Blocks that are not overridden keep their default content
+    assert!(
+        body.contains(r#"class="table-sort sort d-inline" data-sort="name""#),
+        "{body}"
+    );
+    assert!(body.contains("<title>My site</title>"), "{body}");
+    assert!(!body.contains("No data"), "{body}");
+}
+
+/// Partial blocks of a custom template that extends nothing are rendered by handlebars, with their parameters
+#[actix_web::test]
+async fn test_custom_template_partial_blocks() {
+    let mut config = test_config();
+    config.configuration_directory = "tests/template_inheritance".into();
+    let app_data = make_app_data_from_config(config).await;
+    let req = test::TestRequest::get()
+        .uri("/tests/template_inheritance/badges.sql")
+        .insert_header(header::Accept::html())
+        .app_data(app_data)
+        .to_srv_request();
+    let resp = main_handler(req).await.unwrap();
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(
+        body.contains(r#"<span class="badge-red">Team</span>"#),
+        "{body}"
+    );
+}
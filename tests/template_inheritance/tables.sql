@@ -0,0 +1,4 @@
+SELECT 'shell' AS component, 'My site' AS title;
+SELECT 'table' AS component, TRUE AS sort;
+SELECT 'Ada' AS name;
+SELECT 'table' AS component;
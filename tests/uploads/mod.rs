@@ -220,3 +220,281 @@ async fn test_csv_upload() -> actix_web::Result<()> {
     );
     Ok(())
 }
+
+#[actix_web::test]
+async fn test_jsonlines_upload() -> actix_web::Result<()> {
+    let req = get_request_to("/tests/uploads/upload_jsonlines_test.sql")
+        .await?
+        .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+        .set_payload(
+            "--1234567890\r\n\
+            Content-Disposition: form-data; name=\"people_file\"; filename=\"people.jsonl\"\r\n\
+            Content-Type: application/x-ndjson\r\n\
+            \r\n\
+            {\"age\": 29, \"name\": \"Ophir\"}\n\
+            {\"name\": \"Max\"}\n\r\n\
+            --1234567890--\r\n",
+        )
+        .to_srv_request();
+    let resp = main_handler(req).await?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = test::read_body(resp).await;
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body_str.contains("Ophir is 29 years old"), "{body_str}");
+    assert!(body_str.contains("Max is unknown years old"), "{body_str}");
+    Ok(())
+}
+
+/// Downloads a spreadsheet from the `xlsx` component, and imports it back.
+#[actix_web::test]
+async fn test_xlsx_upload() -> actix_web::Result<()> {
+    let app_data = crate::common::make_app_data().await;
+    if matches!(
+        app_data.db.info.database_type,
+        sqlpage::webserver::database::SupportedDatabase::Oracle
+    ) {
+        return Ok(());
+    }
+    let req = crate::common::get_request_to_with_data(
+        "/tests/data_formats/xlsx_data.sql",
+        app_data.clone(),
+    )
+    .await?
+    .to_srv_request();
+    let xlsx = test::read_body(main_handler(req).await?).await;
+
+    let mut payload = b"--1234567890\r\n\
+        Content-Disposition: form-data; name=\"report_file\"; filename=\"report.xlsx\"\r\n\
+        Content-Type: application/vnd.openxmlformats-officedocument.spreadsheetml.sheet\r\n\
+        \r\n"
+        .to_vec();
+    payload.extend_from_slice(&xlsx);
+    payload.extend_from_slice(b"\r\n--1234567890--\r\n");
+    let req =
+        crate::common::get_request_to_with_data("/tests/uploads/upload_xlsx_test.sql", app_data)
+            .await?
+            .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+            .set_payload(payload)
+            .to_srv_request();
+    let resp = main_handler(req).await?;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = test::read_body(resp).await;
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        body_str.contains("row 1: Hello &lt;World&gt; ! since 2024-01-01."),
+        "{body_str}"
+    );
+    assert!(
+        body_str.contains("row 2: none since 2024-01-02."),
+        "{body_str}"
+    );
+    assert!(
+        body_str.contains("row 3: 2024-12-25 since 2024-01-03."),
+        "{body_str}"
+    );
+    Ok(())
+}
+
+fn multipart_file(file_name: &str, contents: &[u8]) -> Vec<u8> {
+    let mut payload = format!(
+        "--1234567890\r\n\
+        Content-Disposition: form-data; name=\"my_file\"; filename=\"{file_name}\"\r\n\
+        Content-Type: application/octet-stream\r\n\
+        \r\n"
+    )
+    .into_bytes();
+    payload.extend_from_slice(contents);
+    payload.extend_from_slice(b"\r\n--1234567890--\r\n");
+    payload
+}
+
+#[actix_web::test]
+async fn test_process_uploaded_image() -> actix_web::Result<()> {
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(80, 40))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let req = get_request_to("/tests/uploads/process_image.sql")
+        .await?
+        .insert_header((actix_web::http::header::ACCEPT, "application/json"))
+        .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+        .set_payload(multipart_file("photo.png", &png))
+        .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body_json: serde_json::Value = test::read_body_json(resp).await;
+    let path = body_json[0]["contents"]
+        .as_str()
+        .unwrap_or_else(|| panic!("no path in {body_json}"));
+    let file_path = std::path::Path::new(path.trim_start_matches('/'));
+    assert_eq!(file_path.extension(), Some("webp".as_ref()), "{path}");
+    let processed = image::open(file_path).unwrap();
+    std::fs::remove_file(file_path)?;
+    assert_eq!((processed.width(), processed.height()), (20, 20));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_process_image_file() -> actix_web::Result<()> {
+    let req = get_request_to("/tests/uploads/process_image_file.sql")
+        .await?
+        .insert_header((actix_web::http::header::ACCEPT, "application/json"))
+        .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body_json: serde_json::Value = test::read_body_json(resp).await;
+    let path = body_json[0]["contents"]
+        .as_str()
+        .unwrap_or_else(|| panic!("no path in {body_json}"));
+    let file_path = std::path::Path::new(path.trim_start_matches('/'));
+    let processed = image::open(file_path).unwrap();
+    std::fs::remove_file(file_path)?;
+    assert_eq!(processed.width(), 20);
+    // Without the source option, the path is the name of an upload field
+    assert_eq!(body_json[1]["contents"], "no upload", "{body_json}");
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_persist_file_with_spoofed_extension() -> actix_web::Result<()> {
+    let req = get_request_to("/tests/uploads/persist_png.sql")
+        .await?
+        .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+        .set_payload(multipart_file("photo.png", b"<script>alert(1)</script>"))
+        .to_srv_request();
+    let resp = main_handler(req).await?;
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("do not match its extension"), "{body}");
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_uploaded_file_blob_round_trip() -> actix_web::Result<()> {
+    use sqlpage::webserver::database::SupportedDatabase;
+    use sqlx::executor::Executor as _;
+    let app_data = crate::common::make_app_data().await;
+    let binary_type = match app_data.db.info.database_type {
+        SupportedDatabase::Sqlite | SupportedDatabase::MySql => "BLOB",
+        SupportedDatabase::Postgres => "BYTEA",
+        SupportedDatabase::Mssql => "VARBINARY(MAX)",
+        _ => return Ok(()),
+    };
+    let connection = &app_data.db.connection;
+    if connection
+        .execute("SELECT 1 FROM sqlpage_blob_test_table WHERE 1 = 0")
+        .await
+        .is_err()
+    {
+        let create_table = format!(
+            "CREATE TABLE sqlpage_blob_test_table(file_name VARCHAR(255), contents {binary_type})"
+        );
+        connection.execute(create_table.as_str()).await.unwrap();
+    }
+
+    let contents: Vec<u8> = (0..=255).collect();
+    let req =
+        crate::common::get_request_to_with_data("/tests/uploads/uploaded_file_blob.sql", app_data)
+            .await?
+            .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+            .set_payload(multipart_file("bytes.bin", &contents))
+            .to_srv_request();
+    let resp = main_handler(req).await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"bytes.bin\""
+    );
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/octet-stream"
+    );
+    let body = test::read_body(resp).await;
+    assert_eq!(body.to_vec(), contents);
+    Ok(())
+}
+
+/// A stand-in for clamd, that finds a virus in files containing `EICAR`
+async fn start_fake_clamd() -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut command = [0; 10];
+            stream.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+            let mut contents = Vec::new();
+            loop {
+                let len = stream.read_u32().await.unwrap() as usize;
+                if len == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; len];
+                stream.read_exact(&mut chunk).await.unwrap();
+                contents.extend(chunk);
+            }
+            let is_infected = contents.windows(5).any(|w| w == b"EICAR");
+            let response: &[u8] = if is_infected {
+                b"stream: Eicar-Test-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            stream.write_all(response).await.unwrap();
+        }
+    });
+    address
+}
+
+async fn upload_with_scanner(
+    clamd_address: &str,
+    reject_infected_uploads: bool,
+    contents: &[u8],
+) -> (StatusCode, String) {
+    let mut config = crate::common::test_config();
+    config.clamd_address = Some(clamd_address.to_string());
+    config.reject_infected_uploads = reject_infected_uploads;
+    let app_data = crate::common::make_app_data_from_config(config).await;
+    let req = crate::common::get_request_to_with_data(
+        "/tests/uploads/uploaded_file_scan_result.sql",
+        app_data,
+    )
+    .await
+    .unwrap()
+    .insert_header(("content-type", "multipart/form-data; boundary=1234567890"))
+    .set_payload(multipart_file("file.txt", contents))
+    .to_srv_request();
+    match main_handler(req).await {
+        Ok(resp) => {
+            let status = resp.status();
+            let body = test::read_body(resp).await;
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+        Err(e) => (e.as_response_error().status_code(), e.to_string()),
+    }
+}
+
+#[actix_web::test]
+async fn test_uploads_are_scanned() {
+    let clamd = start_fake_clamd().await;
+
+    let (status, body) = upload_with_scanner(&clamd, true, b"harmless").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, "clean");
+
+    let (status, body) = upload_with_scanner(&clamd, true, b"X5O EICAR test").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+    assert!(body.contains("Eicar-Test-Signature"), "{body}");
+
+    let (status, body) = upload_with_scanner(&clamd, false, b"X5O EICAR test").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body, "Eicar-Test-Signature");
+
+    // files are never accepted without a scan
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let unreachable = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let (status, body) = upload_with_scanner(&unreachable, true, b"harmless").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{body}");
+}
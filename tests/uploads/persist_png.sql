@@ -0,0 +1 @@
+select 'text' as component, sqlpage.persist_uploaded_file('my_file', 'tests_uploads', 'png') as contents;
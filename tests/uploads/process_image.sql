@@ -0,0 +1,2 @@
+set image = sqlpage.process_image('my_file', '{"width": 20, "height": 20, "fit": "cover", "format": "webp", "folder": "tests_uploads"}');
+select 'text' as component, $image as contents;
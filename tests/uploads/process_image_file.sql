@@ -0,0 +1,4 @@
+select 'text' as component,
+    sqlpage.process_image('examples/official-site/blog/pagination.png', '{"source": "file", "width": 20, "format": "png", "folder": "tests_uploads"}') as contents;
+select 'text' as component,
+    coalesce(sqlpage.process_image('examples/official-site/blog/pagination.png'), 'no upload') as contents;
@@ -0,0 +1,6 @@
+drop table if exists sqlpage_json_import_test;
+create table sqlpage_json_import_test(name varchar(512), age varchar(512));
+copy sqlpage_json_import_test(name, age) from 'people_file' with (format jsonlines);
+select 'text' as component,
+    name || ' is ' || coalesce(age, 'unknown') || ' years old. ' as contents
+from sqlpage_json_import_test;
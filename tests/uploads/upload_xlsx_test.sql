@@ -0,0 +1,7 @@
+drop table if exists sqlpage_xlsx_import_test;
+create table sqlpage_xlsx_import_test(id integer, msg varchar(512), since varchar(64));
+copy sqlpage_xlsx_import_test(since, id, msg) from 'report_file' with (format xlsx, sheet 'People & pets');
+select 'text' as component,
+    'row ' || id || ': ' || coalesce(msg, 'none') || ' since ' || since || '. ' as contents
+from sqlpage_xlsx_import_test
+order by id;
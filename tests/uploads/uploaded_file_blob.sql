@@ -0,0 +1,5 @@
+delete from sqlpage_blob_test_table;
+insert into sqlpage_blob_test_table(file_name, contents)
+values (sqlpage.uploaded_file_name('my_file'), sqlpage.uploaded_file_blob('my_file'));
+select 'blob' as component, contents, file_name as filename
+from sqlpage_blob_test_table;
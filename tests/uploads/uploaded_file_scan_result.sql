@@ -0,0 +1,3 @@
+-- displays the verdict of the upload scanner on the uploaded file
+select 'shell-empty' as component,
+    sqlpage.uploaded_file_scan_result('my_file') as html;